@@ -3,11 +3,15 @@ use super::loc::Span;
 use super::loc::HasSpan;
 use super::loc::SourceInfo;
 
-use super::{BinOp, Length, Name, UnOp, Width, Pat};
+use super::{BinOp, Name, UnOp, Width, Pat};
+
+use std::collections::BTreeMap;
 
 use lalrpop_util::lalrpop_mod;
+use lalrpop_util::lexer::Token;
+use lalrpop_util::ErrorRecovery;
 use lalrpop_util::ParseError;
-lalrpop_mod!(grammar);
+lalrpop_mod!(#[allow(clippy::all)] grammar);
 
 /// A `Package` is a compilation unit built from a single file.
 #[derive(Debug, Clone)]
@@ -33,58 +37,234 @@ pub enum Item {
     AltTypeDef(AltTypeDef),
     FnDef(FnDef),
     TbDef(TbDef),
+    ConstDef(ConstDef),
+    ShapeDef(ShapeDef),
 }
 
 impl Item {
     pub fn name(&self) -> &str {
         match self {
-            Item::ModDef(ModDef(_span, name, _decls)) => name.as_str(),
-            Item::ExtDef(ModDef(_span, name, _decls)) => name.as_str(),
+            Item::ModDef(ModDef(_span, _doc, _visibility, name, _type_args, _decls)) => name.as_str(),
+            Item::ExtDef(ModDef(_span, _doc, _visibility, name, _type_args, _decls)) => name.as_str(),
             Item::EnumTypeDef(typedef) => typedef.name.as_str(),
             Item::StructTypeDef(typedef) => typedef.name.as_str(),
             Item::AltTypeDef(typedef) => typedef.name.as_str(),
             Item::FnDef(fndef) => fndef.name.as_str(),
             Item::TbDef(tbdef) => tbdef.name.as_str(),
+            Item::ConstDef(constdef) => constdef.name.as_str(),
+            Item::ShapeDef(shapedef) => shapedef.name.as_str(),
+        }
+    }
+
+    pub fn visibility(&self) -> Visibility {
+        match self {
+            Item::ModDef(ModDef(_span, _doc, visibility, _name, _type_args, _decls)) => *visibility,
+            Item::ExtDef(ModDef(_span, _doc, visibility, _name, _type_args, _decls)) => *visibility,
+            Item::EnumTypeDef(typedef) => typedef.visibility,
+            Item::StructTypeDef(typedef) => typedef.visibility,
+            Item::AltTypeDef(typedef) => typedef.visibility,
+            Item::FnDef(fndef) => fndef.visibility,
+            Item::TbDef(_tbdef) => Visibility::Private,
+            Item::ConstDef(constdef) => constdef.visibility,
+            Item::ShapeDef(shapedef) => shapedef.visibility,
+        }
+    }
+
+    /// Attach the doc comments which come before the item, and before the declarations inside of it.
+    fn attach_docs(&mut self, docs: &DocComments) {
+        let doc = docs.before(&self.span());
+        match self {
+            Item::ModDef(ModDef(_span, moddef_doc, _visibility, _name, _type_args, decls)) |
+            Item::ExtDef(ModDef(_span, moddef_doc, _visibility, _name, _type_args, decls)) => {
+                *moddef_doc = doc;
+                for decl in decls {
+                    decl.attach_docs(docs);
+                }
+            },
+            Item::EnumTypeDef(typedef) => typedef.doc = doc,
+            Item::StructTypeDef(typedef) => typedef.doc = doc,
+            Item::AltTypeDef(typedef) => typedef.doc = doc,
+            Item::FnDef(fndef) => fndef.doc = doc,
+            Item::TbDef(tbdef) => tbdef.doc = doc,
+            Item::ConstDef(constdef) => constdef.doc = doc,
+            Item::ShapeDef(shapedef) => shapedef.doc = doc,
+        }
+    }
+}
+
+/// The `///` doc comments in a file, by line.
+/// A doc comment has to be on a line of its own. `////` is an ordinary comment.
+struct DocComments(BTreeMap<usize, String>);
+
+impl DocComments {
+    fn from(package_text: &str) -> DocComments {
+        let lines: Vec<&str> = package_text.lines().collect();
+        let mut docs = BTreeMap::new();
+        for comment in parse_comments(package_text) {
+            let line = comment.span.start().line();
+            let Some(text) = comment.text.strip_prefix("///") else { continue };
+            if text.starts_with('/') || lines[line - 1].trim_start() != comment.text.trim_end() {
+                continue;
+            }
+            let text = text.trim_end();
+            docs.insert(line, text.strip_prefix(' ').unwrap_or(text).to_string());
+        }
+        DocComments(docs)
+    }
+
+    /// The run of doc comments on the lines just above `span`.
+    fn before(&self, span: &Span) -> Option<String> {
+        let mut lines = vec![];
+        let mut line = span.start().line();
+        while let Some(text) = self.0.get(&(line - 1)) {
+            lines.push(text.as_str());
+            line -= 1;
+        }
+        if lines.is_empty() {
+            return None;
         }
+        lines.reverse();
+        Some(lines.join("\n"))
     }
 }
 
 impl HasSpan for Item {
     fn span(&self) -> Span {
         match self {
-            Item::ModDef(ModDef(span, _name, _decls)) => span.clone(),
-            Item::ExtDef(ModDef(span, _name, _decls)) => span.clone(),
+            Item::ModDef(ModDef(span, _doc, _visibility, _name, _type_args, _decls)) => span.clone(),
+            Item::ExtDef(ModDef(span, _doc, _visibility, _name, _type_args, _decls)) => span.clone(),
             Item::EnumTypeDef(typedef) => typedef.span.clone(),
             Item::StructTypeDef(typedef) => typedef.span.clone(),
             Item::AltTypeDef(typedef) => typedef.span.clone(),
             Item::FnDef(fndef) => fndef.span.clone(),
             Item::TbDef(tbdef) => tbdef.span.clone(),
+            Item::ConstDef(constdef) => constdef.span.clone(),
+            Item::ShapeDef(shapedef) => shapedef.span.clone(),
         }
     }
 }
 
+/// Whether an item can be used from other packages.
+/// Items are private unless they are marked `pub`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Visibility {
+    Pub,
+    Private,
+}
+
+/// A `mod` definition. Parametric definitions, such as `mod Fifo[T of Type, depth of Nat]`, have type arguments.
+/// The `Option<String>` is its doc comment.
 #[derive(Debug, Clone)]
-pub struct ModDef(pub Span, pub Ident, pub Vec<Decl>);
+pub struct ModDef(pub Span, pub Option<String>, pub Visibility, pub Ident, pub Vec<(Ident, Kind)>, pub Vec<Decl>);
 
 /// A [`Decl`] is a declaration that lives inside of a `mod` or `ext` definiton.
+///
+/// Submodules, ports, and regs keep their doc comments as an `Option<String>` after the span.
 #[derive(Debug, Clone)]
 pub enum Decl {
-    Mod(Span, Ident, Vec<Decl>),
-    ModInst(Span, Ident, Ident),
+    Mod(Span, Option<String>, Ident, Vec<Decl>),
+    ModInst(Span, Ident, Ident, Vec<TypeParam>),
+    /// A vector of instances. Eg, `mod lanes[4] of Adder;`.
+    ModInstVec(Span, Ident, Nat, Ident, Vec<TypeParam>),
     Dom(Span, Ident),
-    Incoming(Span, Ident, Type),
-    Outgoing(Span, Ident, Type),
-    Node(Span, Ident, Type),
-    Reg(Span, Ident, Type, Option<Box<Expr>>),
+    Incoming(Span, Option<String>, Ident, Type),
+    Outgoing(Span, Option<String>, Ident, Type),
+    /// A node. Its type is `None` when it's written `node x of _;` and it is inferred from its driver.
+    Node(Span, Ident, Option<Type>),
+    Reg(Span, Option<String>, Ident, Type, Option<Ident>, Option<Box<Expr>>),
+    /// A memory. Eg, `mem m of Vec[Word[32], 1024] latency 1 { read r; write w mask 4; }`.
+    /// The latency is `None` when it is left out.
+    Mem(Span, Ident, Type, Option<Ident>, Option<u64>, Vec<MemPort>),
     Wire(Span, Wire),
     When(Span, When),
+    /// Replicated declarations. Eg, `for i in 0..4 { ... }`.
+    For(Span, Ident, Nat, Nat, Vec<Decl>),
+    /// An interface port. Eg, `port enq of Channel;` or `port deq of flip Channel;`.
+    Port(Span, Ident, bool, Ident),
+    /// A bulk connection between two interface ports. Eg, `q.enq <> enq;`.
+    Connect(Span, Target, Target),
+    /// Eg, `assert count < 10;`.
+    Assert(Span, Box<Expr>),
+    /// Eg, `cover full;`.
+    Cover(Span, Box<Expr>),
+}
+
+impl HasSpan for Decl {
+    fn span(&self) -> Span {
+        match self {
+            Decl::Mod(span, _doc, _name, _decls) => span.clone(),
+            Decl::ModInst(span, _name, _moddef_name, _params) => span.clone(),
+            Decl::ModInstVec(span, _name, _n, _moddef_name, _params) => span.clone(),
+            Decl::Dom(span, _name) => span.clone(),
+            Decl::Incoming(span, _doc, _name, _typ) => span.clone(),
+            Decl::Outgoing(span, _doc, _name, _typ) => span.clone(),
+            Decl::Node(span, _name, _typ) => span.clone(),
+            Decl::Reg(span, _doc, _name, _typ, _dom, _reset) => span.clone(),
+            Decl::Mem(span, _name, _typ, _dom, _latency, _ports) => span.clone(),
+            Decl::Wire(span, _wire) => span.clone(),
+            Decl::When(span, _when) => span.clone(),
+            Decl::For(span, _var, _start, _end, _decls) => span.clone(),
+            Decl::Port(span, _name, _flip, _shape) => span.clone(),
+            Decl::Connect(span, _a, _b) => span.clone(),
+            Decl::Assert(span, _e) => span.clone(),
+            Decl::Cover(span, _e) => span.clone(),
+        }
+    }
+}
+
+impl Decl {
+    /// Whether the declaration is seen from outside of the `mod`: a port, an interface port, or a clock domain.
+    pub fn is_port_or_dom(&self) -> bool {
+        matches!(self, Decl::Incoming(..) | Decl::Outgoing(..) | Decl::Port(..) | Decl::Dom(..))
+    }
+
+    /// Attach the doc comment which comes before the declaration.
+    /// Only submodules, ports, and regs keep theirs.
+    fn attach_docs(&mut self, docs: &DocComments) {
+        let doc = docs.before(&self.span());
+        match self {
+            Decl::Mod(_span, decl_doc, _name, decls) => {
+                *decl_doc = doc;
+                for decl in decls {
+                    decl.attach_docs(docs);
+                }
+            },
+            Decl::For(_span, _var, _start, _end, decls) => {
+                for decl in decls {
+                    decl.attach_docs(docs);
+                }
+            },
+            Decl::Incoming(_span, decl_doc, _name, _typ) => *decl_doc = doc,
+            Decl::Outgoing(_span, decl_doc, _name, _typ) => *decl_doc = doc,
+            Decl::Reg(_span, decl_doc, _name, _typ, _dom, _reset) => *decl_doc = doc,
+            _ => (),
+        }
+    }
+}
+
+/// A port of a `mem`. See [`Decl::Mem`].
+#[derive(Debug, Clone)]
+pub enum MemPort {
+    /// Eg, `read r;`.
+    Read(Ident),
+    /// Eg, `write w;`, or `write w mask 4;` for a write mask with 4 bits.
+    Write(Ident, Option<u64>),
 }
 
 /// A user-defined `enum` type.
 #[derive(Debug, Clone)]
 pub struct EnumTypeDef {
     pub name: Ident,
-    pub values: Vec<(Ident, WordLit)>,
+    pub visibility: Visibility,
+    /// The text of its `///` doc comments.
+    pub doc: Option<String>,
+    /// Eg, `Word[4]` in `enum type State of Word[4] { ... }`.
+    pub typ: Option<Type>,
+    /// Eg, `onehot enum type State { ... }`.
+    pub onehot: bool,
+    /// Values without an explicit `= 3w2` are numbered automatically.
+    /// The span is that of the literal.
+    pub values: Vec<(Ident, Option<(Span, WordLit)>)>,
     pub span: Span,
 }
 
@@ -92,6 +272,9 @@ pub struct EnumTypeDef {
 #[derive(Debug, Clone)]
 pub struct StructTypeDef {
     pub name: Ident,
+    pub visibility: Visibility,
+    /// The text of its `///` doc comments.
+    pub doc: Option<String>,
     pub fields: Vec<(Ident, Type)>,
     pub span: Span,
 }
@@ -100,14 +283,48 @@ pub struct StructTypeDef {
 #[derive(Debug, Clone)]
 pub struct AltTypeDef {
     pub name: Ident,
+    pub visibility: Visibility,
+    /// The text of its `///` doc comments.
+    pub doc: Option<String>,
+    pub type_args: Vec<(Ident, Kind)>,
     pub alts: Vec<(Ident, Vec<Type>)>,
     pub span: Span,
 }
 
+/// A user-defined `shape`, which groups ports of mixed directions into an interface.
+#[derive(Debug, Clone)]
+pub struct ShapeDef {
+    pub name: Ident,
+    pub visibility: Visibility,
+    /// The text of its `///` doc comments.
+    pub doc: Option<String>,
+    pub ports: Vec<(Ident, Direction, Type)>,
+    pub span: Span,
+}
+
+/// The direction of a port in a [`ShapeDef`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Incoming,
+    Outgoing,
+}
+
+impl Direction {
+    pub fn flip(self) -> Direction {
+        match self {
+            Direction::Incoming => Direction::Outgoing,
+            Direction::Outgoing => Direction::Incoming,
+        }
+    }
+}
+
 /// A user-defined function.
 #[derive(Debug, Clone)]
 pub struct FnDef {
     pub name: Ident,
+    pub visibility: Visibility,
+    /// The text of its `///` doc comments.
+    pub doc: Option<String>,
     pub type_args: Vec<(Ident, Kind)>,
     pub args: Vec<(Ident, Type)>,
     pub ret: Type,
@@ -115,10 +332,31 @@ pub struct FnDef {
     pub span: Span,
 }
 
+/// A package-level `const` definition.
+#[derive(Debug, Clone)]
+pub struct ConstDef {
+    pub name: Ident,
+    pub visibility: Visibility,
+    /// The text of its `///` doc comments.
+    pub doc: Option<String>,
+    pub value: ConstValue,
+    pub span: Span,
+}
+
+/// The value of a [`ConstDef`].
+#[derive(Debug, Clone)]
+pub enum ConstValue {
+    /// Eg, `const XLEN of Nat = 32;`.
+    Nat(Nat),
+    /// Eg, `const RESET_PC of Word[32] = 0x8000_0000w32;`.
+    Expr(Type, Expr),
+}
+
 /// A testbench
 #[derive(Debug, Clone)]
 pub struct TbDef {
     pub name: Ident,
+    pub doc: Option<String>,
     pub span: Span,
     pub statements: Vec<TbStatement>,
 }
@@ -130,6 +368,12 @@ pub enum TbStatement {
     Reset,
     Clock,
     ModInst(Ident, Ident),
+    /// Eg, `poke dut.in = 3;`
+    Poke(Span, Ident, Ident, Box<Expr>),
+    /// Eg, `expect dut.out == 4;`
+    Expect(Span, Ident, Ident, Box<Expr>),
+    /// Eg, `loop 10 { clock; }`
+    Loop(Span, u64, Vec<TbStatement>),
 }
 
 /// An expression.
@@ -165,15 +409,64 @@ pub enum Expr {
     Idx(Span, Box<Expr>, u64),
     /// A static index over a range. Eg, `foo[8..4]`.
     IdxRange(Span, Box<Expr>, u64, u64),
+    /// A dynamic index. Eg, `foo[i]`.
+    IdxDyn(Span, Box<Expr>, Box<Expr>),
     /// A hole. Eg, `?foo`.
     Hole(Span, Option<Ident>),
 }
 
+impl HasSpan for Expr {
+    fn span(&self) -> Span {
+        match self {
+            Expr::Ident(span, _id) => span.clone(),
+            Expr::Dot(span, _e, _id) => span.clone(),
+            Expr::Word(span, _width, _value) => span.clone(),
+            Expr::Enum(span, _typ, _value) => span.clone(),
+            Expr::Struct(span, _fields) => span.clone(),
+            Expr::Vec(span, _es) => span.clone(),
+            Expr::Call(span, _name, _params, _es) => span.clone(),
+            Expr::Let(span, _x, _typ, _e, _b) => span.clone(),
+            Expr::UnOp(span, _op, _e) => span.clone(),
+            Expr::BinOp(span, _op, _e1, _e2) => span.clone(),
+            Expr::If(span, _cond, _e1, _e2) => span.clone(),
+            Expr::Match(span, _e, _arms) => span.clone(),
+            Expr::IdxField(span, _e, _field) => span.clone(),
+            Expr::Idx(span, _e, _i) => span.clone(),
+            Expr::IdxRange(span, _e, _j, _i) => span.clone(),
+            Expr::IdxDyn(span, _e, _i) => span.clone(),
+            Expr::Hole(span, _name) => span.clone(),
+        }
+    }
+}
+
 /// A reference to a hardware component, either in this module, or in a child module.
 #[derive(Debug, Clone)]
 pub enum Target {
     Local(Ident),
     Nonlocal(Ident, Ident),
+    /// A port of one of a vector of instances. Eg, `lanes[i].in`.
+    Indexed(Ident, Nat, Ident),
+    /// A port of an interface port of a submodule. Eg, `q.enq.valid`.
+    Nested(Ident, Ident, Ident),
+    /// Part of a component. Eg, `out->valid` or `bus[8..4]`.
+    Partial(Box<Target>, Select),
+}
+
+impl Target {
+    fn partial(target: Target, select: Option<Select>) -> Target {
+        match select {
+            Some(select) => Target::Partial(Box::new(target), select),
+            None => target,
+        }
+    }
+}
+
+/// The part of a component driven by a partial connection.
+#[derive(Debug, Clone)]
+pub enum Select {
+    Field(Ident),
+    Idx(Nat),
+    IdxRange(Nat, Nat),
 }
 
 impl Ident {
@@ -221,22 +514,36 @@ impl WordLit {
 #[derive(Clone, Debug)]
 pub enum Type {
     /// An n-bit two's complement integer. Nominally unsigned. Written `Word[n]`.
-    Word(Width),
+    Word(Span, Nat),
+    /// An n-bit two's complement signed integer. Written `SInt[n]`.
+    SInt(Span, Nat),
     /// A n-element vector. Written `Vec[T, n]`.
-    Vec(Box<Type>, Length),
+    Vec(Span, Box<Type>, Nat),
     /// An optional value. Written `Valid[T]`.
     Valid(Box<Type>),
     /// An unresolved reference to a user-defined type.
     TypeRef(Ident, Vec<TypeParam>),
 }
 
+/// A natural number used as a width or a length.
+/// It may refer to a `Nat` parameter. Eg, the `depth` in `Vec[T, depth]`.
+/// Widths may also be computed from parameters. Eg, `Word[n+1]`.
+#[derive(Clone, Debug)]
+pub enum Nat {
+    Lit(u64),
+    Param(Ident),
+    Add(Box<Nat>, Box<Nat>),
+    Sub(Box<Nat>, Box<Nat>),
+    Mul(Box<Nat>, Box<Nat>),
+}
+
 #[derive(Clone, Debug)]
 pub enum TypeParam {
     Nat(u64),
     Type(Type),
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Kind {
     Nat,
     Type,
@@ -265,38 +572,139 @@ impl HasSpan for Ident {
     }
 }
 
+/// Parse a package, collecting every independent syntax error.
+///
+/// After a syntax error, the parser skips ahead to the end of the enclosing declaration,
+/// `match` arm, or item and carries on from there.
 pub fn parse_package_from_string(package_text: &str) -> Result<Package, Vec<BitsyError>> {
-    let source_info = SourceInfo::from_string(package_text);
-    match grammar::PackageParser::new().parse(&source_info, &package_text) {
-        Err(ParseError::UnrecognizedToken { token, expected }) => {
-            let start_idx = token.0;
-            let end_idx = token.2;
-            let span = Span::from(&source_info, start_idx, end_idx);
-
-            let message = format!("Parse error: Expected one of {}", expected.join(" "));
-            return Err(vec![BitsyError::ParseError(span, message)]);
+    parse_package(&SourceInfo::from_string(package_text), package_text)
+}
+
+/// Parse a package, with spans pointing into `source_info`. See [`parse_package_from_string`].
+pub fn parse_package(source_info: &SourceInfo, package_text: &str) -> Result<Package, Vec<BitsyError>> {
+    let mut recovered = vec![];
+    let result = grammar::PackageParser::new().parse(source_info, &mut recovered, package_text);
+
+    let mut errors: Vec<BitsyError> = recovered
+        .into_iter()
+        .map(|ErrorRecovery { error, dropped_tokens: _ }| parse_error(source_info, error))
+        .collect();
+    match result {
+        Ok(mut package) if errors.is_empty() => {
+            let docs = DocComments::from(package_text);
+            for item in &mut package.items {
+                item.attach_docs(&docs);
+            }
+            Ok(package)
+        },
+        Ok(_package) => Err(errors),
+        Err(error) => {
+            errors.push(parse_error(source_info, error));
+            Err(errors)
         },
-        Err(ParseError::InvalidToken { location }) => {
-            let span = Span::from(&source_info, location, location + 1);
-            let message = format!("Parse error");
-            return Err(vec![BitsyError::ParseError(span, message)]);
+    }
+}
+
+/// A `//` or `/* */` comment, along with where it appears.
+#[derive(Debug, Clone)]
+pub struct Comment {
+    pub span: Span,
+    pub text: String,
+}
+
+/// Collect the comments in a package.
+///
+/// The lexer skips over comments, so they never make it into the AST.
+/// Tools which need to keep them, such as the formatter, find them here instead.
+pub fn parse_comments(package_text: &str) -> Vec<Comment> {
+    let source_info = SourceInfo::from_string(package_text);
+    let mut comments = vec![];
+    let mut pos = 0;
+    while pos < package_text.len() {
+        let rest = &package_text[pos..];
+        let len = if rest.starts_with("//") {
+            rest.find(['\n', '\r']).unwrap_or(rest.len())
+        } else if let Some(body) = rest.strip_prefix("/*") {
+            body.find("*/").map(|end| end + 4).unwrap_or(rest.len())
+        } else if let Some(body) = rest.strip_prefix('"') {
+            // Skip over strings, which may contain `//`.
+            let len = body.find('"').map(|end| end + 2).unwrap_or(rest.len());
+            pos += len;
+            continue;
+        } else {
+            pos += rest.chars().next().unwrap().len_utf8();
+            continue;
+        };
+        comments.push(Comment {
+            span: Span::from(&source_info, pos, pos + len),
+            text: rest[..len].to_string(),
+        });
+        pos += len;
+    }
+    comments
+}
+
+fn parse_error(source_info: &SourceInfo, error: ParseError<usize, Token<'_>, BitsyError>) -> BitsyError {
+    match error {
+        ParseError::UnrecognizedToken { token: (start_idx, Token(_, text), end_idx), expected } => {
+            let span = Span::from(source_info, start_idx, end_idx);
+            let message = format!("Parse error: unexpected `{text}`, expected {}", describe_expected(&expected));
+            BitsyError::ParseError(span, message)
         },
-        Err(ParseError::ExtraToken { token }) => {
-            let start_idx = token.0;
-            let end_idx = token.2;
-            let span = Span::from(&source_info, start_idx, end_idx);
-            let message = format!("Parse error: extra token: {token:?}");
-            return Err(vec![BitsyError::ParseError(span, message)]);
+        ParseError::InvalidToken { location } => {
+            let span = Span::from(source_info, location, location + 1);
+            BitsyError::ParseError(span, "Parse error: invalid token".to_string())
         },
-        Err(ParseError::UnrecognizedEof { location, expected }) => {
-            let span = Span::from(&source_info, location, location + 1);
-            let message = format!("Parse error: Unexpected end of file: Expected {expected:?}");
-            return Err(vec![BitsyError::ParseError(span, message)]);
+        ParseError::ExtraToken { token: (start_idx, Token(_, text), end_idx) } => {
+            let span = Span::from(source_info, start_idx, end_idx);
+            BitsyError::ParseError(span, format!("Parse error: extra token `{text}`"))
         },
-        Err(ParseError::User { error }) => {
-            let message = format!("Parse error: {error:?}");
-            return Err(vec![BitsyError::ParseError(Span::unknown(), message)]);
+        ParseError::UnrecognizedEof { location, expected } => {
+            let span = Span::from(source_info, location, location);
+            let message = format!("Parse error: unexpected end of file, expected {}", describe_expected(&expected));
+            BitsyError::ParseError(span, message)
         },
-        Ok(package) => Ok(package),
+        ParseError::User { error } => error,
+    }
+}
+
+/// Describe the tokens the parser expected. Eg, "`;` or `}`".
+///
+/// LALRPOP names tokens by their pattern, so the regular expressions are replaced by what they match.
+fn describe_expected(expected: &[String]) -> String {
+    let mut descriptions: Vec<String> = vec![];
+    for token in expected {
+        let description = if token.starts_with("r#") {
+            if token.contains("@") {
+                "a constructor".to_string()
+            } else if token.contains("A-Za-z") {
+                "an identifier".to_string()
+            } else if token.contains("w[0-9]") {
+                "a word literal".to_string()
+            } else if token.contains("0-9") {
+                "a number".to_string()
+            } else {
+                "a string".to_string()
+            }
+        } else {
+            format!("`{}`", token.trim_matches('"'))
+        };
+        if !descriptions.contains(&description) {
+            descriptions.push(description);
+        }
+    }
+
+    match descriptions.as_slice() {
+        [] => "nothing".to_string(),
+        [description] => description.clone(),
+        [rest @ .., last] => format!("{} or {last}", rest.join(", ")),
     }
 }
+
+/// Parse the digits of a literal, which may contain underscores.
+fn parse_lit<'input>(span: &Span, digits: &str, radix: u32) -> Result<u64, ParseError<usize, Token<'input>, BitsyError>> {
+    let digits: String = digits.chars().filter(|&c| c != '_').collect();
+    u64::from_str_radix(&digits, radix).map_err(|_| ParseError::User {
+        error: BitsyError::ParseError(span.clone(), format!("Parse error: invalid literal: {digits}")),
+    })
+}
@@ -1,7 +1,11 @@
 mod check;
+mod elaborate;
 mod mlir;
 
+pub(crate) use elaborate::Instance;
+
 use super::*;
+use std::collections::BTreeMap;
 use std::collections::BTreeSet;
 use std::sync::Arc;
 
@@ -38,14 +42,14 @@ impl Circuit {
         let mut result: Arc<Component> = component;
         for part in path.split(".") {
             if let Some(child) = result.child(part) {
-                if let Component::ModInst(_loc, _name, moddef) = &*child {
-                    result = moddef.clone();
+                if let Component::ModInst(_loc, _name, _moddef, _params) = &*child {
+                    result = self.elaborate(&child);
                 } else {
                     result = child.clone();
                 }
             } else if part == "set" {
                 // TODO HACK I don't like this.
-                if let Component::Reg(_loc, _name, _typ, _reset) = &*result {
+                if let Component::Reg(_loc, _doc, _name, _typ, _dom, _reset) = &*result {
                     // ignore so that .set on a reg will return the reg itself.
                     // This is only for bitsy_lang.sim, and should be removed.
                 } else {
@@ -63,7 +67,7 @@ impl Circuit {
     pub fn wires(&self) -> Vec<(Path, Wire)> {
         let mut results = vec![];
         for (path, component) in self.walk_instances() {
-            for wire in component.wires() {
+            for wire in component.netlist_wires() {
                 // TODO _loc should be loc?
                 results.push((path.clone(), wire));
             }
@@ -71,10 +75,33 @@ impl Circuit {
         results
     }
 
+    /// Walk the instance's module hierarchy, returning all `assert` and `cover` statements,
+    /// along with the [`Path`] of the instance they belong to.
+    pub fn assertions(&self) -> Vec<(Path, Assertion)> {
+        let mut results = vec![];
+        for (path, component) in self.walk_instances() {
+            for assertion in component.assertions() {
+                results.push((path.clone(), assertion));
+            }
+        }
+        results
+    }
+
     pub fn exts(&self) -> Vec<(Path, Arc<Component>)> {
         let mut results = vec![];
         for (path, component) in self.walk_instances() {
-            if let Component::Ext(_loc, _name, _children) = &*component {
+            if let Component::Ext(_loc, _doc, _name, _children) = &*component {
+                results.push((path, component.clone()));
+            }
+        }
+        results
+    }
+
+    /// Walk the instance's module hierarchy, returning all `mem`s.
+    pub fn mems(&self) -> Vec<(Path, Arc<Component>)> {
+        let mut results = vec![];
+        for (path, component) in self.walk_instances() {
+            if let Component::Mem(_loc, _name, _typ, _dom, _latency, _ports) = &*component {
                 results.push((path, component.clone()));
             }
         }
@@ -85,31 +112,95 @@ impl Circuit {
     pub fn regs(&self) -> Vec<Path> {
         let mut results = vec![];
         for (path, component) in self.walk_instances() {
-            if let Component::Reg(_loc, _name, _typ, _reset) = &*component {
+            if let Component::Reg(_loc, _doc, _name, _typ, _dom, _reset) = &*component {
+                results.push(path);
+            }
+        }
+        results
+    }
+
+    /// Walk the instance's module hierarchy, returning the [`Path`]s of all clock domains.
+    pub fn doms(&self) -> Vec<Path> {
+        let mut results = vec![];
+        for (path, component) in self.walk_instances() {
+            if let Component::Dom(_loc, _name) = &*component {
                 results.push(path);
             }
         }
         results
     }
 
-    /// Walk the instance's module hierarchy, returning all [`Path`]s for everything.
+    /// Given the [`Path`] of a clock domain, follow the `$=` wires back to the domain which drives it.
+    pub fn root_dom(&self, dom: Path) -> Path {
+        let mut driver_for: BTreeMap<Path, Path> = BTreeMap::new();
+        for (path, Wire(_loc, target, _select, expr, wire_type)) in self.wires() {
+            if let (WireType::Dom, Expr::Reference(_loc, _typ, driver)) = (wire_type, &*expr) {
+                driver_for.insert(path.join(target), path.join(driver.clone()));
+            }
+        }
+
+        let mut dom = dom;
+        while let Some(driver) = driver_for.get(&dom) {
+            dom = driver.clone();
+        }
+        dom
+    }
+
+    /// Given a [`Path`], if it is a [`Component::Reg`] or [`Component::Mem`] declared `on` a clock domain,
+    /// return the root of that domain.
+    /// Registers and memories without a domain are clocked by the implicit clock.
+    pub fn dom_for(&self, path: Path) -> Option<Path> {
+        match &*self.component(path.clone())? {
+            Component::Reg(_loc, _doc, _name, _typ, Some(dom), _reset) => Some(self.root_dom(path.parent().join(dom.clone().into()))),
+            Component::Mem(_loc, _name, _typ, Some(dom), _latency, _ports) => Some(self.root_dom(path.parent().join(dom.clone().into()))),
+            _ => None,
+        }
+    }
+
+    /// Walk the instance's module hierarchy, returning all [`Path`]s for everything which carries a value.
+    /// Clock domains are listed separately by [`Circuit::doms`].
     pub fn paths(&self) -> Vec<Path> {
-        self.top().paths_rec("top".into())
+        let mut results = vec![];
+        for (path, component) in self.walk_instances() {
+            match &*component {
+                Component::Reg(_loc, _doc, _name, _typ, _dom, _reset) => {
+                    results.push(path.set());
+                    results.push(path);
+                },
+                Component::Node(_loc, _name, _typ) => results.push(path),
+                Component::Dom(_loc, _name) => (),
+                Component::Incoming(_loc, _doc, _name, _typ) => results.push(path),
+                Component::Outgoing(_loc, _doc, _name, _typ) => results.push(path),
+                Component::Mod(_loc, _doc, _name, _type_args, _children, _wires, _whens, _assertions, _interfaces) => (),
+                Component::ModInst(_loc, _name, _moddef, _params) => (),
+                Component::ModInstVec(_loc, _name, _insts) => (),
+                Component::Ext(_loc, _doc, _name, _children) => (),
+                Component::Mem(_loc, _name, _typ, _dom, _latency, _ports) => (),
+            }
+        }
+        results
     }
 
     /// Given a [`Path`], if it is a [`Component::Reg`], return its reset value.
     pub fn reset_for_reg(&self, path: Path) -> Option<Arc<Expr>> {
-        if let Component::Reg(_loc, _name, _typ, reset) = &*self.component(path)? {
+        if let Component::Reg(_loc, _doc, _name, _typ, _dom, reset) = &*self.component(path)? {
             reset.clone()
         } else {
             None
         }
     }
 
+    /// Walk the instance's module hierarchy.
+    /// Each [`Component::ModInst`] is elaborated to a concrete, monomorphized definition.
     fn walk_instances(&self) -> Vec<(Path, Arc<Component>)> {
         self.walk_instances_rec(self.top(), "top".into())
     }
 
+    fn elaborate(&self, modinst: &Component) -> Arc<Component> {
+        // Every instance was already checked when the package was loaded.
+        self.package().instance(modinst)
+    }
+
     fn walk_instances_rec(
         &self,
         component: Arc<Component>,
@@ -117,14 +208,10 @@ impl Circuit {
     ) -> Vec<(Path, Arc<Component>)> {
         let mut results = vec![(path.clone(), component.clone())];
         for child in component.children() {
-            if let Component::ModInst(_loc, name, reference) = &*child {
-                if let Some(moddef) = self.package().moddef(reference.name()) {
-                    results.extend(
-                        self.walk_instances_rec(moddef.clone(), path.join(child.name().into())),
-                    );
-                } else {
-                    panic!("Undefined reference to ext: {name}")
-                }
+            if let Component::ModInst(_loc, _name, _moddef, _params) = &*child {
+                results.extend(
+                    self.walk_instances_rec(self.elaborate(&child), path.join(child.name().into())),
+                );
             } else {
                 results
                     .extend(self.walk_instances_rec(child.clone(), path.join(child.name().into())));
@@ -28,14 +28,24 @@ impl Package {
         }
 
         for moddef in self.moddefs() {
-            if let Err(component_errors) = self.check_component(moddef.clone()) {
-                for component_error in component_errors {
-                    errors.push(component_error);
-                }
+            // Only the ports of a deferred mod are known until it's instantiated. See resolve_instance.
+            // Its instances are checked when they are elaborated, and a mod without any is warned about.
+            // See Package::unchecked_moddefs.
+            if self.deferred_moddef(&moddef).is_some() {
+                continue;
             }
 
-            for submod in moddef.submods() {
-                if let Err(component_errors) = self.check_component(submod) {
+            let mut components = vec![moddef.clone()];
+            components.extend(moddef.submods());
+            for component in components {
+                // Parametric mods are typechecked once for each way they are instantiated. See Package::elaborate.
+                // The rest is checked here, so that mistakes are reported even in a mod which is never used.
+                let result = if moddef.is_parametric() {
+                    self.check_generic_component(component)
+                } else {
+                    self.check_component(component)
+                };
+                if let Err(component_errors) = result {
                     for component_error in component_errors {
                         errors.push(component_error);
                     }
@@ -43,6 +53,9 @@ impl Package {
             }
         }
 
+        // Each instance is checked once when it's elaborated, however many times it's used.
+        errors.extend(self.instance_errors());
+
         if errors.is_empty() {
             Ok(())
         } else {
@@ -51,18 +64,39 @@ impl Package {
     }
 
     fn check_enum_typedef(&self, typedef: Arc<EnumTypeDef>) -> Result<(), Vec<BitsyError>> {
-        // TODO maybe print the values in actual form presented in the program text
         // TODO and also show the location of the error
         let mut names = BTreeSet::new();
         let mut values = BTreeSet::new();
         let mut errors = vec![];
+        let width = typedef.bitwidth();
+        let typename = &typedef.name;
 
         for (name, value) in &typedef.values {
             if !names.insert(name.clone()) {
                 errors.push(BitsyError::Unknown(Some(typedef.span.clone()), format!("Duplicate name in enum: {name}")));
             }
             if !values.insert(value.value()) {
-                errors.push(BitsyError::Unknown(Some(typedef.span.clone()), format!("Duplicate value in enum: {value:?}")));
+                errors.push(BitsyError::Unknown(Some(typedef.span.clone()), format!("Duplicate value in enum {typename}: {name} = {}", value.value())));
+            }
+            if let Some(value_width) = value.width() {
+                if value_width != width {
+                    errors.push(BitsyError::Unknown(
+                        Some(typedef.span.clone()),
+                        format!("Value {name} of enum {typename} has width {value_width}, but the enum has width {width}"),
+                    ));
+                }
+            }
+            if width < 64 && value.value() >> width != 0 {
+                errors.push(BitsyError::Unknown(
+                    Some(typedef.span.clone()),
+                    format!("Value {name} of enum {typename} doesn't fit in Word[{width}]"),
+                ));
+            }
+            if typedef.onehot && value.value().count_ones() != 1 {
+                errors.push(BitsyError::Unknown(
+                    Some(typedef.span.clone()),
+                    format!("Value {name} of one-hot enum {typename} must have exactly one bit set"),
+                ));
             }
         }
         if errors.len() > 0 {
@@ -72,20 +106,23 @@ impl Package {
         }
     }
 
-    fn check_component(&self, component: Arc<Component>) -> Result<(), Vec<BitsyError>> {
+    pub(crate) fn check_component(&self, component: Arc<Component>) -> Result<(), Vec<BitsyError>> {
         let mut errors = vec![];
 
         match &*component {
-            Component::Mod(_loc, _name, _children, _wires, _whens) => {
+            Component::Mod(_loc, _doc, _name, _type_args, _children, _wires, _whens, _assertions, _interfaces) => {
                 errors.extend(self.check_typecheck_component(component.clone()));
                 errors.extend(self.check_wires_no_such_component(component.clone()));
                 errors.extend(self.check_children_duplicate_names(component.clone()));
                 errors.extend(self.check_wires_duplicate_targets(component.clone()));
                 errors.extend(self.check_missing_drivers(component.clone()));
                 errors.extend(self.check_wires_wiretype(component.clone()));
+                errors.extend(self.check_doms(component.clone()));
                 errors.extend(self.check_incoming_port_driven(component.clone()));
+                errors.extend(self.check_mod_insts(component.clone()));
+                errors.extend(self.check_mems(component.clone()));
             },
-            Component::Ext(loc, _name, children) => {
+            Component::Ext(loc, _doc, _name, children) => {
                 for component in children {
                     if !component.is_port() {
                         errors.push(BitsyError::ExtHasNonPort(loc.clone(), component.name().to_string()));
@@ -102,14 +139,113 @@ impl Package {
         }
     }
 
+    /// The checks of [`Package::check_component`] which don't depend on the parameters of a parametric `mod`.
+    fn check_generic_component(&self, component: Arc<Component>) -> Result<(), Vec<BitsyError>> {
+        let mut errors = vec![];
+        errors.extend(self.check_wires_no_such_component(component.clone()));
+        errors.extend(self.check_children_duplicate_names(component.clone()));
+        errors.extend(self.check_wires_duplicate_targets(component.clone()));
+        errors.extend(self.check_missing_drivers(component.clone()));
+        errors.extend(self.check_wires_wiretype(component.clone()));
+        errors.extend(self.check_doms(component.clone()));
+        errors.extend(self.check_incoming_port_driven(component.clone()));
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// The checks of [`Package::check_component`] for an instance of a parametric `mod` which depend on its parameters.
+    /// The rest are done once, on the `generic` definition. See [`Package::check_generic_component`].
+    pub(crate) fn check_instance(&self, component: Arc<Component>, generic: Arc<Component>) -> Result<(), Vec<BitsyError>> {
+        let mut errors = vec![];
+        errors.extend(self.check_typecheck_component(component.clone()));
+        errors.extend(self.check_mod_insts(component.clone()));
+        errors.extend(self.check_mems(component.clone()));
+        // The parts of a component like a `Word[n]` can only be counted once `n` is known.
+        let uncounted = |target: &Path| generic.type_of_path(target).is_some_and(|typ| Select::atoms_of(&typ).is_none());
+        errors.extend(self.check_partial_drivers(component.clone(), uncounted));
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn check_mod_insts(&self, component: Arc<Component>) -> Vec<BitsyError> {
+        let mut errors = vec![];
+        for child in component.children() {
+            match &*child {
+                // The errors in the instance itself are reported by Package::check. See Package::instance_errors.
+                Component::ModInst(_loc, _name, _moddef, _params) => {
+                    if let Err(errs) = self.check_params(&child) {
+                        errors.extend(errs);
+                    } else {
+                        let _result = self.elaborate(&child);
+                    }
+                },
+                // Every instance in the vector is the same, so checking the first suffices.
+                Component::ModInstVec(_loc, _name, insts) => {
+                    if let Some(inst) = insts.first() {
+                        if let Err(errs) = self.check_params(inst) {
+                            errors.extend(errs);
+                        } else {
+                            let _result = self.elaborate(inst);
+                        }
+                    }
+                },
+                _ => (),
+            }
+        }
+        errors
+    }
+
+    fn check_mems(&self, component: Arc<Component>) -> Vec<BitsyError> {
+        let mut errors = vec![];
+        for child in component.children() {
+            if let Component::Mem(loc, name, typ, _dom, _latency, ports) = &*child {
+                let mut port_names = BTreeSet::new();
+                let mut masks = BTreeSet::new();
+                for port in ports {
+                    let (port_name, mask) = match port {
+                        MemPort::Read(port_name) => (port_name, None),
+                        MemPort::Write(port_name, mask) => (port_name, *mask),
+                    };
+                    if !port_names.insert(port_name.clone()) {
+                        errors.push(BitsyError::InvalidMem(loc.clone(), name.clone(), format!("duplicate port {port_name}")));
+                    }
+                    if let (Some(mask), Type::Vec(elem_typ, _n)) = (mask, typ) {
+                        let divides = matches!(**elem_typ, Type::Word(width) if mask > 0 && width % mask == 0);
+                        if !divides {
+                            errors.push(BitsyError::InvalidMem(
+                                loc.clone(),
+                                name.clone(),
+                                format!("the write mask of {port_name} must have a width which divides the width of {elem_typ:?}"),
+                            ));
+                        }
+                        masks.insert(mask);
+                    }
+                }
+                if masks.len() > 1 {
+                    errors.push(BitsyError::InvalidMem(loc.clone(), name.clone(), "the masked write ports must all use the same mask width".to_string()));
+                }
+            }
+        }
+        errors
+    }
+
     fn check_children_duplicate_names(&self, component: Arc<Component>) -> Vec<BitsyError> {
         let mut errors = vec![];
-        let mut seen = BTreeSet::new();
-        for child in &component.children() {
-            if !seen.contains(child.name()) {
-                seen.insert(child.name());
+        let mut seen: BTreeMap<&str, &Arc<Component>> = BTreeMap::new();
+        let children = component.children();
+        for child in &children {
+            if let Some(first) = seen.get(child.name()) {
+                errors.push(BitsyError::DuplicateComponent(child.clone(), (*first).clone()));
             } else {
-                errors.push(BitsyError::DuplicateComponent(child.clone()));
+                seen.insert(child.name(), child);
             }
         }
         errors
@@ -117,12 +253,40 @@ impl Package {
 
     fn check_wires_duplicate_targets(&self, component: Arc<Component>) -> Vec<BitsyError> {
         let mut errors = vec![];
-        let mut seen = BTreeSet::new();
-        for Wire(loc, target, _expr, _typ) in &component.wires() {
-            if !seen.contains(target) {
-                seen.insert(target);
+        // Where each target was first driven.
+        let mut seen: BTreeMap<&Path, &Span> = BTreeMap::new();
+        let wires = component.wires();
+        for Wire(loc, target, select, _expr, wiretype) in &wires {
+            // A register may be assigned procedurally any number of times.
+            if *wiretype == WireType::Proc || select.is_some() {
+                continue;
+            }
+            if let Some(first) = seen.get(target) {
+                errors.push(BitsyError::MultipleDrivers(loc.clone(), target.to_string(), (*first).clone()));
             } else {
-                errors.push(BitsyError::MultipleDrivers(loc.clone(), target.to_string()));
+                seen.insert(target, loc);
+            }
+        }
+
+        // Each part of a component driven by partial connections must be driven only once.
+        let mut seen_parts: BTreeMap<(&Path, Select), &Span> = BTreeMap::new();
+        for Wire(loc, target, select, _expr, _wiretype) in &wires {
+            if let Some(select) = select {
+                let mut first = seen.get(target).copied();
+                for atom in select.atoms() {
+                    if let Some(other) = seen_parts.insert((target, atom), loc) {
+                        first = first.or(Some(other));
+                    }
+                }
+                if let Some(first) = first {
+                    errors.push(BitsyError::MultipleDrivers(loc.clone(), format!("{target}{select}"), first.clone()));
+                }
+            }
+        }
+
+        for Wire(loc, target, _select, _expr, _wiretype) in &component.proc_wires() {
+            if let Some(first) = seen.get(target) {
+                errors.push(BitsyError::MultipleDrivers(loc.clone(), target.to_string(), (*first).clone()));
             }
         }
         errors
@@ -131,6 +295,11 @@ impl Package {
     fn check_typecheck_fndef(&self, fndef: Arc<FnDef>) -> Result<(), Vec<BitsyError>> {
         let mut errors = vec![];
 
+        // A generic fn is typechecked at each call, once its parameters are known.
+        if fndef.is_parametric() {
+            return Ok(());
+        }
+
         match fndef.body.typecheck(fndef.ret.clone(), fndef.context()) {
             Err(e) => errors.push(BitsyError::TypeError(e)),
             Ok(()) => fndef.body.assert_has_types(),
@@ -147,12 +316,22 @@ impl Package {
         let ctx = self.context_for(component.clone());
         let mut errors = vec![];
 
-        for Wire(loc, target, expr, _wiretype) in &component.wires() {
-            let target_typ = if let Some(typ) = ctx.lookup(target) {
-                typ
-            } else {
-                errors.push(BitsyError::NoSuchComponent(loc.clone(), target.to_string()));
+        for Wire(loc, target, select, expr, wiretype) in &component.wires() {
+            // Clock domains don't have types. See check_doms().
+            if *wiretype == WireType::Dom {
                 continue;
+            }
+
+            let target_typ = match (ctx.lookup(target), select) {
+                (Some(typ), None) => typ,
+                (Some(typ), Some(select)) => if let Some(typ) = select.type_of(&typ) {
+                    typ
+                } else {
+                    errors.push(BitsyError::NoSuchComponent(loc.clone(), format!("{target}{select}")));
+                    continue;
+                },
+                // Reported by check_wires_no_such_component().
+                (None, _select) => continue,
             };
 
             match expr.typecheck(target_typ, ctx.clone()) {
@@ -166,7 +345,7 @@ impl Package {
                 Err(e) => errors.push(BitsyError::TypeError(e)),
                 Ok(()) => expr.assert_has_types(),
             }
-            for Wire(loc, target, expr, _wiretype) in wires {
+            for Wire(loc, target, _select, expr, _wiretype) in wires {
                 let target_typ = if let Some(typ) = ctx.lookup(target) {
                     typ
                 } else {
@@ -181,8 +360,15 @@ impl Package {
             }
         }
 
+        for Assertion(_loc, _kind, expr) in &component.assertions() {
+            match expr.typecheck(Type::Word(1), ctx.clone()) {
+                Err(e) => errors.push(BitsyError::TypeError(e)),
+                Ok(()) => expr.assert_has_types(),
+            }
+        }
+
         for child in component.children() {
-            if let Component::Reg(_loc, _name, _typ, Some(reset)) = &*child {
+            if let Component::Reg(_loc, _doc, _name, _typ, _dom, Some(reset)) = &*child {
                 // TODO This is done to turn the reference to the type into the actual type.
                 let typ = self.type_of(child.clone()).unwrap();
                 match reset.typecheck(typ, ctx.clone()) {
@@ -198,7 +384,7 @@ impl Package {
     fn check_wires_no_such_component(&self, component: Arc<Component>) -> Vec<BitsyError> {
         let mut errors = vec![];
 
-        for Wire(loc, target, _expr, _wiretype) in &component.wires() {
+        for Wire(loc, target, _select, _expr, _wiretype) in &component.wires() {
             if self.component_from(component.clone(), target.clone()).is_none() {
                 errors.push(BitsyError::NoSuchComponent(loc.clone(), target.to_string()));
             }
@@ -209,34 +395,126 @@ impl Package {
     fn check_wires_wiretype(&self, component: Arc<Component>) -> Vec<BitsyError> {
         let mut errors = vec![];
 
-        for Wire(loc, target, _expr, wiretype) in &component.wires() {
+        for Wire(loc, target, select, _expr, wiretype) in &component.wires() {
+            // Domains can't be driven in parts, and procedural wires drive whole registers.
+            if select.is_some() && (*wiretype == WireType::Dom || *wiretype == WireType::Proc) {
+                errors.push(BitsyError::WrongWireType(loc.clone(), target.to_string(), wiretype.clone()));
+                continue;
+            }
+
             if let Some(component) = self.component_from(component.clone(), target.clone()) {
                 match (&*component, wiretype) {
-                    (Component::Reg(_loc, name, _typ, _reset), WireType::Direct) => {
+                    (Component::Reg(_loc, _doc, name, _typ, _dom, _reset), WireType::Direct) => {
                         errors.push(BitsyError::WrongWireType(loc.clone(), name.clone(), WireType::Direct))
                     },
                     (Component::Node(_loc, name, _typ), WireType::Latch) => {
                         errors.push(BitsyError::WrongWireType(loc.clone(), name.clone(), WireType::Latch))
                     },
-                    (Component::Outgoing(_loc, name, _typ), WireType::Latch) => {
+                    (Component::Outgoing(_loc, _doc, name, _typ), WireType::Latch) => {
                         errors.push(BitsyError::WrongWireType(loc.clone(), name.clone(), WireType::Latch))
                     },
+                    (Component::Dom(_loc, name), wiretype) if *wiretype != WireType::Dom => {
+                        errors.push(BitsyError::WrongWireType(loc.clone(), name.clone(), wiretype.clone()))
+                    },
+                    (Component::Dom(_loc, _name), WireType::Dom) => (),
+                    (_, WireType::Dom) => {
+                        errors.push(BitsyError::WrongWireType(loc.clone(), target.to_string(), WireType::Dom))
+                    },
+                    (Component::Reg(_loc, _doc, _name, _typ, _dom, _reset), WireType::Proc) if !target.contains(".") => (),
+                    (_, WireType::Proc) => {
+                        errors.push(BitsyError::WrongWireType(loc.clone(), target.to_string(), WireType::Proc))
+                    },
                     (_, _) => (),
                 }
             }
         }
+
+        // Only procedural wires may appear inside of a `when`, and only local registers may be their targets.
+        for When(_expr, wires) in &component.whens() {
+            for Wire(loc, target, select, _expr, wiretype) in wires {
+                let is_local_reg = matches!(component.child(target).as_deref(), Some(Component::Reg(_loc, _doc, _name, _typ, _dom, _reset)));
+                if *wiretype != WireType::Proc {
+                    errors.push(BitsyError::WrongWireType(loc.clone(), target.to_string(), wiretype.clone()));
+                } else if !is_local_reg || select.is_some() {
+                    errors.push(BitsyError::WrongWireType(loc.clone(), target.to_string(), WireType::Proc));
+                }
+            }
+        }
+        errors
+    }
+
+    fn check_doms(&self, component: Arc<Component>) -> Vec<BitsyError> {
+        let mut errors = vec![];
+
+        for child in component.children() {
+            let (loc, dom) = match &*child {
+                Component::Reg(loc, _doc, _name, _typ, Some(dom), _reset) => (loc, dom),
+                Component::Mem(loc, _name, _typ, Some(dom), _latency, _ports) => (loc, dom),
+                _ => continue,
+            };
+            match component.child(dom).as_deref() {
+                Some(Component::Dom(_loc, _name)) => (),
+                Some(_) => errors.push(BitsyError::NotADomain(loc.clone(), dom.clone())),
+                None => errors.push(BitsyError::NoSuchComponent(loc.clone(), dom.clone())),
+            }
+        }
+
+        let mut driven = BTreeSet::new();
+        for Wire(_loc, target, _select, expr, wiretype) in &component.wires() {
+            if *wiretype != WireType::Dom {
+                continue;
+            }
+            driven.insert(target.clone());
+
+            if let Expr::Reference(_loc, _typ, driver) = &**expr {
+                let driver_component = self.component_from(component.clone(), driver.clone());
+                if !matches!(driver_component.as_deref(), Some(Component::Dom(_loc, _name))) {
+                    errors.push(BitsyError::NotADomain(expr.span(), driver.to_string()));
+                }
+            } else {
+                errors.push(BitsyError::NotADomain(expr.span(), format!("the expression driving {target}")));
+            }
+        }
+
+        // The domains of a submodule must be driven, just like its incoming ports.
+        let mut submods: Vec<(Path, Arc<Component>, Arc<Component>)> = vec![];
+        for child in component.children() {
+            let path: Path = child.name().to_string().into();
+            match &*child {
+                Component::Mod(_loc, _doc, _name, _type_args, _children, _wires, _whens, _assertions, _interfaces) => submods.push((path, child.clone(), child.clone())),
+                Component::ModInst(_loc, _name, moddef, _params) => submods.push((path, child.clone(), moddef.clone())),
+                Component::ModInstVec(_loc, _name, insts) => {
+                    for inst in insts {
+                        if let Component::ModInst(_loc, name, moddef, _params) = &**inst {
+                            submods.push((path.join(name.clone().into()), inst.clone(), moddef.clone()));
+                        }
+                    }
+                },
+                _ => (),
+            }
+        }
+
+        for (path, inst, moddef) in submods {
+            for dom in moddef.children() {
+                if let Component::Dom(_loc, name) = &*dom {
+                    if !driven.contains(&path.join(name.clone().into())) {
+                        errors.push(BitsyError::NoDriversPort(inst.clone(), path.join(name.clone().into()), dom.clone()));
+                    }
+                }
+            }
+        }
         errors
     }
 
     fn check_incoming_port_driven(&self, component: Arc<Component>) -> Vec<BitsyError> {
         let mut errors = vec![];
 
-        for Wire(_loc, target, _expr, _wiretype) in &component.wires() {
+        for Wire(_loc, target, _select, _expr, _wiretype) in &component.wires() {
             if let Some(component) = self.component_from(component.clone(), target.clone()) {
                 let is_local = !target.contains(".");
                 if is_local {
                     match &*component {
-                        Component::Incoming(loc, name, _typ) => {
+                        Component::Incoming(loc, _doc, name, _typ) => {
                             errors.push(BitsyError::IncomingPortDriven(loc.clone(), name.clone()))
                         },
                         _ => (),
@@ -251,12 +529,14 @@ impl Package {
         let mut errors = vec![];
         let mut terminals_remaining: Vec<(Path, Arc<Component>)> = self.visible_paths(component.clone());
 
-        for Wire(_loc, target, _expr, _typ) in &component.wires() {
+        for Wire(_loc, target, _select, _expr, _typ) in &component.wires() {
             terminals_remaining = terminals_remaining.into_iter().filter(|(path, _component)| path != target).collect();
         }
 
+        errors.extend(self.check_partial_drivers(component.clone(), |_target| true));
+
         for When(_expr, wires) in &component.whens() {
-            for Wire(_loc, target, _expr, _typ) in wires {
+            for Wire(_loc, target, _select, _expr, _typ) in wires {
                 terminals_remaining = terminals_remaining.into_iter().filter(|(path, _component)| path != target).collect();
             }
         }
@@ -264,7 +544,7 @@ impl Package {
         for (path, remaining_component) in terminals_remaining.into_iter() {
             let mut is_incoming_port = false;
 
-            if let Component::Incoming(_loc, _name, _typ) = &*remaining_component {
+            if let Component::Incoming(_loc, _doc, _name, _typ) = &*remaining_component {
                 is_incoming_port = true;
             }
 
@@ -272,11 +552,47 @@ impl Package {
 
             if !is_local && is_incoming_port {
                 let inst_component = self.component_from(component.clone(), path.parent()).unwrap();
-                errors.push(BitsyError::NoDriversPort(inst_component.clone(), remaining_component.clone()));
+                errors.push(BitsyError::NoDriversPort(inst_component.clone(), path, remaining_component.clone()));
             } else if is_local && !is_incoming_port {
                 errors.push(BitsyError::NoDrivers(remaining_component.clone()));
             }
         }
         errors
     }
+
+    /// A component driven by partial connections must be driven in every part.
+    /// Only the components whose paths pass `filter` are checked.
+    fn check_partial_drivers(&self, component: Arc<Component>, filter: impl Fn(&Path) -> bool) -> Vec<BitsyError> {
+        let mut errors = vec![];
+
+        // Components which are also driven whole are reported by check_wires_duplicate_targets().
+        let mut parts: BTreeMap<Path, (Span, BTreeSet<Select>)> = BTreeMap::new();
+        for Wire(loc, target, select, _expr, _typ) in &component.wires() {
+            if let Some(select) = select {
+                let (_loc, atoms) = parts.entry(target.clone()).or_insert_with(|| (loc.clone(), BTreeSet::new()));
+                atoms.extend(select.atoms());
+            }
+        }
+        for Wire(_loc, target, select, _expr, _typ) in &component.wires() {
+            if select.is_none() {
+                parts.remove(target);
+            }
+        }
+        for (target, (loc, atoms)) in parts {
+            if !filter(&target) {
+                continue;
+            }
+            let Some(typ) = component.type_of_path(&target) else { continue };
+            let Some(expected) = Select::atoms_of(&typ) else { continue };
+            let missing: Vec<String> = expected
+                .into_iter()
+                .filter(|atom| !atoms.contains(atom))
+                .map(|atom| format!("{target}{atom}"))
+                .collect();
+            if !missing.is_empty() {
+                errors.push(BitsyError::NotFullyDriven(loc, missing.join(", ")));
+            }
+        }
+        errors
+    }
 }
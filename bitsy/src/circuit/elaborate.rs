@@ -0,0 +1,373 @@
+use super::*;
+use once_cell::sync::OnceCell;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+impl Package {
+    /// Elaborate a [`Component::ModInst`], returning the monomorphized definition it instantiates.
+    ///
+    /// For a parametric definition, the parameters are substituted and the result is checked.
+    /// This happens once for each set of parameters, and the result is kept for later calls.
+    /// Otherwise, this is just the definition itself.
+    pub(crate) fn elaborate(&self, modinst: &Component) -> Result<Arc<Component>, Vec<BitsyError>> {
+        let (moddef, params) = match modinst {
+            Component::ModInst(_span, _name, moddef, params) => (moddef, params),
+            _ => panic!("Only a ModInst can be elaborated: {}", modinst.name()),
+        };
+
+        if moddef.type_args().is_empty() && params.is_empty() {
+            return Ok(moddef.clone());
+        }
+        self.check_params(modinst)?;
+
+        // Inside of a parametric definition, the parameters may refer to its own.
+        // Such an instance is checked when the enclosing definition is elaborated.
+        if params.iter().any(|param| param.is_generic()) {
+            return Ok(moddef.instantiate(params));
+        }
+
+        let instances = self.instances.lock().unwrap();
+        if let Some(instance) = instances.iter().find(|instance| instance.is_of(moddef, params)) {
+            return instance.result.clone();
+        }
+        // The lock isn't held while checking, since the instance may have instances of its own.
+        drop(instances);
+
+        let instance = match self.deferred_moddef(moddef) {
+            Some((package, ast)) => resolve_instance(package, ast, params),
+            None => Ok(moddef.instantiate(params)),
+        };
+        let instance = match instance {
+            Ok(instance) => instance,
+            Err(errs) => {
+                self.cache_instance(moddef, params, Err(errs.clone()));
+                return Err(errs);
+            },
+        };
+
+        // A width such as `Word[n-4]` is only known to be negative, or `Word[n*n]` too large, once `n` is.
+        let mut errors = vec![];
+        for child in instance.children() {
+            if let Some(typ) = child.type_of() {
+                if typ.is_overflow() {
+                    errors.push(BitsyError::InvalidWidth(child.span(), format!("the width of {typ:?} is too large")));
+                } else if typ.is_generic() {
+                    errors.push(BitsyError::InvalidWidth(child.span(), format!("the width of {typ:?} is negative")));
+                }
+            }
+        }
+        if errors.is_empty() {
+            let mut components = vec![(instance.clone(), moddef.clone())];
+            components.extend(instance.submods().into_iter().zip(moddef.submods()));
+            for (component, generic) in components {
+                // Only the ports of a deferred mod were checked with its definition.
+                // Otherwise, only what depends on the parameters is left to check. See Package::check.
+                let result = if self.deferred_moddef(moddef).is_some() {
+                    self.check_component(component)
+                } else {
+                    self.check_instance(component, generic)
+                };
+                if let Err(errs) = result {
+                    errors.extend(errs);
+                }
+            }
+        }
+
+        let result = if errors.is_empty() {
+            Ok(instance)
+        } else {
+            Err(errors)
+        };
+        self.cache_instance(moddef, params, result.clone());
+        result
+    }
+
+    /// Check that the parameters of a [`Component::ModInst`] fit the definition it instantiates.
+    pub(crate) fn check_params(&self, modinst: &Component) -> Result<(), Vec<BitsyError>> {
+        let (span, moddef, params) = match modinst {
+            Component::ModInst(span, _name, moddef, params) => (span, moddef, params),
+            _ => panic!("Only a ModInst has parameters: {}", modinst.name()),
+        };
+
+        let type_args = moddef.type_args();
+        let moddef_name = moddef.name().to_string();
+        if type_args.len() != params.len() {
+            let message = format!("expected {} parameters, but found {}", type_args.len(), params.len());
+            return Err(vec![BitsyError::WrongParams(span.clone(), moddef_name, message)]);
+        }
+
+        let mut errors = vec![];
+        for ((arg_name, kind), param) in type_args.iter().zip(params.iter()) {
+            if param.kind() != *kind {
+                let message = format!("{arg_name} should be a {kind:?}, but found {param}");
+                errors.push(BitsyError::WrongParams(span.clone(), moddef_name.clone(), message));
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// The errors found in the instances elaborated so far, once for each instance,
+    /// however many times it's used. See [`Package::check`].
+    pub(crate) fn instance_errors(&self) -> Vec<BitsyError> {
+        let instances = self.instances.lock().unwrap();
+        instances
+            .iter()
+            .filter_map(|instance| instance.result.clone().err())
+            .flatten()
+            .collect()
+    }
+
+    fn cache_instance(&self, moddef: &Arc<Component>, params: &[TypeParam], result: Result<Arc<Component>, Vec<BitsyError>>) {
+        self.instances.lock().unwrap().push(Instance {
+            moddef: moddef.clone(),
+            params: params.to_vec(),
+            result,
+        });
+    }
+
+    /// The elaborated instances of the deferred `mod` definitions of this package.
+    /// The body of a deferred definition is only resolved in its instances. See [`Package::deferred_moddef`].
+    pub(crate) fn deferred_instances(&self) -> Vec<Arc<Component>> {
+        let instances = self.instances.lock().unwrap();
+        instances
+            .iter()
+            .filter(|instance| self.deferred_moddef(&instance.moddef).is_some_and(|(package, _ast)| std::ptr::eq(package, self)))
+            .filter_map(|instance| instance.result.clone().ok())
+            .collect()
+    }
+
+    /// The deferred `mod` definitions of this package which are never instantiated.
+    /// Only their ports are resolved, so the rest of their bodies goes unchecked. See [`Package::check`].
+    pub fn unchecked_moddefs(&self) -> Vec<Arc<Component>> {
+        let instances = self.instances.lock().unwrap();
+        self.moddefs()
+            .into_iter()
+            .filter(|moddef| self.deferred_moddef(moddef).is_some_and(|(package, _ast)| std::ptr::eq(package, self)))
+            .filter(|moddef| !instances.iter().any(|instance| Arc::ptr_eq(&instance.moddef, moddef)))
+            .collect()
+    }
+
+    /// The definition a [`Component::ModInst`] instantiates, for looking up its ports.
+    ///
+    /// This is the elaborated definition when it checks,
+    /// and otherwise it is the definition with its parameters substituted.
+    /// The errors are reported by [`Package::check`].
+    pub(crate) fn instance(&self, modinst: &Component) -> Arc<Component> {
+        self.elaborate(modinst).unwrap_or_else(|_errors| modinst.instantiated())
+    }
+}
+
+/// A parametric `mod` definition elaborated with a set of parameters. See [`Package::elaborate`].
+#[derive(Debug)]
+pub(crate) struct Instance {
+    moddef: Arc<Component>,
+    params: Vec<TypeParam>,
+    result: Result<Arc<Component>, Vec<BitsyError>>,
+}
+
+impl Instance {
+    fn is_of(&self, moddef: &Arc<Component>, params: &[TypeParam]) -> bool {
+        Arc::ptr_eq(&self.moddef, moddef) &&
+            self.params.len() == params.len() &&
+            self.params.iter().zip(params.iter()).all(|(param1, param2)| param1.equals(param2))
+    }
+}
+
+impl Component {
+    /// Substitute the parameters into a parametric `mod` definition.
+    /// A `Nat` parameter which appears in an expression becomes a literal.
+    ///
+    /// The result has not been typechecked. See [`Package::elaborate`].
+    pub(crate) fn instantiate(self: &Arc<Self>, params: &[TypeParam]) -> Arc<Component> {
+        if !self.is_parametric() {
+            return self.clone();
+        }
+
+        let params: BTreeMap<Name, TypeParam> = self.type_args()
+            .into_iter()
+            .map(|(name, _kind)| name)
+            .zip(params.iter().cloned())
+            .collect();
+        self.subst(&params)
+    }
+
+    /// The definition a [`Component::ModInst`] instantiates, with its parameters substituted.
+    ///
+    /// The result has not been checked. See [`Package::instance`].
+    pub(crate) fn instantiated(&self) -> Arc<Component> {
+        match self {
+            Component::ModInst(_span, _name, moddef, params) => moddef.instantiate(params),
+            _ => panic!("Only a ModInst can be instantiated: {}", self.name()),
+        }
+    }
+
+    pub(crate) fn subst(&self, params: &BTreeMap<Name, TypeParam>) -> Arc<Component> {
+        Arc::new(match self {
+            Component::Mod(span, doc, name, _type_args, children, wires, whens, assertions, interfaces) => {
+                Component::Mod(
+                    span.clone(),
+                    doc.clone(),
+                    name.clone(),
+                    vec![],
+                    children.iter().map(|child| child.subst(params)).collect(),
+                    wires.iter().map(|wire| wire.subst(params)).collect(),
+                    whens.iter().map(|When(cond, wires)| {
+                        When(cond.subst(params, &BTreeSet::new()), wires.iter().map(|wire| wire.subst(params)).collect())
+                    }).collect(),
+                    assertions.iter().map(|Assertion(span, kind, cond)| {
+                        Assertion(span.clone(), *kind, cond.subst(params, &BTreeSet::new()))
+                    }).collect(),
+                    interfaces.clone(),
+                )
+            },
+            Component::ModInst(span, name, moddef, inst_params) => {
+                Component::ModInst(span.clone(), name.clone(), moddef.clone(), inst_params.iter().map(|param| param.subst(params)).collect())
+            },
+            Component::ModInstVec(span, name, insts) => {
+                Component::ModInstVec(span.clone(), name.clone(), insts.iter().map(|inst| inst.subst(params)).collect())
+            },
+            Component::Ext(_span, _doc, _name, _children) => self.clone(),
+            Component::Dom(_span, _name) => self.clone(),
+            Component::Incoming(span, doc, name, typ) => Component::Incoming(span.clone(), doc.clone(), name.clone(), typ.subst(params)),
+            Component::Outgoing(span, doc, name, typ) => Component::Outgoing(span.clone(), doc.clone(), name.clone(), typ.subst(params)),
+            Component::Node(span, name, typ) => Component::Node(span.clone(), name.clone(), typ.subst(params)),
+            Component::Reg(span, doc, name, typ, dom, reset) => {
+                Component::Reg(
+                    span.clone(),
+                    doc.clone(),
+                    name.clone(),
+                    typ.subst(params),
+                    dom.clone(),
+                    reset.as_ref().map(|reset| reset.subst(params, &BTreeSet::new())),
+                )
+            },
+            Component::Mem(span, name, typ, dom, latency, ports) => {
+                Component::Mem(span.clone(), name.clone(), typ.subst(params), dom.clone(), *latency, ports.clone())
+            },
+        })
+    }
+}
+
+impl FnDef {
+    /// Substitute the parameters into a generic `fn` definition.
+    ///
+    /// The result has not been typechecked.
+    pub(crate) fn instantiate(&self, params: &BTreeMap<Name, TypeParam>) -> Arc<FnDef> {
+        Arc::new(FnDef {
+            span: self.span.clone(),
+            name: self.name.clone(),
+            visibility: self.visibility,
+            doc: self.doc.clone(),
+            type_args: vec![],
+            args: self.args.iter().map(|(name, typ)| (name.clone(), typ.subst(params))).collect(),
+            ret: self.ret.subst(params),
+            body: self.body.subst(params, &self.args.iter().map(|(name, _typ)| name.clone().into()).collect()),
+        })
+    }
+}
+
+impl Wire {
+    fn subst(&self, params: &BTreeMap<Name, TypeParam>) -> Wire {
+        let Wire(span, target, select, expr, wire_type) = self;
+        Wire(span.clone(), target.clone(), select.clone(), expr.subst(params, &BTreeSet::new()), wire_type.clone())
+    }
+}
+
+impl Expr {
+    /// Substitute the parameters of a parametric definition.
+    /// Every node gets a fresh type annotation, since the types may have changed.
+    fn subst(&self, params: &BTreeMap<Name, TypeParam>, shadowed: &BTreeSet<Path>) -> Arc<Expr> {
+        Arc::new(match self {
+            Expr::Reference(loc, _typ, path) => {
+                match params.get(&path.to_string()) {
+                    Some(TypeParam::Nat(Nat::Lit(n))) if !shadowed.contains(path) => {
+                        Expr::Word(loc.clone(), OnceCell::new(), None, *n)
+                    },
+                    _ => Expr::Reference(loc.clone(), OnceCell::new(), path.clone()),
+                }
+            },
+            Expr::Net(_loc, _typ, _net_id) => panic!("subst() only works on reference expressions."),
+            Expr::Word(loc, _typ, width, value) => Expr::Word(loc.clone(), OnceCell::new(), *width, *value),
+            Expr::Enum(loc, _typ, typedef, name) => Expr::Enum(loc.clone(), OnceCell::new(), typedef.subst(params), name.clone()),
+            Expr::Ctor(loc, _typ, name, es) => {
+                Expr::Ctor(loc.clone(), OnceCell::new(), name.clone(), es.iter().map(|e| e.subst(params, shadowed)).collect())
+            },
+            Expr::Struct(loc, _typ, fields) => {
+                Expr::Struct(
+                    loc.clone(),
+                    OnceCell::new(),
+                    fields.iter().map(|(name, e)| (name.clone(), e.subst(params, shadowed))).collect(),
+                )
+            },
+            Expr::Let(loc, _typ, name, ascription, e, b) => {
+                let mut new_shadowed = shadowed.clone();
+                new_shadowed.insert(name.clone().into());
+                Expr::Let(
+                    loc.clone(),
+                    OnceCell::new(),
+                    name.clone(),
+                    ascription.as_ref().map(|typ| typ.subst(params)),
+                    e.subst(params, shadowed),
+                    b.subst(params, &new_shadowed),
+                )
+            },
+            Expr::UnOp(loc, _typ, op, e) => Expr::UnOp(loc.clone(), OnceCell::new(), *op, e.subst(params, shadowed)),
+            Expr::BinOp(loc, _typ, op, e1, e2) => {
+                Expr::BinOp(loc.clone(), OnceCell::new(), *op, e1.subst(params, shadowed), e2.subst(params, shadowed))
+            },
+            Expr::If(loc, _typ, cond, e1, e2) => {
+                Expr::If(
+                    loc.clone(),
+                    OnceCell::new(),
+                    cond.subst(params, shadowed),
+                    e1.subst(params, shadowed),
+                    e2.subst(params, shadowed),
+                )
+            },
+            Expr::Match(loc, _typ, e, arms) => {
+                let new_arms = arms.iter().map(|MatchArm(pat, e)| {
+                    let mut new_shadowed = shadowed.clone();
+                    new_shadowed.extend(pat.bound_vars().into_iter().map(|v| v.into()));
+                    MatchArm(pat.clone(), e.subst(params, &new_shadowed))
+                }).collect();
+                Expr::Match(loc.clone(), OnceCell::new(), e.subst(params, shadowed), new_arms)
+            },
+            Expr::Mux(loc, _typ, cond, e1, e2) => {
+                Expr::Mux(
+                    loc.clone(),
+                    OnceCell::new(),
+                    cond.subst(params, shadowed),
+                    e1.subst(params, shadowed),
+                    e2.subst(params, shadowed),
+                )
+            },
+            Expr::Cat(loc, _typ, es) => Expr::Cat(loc.clone(), OnceCell::new(), es.iter().map(|e| e.subst(params, shadowed)).collect()),
+            Expr::Sext(loc, _typ, e) => Expr::Sext(loc.clone(), OnceCell::new(), e.subst(params, shadowed)),
+            Expr::Zext(loc, _typ, e) => Expr::Zext(loc.clone(), OnceCell::new(), e.subst(params, shadowed)),
+            Expr::TryCast(loc, _typ, e) => Expr::TryCast(loc.clone(), OnceCell::new(), e.subst(params, shadowed)),
+            Expr::ToWord(loc, _typ, e) => Expr::ToWord(loc.clone(), OnceCell::new(), e.subst(params, shadowed)),
+            Expr::AsSigned(loc, _typ, e) => Expr::AsSigned(loc.clone(), OnceCell::new(), e.subst(params, shadowed)),
+            Expr::AsUnsigned(loc, _typ, e) => Expr::AsUnsigned(loc.clone(), OnceCell::new(), e.subst(params, shadowed)),
+            Expr::Vec(loc, _typ, es) => Expr::Vec(loc.clone(), OnceCell::new(), es.iter().map(|e| e.subst(params, shadowed)).collect()),
+            Expr::IdxField(loc, _typ, e, field) => Expr::IdxField(loc.clone(), OnceCell::new(), e.subst(params, shadowed), field.clone()),
+            Expr::Idx(loc, _typ, e, i) => Expr::Idx(loc.clone(), OnceCell::new(), e.subst(params, shadowed), *i),
+            Expr::IdxRange(loc, _typ, e, j, i) => Expr::IdxRange(loc.clone(), OnceCell::new(), e.subst(params, shadowed), *j, *i),
+            Expr::IdxDyn(loc, _typ, e, i) => Expr::IdxDyn(loc.clone(), OnceCell::new(), e.subst(params, shadowed), i.subst(params, shadowed)),
+            Expr::Call(loc, _typ, fndef, type_params, _instance, es) => {
+                Expr::Call(
+                    loc.clone(),
+                    OnceCell::new(),
+                    fndef.clone(),
+                    type_params.iter().map(|param| param.subst(params)).collect(),
+                    OnceCell::new(),
+                    es.iter().map(|e| e.subst(params, shadowed)).collect(),
+                )
+            },
+            Expr::Hole(loc, _typ, name, _ctx) => Expr::Hole(loc.clone(), OnceCell::new(), name.clone(), OnceCell::new()),
+        })
+    }
+}
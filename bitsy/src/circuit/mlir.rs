@@ -1,17 +1,51 @@
 use super::*;
 use std::sync::Arc;
 use std::collections::BTreeMap;
+use std::fmt::Write;
 
 impl Package {
     pub fn emit_mlir(&self) {
+        print!("{}", self.mlir());
+    }
+
+    /// The MLIR for the package, as printed by [`Package::emit_mlir`].
+    pub fn mlir(&self) -> String {
+        let mut out = String::new();
+        let mut instances: BTreeMap<String, Arc<Component>> = BTreeMap::new();
         for moddef in self.moddefs() {
-            if let Component::Mod(_loc, _name, _children, _wires, _whens) = &*moddef {
-                self.emit_mlir_moddef(moddef);
+            if moddef.is_parametric() {
+                continue;
+            }
+
+            if let Component::Mod(_loc, _doc, name, _type_args, _children, _wires, _whens, _assertions, _interfaces) = &*moddef {
+                self.emit_mlir_moddef(&mut out, name, moddef.clone());
+                self.collect_mlir_instances(moddef.clone(), &mut instances);
             }
         }
+
+        for (name, instance) in instances {
+            self.emit_mlir_moddef(&mut out, &name, instance);
+        }
+        out
     }
 
-    fn emit_mlir_moddef(&self, moddef: Arc<Component>) {
+    /// Parametric mods are emitted once for each way they are instantiated.
+    fn collect_mlir_instances(&self, component: Arc<Component>, instances: &mut BTreeMap<String, Arc<Component>>) {
+        for child in component.children() {
+            if let Component::ModInst(_loc, _name, moddef, params) = &*child {
+                let name = mlir_instance_name(moddef.name(), params);
+                if !params.is_empty() && !instances.contains_key(&name) {
+                    let instance = self.instance(&child);
+                    instances.insert(name, instance.clone());
+                    self.collect_mlir_instances(instance, instances);
+                }
+            } else if let Component::ModInstVec(_loc, _name, _insts) = &*child {
+                self.collect_mlir_instances(child.clone(), instances);
+            }
+        }
+    }
+
+    fn emit_mlir_moddef(&self, out: &mut String, name: &str, moddef: Arc<Component>) {
         let mut ports: Vec<(bool, String, Type)> = vec![];
         let mut output_ports: Vec<String> = vec![];
         let mut output_port_ssas: BTreeMap<String, String> = BTreeMap::new();
@@ -27,37 +61,83 @@ impl Package {
             }
         }
 
-        let ctx = self.context_for(moddef.clone());
+        // Each domain which isn't driven from inside of the module gets its own clock port.
+        let mut dom_driver_for: BTreeMap<Path, Path> = BTreeMap::new();
+        for Wire(_loc, target, _select, expr, wire_type) in moddef.wires() {
+            if let (WireType::Dom, Expr::Reference(_loc, _typ, driver)) = (wire_type, &*expr) {
+                dom_driver_for.insert(target, driver.clone());
+            }
+        }
+        let mut doms: Vec<String> = vec![];
+        for child in moddef.children() {
+            if let Component::Dom(_loc, name) = &*child {
+                if !dom_driver_for.contains_key(&name.clone().into()) {
+                    doms.push(name.clone());
+                }
+            }
+        }
 
-        println!("hw.module @{}(", moddef.name());
-        self.emit_mlir_moddef_portlist(&ports);
-        println!(") {{");
+        // Maps the variables bound by `match` arms to their SSA names.
+        let ctx: Context<Path, String> = Context::empty();
 
-        for (i, Wire(_loc, target, expr, wire_type)) in moddef.wires().iter().enumerate() {
+        writeln!(out, "hw.module @{name}(").unwrap();
+        self.emit_mlir_moddef_portlist(out, &doms, &ports);
+        writeln!(out, ") {{").unwrap();
+
+        for (i, Wire(_loc, target, _select, expr, wire_type)) in moddef.netlist_wires().iter().enumerate() {
             match wire_type {
                 WireType::Direct => {
-                    let ssa = expr.emit_mlir(format!("$comb{i}"), ctx.clone());
+                    let ssa = expr.emit_mlir(out, format!("$comb{i}"), ctx.clone());
                     let target_string = target.to_string();
                     if output_ports.contains(&target_string) {
                         output_port_ssas.insert(target_string, ssa);
                     } else if let Some(Component::Node(_loc, name, typ)) = moddef.child(target).as_ref().map(|arc| &**arc) {
                         let type_name = type_to_mlir(typ.clone());
-                        println!("    %{name} = comb.add {ssa} : {type_name}");
+                        writeln!(out, "    %{name} = comb.add {ssa} : {type_name}").unwrap();
+                    } else if let Some(Component::Mem(..)) = moddef.child(&target.parent()).as_deref() {
+                        // The ports of a `mem` are named like `%m.r_addr`.
+                        let port = moddef.child(&target.parent()).unwrap().child(target.rsplit('.').next().unwrap()).unwrap();
+                        let type_name = type_to_mlir(port.type_of().unwrap());
+                        writeln!(out, "    %{target} = comb.add {ssa} : {type_name}").unwrap();
                     }
                 },
                 WireType::Latch => {
-                    let next_ssa = expr.emit_mlir(format!("$comb{i}"), ctx.clone());
+                    let next_ssa = expr.emit_mlir(out, format!("$comb{i}"), ctx.clone());
                     let target_string = target.to_string();
                     let reg = moddef.child(target).unwrap();
                     let typ = reg.type_of().unwrap();
                     let reset = reg.reset().unwrap();
-                    let reset_ssa = reset.emit_mlir(format!("$reset{i}"), ctx.clone());
-                    println!("    %{target_string} = seq.firreg {next_ssa} clock %_clock reset sync %_reset, {reset_ssa} : {}", type_to_mlir(typ));
+                    let reset_ssa = reset.emit_mlir(out, format!("$reset{i}"), ctx.clone());
+                    let clock = match &*reg {
+                        Component::Reg(_loc, _doc, _name, _typ, Some(dom), _reset) => clock_ssa(dom, &dom_driver_for),
+                        _ => "%_clock".to_string(),
+                    };
+                    writeln!(out, "    %{target_string} = seq.firreg {next_ssa} clock {clock} reset sync %_reset, {reset_ssa} : {}", type_to_mlir(typ)).unwrap();
                 },
+                // Domains are passed in as clock ports.
+                WireType::Dom => (),
                 _ => panic!(),
             }
         }
 
+        for child in moddef.children() {
+            if let Component::Mem(_loc, name, Type::Vec(typ, depth), dom, latency, ports) = &*child {
+                let clock = match dom {
+                    Some(dom) => clock_ssa(dom, &dom_driver_for),
+                    None => "%_clock".to_string(),
+                };
+                emit_mlir_mem(out, name, typ, *depth, *latency, &clock, ports);
+            }
+        }
+
+        for (i, Assertion(_loc, kind, expr)) in moddef.assertions().iter().enumerate() {
+            let ssa = expr.emit_mlir(out, format!("$assert{i}"), ctx.clone());
+            match kind {
+                AssertionKind::Assert => writeln!(out, "    verif.assert {ssa} : i1").unwrap(),
+                AssertionKind::Cover => writeln!(out, "    verif.cover {ssa} : i1").unwrap(),
+            }
+        }
+
         let output_port_ssas: Vec<&str> = output_ports.iter().map(|output_port| {
             output_port_ssas[output_port].as_str()
         }).collect();
@@ -65,48 +145,54 @@ impl Package {
             type_to_mlir(typ.clone())
         }).collect();
         if output_port_ssas.len() > 0 {
-            println!("    hw.output {} : {}", output_port_ssas.join(","), output_port_types.join(","));
+            writeln!(out, "    hw.output {} : {}", output_port_ssas.join(","), output_port_types.join(",")).unwrap();
         }
-        println!("}}");
+        writeln!(out, "}}").unwrap();
     }
 
-    fn emit_mlir_moddef_portlist(&self, ports: &[(bool, String, Type)]) {
-        println!("    in %_clock : !seq.clock,");
-        print!("    in %_reset : i1");
+    fn emit_mlir_moddef_portlist(&self, out: &mut String, doms: &[String], ports: &[(bool, String, Type)]) {
+        writeln!(out, "    in %_clock : !seq.clock,").unwrap();
+        for dom in doms {
+            writeln!(out, "    in %{dom} : !seq.clock,").unwrap();
+        }
+        write!(out, "    in %_reset : i1").unwrap();
         if ports.len() > 0 {
-            println!(",");
+            writeln!(out, ",").unwrap();
         } else {
-            println!();
+            writeln!(out).unwrap();
         }
 
         for (i, (is_input, name, typ)) in ports.iter().enumerate() {
             let typ_name = type_to_mlir(typ.clone());
             if *is_input {
-                print!("    in %{name} : {typ_name}");
+                write!(out, "    in %{name} : {typ_name}").unwrap();
             } else {
-                print!("    out {name} : {typ_name}");
+                write!(out, "    out {name} : {typ_name}").unwrap();
             }
             if i + 1 < ports.len() {
-                println!(",");
+                writeln!(out, ",").unwrap();
             } else {
-                println!();
+                writeln!(out).unwrap();
             }
         }
     }
 }
 
 impl Expr {
-    fn emit_mlir(&self, prefix: String, ctx: Context<Path, Type>) -> String {
+    fn emit_mlir(&self, out: &mut String, prefix: String, ctx: Context<Path, String>) -> String {
         let typ: Type = self.type_of();
         let type_name = type_to_mlir(typ.clone());
 
         match self {
             Expr::Reference(_loc, _typ, name) => {
-                format!("%{name}")
+                match ctx.lookup(name) {
+                    Some(ssa) => ssa,
+                    None => format!("%{name}"),
+                }
             },
             Expr::Word(_loc, _typ, _w, n) => {
                 let name = format!("%{prefix}_word");
-                println!("    {name} = hw.constant {n} : {type_name}");
+                writeln!(out, "    {name} = hw.constant {n} : {type_name}").unwrap();
                 name
             },
             Expr::Enum(_loc, typ, _typedef, valname) => {
@@ -117,93 +203,148 @@ impl Expr {
                     panic!();
                 };
                 let v = typedef.value_of(&*valname).unwrap();
-                println!("    {name} = hw.constant {v} : {type_name}");
+                writeln!(out, "    {name} = hw.constant {v} : {type_name}").unwrap();
                 name
             },
+            // SInts and Words have the same representation.
+            Expr::AsSigned(_loc, _typ, e1) => e1.emit_mlir(out, format!("{prefix}_as_signed"), ctx.clone()),
+            Expr::AsUnsigned(_loc, _typ, e1) => e1.emit_mlir(out, format!("{prefix}_as_unsigned"), ctx.clone()),
             Expr::ToWord(_loc, _typ, e1) => {
                 let name = format!("%{prefix}_toword");
-                let e1_ssa = e1.emit_mlir(format!("{prefix}_e1"), ctx.clone());
-                println!("    {name} = comb.add {e1_ssa} : {type_name}");
+                let e1_ssa = e1.emit_mlir(out, format!("{prefix}_e1"), ctx.clone());
+                writeln!(out, "    {name} = comb.add {e1_ssa} : {type_name}").unwrap();
                 name
             },
             Expr::UnOp(_loc, _typ, UnOp::Not, e1) => {
                 let name = format!("%{prefix}_not");
-                let e1_ssa = e1.emit_mlir(format!("{prefix}_not_e1"), ctx.clone());
+                let e1_ssa = e1.emit_mlir(out, format!("{prefix}_not_e1"), ctx.clone());
                 // %c-1_i8 = hw.constant -1 : i8
                 // %0 = comb.xor bin %a, %c-1_i8 : i8
-                println!("    %{prefix}_not_negone = hw.constant -1 : {type_name}");
-                println!("    {name} = comb.xor {e1_ssa}, %{prefix}_not_negone : {type_name}");
+                writeln!(out, "    %{prefix}_not_negone = hw.constant -1 : {type_name}").unwrap();
+                writeln!(out, "    {name} = comb.xor {e1_ssa}, %{prefix}_not_negone : {type_name}").unwrap();
                 name
             },
             Expr::BinOp(_loc, _typ, BinOp::Add, e1, e2) => {
                 let name = format!("%{prefix}_add");
-                let e1_ssa = e1.emit_mlir(format!("{prefix}_add_e1"), ctx.clone());
-                let e2_ssa = e2.emit_mlir(format!("{prefix}_add_e2"), ctx.clone());
-                println!("    {name} = comb.add {e1_ssa}, {e2_ssa} : {type_name}");
+                let e1_ssa = e1.emit_mlir(out, format!("{prefix}_add_e1"), ctx.clone());
+                let e2_ssa = e2.emit_mlir(out, format!("{prefix}_add_e2"), ctx.clone());
+                writeln!(out, "    {name} = comb.add {e1_ssa}, {e2_ssa} : {type_name}").unwrap();
                 name
             },
             Expr::BinOp(_loc, _typ, BinOp::Sub, e1, e2) => {
                 let name = format!("%{prefix}_sub");
-                let e1_ssa = e1.emit_mlir(format!("{prefix}_sub_e1"), ctx.clone());
-                let e2_ssa = e2.emit_mlir(format!("{prefix}_sub_e2"), ctx.clone());
-                println!("    {name} = comb.sub {e1_ssa}, {e2_ssa} : {type_name}");
+                let e1_ssa = e1.emit_mlir(out, format!("{prefix}_sub_e1"), ctx.clone());
+                let e2_ssa = e2.emit_mlir(out, format!("{prefix}_sub_e2"), ctx.clone());
+                writeln!(out, "    {name} = comb.sub {e1_ssa}, {e2_ssa} : {type_name}").unwrap();
+                name
+            },
+            Expr::BinOp(_loc, _typ, BinOp::Mul, e1, e2) => {
+                let name = format!("%{prefix}_mul");
+                let e1_ssa = e1.emit_mlir(out, format!("{prefix}_mul_e1"), ctx.clone());
+                let e2_ssa = e2.emit_mlir(out, format!("{prefix}_mul_e2"), ctx.clone());
+                writeln!(out, "    {name} = comb.mul {e1_ssa}, {e2_ssa} : {type_name}").unwrap();
+                name
+            },
+            Expr::BinOp(_loc, _typ, op @ (BinOp::AddCarry | BinOp::SubBorrow), e1, e2) => {
+                let (opname, mlir_op) = if *op == BinOp::AddCarry { ("addcarry", "add") } else { ("subborrow", "sub") };
+                let name = format!("%{prefix}_{opname}");
+                let inner_width = e1.type_of().bitwidth();
+                let e1_ssa = e1.emit_mlir(out, format!("{prefix}_{opname}_e1"), ctx.clone());
+                let e2_ssa = e2.emit_mlir(out, format!("{prefix}_{opname}_e2"), ctx.clone());
+                // Widen both sides by one bit so the carry (or borrow) lands in the top bit.
+                // %false = hw.constant false
+                // %0 = comb.concat %false, %a : i1, i8
+                writeln!(out, "    %{prefix}_{opname}_zero = hw.constant 0 : i1").unwrap();
+                writeln!(out, "    %{prefix}_{opname}_e1_ext = comb.concat %{prefix}_{opname}_zero, {e1_ssa} : i1, i{inner_width}").unwrap();
+                writeln!(out, "    %{prefix}_{opname}_e2_ext = comb.concat %{prefix}_{opname}_zero, {e2_ssa} : i1, i{inner_width}").unwrap();
+                writeln!(out, "    {name} = comb.{mlir_op} %{prefix}_{opname}_e1_ext, %{prefix}_{opname}_e2_ext : {type_name}").unwrap();
+                name
+            },
+            Expr::BinOp(_loc, _typ, op @ (BinOp::Shl | BinOp::Shr | BinOp::Sra), e1, e2) => {
+                let op = if let Type::SInt(_n) = e1.type_of() { op.signed() } else { *op };
+                let mlir_op = match op {
+                    BinOp::Shl => "shl",
+                    BinOp::Shr => "shru",
+                    _ => "shrs",
+                };
+                let name = format!("%{prefix}_{mlir_op}");
+                let width = e1.type_of().bitwidth();
+                let shamt_width = e2.type_of().bitwidth();
+                let e1_ssa = e1.emit_mlir(out, format!("{prefix}_{mlir_op}_e1"), ctx.clone());
+                let e2_ssa = e2.emit_mlir(out, format!("{prefix}_{mlir_op}_e2"), ctx.clone());
+                // The shift amount must be as wide as the word.
+                let shamt_ssa = if width > shamt_width {
+                    let extension_width = width - shamt_width;
+                    writeln!(out, "    %{prefix}_{mlir_op}_zero = hw.constant 0 : i{extension_width}").unwrap();
+                    writeln!(out, "    %{prefix}_{mlir_op}_shamt = comb.concat %{prefix}_{mlir_op}_zero, {e2_ssa} : i{extension_width}, i{shamt_width}").unwrap();
+                    format!("%{prefix}_{mlir_op}_shamt")
+                } else {
+                    e2_ssa
+                };
+                // %0 = comb.shl %a, %b : i8
+                writeln!(out, "    {name} = comb.{mlir_op} {e1_ssa}, {shamt_ssa} : {type_name}").unwrap();
                 name
             },
             Expr::BinOp(_loc, _typ, BinOp::And, e1, e2) => {
                 let name = format!("%{prefix}_and");
-                let e1_ssa = e1.emit_mlir(format!("{prefix}_and_e1"), ctx.clone());
-                let e2_ssa = e2.emit_mlir(format!("{prefix}_and_e2"), ctx.clone());
-                println!("    {name} = comb.and {e1_ssa}, {e2_ssa} : {type_name}");
+                let e1_ssa = e1.emit_mlir(out, format!("{prefix}_and_e1"), ctx.clone());
+                let e2_ssa = e2.emit_mlir(out, format!("{prefix}_and_e2"), ctx.clone());
+                writeln!(out, "    {name} = comb.and {e1_ssa}, {e2_ssa} : {type_name}").unwrap();
                 name
             },
             Expr::BinOp(_loc, _typ, BinOp::Or, e1, e2) => {
                 let name = format!("%{prefix}_or");
-                let e1_ssa = e1.emit_mlir(format!("{prefix}_or_e1"), ctx.clone());
-                let e2_ssa = e2.emit_mlir(format!("{prefix}_or_e2"), ctx.clone());
-                println!("    {name} = comb.or {e1_ssa}, {e2_ssa} : {type_name}");
+                let e1_ssa = e1.emit_mlir(out, format!("{prefix}_or_e1"), ctx.clone());
+                let e2_ssa = e2.emit_mlir(out, format!("{prefix}_or_e2"), ctx.clone());
+                writeln!(out, "    {name} = comb.or {e1_ssa}, {e2_ssa} : {type_name}").unwrap();
                 name
             },
             Expr::BinOp(_loc, _typ, BinOp::Xor, e1, e2) => {
                 let name = format!("%{prefix}_xor");
-                let e1_ssa = e1.emit_mlir(format!("{prefix}_or_e1"), ctx.clone());
-                let e2_ssa = e2.emit_mlir(format!("{prefix}_or_e2"), ctx.clone());
-                println!("    {name} = comb.xor {e1_ssa}, {e2_ssa} : {type_name}");
-                name
-            },
-            Expr::BinOp(_loc, _typ, BinOp::Eq, e1, e2) => {
-                let name = format!("%{prefix}_eq");
-                let e1_type_name = type_to_mlir(e1.type_of());
-                let e1_ssa = e1.emit_mlir(format!("{prefix}_eq_e1"), ctx.clone());
-                let e2_ssa = e2.emit_mlir(format!("{prefix}_eq_e2"), ctx.clone());
-                // %0 = comb.icmp bin eq %a, %b : i8
-                println!("    {name} = comb.icmp bin eq {e1_ssa}, {e2_ssa} : {e1_type_name}");
+                let e1_ssa = e1.emit_mlir(out, format!("{prefix}_or_e1"), ctx.clone());
+                let e2_ssa = e2.emit_mlir(out, format!("{prefix}_or_e2"), ctx.clone());
+                writeln!(out, "    {name} = comb.xor {e1_ssa}, {e2_ssa} : {type_name}").unwrap();
                 name
             },
-            Expr::BinOp(_loc, _typ, BinOp::Lt, e1, e2) => {
-                let name = format!("%{prefix}_eq");
+            Expr::BinOp(_loc, _typ, op, e1, e2) if op.is_comparison() => {
+                let op = if let Type::SInt(_n) = e1.type_of() { op.signed() } else { *op };
+                let predicate = match op {
+                    BinOp::Eq => "eq",
+                    BinOp::Neq => "ne",
+                    BinOp::Lt => "ult",
+                    BinOp::Lte => "ule",
+                    BinOp::Gt => "ugt",
+                    BinOp::Gte => "uge",
+                    BinOp::Slt => "slt",
+                    BinOp::Slte => "sle",
+                    BinOp::Sgt => "sgt",
+                    BinOp::Sgte => "sge",
+                    _ => unreachable!(),
+                };
+                let name = format!("%{prefix}_{predicate}");
                 let e1_type_name = type_to_mlir(e1.type_of());
-                let e1_ssa = e1.emit_mlir(format!("{prefix}_lt_e1"), ctx.clone());
-                let e2_ssa = e2.emit_mlir(format!("{prefix}_lt_e2"), ctx.clone());
+                let e1_ssa = e1.emit_mlir(out, format!("{prefix}_{predicate}_e1"), ctx.clone());
+                let e2_ssa = e2.emit_mlir(out, format!("{prefix}_{predicate}_e2"), ctx.clone());
                 // %0 = comb.icmp bin ult %a, %b : i8
-                println!("    {name} = comb.icmp bin ult {e1_ssa}, {e2_ssa} : {e1_type_name}");
+                writeln!(out, "    {name} = comb.icmp bin {predicate} {e1_ssa}, {e2_ssa} : {e1_type_name}").unwrap();
                 name
             },
             Expr::If(_loc, _typ, cond, e1, e2) => {
                 let name = format!("%{prefix}_if");
-                let cond_ssa = cond.emit_mlir(format!("{prefix}_if_cond"), ctx.clone());
-                let e1_ssa   =   e1.emit_mlir(format!("{prefix}_if_e1"),   ctx.clone());
-                let e2_ssa   =   e2.emit_mlir(format!("{prefix}_if_e2"),   ctx.clone());
+                let cond_ssa = cond.emit_mlir(out, format!("{prefix}_if_cond"), ctx.clone());
+                let e1_ssa   =   e1.emit_mlir(out, format!("{prefix}_if_e1"),   ctx.clone());
+                let e2_ssa   =   e2.emit_mlir(out, format!("{prefix}_if_e2"),   ctx.clone());
                 // %0 = comb.mux bin %in, %a, %b : i8
-                println!("    {name} = comb.mux bin {cond_ssa}, {e1_ssa}, {e2_ssa} : {type_name}");
+                writeln!(out, "    {name} = comb.mux bin {cond_ssa}, {e1_ssa}, {e2_ssa} : {type_name}").unwrap();
                 name
             },
             Expr::Mux(_loc, _typ, cond, e1, e2) => {
                 let name = format!("%{prefix}_mux");
-                let cond_ssa = cond.emit_mlir(format!("{prefix}_mux_cond"), ctx.clone());
-                let e1_ssa   =   e1.emit_mlir(format!("{prefix}_mux_e1"),   ctx.clone());
-                let e2_ssa   =   e2.emit_mlir(format!("{prefix}_mux_e2"),   ctx.clone());
+                let cond_ssa = cond.emit_mlir(out, format!("{prefix}_mux_cond"), ctx.clone());
+                let e1_ssa   =   e1.emit_mlir(out, format!("{prefix}_mux_e1"),   ctx.clone());
+                let e2_ssa   =   e2.emit_mlir(out, format!("{prefix}_mux_e2"),   ctx.clone());
                 // %0 = comb.mux bin %in, %a, %b : i8
-                println!("    {name} = comb.mux bin {cond_ssa}, {e1_ssa}, {e2_ssa} : {type_name}");
+                writeln!(out, "    {name} = comb.mux bin {cond_ssa}, {e1_ssa}, {e2_ssa} : {type_name}").unwrap();
                 name
             },
             Expr::Cat(_loc, _typ, es) => {
@@ -211,67 +352,324 @@ impl Expr {
                 let mut es_ssas = vec![];
                 let mut es_typenames = vec![];
                 for (i, e) in es.iter().enumerate() {
-                    let ssa = e.emit_mlir(format!("{prefix}_cat_e{i}"),ctx.clone());
+                    let ssa = e.emit_mlir(out, format!("{prefix}_cat_e{i}"),ctx.clone());
                     let width = e.type_of().bitwidth();
                     let type_name = format!("i{width}");
                     es_ssas.push(ssa);
                     es_typenames.push(type_name);
                 }
 
-                println!("    {name} = comb.concat {} : {}", es_ssas.join(", "), es_typenames.join(", "));
+                writeln!(out, "    {name} = comb.concat {} : {}", es_ssas.join(", "), es_typenames.join(", ")).unwrap();
                 name
             },
             Expr::Sext(_loc, _typ, e1) => {
                 let name = format!("%{prefix}_sext");
                 match (typ, e1.type_of()) {
-                    (Type::Word(outer_width), Type::Word(inner_width)) => {
+                    (Type::Word(outer_width), Type::Word(inner_width)) |
+                    (Type::SInt(outer_width), Type::SInt(inner_width)) => {
                         assert!(outer_width >= inner_width);
                         let extension_width = outer_width - inner_width;
-                        let e1_ssa = e1.emit_mlir(format!("{prefix}_sext_e1"),   ctx.clone());
-                        // %c0_i7 = hw.constant 0 : i7
-                        // %0 = comb.concat %c0_i7, %a : i7, i1
-                        println!("    %{prefix}_sext_zero = hw.constant 0 : i{extension_width}");
-                        println!("    {name} = comb.concat %{prefix}_sext_zero, {e1_ssa} : i{extension_width}, i{inner_width}");
+                        let e1_ssa = e1.emit_mlir(out, format!("{prefix}_sext_e1"),   ctx.clone());
+                        if extension_width == 0 {
+                            return e1_ssa;
+                        }
+                        let sign_bit = inner_width - 1;
+                        // %0 = comb.extract %a from 0 : (i1) -> i1
+                        // %1 = comb.replicate %0 : (i1) -> i7
+                        // %2 = comb.concat %1, %a : i7, i1
+                        writeln!(out, "    %{prefix}_sext_sign = comb.extract {e1_ssa} from {sign_bit} : (i{inner_width}) -> i1").unwrap();
+                        writeln!(out, "    %{prefix}_sext_ext = comb.replicate %{prefix}_sext_sign : (i1) -> i{extension_width}").unwrap();
+                        writeln!(out, "    {name} = comb.concat %{prefix}_sext_ext, {e1_ssa} : i{extension_width}, i{inner_width}").unwrap();
                         name
                     },
                     _ => panic!(),
                 }
             },
-            /*
-            Expr::Let(loc, _typ, name, e, b) => {
-            let name = format!("%{prefix}_let");
-                let e_ssa = e.emit_mlir(format!("{prefix}_let_{name}"), ctx.clone());
-                let b_ssa = b.emit_mlir(format!("{prefix}_let_{name}"), new_ctx);
-
-                println!("comb.add %{b_ssa} : {type_name}");
+            Expr::Let(_loc, _typ, x, _ascription, e, b) => {
+                let e_ssa = e.emit_mlir(out, format!("{prefix}_let_{x}"), ctx.clone());
+                b.emit_mlir(out, format!("{prefix}_let_body"), ctx.extend(x.clone().into(), e_ssa))
+            },
+            Expr::Ctor(_loc, _typ, ctor, es) => {
+                // See Type::ctors for the layout.
+                let name = format!("%{prefix}_ctor");
+                let (tag, field_typs) = typ.ctor_tag(ctor).unwrap();
+                let tag_width = typ.tag_width().unwrap();
+                writeln!(out, "    %{prefix}_ctor_tag = hw.constant {tag} : i{tag_width}").unwrap();
+                let mut ssas = vec![format!("%{prefix}_ctor_tag")];
+                let mut typenames = vec![format!("i{tag_width}")];
+                let mut used_width = tag_width;
+                for (i, (e, field_typ)) in es.iter().zip(field_typs.iter()).enumerate() {
+                    ssas.push(e.emit_mlir(out, format!("{prefix}_ctor_e{i}"), ctx.clone()));
+                    typenames.push(format!("i{}", field_typ.bitwidth()));
+                    used_width += field_typ.bitwidth();
+                }
+                let padding_width = typ.bitwidth() - used_width;
+                if padding_width > 0 {
+                    writeln!(out, "    %{prefix}_ctor_pad = hw.constant 0 : i{padding_width}").unwrap();
+                    ssas.push(format!("%{prefix}_ctor_pad"));
+                    typenames.push(format!("i{padding_width}"));
+                }
+                if ssas.len() == 1 {
+                    return ssas.pop().unwrap();
+                }
+                // %0 = comb.concat %tag, %a, %pad : i1, i8, i3
+                writeln!(out, "    {name} = comb.concat {} : {}", ssas.join(", "), typenames.join(", ")).unwrap();
+                name
+            },
+            Expr::Struct(_loc, _typ, fields) => {
+                // The first field goes in the highest bits.
+                let name = format!("%{prefix}_struct");
+                let Type::Struct(typedef) = &typ else { unreachable!() };
+                let mut ssas = vec![];
+                let mut typenames = vec![];
+                for (field, field_typ) in &typedef.fields {
+                    let (_field, e) = fields.iter().find(|(name, _e)| name == field).unwrap();
+                    ssas.push(e.emit_mlir(out, format!("{prefix}_struct_{field}"), ctx.clone()));
+                    typenames.push(format!("i{}", field_typ.bitwidth()));
+                }
+                writeln!(out, "    {name} = comb.concat {} : {}", ssas.join(", "), typenames.join(", ")).unwrap();
+                name
+            },
+            Expr::IdxField(_loc, _typ, e1, field) => {
+                let name = format!("%{prefix}_field");
+                let e1_type = e1.type_of();
+                let Type::Struct(typedef) = &e1_type else { unreachable!() };
+                let width = e1_type.bitwidth();
+                let mut offset = width;
+                for (other, field_typ) in &typedef.fields {
+                    offset -= field_typ.bitwidth();
+                    if other == field {
+                        break;
+                    }
+                }
+                let e1_ssa = e1.emit_mlir(out, format!("{prefix}_field_e1"), ctx.clone());
+                // %0 = comb.extract %a from 8 : (i16) -> i8
+                writeln!(out, "    {name} = comb.extract {e1_ssa} from {offset} : (i{width}) -> i{}", typ.bitwidth()).unwrap();
                 name
             },
-            */
+            Expr::Match(_loc, _typ, subject, arms) => {
+                let subject_typ = subject.type_of();
+                let subject_ssa = subject.emit_mlir(out, format!("{prefix}_match_subject"), ctx.clone());
+                let mut arm_ssas = vec![];
+                for (i, MatchArm(pat, e)) in arms.iter().enumerate() {
+                    let (cond_ssa, arm_ctx) = pat.emit_mlir(out, format!("{prefix}_match_arm{i}"), &subject_ssa, &subject_typ, ctx.clone());
+                    let e_ssa = e.emit_mlir(out, format!("{prefix}_match_arm{i}_e"), arm_ctx);
+                    arm_ssas.push((cond_ssa, e_ssa));
+                }
+                // The match is exhaustive, so the last arm is taken whenever no earlier arm matches.
+                let (_cond_ssa, mut result_ssa) = arm_ssas.pop().unwrap();
+                for (i, (cond_ssa, e_ssa)) in arm_ssas.into_iter().enumerate().rev() {
+                    result_ssa = match cond_ssa {
+                        None => e_ssa,
+                        Some(cond_ssa) => {
+                            let name = format!("%{prefix}_match_mux{i}");
+                            // %0 = comb.mux bin %cond, %a, %b : i8
+                            writeln!(out, "    {name} = comb.mux bin {cond_ssa}, {e_ssa}, {result_ssa} : {type_name}").unwrap();
+                            name
+                        },
+                    };
+                }
+                result_ssa
+            },
             Expr::Idx(_loc, _typ, e1, i) => {
                 let name = format!("%{prefix}_idx");
                 let e1_type_name = type_to_mlir(e1.type_of());
-                let e1_ssa = e1.emit_mlir(format!("{prefix}_idx_e1"), ctx.clone());
+                let e1_ssa = e1.emit_mlir(out, format!("{prefix}_idx_e1"), ctx.clone());
                 // %0 = comb.extract %b from 0 : (i8) -> i1
-                println!("    {name} = comb.extract {e1_ssa} from {i} : ({e1_type_name}) -> i1");
+                writeln!(out, "    {name} = comb.extract {e1_ssa} from {i} : ({e1_type_name}) -> i1").unwrap();
                 name
             },
             Expr::IdxRange(_loc, _typ, e1, j, i) => {
                 let name = format!("%{prefix}_idxrange");
                 let e1_type_name = type_to_mlir(e1.type_of());
                 let width = j - i;
-                let e1_ssa = e1.emit_mlir(format!("{prefix}_idxrange_e1"), ctx.clone());
+                let e1_ssa = e1.emit_mlir(out, format!("{prefix}_idxrange_e1"), ctx.clone());
                 // %0 = comb.extract %b from 0 : (i8) -> i3
-                println!("    {name} = comb.extract {e1_ssa} from {i} : ({e1_type_name}) -> i{width}");
+                writeln!(out, "    {name} = comb.extract {e1_ssa} from {i} : ({e1_type_name}) -> i{width}").unwrap();
                 name
-            }
+            },
+            Expr::IdxDyn(_loc, _typ, e1, i) => {
+                let name = format!("%{prefix}_idxdyn");
+                let e1_type = e1.type_of();
+                let e1_type_name = type_to_mlir(e1_type.clone());
+                let index_width = e1_type.index_width().unwrap();
+                let e1_ssa = e1.emit_mlir(out, format!("{prefix}_idxdyn_e1"), ctx.clone());
+                let i_ssa = i.emit_mlir(out, format!("{prefix}_idxdyn_i"), ctx.clone());
+                match e1_type {
+                    Type::Vec(_typ, _n) => {
+                        // %0 = hw.array_get %a[%i] : !hw.array<4xi8>, i2
+                        writeln!(out, "    {name} = hw.array_get {e1_ssa}[{i_ssa}] : {e1_type_name}, i{index_width}").unwrap();
+                    },
+                    Type::Word(width) => {
+                        // The shift amount must be as wide as the word.
+                        let shamt_ssa = if width > index_width {
+                            let extension_width = width - index_width;
+                            writeln!(out, "    %{prefix}_idxdyn_zero = hw.constant 0 : i{extension_width}").unwrap();
+                            writeln!(out, "    %{prefix}_idxdyn_shamt = comb.concat %{prefix}_idxdyn_zero, {i_ssa} : i{extension_width}, i{index_width}").unwrap();
+                            format!("%{prefix}_idxdyn_shamt")
+                        } else {
+                            i_ssa
+                        };
+                        // %0 = comb.shru %a, %i : i8
+                        // %1 = comb.extract %0 from 0 : (i8) -> i1
+                        writeln!(out, "    %{prefix}_idxdyn_shru = comb.shru {e1_ssa}, {shamt_ssa} : {e1_type_name}").unwrap();
+                        writeln!(out, "    {name} = comb.extract %{prefix}_idxdyn_shru from 0 : ({e1_type_name}) -> i1").unwrap();
+                    },
+                    _ => unreachable!(),
+                }
+                name
+            },
             _ => panic!("Can't lower expression {self:?}"),
         }
     }
 }
 
+impl Pat {
+    /// Emit the condition under which this pattern matches `subject_ssa`, a value of type `typ`.
+    /// The condition is `None` when the pattern always matches.
+    /// Also returns `ctx` extended with the SSA names of the variables the pattern binds.
+    fn emit_mlir(&self, out: &mut String, prefix: String, subject_ssa: &str, typ: &Type, ctx: Context<Path, String>) -> (Option<String>, Context<Path, String>) {
+        let width = typ.bitwidth();
+        match self {
+            Pat::Otherwise => (None, ctx),
+            Pat::Bind(x) => (None, ctx.extend(x.clone().into(), subject_ssa.to_string())),
+            Pat::Word(_width, value) => (Some(emit_mlir_eq(out, &prefix, subject_ssa, *value, width)), ctx),
+            Pat::Enum(_typename, valname) => {
+                let Type::Enum(typedef) = typ else { unreachable!() };
+                let value = typedef.value_of(valname).unwrap();
+                (Some(emit_mlir_eq(out, &prefix, subject_ssa, value, width)), ctx)
+            },
+            Pat::At(ctor, subpats) => {
+                if let Type::Enum(typedef) = typ {
+                    let value = typedef.value_of(ctor).unwrap();
+                    return (Some(emit_mlir_eq(out, &prefix, subject_ssa, value, width)), ctx);
+                }
+                // See Type::ctors for the layout.
+                let (tag, field_typs) = typ.ctor_tag(ctor).unwrap();
+                let tag_width = typ.tag_width().unwrap();
+                let mut offset = width - tag_width;
+                // %0 = comb.extract %a from 8 : (i9) -> i1
+                writeln!(out, "    %{prefix}_tag = comb.extract {subject_ssa} from {offset} : (i{width}) -> i{tag_width}").unwrap();
+                let cond_ssa = emit_mlir_eq(out, &format!("{prefix}_tag"), &format!("%{prefix}_tag"), tag, tag_width);
+
+                let mut fields = vec![];
+                for (field_typ, subpat) in field_typs.into_iter().zip(subpats.iter()) {
+                    offset -= field_typ.bitwidth();
+                    fields.push((offset, field_typ, subpat));
+                }
+                emit_mlir_fields(out, &prefix, subject_ssa, width, fields, Some(cond_ssa), ctx)
+            },
+            Pat::Struct(field_pats) => {
+                let Type::Struct(typedef) = typ else { unreachable!() };
+                let mut offset = width;
+                let mut fields = vec![];
+                for (name, field_typ) in &typedef.fields {
+                    offset -= field_typ.bitwidth();
+                    if let Some((_name, pat)) = field_pats.iter().find(|(field, _pat)| field == name) {
+                        fields.push((offset, field_typ.clone(), pat));
+                    }
+                }
+                emit_mlir_fields(out, &prefix, subject_ssa, width, fields, None, ctx)
+            },
+        }
+    }
+}
+
+/// Match the fields of `subject_ssa` against their patterns, and with `cond_ssa`, if any.
+/// Each field is given by its offset from the bottom, its type, and its pattern.
+fn emit_mlir_fields(
+    out: &mut String,
+    prefix: &str,
+    subject_ssa: &str,
+    width: Width,
+    fields: Vec<(Width, Type, &Pat)>,
+    mut cond_ssa: Option<String>,
+    mut ctx: Context<Path, String>,
+) -> (Option<String>, Context<Path, String>) {
+    for (i, (offset, field_typ, pat)) in fields.into_iter().enumerate() {
+        let field_width = field_typ.bitwidth();
+        let field_ssa = format!("%{prefix}_f{i}");
+        writeln!(out, "    {field_ssa} = comb.extract {subject_ssa} from {offset} : (i{width}) -> i{field_width}").unwrap();
+        let (field_cond_ssa, field_ctx) = pat.emit_mlir(out, format!("{prefix}_f{i}"), &field_ssa, &field_typ, ctx);
+        ctx = field_ctx;
+        cond_ssa = match (cond_ssa, field_cond_ssa) {
+            (Some(cond_ssa), Some(field_cond_ssa)) => {
+                let name = format!("%{prefix}_f{i}_and");
+                writeln!(out, "    {name} = comb.and {cond_ssa}, {field_cond_ssa} : i1").unwrap();
+                Some(name)
+            },
+            (cond_ssa, field_cond_ssa) => cond_ssa.or(field_cond_ssa),
+        };
+    }
+    (cond_ssa, ctx)
+}
+
+fn emit_mlir_eq(out: &mut String, prefix: &str, ssa: &str, value: u64, width: Width) -> String {
+    // %0 = comb.icmp bin eq %a, %b : i8
+    writeln!(out, "    %{prefix}_const = hw.constant {value} : i{width}").unwrap();
+    writeln!(out, "    %{prefix}_eq = comb.icmp bin eq {ssa}, %{prefix}_const : i{width}").unwrap();
+    format!("%{prefix}_eq")
+}
+
+fn emit_mlir_mem(out: &mut String, name: &str, typ: &Type, depth: u64, latency: u64, clock: &str, ports: &[MemPort]) {
+    let width = typ.bitwidth();
+    // A `mem` allows only one mask width, so it goes on the type of the whole memory.
+    let mask = ports.iter().find_map(|port| match port {
+        MemPort::Write(_name, mask) => *mask,
+        MemPort::Read(_name) => None,
+    });
+    let mem_type = match mask {
+        Some(mask) => format!("<{depth} x {width}, mask {mask}>"),
+        None => format!("<{depth} x {width}>"),
+    };
+
+    writeln!(out, "    %{name} = seq.firmem {latency}, 1, undefined, undefined : {mem_type}").unwrap();
+    for port in ports {
+        match port {
+            MemPort::Read(port) => {
+                writeln!(out, "    %{name}.{port}_data = seq.firmem.read_port %{name}[%{name}.{port}_addr], clock {clock} : {mem_type}").unwrap();
+            },
+            MemPort::Write(port, None) => {
+                write!(out, "    seq.firmem.write_port %{name}[%{name}.{port}_addr] = %{name}.{port}_data, clock {clock} ").unwrap();
+                writeln!(out, "enable %{name}.{port}_en : {mem_type}").unwrap();
+            },
+            MemPort::Write(port, Some(mask)) => {
+                write!(out, "    seq.firmem.write_port %{name}[%{name}.{port}_addr] = %{name}.{port}_data, clock {clock} ").unwrap();
+                writeln!(out, "enable %{name}.{port}_en mask %{name}.{port}_mask : {mem_type}, i{mask}").unwrap();
+            },
+        }
+    }
+}
+
+/// The SSA name of the clock of the domain `dom`, following the domains it is wired to with `$=`.
+fn clock_ssa(dom: &str, dom_driver_for: &BTreeMap<Path, Path>) -> String {
+    let mut dom: Path = dom.to_string().into();
+    while let Some(driver) = dom_driver_for.get(&dom) {
+        dom = driver.clone();
+    }
+    format!("%{dom}")
+}
+
+/// The name of a monomorphized parametric mod. Eg, `Fifo[Word[8], 16]` becomes `Fifo_Word_8_16`.
+fn mlir_instance_name(name: &str, params: &[TypeParam]) -> String {
+    let mut result = name.to_string();
+    for param in params {
+        result.push('_');
+        for c in param.to_string().chars() {
+            if c.is_ascii_alphanumeric() {
+                result.push(c);
+            } else if !result.ends_with('_') {
+                result.push('_');
+            }
+        }
+    }
+    result.trim_end_matches('_').to_string()
+}
+
 fn type_to_mlir(typ: Type) -> String {
     match typ {
         Type::Word(n) => format!("i{n}"),
+        Type::SInt(n) => format!("i{n}"),
         Type::Struct(typedef) => {
             let typedef = typedef;
             let n = typedef.bitwidth();
@@ -280,7 +678,9 @@ fn type_to_mlir(typ: Type) -> String {
         Type::Enum(typedef) => {
             let n = typedef.bitwidth();
             format!("i{n}")
-        }
+        },
+        Type::Valid(_) | Type::Alt(_, _) => format!("i{}", typ.bitwidth()),
+        Type::Vec(typ, n) => format!("!hw.array<{n}x{}>", type_to_mlir(*typ)),
         _ => panic!("Can't lower type to MLIR directly"),
     }
 }
@@ -1,30 +1,97 @@
 use super::*;
+use once_cell::sync::OnceCell;
 use std::sync::Arc;
 
 /// A [`Component`] is a declaration that lives inside of a `mod` or `ext` definiton.
+///
+/// A parametric `mod` definition, such as `mod Fifo[T of Type, depth of Nat]`, lists its type arguments.
+/// A [`Component::ModInst`] of one supplies the parameters. Eg, `mod q of Fifo[Word[8], 16];`.
+///
+/// A [`Component::ModInstVec`], such as `mod lanes[4] of Adder;`,
+/// holds one [`Component::ModInst`] for each index, named `0`, `1`, and so on.
+///
+/// A [`Component::Mem`] is a memory of type `Vec[T, n]`, with its clock domain, its read latency, and its ports.
+/// Its ports are reached like the ports of a submodule. See [`MemPort`].
+///
+/// A `mod` lists its interface ports, each of which is flattened into ordinary ports. See [`InterfacePort`].
+///
+/// Mods, ports, and regs keep the text of their `///` doc comments as an `Option<String>` after the span.
 #[derive(Debug, Clone)]
 pub enum Component {
-    Mod(Span, Name, Vec<Arc<Component>>, Vec<Wire>, Vec<When>),
-    ModInst(Span, Name, Arc<Component>),
-    Ext(Span, Name, Vec<Arc<Component>>),
+    Mod(Span, Option<String>, Name, Vec<(Name, Kind)>, Vec<Arc<Component>>, Vec<Wire>, Vec<When>, Vec<Assertion>, Vec<InterfacePort>),
+    ModInst(Span, Name, Arc<Component>, Vec<TypeParam>),
+    ModInstVec(Span, Name, Vec<Arc<Component>>),
+    Ext(Span, Option<String>, Name, Vec<Arc<Component>>),
     Dom(Span, Name),
-    Incoming(Span, Name, Type),
-    Outgoing(Span, Name, Type),
+    Incoming(Span, Option<String>, Name, Type),
+    Outgoing(Span, Option<String>, Name, Type),
     Node(Span, Name, Type),
-    Reg(Span, Name, Type, Option<Arc<Expr>>),
+    Reg(Span, Option<String>, Name, Type, Option<Name>, Option<Arc<Expr>>),
+    Mem(Span, Name, Type, Option<Name>, u64, Vec<MemPort>),
+}
+
+/// An interface port of a `mod`, such as `port enq of flip Channel;`.
+///
+/// It is flattened into one [`Component::Incoming`] or [`Component::Outgoing`] for each port of its shape,
+/// named like `enq_valid`.
+#[derive(Debug, Clone)]
+pub struct InterfacePort {
+    pub name: Name,
+    pub shapedef: Arc<ShapeDef>,
+    pub flipped: bool,
+}
+
+impl InterfacePort {
+    /// The ports of the shape, as the name of the port in the [`ShapeDef`], the name of the flattened port,
+    /// its direction once flipped, and its type.
+    pub fn ports(&self) -> Vec<(Name, Name, Direction, Type)> {
+        self.shapedef.ports.iter().map(|(port_name, dir, typ)| {
+            let dir = if self.flipped { dir.flip() } else { *dir };
+            (port_name.clone(), format!("{}_{port_name}", self.name), dir, typ.clone())
+        }).collect()
+    }
+}
+
+/// A port of a [`Component::Mem`].
+///
+/// A read port `r` has an incoming `r_addr` and an outgoing `r_data`.
+/// A write port `w` has incoming `w_en`, `w_addr`, and `w_data`, and `w_mask` if it has a write mask.
+/// Each bit of the mask enables the write to one slice of the word, the lowest bit for the lowest slice.
+#[derive(Debug, Clone)]
+pub enum MemPort {
+    Read(Name),
+    Write(Name, Option<Width>),
 }
 
 impl Component {
     pub fn name(&self) -> &str {
         match self {
-            Component::Mod(_loc, name, _children, _wires, _whens) => name.as_str(),
-            Component::ModInst(_loc, name, _defname) => name.as_str(),
-            Component::Ext(_loc, name, _children) => name.as_str(),
+            Component::Mod(_loc, _doc, name, _type_args, _children, _wires, _whens, _assertions, _interfaces) => name.as_str(),
+            Component::ModInst(_loc, name, _defname, _params) => name.as_str(),
+            Component::ModInstVec(_loc, name, _insts) => name.as_str(),
+            Component::Ext(_loc, _doc, name, _children) => name.as_str(),
             Component::Dom(_loc, name) => name.as_str(),
-            Component::Incoming(_loc, name, _typ) => name.as_str(),
-            Component::Outgoing(_loc, name, _typ) => name.as_str(),
+            Component::Incoming(_loc, _doc, name, _typ) => name.as_str(),
+            Component::Outgoing(_loc, _doc, name, _typ) => name.as_str(),
             Component::Node(_loc, name, _typ) => name.as_str(),
-            Component::Reg(_loc, name, _typ, _value) => name.as_str(),
+            Component::Reg(_loc, _doc, name, _typ, _dom, _value) => name.as_str(),
+            Component::Mem(_loc, name, _typ, _dom, _latency, _ports) => name.as_str(),
+        }
+    }
+
+    /// The text of the `///` doc comments of a mod, port, or reg.
+    pub fn doc(&self) -> Option<&str> {
+        match self {
+            Component::Mod(_loc, doc, _name, _type_args, _children, _wires, _whens, _assertions, _interfaces) => doc.as_deref(),
+            Component::ModInst(_loc, _name, _defname, _params) => None,
+            Component::ModInstVec(_loc, _name, _insts) => None,
+            Component::Ext(_loc, doc, _name, _children) => doc.as_deref(),
+            Component::Dom(_loc, _name) => None,
+            Component::Incoming(_loc, doc, _name, _typ) => doc.as_deref(),
+            Component::Outgoing(_loc, doc, _name, _typ) => doc.as_deref(),
+            Component::Node(_loc, _name, _typ) => None,
+            Component::Reg(_loc, doc, _name, _typ, _dom, _value) => doc.as_deref(),
+            Component::Mem(_loc, _name, _typ, _dom, _latency, _ports) => None,
         }
     }
 
@@ -39,20 +106,22 @@ impl Component {
 
     pub fn children(&self) -> Vec<Arc<Component>> {
         match self {
-            Component::Mod(_loc, _name, children, _wires, _whens) => children.iter().cloned().collect(),
-            Component::ModInst(_loc, _name, _defname) => vec![],
-            Component::Ext(_loc, _name, children) => children.iter().cloned().collect(),
+            Component::Mod(_loc, _doc, _name, _type_args, children, _wires, _whens, _assertions, _interfaces) => children.iter().cloned().collect(),
+            Component::ModInst(_loc, _name, _defname, _params) => vec![],
+            Component::ModInstVec(_loc, _name, insts) => insts.clone(),
+            Component::Ext(_loc, _doc, _name, children) => children.iter().cloned().collect(),
             Component::Dom(_loc, _name) => vec![],
-            Component::Incoming(_loc, _name, _typ) => vec![],
-            Component::Outgoing(_loc, _name, _typ) => vec![],
+            Component::Incoming(_loc, _doc, _name, _typ) => vec![],
+            Component::Outgoing(_loc, _doc, _name, _typ) => vec![],
             Component::Node(_loc, _name, _typ) => vec![],
-            Component::Reg(_loc, _name, _typ, _value) => vec![],
+            Component::Reg(_loc, _doc, _name, _typ, _dom, _value) => vec![],
+            Component::Mem(loc, _name, typ, _dom, _latency, ports) => mem_port_components(loc, typ, ports),
         }
     }
 
     pub(crate) fn wires(&self) -> Vec<Wire> {
         match self {
-            Component::Mod(_loc, _name, _children, wires, _whens) => {
+            Component::Mod(_loc, _doc, _name, _type_args, _children, wires, _whens, _assertions, _interfaces) => {
                 wires.clone()
             }
             _ => vec![],
@@ -61,32 +130,168 @@ impl Component {
 
     pub(crate) fn whens(&self) -> Vec<When> {
         match self {
-            Component::Mod(_loc, _name, _children, _wires, whens) => {
+            Component::Mod(_loc, _doc, _name, _type_args, _children, _wires, whens, _assertions, _interfaces) => {
                 whens.clone()
             }
             _ => vec![],
         }
     }
 
-    pub(crate) fn paths_rec(&self, path: Path) -> Vec<Path> {
+    /// The `assert` and `cover` statements of a `mod`.
+    pub(crate) fn assertions(&self) -> Vec<Assertion> {
+        match self {
+            Component::Mod(_loc, _doc, _name, _type_args, _children, _wires, _whens, assertions, _interfaces) => {
+                assertions.clone()
+            }
+            _ => vec![],
+        }
+    }
+
+    /// The interface ports of a `mod`.
+    pub(crate) fn interfaces(&self) -> Vec<InterfacePort> {
+        match self {
+            Component::Mod(_loc, _doc, _name, _type_args, _children, _wires, _whens, _assertions, interfaces) => {
+                interfaces.clone()
+            }
+            _ => vec![],
+        }
+    }
+
+    /// The [`Wire`]s which make up the netlist of a `mod`.
+    ///
+    /// These are the ordinary wires, followed by the partial connections assembled by [`Component::partial_wires`],
+    /// and the procedural logic lowered by [`Component::proc_wires`].
+    pub(crate) fn netlist_wires(&self) -> Vec<Wire> {
+        let mut results: Vec<Wire> = self.wires()
+            .into_iter()
+            .filter(|Wire(_loc, _target, select, _expr, wire_type)| select.is_none() && *wire_type != WireType::Proc)
+            .collect();
+        results.extend(self.partial_wires());
+        results.extend(self.proc_wires());
+        results
+    }
+
+    /// Assemble the partial connections to each component into a single [`Wire`] which drives the whole value.
+    ///
+    /// Struct fields become an [`Expr::Struct`], the bits of a `Word` an [`Expr::Cat`], and the elements of a `Vec` an [`Expr::Vec`].
+    /// A component which isn't driven exactly once in every part is skipped. See `check_wires_duplicate_targets`.
+    pub(crate) fn partial_wires(&self) -> Vec<Wire> {
+        let mut targets: Vec<(Path, WireType)> = vec![];
+        for Wire(_loc, target, select, _expr, wire_type) in self.wires() {
+            if select.is_some() && !targets.contains(&(target.clone(), wire_type.clone())) {
+                targets.push((target, wire_type));
+            }
+        }
+
         let mut results = vec![];
-        for child in self.children() {
-            match &*child {
-                Component::Node(_loc, name, _typ) => results.push(path.join(name.clone().into())),
-                Component::Reg(_loc, name, _typ, _reset) => {
-                    results.push(path.join(format!("{name}.set").into()));
-                    results.push(path.join(name.clone().into()));
+        for (target, wire_type) in targets {
+            let mut parts: Vec<(Select, Arc<Expr>)> = vec![];
+            let mut span = None;
+            for Wire(loc, wire_target, select, expr, wire_wire_type) in self.wires() {
+                if let Some(select) = select {
+                    if wire_target == target && wire_wire_type == wire_type {
+                        span.get_or_insert(loc);
+                        parts.push((select, expr));
+                    }
+                }
+            }
+
+            let Some(typ) = self.type_of_path(&target) else { continue };
+            let Some(mut expected) = Select::atoms_of(&typ) else { continue };
+            let mut driven: Vec<Select> = parts.iter().flat_map(|(select, _expr)| select.atoms()).collect();
+            driven.sort();
+            expected.sort();
+            if driven != expected {
+                continue;
+            }
+
+            let span = span.unwrap();
+            let expr = match &typ {
+                Type::Struct(_typedef) => {
+                    let fields = parts.into_iter().map(|(select, expr)| match select {
+                        Select::Field(field) => (field, expr),
+                        _ => unreachable!(),
+                    }).collect();
+                    Expr::Struct(span.clone(), OnceCell::from(typ.clone()), fields)
+                },
+                Type::Vec(_typ, _n) => {
+                    parts.sort_by_key(|(select, _expr)| select.clone());
+                    Expr::Vec(span.clone(), OnceCell::from(typ.clone()), parts.into_iter().map(|(_select, expr)| expr).collect())
                 },
-                Component::Dom(_loc, name) => results.push(path.join(name.clone().into())),
-                Component::Incoming(_loc, name, _typ) => results.push(path.join(name.clone().into())),
-                Component::Outgoing(_loc, name, _typ) => results.push(path.join(name.clone().into())),
-                Component::Mod(_loc, name, _children, _wires, _whens) => results.extend(child.paths_rec(path.join(name.clone().into()))),
-                Component::ModInst(_loc, name, moddef) => {
-                    results.extend(moddef.paths_rec(path.join(name.clone().into())))
+                _ => {
+                    // The most significant bits come first.
+                    parts.sort_by_key(|(select, _expr)| select.atoms()[0].clone());
+                    parts.reverse();
+                    Expr::Cat(span.clone(), OnceCell::from(typ.clone()), parts.into_iter().map(|(_select, expr)| expr).collect())
+                },
+            };
+            results.push(Wire(span, target, None, Arc::new(expr), wire_type));
+        }
+        results
+    }
+
+    /// The type of the port, node, or register at a path relative to this `mod`. Eg, `buf.out`.
+    pub(crate) fn type_of_path(&self, path: &Path) -> Option<Type> {
+        let mut parts = path.split('.');
+        let mut component = self.child(parts.next()?)?;
+        for part in parts {
+            component = match &*component {
+                Component::ModInst(_loc, _name, _moddef, _params) => component.instantiated().child(part)?,
+                _ => component.child(part)?,
+            };
+        }
+        component.type_of()
+    }
+
+    /// Lower the procedural logic of a `mod` into a single [`WireType::Latch`] wire for each register it drives.
+    ///
+    /// Every `<=!` to a register, guarded by a `when` or not, is applied in source order,
+    /// so the last one whose condition holds wins.
+    /// When none of them apply, the register holds its value.
+    pub(crate) fn proc_wires(&self) -> Vec<Wire> {
+        let mut assignments: Vec<(Option<Arc<Expr>>, Wire)> = vec![];
+        for wire in self.wires() {
+            if let Wire(_loc, _target, None, _expr, WireType::Proc) = &wire {
+                assignments.push((None, wire.clone()));
+            }
+        }
+        for When(cond, wires) in self.whens() {
+            for wire in wires {
+                if let Wire(_loc, _target, None, _expr, _wire_type) = &wire {
+                    assignments.push((Some(cond.clone()), wire.clone()));
                 }
-                Component::Ext(_loc, name, _children) => results.extend(child.paths_rec(path.join(name.clone().into()))),
             }
         }
+        assignments.sort_by_key(|(_cond, wire)| wire.span().start());
+
+        let mut targets: Vec<Path> = vec![];
+        for (_cond, Wire(_loc, target, _select, _expr, _wire_type)) in &assignments {
+            if !targets.contains(target) {
+                targets.push(target.clone());
+            }
+        }
+
+        let mut results = vec![];
+        for target in targets {
+            let typ = match self.child(&target).as_deref() {
+                Some(Component::Reg(_loc, _doc, _name, typ, _dom, _reset)) => typ.clone(),
+                _ => continue,
+            };
+
+            let mut span = None;
+            let mut next = Arc::new(Expr::Reference(Span::unknown(), OnceCell::from(typ.clone()), target.clone()));
+            for (cond, Wire(loc, wire_target, _select, expr, _wire_type)) in &assignments {
+                if *wire_target != target {
+                    continue;
+                }
+                span.get_or_insert_with(|| loc.clone());
+                next = match cond {
+                    None => expr.clone(),
+                    Some(cond) => Arc::new(Expr::If(loc.clone(), OnceCell::from(typ.clone()), cond.clone(), expr.clone(), next)),
+                };
+            }
+            results.push(Wire(span.unwrap(), target, None, next, WireType::Latch));
+        }
         results
     }
 
@@ -94,8 +299,8 @@ impl Component {
         let mut results = vec![];
         for child in self.children() {
             match &*child {
-                Component::Incoming(_loc, name, _typ) => results.push((name.to_string().into(), child.clone())),
-                Component::Outgoing(_loc, name, _typ) => results.push((name.to_string().into(), child.clone())),
+                Component::Incoming(_loc, _doc, name, _typ) => results.push((name.to_string().into(), child.clone())),
+                Component::Outgoing(_loc, _doc, name, _typ) => results.push((name.to_string().into(), child.clone())),
                 _ => (),
             }
         }
@@ -104,29 +309,41 @@ impl Component {
 
     pub fn is_mod(&self) -> bool {
         match self {
-            Component::Mod(_loc, _name, _children, _wires, _whens) => true,
+            Component::Mod(_loc, _doc, _name, _type_args, _children, _wires, _whens, _assertions, _interfaces) => true,
             _ => false
         }
     }
 
+    /// The type arguments of a parametric `mod` definition.
+    pub fn type_args(&self) -> Vec<(Name, Kind)> {
+        match self {
+            Component::Mod(_loc, _doc, _name, type_args, _children, _wires, _whens, _assertions, _interfaces) => type_args.clone(),
+            _ => vec![],
+        }
+    }
+
+    pub fn is_parametric(&self) -> bool {
+        !self.type_args().is_empty()
+    }
+
     pub fn is_port(&self) -> bool {
         match self {
-            Component::Incoming(_loc, _name, _typ) => true,
-            Component::Outgoing(_loc, _name, _typ) => true,
+            Component::Incoming(_loc, _doc, _name, _typ) => true,
+            Component::Outgoing(_loc, _doc, _name, _typ) => true,
             _ => false
         }
     }
 
     pub fn is_incoming_port(&self) -> bool {
         match self {
-            Component::Incoming(_loc, _name, _typ) => true,
+            Component::Incoming(_loc, _doc, _name, _typ) => true,
             _ => false
         }
     }
 
     pub fn is_outgoing_port(&self) -> bool {
         match self {
-            Component::Outgoing(_loc, _name, _typ) => true,
+            Component::Outgoing(_loc, _doc, _name, _typ) => true,
             _ => false
         }
     }
@@ -134,19 +351,21 @@ impl Component {
     pub fn type_of(&self) -> Option<Type> {
         match self {
             Component::Node(_loc,_name, typ) => Some(typ.clone()),
-            Component::Reg(_loc,_name, typ, _reset) => Some(typ.clone()),
+            Component::Reg(_loc, _doc, _name, typ, _dom, _reset) => Some(typ.clone()),
             Component::Dom(_loc, _name) => None,
-            Component::Incoming(_loc,_name, typ) => Some(typ.clone()),
-            Component::Outgoing(_loc,_name, typ) => Some(typ.clone()),
-            Component::Mod(_loc,_name, _children, _wires, _whens) => None,
-            Component::ModInst(_loc,_name, _defname) => None,
-            Component::Ext(_loc,_name, _children) => None,
+            Component::Incoming(_loc, _doc, _name, typ) => Some(typ.clone()),
+            Component::Outgoing(_loc, _doc, _name, typ) => Some(typ.clone()),
+            Component::Mod(_loc, _doc, _name, _type_args, _children, _wires, _whens, _assertions, _interfaces) => None,
+            Component::ModInst(_loc, _name, _defname, _params) => None,
+            Component::ModInstVec(_loc, _name, _insts) => None,
+            Component::Ext(_loc, _doc, _name, _children) => None,
+            Component::Mem(_loc, _name, _typ, _dom, _latency, _ports) => None,
         }
     }
 
     pub fn reset(&self) -> Option<Arc<Expr>> {
         match self {
-            Component::Reg(_loc, _name, _typ, reset) => reset.clone(),
+            Component::Reg(_loc, _doc, _name, _typ, _dom, reset) => reset.clone(),
             _ => None
         }
     }
@@ -154,7 +373,7 @@ impl Component {
     pub fn submods(&self) -> Vec<Arc<Component>> {
         let mut results = vec![];
         for child in self.children() {
-            if let Component::Mod(_loc,_name, _children, _wires, _whens) = &*child {
+            if let Component::Mod(_loc, _doc, _name, _type_args, _children, _wires, _whens, _assertions, _interfaces) = &*child {
                 results.push(child.clone());
             }
         }
@@ -162,11 +381,98 @@ impl Component {
     }
 }
 
+/// The paths which can be referred to from among a list of sibling components,
+/// along with the component each one names. Eg, `x`, or `buf.out` for a port of a submodule.
+///
+/// The ports of a submodule are looked up on the definition given by `instance`.
+/// See [`Package::instance`].
+pub(crate) fn visible_paths(children: &[Arc<Component>], instance: &dyn Fn(&Component) -> Arc<Component>) -> Vec<(Path, Arc<Component>)> {
+    let mut results = vec![];
+    for child in children {
+        match &**child {
+            Component::Node(_span, name, _typ) => results.push((name.to_string().into(), child.clone())),
+            Component::Dom(_span, _name) => (),
+            Component::Incoming(_span, _doc, name, _typ) => results.push((name.to_string().into(), child.clone())),
+            Component::Outgoing(_span, _doc, name, _typ) => results.push((name.to_string().into(), child.clone())),
+            Component::Reg(_span, _doc, name, _typ, _dom, _reset) => results.push((name.to_string().into(), child.clone())),
+            Component::Mod(_span, _doc, name, _type_args, _children, _wires, _whens, _assertions, _interfaces) => {
+                let mod_path: Path = name.to_string().into();
+                for (path, component) in child.port_paths() {
+                    results.push((mod_path.join(path), component.clone()));
+                }
+            },
+            Component::ModInst(_span, name, _moddef, _params) => {
+                let mod_path: Path = name.to_string().into();
+                // The ports of a parametric mod only have concrete types once it's instantiated.
+                let moddef = instance(child);
+                for (path, component) in moddef.port_paths() {
+                    results.push((mod_path.join(path), component.clone()));
+                }
+            },
+            Component::ModInstVec(_span, name, _insts) => {
+                let vec_path: Path = name.to_string().into();
+                for (path, component) in visible_paths(&child.children(), instance) {
+                    results.push((vec_path.join(path), component));
+                }
+            },
+            Component::Ext(_span, _doc, name, _children) => {
+                let ext_path: Path = name.to_string().into();
+                for (path, component) in child.port_paths() {
+                    results.push((ext_path.join(path), component.clone()));
+                }
+            },
+            Component::Mem(_span, name, _typ, _dom, _latency, _ports) => {
+                let mem_path: Path = name.to_string().into();
+                for (path, component) in child.port_paths() {
+                    results.push((mem_path.join(path), component.clone()));
+                }
+            },
+        }
+    }
+    results
+}
+
+/// The ports of a `mem` of type `typ`. See [`MemPort`].
+/// A `mem` whose type still has parameters has no ports until it is elaborated.
+fn mem_port_components(span: &Span, typ: &Type, ports: &[MemPort]) -> Vec<Arc<Component>> {
+    let Type::Vec(elem_typ, _n) = typ else { return vec![] };
+    let addr_typ = Type::word(typ.index_width().unwrap());
+    let mut results = vec![];
+    for port in ports {
+        match port {
+            MemPort::Read(name) => {
+                results.push(Component::Incoming(span.clone(), None, format!("{name}_addr"), addr_typ.clone()));
+                results.push(Component::Outgoing(span.clone(), None, format!("{name}_data"), *elem_typ.clone()));
+            },
+            MemPort::Write(name, mask) => {
+                results.push(Component::Incoming(span.clone(), None, format!("{name}_en"), Type::word(1)));
+                results.push(Component::Incoming(span.clone(), None, format!("{name}_addr"), addr_typ.clone()));
+                results.push(Component::Incoming(span.clone(), None, format!("{name}_data"), *elem_typ.clone()));
+                if let Some(mask) = mask {
+                    results.push(Component::Incoming(span.clone(), None, format!("{name}_mask"), Type::word(*mask)));
+                }
+            },
+        }
+    }
+    results.into_iter().map(Arc::new).collect()
+}
+
+/// A [`Context`] with the type of each of the [`visible_paths`] among a list of sibling components.
+pub(crate) fn context_of(children: &[Arc<Component>], instance: &dyn Fn(&Component) -> Arc<Component>) -> Context<Path, Type> {
+    let mut ctx = vec![];
+    for (path, target) in visible_paths(children, instance) {
+        let typ = target.type_of().unwrap();
+        ctx.push((path, typ));
+    }
+    Context::from(ctx)
+}
+
 /// A user-defined `tb` testbench.
 #[derive(Debug, Clone)]
 pub struct TbDef {
     pub span: Span,
     pub name: String,
+    pub doc: Option<String>,
     pub statements: Vec<TbStatement>,
 }
 
@@ -177,4 +483,10 @@ pub enum TbStatement {
     Reset,
     Clock,
     ModInst(String, Arc<Component>),
+    /// Set an incoming port of a DUT to the value of a constant expression.
+    Poke(Span, Name, Name, Arc<Expr>),
+    /// Check that a port of a DUT has the value of a constant expression.
+    Expect(Span, Name, Name, Arc<Expr>),
+    /// Run the statements some number of times.
+    Loop(Span, u64, Vec<TbStatement>),
 }
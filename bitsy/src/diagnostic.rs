@@ -0,0 +1,378 @@
+use super::*;
+
+use std::collections::BTreeMap;
+
+/// Render an error for the terminal, the way `rustc` does.
+///
+/// The error is shown with its code and where it happened, followed by the lines of source involved.
+/// The error itself is underlined with `^`, and each of its [`BitsyError::labels`] with `-`:
+///
+/// ```text
+/// error[E0004]: Component has multiple drivers: out.
+///  --> top.bitsy:6:5
+///   |
+/// 5 |     out := in;
+///   |     ---------- out is first driven here
+/// 6 |     out := 1;
+///   |     ^^^^^^^^^
+/// ```
+pub fn render_error(error: &BitsyError) -> String {
+    let mut annotations = vec![Annotation { span: error.span(), label: None, primary: true }];
+    for (span, label) in error.labels() {
+        annotations.push(Annotation { span, label: Some(label), primary: false });
+    }
+    render(format!("error[{}]: {error}\n", error.code()), annotations)
+}
+
+/// Render a warning for the terminal, the same way as [`render_error`].
+///
+/// ```text
+/// warning: Hole ?x has type Word[8]
+///  --> top.bitsy:4:12
+///   |
+/// 4 |     out := ?x;
+///   |            ^^
+/// ```
+pub fn render_warning(span: &Span, message: &str) -> String {
+    let annotations = vec![Annotation { span: span.clone(), label: None, primary: true }];
+    render(format!("warning: {message}\n"), annotations)
+}
+
+/// Render the `heading`, followed by the source lines of the `annotations`.
+fn render(heading: String, mut annotations: Vec<Annotation>) -> String {
+    let mut out = heading;
+    annotations.retain(|annotation| annotation.span.source_info().text().is_some());
+    if annotations.is_empty() {
+        return out;
+    }
+
+    let width = annotations.iter().map(|annotation| annotation.span.start().line().to_string().len()).max().unwrap_or(1);
+    let pad = " ".repeat(width);
+
+    // Annotations are shown grouped by the file they're in, starting with the one the error is in.
+    let mut groups: Vec<(String, Vec<&Annotation>)> = vec![];
+    for annotation in &annotations {
+        let name = annotation.span.source_info().name();
+        match groups.iter_mut().find(|(group_name, _annotations)| *group_name == name) {
+            Some((_name, group)) => group.push(annotation),
+            None => groups.push((name, vec![annotation])),
+        }
+    }
+
+    for (i, (name, group)) in groups.iter().enumerate() {
+        let arrow = if i == 0 { "-->" } else { ":::" };
+        out.push_str(&format!("{pad}{arrow} {name}:{}\n", group[0].span.start()));
+        out.push_str(&format!("{pad} |\n"));
+
+        let mut lines: BTreeMap<usize, Vec<&Annotation>> = BTreeMap::new();
+        for annotation in group {
+            lines.entry(annotation.span.start().line()).or_default().push(annotation);
+        }
+
+        let text = group[0].span.source_info().text().unwrap_or_default();
+        let mut last_line = None;
+        for (line, line_annotations) in lines {
+            if last_line.is_some_and(|last_line| line > last_line + 1) {
+                out.push_str("...\n");
+            }
+            last_line = Some(line);
+
+            let line_text = text.lines().nth(line - 1).unwrap_or_default();
+            out.push_str(&format!("{line:>width$} | {line_text}\n"));
+            for annotation in line_annotations {
+                let start = annotation.span.start();
+                let end = annotation.span.end();
+                // A span over several lines is underlined to the end of its first line.
+                let len = if end.line() == start.line() {
+                    end.col().saturating_sub(start.col())
+                } else {
+                    line_text.len().saturating_sub(start.col() - 1)
+                };
+                let marker = if annotation.primary { "^" } else { "-" };
+                let underline = format!("{}{}", " ".repeat(start.col() - 1), marker.repeat(len.max(1)));
+                match &annotation.label {
+                    Some(label) => out.push_str(&format!("{pad} | {underline} {label}\n")),
+                    None => out.push_str(&format!("{pad} | {underline}\n")),
+                }
+            }
+        }
+    }
+    out
+}
+
+/// A span of source to underline in a rendered error.
+struct Annotation {
+    span: Span,
+    label: Option<String>,
+    /// Whether this is where the error is, rather than a place which helps explain it.
+    primary: bool,
+}
+
+/// The long-form description of an error code. Eg, `explain("E0004")`. See [`BitsyError::code`].
+pub fn explain(code: &str) -> Option<&'static str> {
+    let explanation = match code {
+        "E0000" => "\
+An error which doesn't have a more specific code.
+
+The message describes what went wrong.",
+        "E0001" => "\
+The source could not be parsed.
+
+The message says what the parser found, and what it expected to find instead.
+After a syntax error, the parser skips ahead to the end of the declaration or item,
+so several independent errors may be reported at once.",
+        "E0002" => "\
+An `ext mod` declared something other than a port.
+
+An `ext mod` is implemented outside of Bitsy, so it may only declare
+its `incoming` and `outgoing` ports:
+
+    ext mod Uart {
+        incoming data of Word[8];
+        outgoing ready of Word[1];
+    }",
+        "E0003" => "\
+Two components in the same `mod` have the same name.
+
+Every port, node, register, memory, and submodule in a `mod` needs its own name:
+
+    mod Top {
+        incoming x of Word[8];
+        node x of Word[8];      // error: x is already declared
+    }",
+        "E0004" => "\
+A component is driven more than once.
+
+Each port, node, and register has exactly one driver.
+If you want to pick between several values, use an `if` or a `match` expression:
+
+    out := in;
+    out := 0;                       // error: out is already driven
+
+    out := if sel { in } else { 0 }; // ok
+
+Registers may also be assigned with `<=!` inside of `when` blocks, any number of times,
+but then they can't be driven with `<=` as well.",
+        "E0005" => "\
+A component is never driven.
+
+Every `outgoing` port, `node`, and `reg` of a `mod` needs a driver.
+Ports use `:=`, and registers use `<=`:
+
+    mod Top {
+        outgoing out of Word[8];
+        reg r of Word[8];
+        r <= r + 1;
+        out := r;
+    }",
+        "E0006" => "\
+An `incoming` port of a submodule is never driven.
+
+When you instantiate a `mod`, the parent has to drive each of its incoming ports:
+
+    mod Top {
+        mod adder of Adder;
+        adder.a := 1w8;
+        adder.b := 2w8;
+    }
+
+The same goes for the clock domains (`dom`) declared by the submodule.",
+        "E0007" => "\
+A component driven in parts doesn't have every part driven.
+
+When a component is driven through indexes or fields, such as `out[0] := ...`,
+each bit, element, or field has to be driven exactly once.
+The message lists the parts which aren't.",
+        "E0008" => "\
+A component is driven with the wrong kind of wire.
+
+Ports and nodes are driven with `:=`, registers with `<=`, and clock domains with `$=`:
+
+    out := in;
+    r <= r + 1;
+    clk $= io.clk;
+
+Inside of a `when` block, only the registers of the `mod` itself may be assigned, using `<=!`.",
+        "E0009" => "\
+An `incoming` port is driven from inside of its own `mod`.
+
+Incoming ports are driven by whoever instantiates the `mod`.
+Inside, they can only be read from.",
+        "E0010" => "\
+Something other than a clock domain was used as one.
+
+Registers are clocked by a `dom`, and domains can only be driven by other domains:
+
+    mod Top {
+        dom clk;
+        reg r of Word[8] on clk;
+    }",
+        "E0011" => "\
+A wire refers to a component which doesn't exist.
+
+Check the spelling of the name, and that the submodule, field, or index exists.",
+        "E0012" => "\
+A parametric definition was given the wrong parameters.
+
+Each parameter must be given, in order, and must be of the right kind.
+A parameter declared `of Nat` takes a number, and one declared `of Type` takes a type:
+
+    mod Fifo[T of Type, depth of Nat] { ... }
+
+    mod queue of Fifo[Word[8], 4];",
+        "E0013" => "\
+An imported package couldn't be found.
+
+`import lib` looks for `lib.bitsy` next to the file doing the importing,
+and then in each directory added to the search path with `-I`:
+
+    bitsy -I path/to/libs top.bitsy",
+        "E0014" => "\
+Packages import each other in a cycle.
+
+The message shows the chain of imports which leads back to where it started.
+Move whatever the packages share into a package of its own, which both can import.",
+        "E0015" => "\
+An item was used from another package, but it isn't `pub`.
+
+Items are private to the package they're defined in unless they're marked `pub`:
+
+    pub mod Adder { ... }
+    pub struct type Pixel { ... }",
+        "E0016" => "\
+A package file couldn't be read.
+
+The message gives the path and the reason from the operating system.
+Check that the file exists and that you have permission to read it.",
+        "E0017" => "\
+The width of a `Word` or `SInt`, or the length of a `Vec`, is invalid.
+
+Widths may be computed from constants and `Nat` parameters, but they can't be negative,
+and they must fit in 64 bits:
+
+    const XLEN of Nat = 32;
+    incoming in of Word[XLEN-1];    // ok
+    incoming out of Word[2-4];      // error: the width is negative
+    incoming big of Word[4294967296*4294967296]; // error: the width is too large",
+        "E0018" => "\
+A `port` was declared with something other than a `shape`.
+
+An interface port takes its ports from a `shape`, optionally flipped:
+
+    shape Channel {
+        outgoing valid of Word[1];
+        incoming ready of Word[1];
+    }
+
+    port enq of flip Channel;",
+        "E0019" => "\
+Something other than an interface port was used as one.
+
+Only ports declared with `port`, on the `mod` itself or on one of its submodules,
+can be connected with `<>`:
+
+    enq <> queue.enq;",
+        "E0020" => "\
+The two ends of a `<>` connection don't match.
+
+Both ends need the same ports, and each port must be outgoing from exactly one of them.
+Usually, one end is declared with the shape, and the other with its `flip`.",
+        "E0021" => "\
+A `mem` is declared wrong.
+
+A `mem` has a `Vec` type, and each of its ports needs its own name.
+The write mask of a port splits each word into equal slices,
+so its width must divide the width of the word, and every masked port must use the same width:
+
+    mem m of Vec[Word[32], 256] {
+        read r;
+        write w mask 4;
+    }",
+        "E0022" => "\
+A `poke` or `expect` names a DUT which the testbench doesn't declare.
+
+Each DUT is declared with `mod` at the top of the `tb` before it is used:
+
+    tb Counts {
+        mod dut of Counter;
+        poke dut.inc = 1;
+    }",
+        "E0023" => "\
+A `poke` or `expect` names a port which the DUT doesn't have.
+
+Check the spelling of the port, and that it is declared in the `mod` being tested.",
+        "E0024" => "\
+A `poke` or `expect` names a component which doesn't carry a value.
+
+Ports, nodes, and registers carry values. Submodules, domains, and memories don't.",
+        "E0025" => "\
+A `poke` sets something other than an incoming port.
+
+A testbench drives the incoming ports of a DUT, and its outgoing ports are driven by the DUT itself.
+Use `expect` to check the value of an outgoing port.",
+        "E0026" => "\
+A testbench declares a DUT of a `mod` which doesn't exist.
+
+Check the spelling of the name, and that the `mod` is defined or imported.",
+        "E0027" => "\
+An `expect` didn't hold when the testbench ran.
+
+The message shows the value which was expected and the value the port actually had.",
+        "E0028" => "\
+An `assert` failed while the testbench ran.
+
+The message gives the path of the instance the `assert` belongs to and the cycle on which it failed.",
+        "E0029" => "\
+An `ext mod` contains a connection, a `when`, or an `assert` or `cover`.
+
+An `ext mod` is implemented outside of Bitsy, so it has no logic to connect or check.
+Put the connections and checks in a `mod` which instantiates it instead:
+
+    ext mod Uart {
+        incoming data of Word[8];
+        assert data != 0;           // error: not allowed in an ext mod
+    }",
+        "E0030" => "\
+Items in a package depend on each other in a cycle.
+
+The message shows the chain of definitions which leads back to where it started.
+A `const` can't be defined in terms of itself, and a `mod` can't contain an instance of itself:
+
+    const A of Nat = B;
+    const B of Nat = A;             // error: A -> B -> A",
+        "E0031" => "\
+A `const` has a value which can't be used.
+
+The value of a `const` is computed when the package is loaded, so it must be fully known.
+It can't be X, or contain an X, which is what a hole evaluates to:
+
+    const RESET of Word[32] = ?pc;  // error: the value is X",
+        "E0100" => "\
+An expression refers to something which isn't defined.
+
+Check the spelling, and that the name is in scope here.
+Within a `mod`, references are to its ports, nodes, registers, and the ports of its submodules.",
+        "E0101" => "\
+An expression doesn't have the type it is expected to have.
+
+The message shows both types. Bitsy doesn't convert between types implicitly.
+For example, a `Word[4]` can't be used where a `Word[8]` is expected.",
+        "E0102" => "\
+A word literal doesn't fit in its width.
+
+The value of a literal such as `300w8` needs more bits than it was given.
+Either give it a larger width or use a smaller value.",
+        "E0103" => "\
+The type of an expression couldn't be worked out.
+
+This usually happens with a literal whose width isn't written, such as `0`,
+in a place where nothing else determines its width.
+Write the width out, as in `0w8`, or give the node a type.",
+        "E0104" => "\
+An expression is used in a way its type doesn't allow.
+
+The message explains what is wrong.",
+        _ => return None,
+    };
+    Some(explanation)
+}
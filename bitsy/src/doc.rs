@@ -0,0 +1,508 @@
+use super::*;
+
+use std::sync::Arc;
+
+/// The format of the pages written by [`doc_package`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocFormat {
+    Html,
+    Markdown,
+}
+
+impl DocFormat {
+    /// The file extension of a page.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            DocFormat::Html => "html",
+            DocFormat::Markdown => "md",
+        }
+    }
+}
+
+/// Render the documentation page of a package.
+///
+/// Items from imported packages link to the pages of those packages,
+/// which are expected to sit alongside this one, named after the import.
+pub fn doc_package(package: &Package, name: &str, format: DocFormat) -> String {
+    let mut page = Page {
+        package,
+        format,
+        out: String::new(),
+    };
+    page.package(name);
+    page.out
+}
+
+/// The declaration of an item, as it would be written in the source. Eg, `pub mod Top`.
+pub fn item_signature(item: &Item) -> String {
+    let vis = match item.visibility() {
+        Visibility::Pub => "pub ",
+        Visibility::Private => "",
+    };
+    match item {
+        Item::ModDef(_visibility, component) => {
+            format!("{vis}mod {}{}", component.name(), type_args_str(&component.type_args()))
+        },
+        Item::ExtDef(_visibility, component) => format!("{vis}ext mod {}", component.name()),
+        Item::EnumTypeDef(typedef) => {
+            let onehot = if typedef.onehot { "onehot " } else { "" };
+            format!("{vis}{onehot}enum type {} of Word[{}]", typedef.name, typedef.bitwidth())
+        },
+        Item::StructTypeDef(typedef) => format!("{vis}struct type {}", typedef.name),
+        Item::AltTypeDef(typedef) => format!("{vis}alt type {}{}", typedef.name, type_args_str(&typedef.type_args)),
+        Item::FnDef(fndef) => {
+            let args: Vec<String> = fndef.args.iter().map(|(name, typ)| format!("{name} of {}", type_str(typ))).collect();
+            format!(
+                "{vis}fn {}{}({}) -> {}",
+                fndef.name,
+                type_args_str(&fndef.type_args),
+                args.join(", "),
+                type_str(&fndef.ret),
+            )
+        },
+        Item::TbDef(tbdef) => format!("tb {}", tbdef.name),
+        Item::ConstDef(constdef) => match &constdef.value {
+            ConstValue::Nat(n) => format!("{vis}const {} of Nat = {n}", constdef.name),
+            ConstValue::Value(typ, value) => format!("{vis}const {} of {} = {value}", constdef.name, type_str(typ)),
+        },
+        Item::ShapeDef(shapedef) => format!("{vis}shape {}", shapedef.name),
+    }
+}
+
+/// The declaration of a component, as it would be written in the source. Eg, `incoming in of Word[8]`.
+pub fn component_signature(component: &Component) -> String {
+    match component {
+        Component::Mod(_loc, _doc, name, _type_args, _children, _wires, _whens, _assertions, _interfaces) => format!("mod {name}"),
+        Component::ModInst(_loc, name, moddef, params) => format!("mod {name} of {}{}", moddef.name(), type_params_str(params)),
+        Component::ModInstVec(_loc, name, insts) => match insts.first().map(|inst| &**inst) {
+            Some(Component::ModInst(_loc, _name, moddef, params)) => {
+                format!("mod {name}[{}] of {}{}", insts.len(), moddef.name(), type_params_str(params))
+            },
+            _ => format!("mod {name}[{}]", insts.len()),
+        },
+        Component::Ext(_loc, _doc, name, _children) => format!("ext mod {name}"),
+        Component::Dom(_loc, name) => format!("dom {name}"),
+        Component::Incoming(_loc, _doc, name, typ) => format!("incoming {name} of {}", type_str(typ)),
+        Component::Outgoing(_loc, _doc, name, typ) => format!("outgoing {name} of {}", type_str(typ)),
+        Component::Node(_loc, name, typ) => format!("node {name} of {}", type_str(typ)),
+        Component::Reg(_loc, _doc, name, typ, dom, _reset) => match dom {
+            Some(dom) => format!("reg {name} of {} on {dom}", type_str(typ)),
+            None => format!("reg {name} of {}", type_str(typ)),
+        },
+        Component::Mem(_loc, name, typ, dom, latency, _ports) => match dom {
+            Some(dom) => format!("mem {name} of {} on {dom} latency {latency}", type_str(typ)),
+            None => format!("mem {name} of {} latency {latency}", type_str(typ)),
+        },
+    }
+}
+
+fn type_str(typ: &Type) -> String {
+    type_text(typ, &mut |_typ, name| name.to_string())
+}
+
+/// Write out a type, using `name` for each user-defined type it mentions.
+fn type_text(typ: &Type, name: &mut dyn FnMut(&Type, &str) -> String) -> String {
+    match typ {
+        Type::Word(n) => format!("Word[{n}]"),
+        Type::SInt(n) => format!("SInt[{n}]"),
+        Type::Vec(elem_typ, n) => format!("Vec[{}, {n}]", type_text(elem_typ, name)),
+        Type::Valid(inner_typ) => format!("Valid[{}]", type_text(inner_typ, name)),
+        Type::Enum(typedef) => name(typ, &typedef.name),
+        Type::Struct(typedef) => name(typ, &typedef.name),
+        Type::Alt(typedef, params) => {
+            let mut result = name(typ, &typedef.name);
+            if !params.is_empty() {
+                let params: Vec<String> = params.iter().map(|param| match param {
+                    TypeParam::Nat(n) => n.to_string(),
+                    TypeParam::Type(param_typ) => type_text(param_typ, name),
+                }).collect();
+                result.push_str(&format!("[{}]", params.join(", ")));
+            }
+            result
+        },
+        Type::Var(var) => var.to_string(),
+        Type::GenericWord(n) => format!("Word[{n}]"),
+        Type::GenericSInt(n) => format!("SInt[{n}]"),
+        Type::GenericVec(elem_typ, n) => format!("Vec[{}, {n}]", type_text(elem_typ, name)),
+    }
+}
+
+fn type_args_str(type_args: &[(Name, Kind)]) -> String {
+    if type_args.is_empty() {
+        return String::new();
+    }
+    let type_args: Vec<String> = type_args.iter().map(|(name, kind)| match kind {
+        Kind::Nat => format!("{name} of Nat"),
+        Kind::Type => format!("{name} of Type"),
+    }).collect();
+    format!("[{}]", type_args.join(", "))
+}
+
+fn type_params_str(params: &[TypeParam]) -> String {
+    if params.is_empty() {
+        return String::new();
+    }
+    let params: Vec<String> = params.iter().map(|param| match param {
+        TypeParam::Nat(n) => n.to_string(),
+        TypeParam::Type(typ) => type_str(typ),
+    }).collect();
+    format!("[{}]", params.join(", "))
+}
+
+/// The sections of a page, in order.
+const SECTIONS: [&str; 6] = ["Modules", "Types", "Functions", "Constants", "Shapes", "Testbenches"];
+
+fn section(item: &Item) -> &'static str {
+    match item {
+        Item::ModDef(_visibility, _component) => "Modules",
+        Item::ExtDef(_visibility, _component) => "Modules",
+        Item::EnumTypeDef(_typedef) => "Types",
+        Item::StructTypeDef(_typedef) => "Types",
+        Item::AltTypeDef(_typedef) => "Types",
+        Item::FnDef(_fndef) => "Functions",
+        Item::ConstDef(_constdef) => "Constants",
+        Item::ShapeDef(_shapedef) => "Shapes",
+        Item::TbDef(_tbdef) => "Testbenches",
+    }
+}
+
+/// A page of documentation, written out in either HTML or Markdown.
+///
+/// The methods which return a `String` render inline text, ready to be placed in a table or a list.
+struct Page<'a> {
+    package: &'a Package,
+    format: DocFormat,
+    out: String,
+}
+
+impl<'a> Page<'a> {
+    fn package(&mut self, name: &str) {
+        if self.format == DocFormat::Html {
+            self.out.push_str(&format!("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body>\n", self.escape(name)));
+        }
+        self.heading(1, name, &format!("Package {name}"));
+
+        if !self.package.imports().is_empty() {
+            let imports: Vec<String> = self.package.imports().keys().map(|import| {
+                self.link(&self.code(import), &format!("{import}.{}", self.format.extension()))
+            }).collect();
+            self.paragraph(&format!("Imports: {}", imports.join(", ")));
+        }
+
+        let items = self.package.items();
+        let mut contents = vec![];
+        for title in SECTIONS {
+            let section_items: Vec<&Item> = items.iter().filter(|item| section(item) == title).collect();
+            if section_items.is_empty() {
+                continue;
+            }
+            contents.push((0, self.link(&self.escape(title), &format!("#{}", title.to_lowercase()))));
+            for item in section_items {
+                contents.push((1, self.link(&self.code(item.name()), &format!("#{}", item.name()))));
+            }
+        }
+        self.list(&contents);
+
+        for title in SECTIONS {
+            let section_items: Vec<&Item> = items.iter().filter(|item| section(item) == title).collect();
+            if section_items.is_empty() {
+                continue;
+            }
+            self.heading(2, &title.to_lowercase(), title);
+            for item in section_items {
+                self.item(item);
+            }
+        }
+
+        if self.format == DocFormat::Html {
+            self.out.push_str("</body>\n</html>\n");
+        }
+    }
+
+    fn item(&mut self, item: &Item) {
+        self.heading(3, item.name(), item.name());
+        self.code_block(&item_signature(item));
+        self.doc(item.doc());
+
+        match item {
+            Item::ModDef(_visibility, component) => self.component(component),
+            Item::ExtDef(_visibility, component) => self.component(component),
+            Item::EnumTypeDef(typedef) => {
+                let width = typedef.bitwidth();
+                let rows = typedef.values.iter().map(|(name, value)| {
+                    vec![self.code(name), self.code(&format!("{}w{width}", value.value()))]
+                }).collect();
+                self.table(&["Value", "Encoding"], rows);
+            },
+            Item::StructTypeDef(typedef) => {
+                // The first field is packed into the highest bits.
+                let mut offset = typedef.bitwidth();
+                let mut rows = vec![];
+                for (name, typ) in &typedef.fields {
+                    let width = typ.bitwidth();
+                    offset -= width;
+                    rows.push(vec![self.code(name), self.typ(typ), offset.to_string(), width.to_string()]);
+                }
+                self.table(&["Field", "Type", "Offset", "Width"], rows);
+            },
+            Item::AltTypeDef(typedef) => {
+                let rows = typedef.alts.iter().enumerate().map(|(tag, (name, typs))| {
+                    let typs: Vec<String> = typs.iter().map(|typ| self.typ(typ)).collect();
+                    vec![self.code(name), typs.join(", "), tag.to_string()]
+                }).collect();
+                self.table(&["Alternative", "Fields", "Tag"], rows);
+            },
+            Item::ShapeDef(shapedef) => {
+                let rows = shapedef.ports.iter().map(|(name, dir, typ)| {
+                    let dir = match dir {
+                        Direction::Incoming => "incoming",
+                        Direction::Outgoing => "outgoing",
+                    };
+                    vec![self.code(name), dir.to_string(), self.typ(typ)]
+                }).collect();
+                self.table(&["Port", "Direction", "Type"], rows);
+            },
+            Item::FnDef(_fndef) => (),
+            Item::TbDef(_tbdef) => (),
+            Item::ConstDef(_constdef) => (),
+        }
+    }
+
+    /// The ports, regs, and instance hierarchy of a `mod` or `ext`.
+    fn component(&mut self, component: &Arc<Component>) {
+        let mut ports = vec![];
+        let mut regs = vec![];
+        for child in component.children() {
+            match &*child {
+                Component::Incoming(_loc, doc, name, typ) => {
+                    ports.push(vec![self.code(name), "incoming".to_string(), self.typ(typ), self.summary(doc.as_deref())]);
+                },
+                Component::Outgoing(_loc, doc, name, typ) => {
+                    ports.push(vec![self.code(name), "outgoing".to_string(), self.typ(typ), self.summary(doc.as_deref())]);
+                },
+                Component::Reg(_loc, doc, name, typ, dom, _reset) => {
+                    let dom = dom.as_ref().map(|dom| self.code(dom)).unwrap_or_default();
+                    regs.push(vec![self.code(name), self.typ(typ), dom, self.summary(doc.as_deref())]);
+                },
+                _ => (),
+            }
+        }
+
+        if !ports.is_empty() {
+            self.heading(4, &format!("{}.ports", component.name()), "Ports");
+            self.table(&["Port", "Direction", "Type", "Description"], ports);
+        }
+        if !regs.is_empty() {
+            self.heading(4, &format!("{}.regs", component.name()), "Registers");
+            self.table(&["Register", "Type", "Domain", "Description"], regs);
+        }
+
+        let mut instances = vec![];
+        self.instances(component, 0, &mut instances);
+        if !instances.is_empty() {
+            self.heading(4, &format!("{}.instances", component.name()), "Instances");
+            self.list(&instances);
+        }
+    }
+
+    /// The submodules and memories of a `mod`, and theirs in turn.
+    fn instances(&self, component: &Component, depth: usize, results: &mut Vec<(usize, String)>) {
+        for child in component.children() {
+            match &*child {
+                Component::ModInst(_loc, name, moddef, _params) => {
+                    results.push((depth, format!("{}: {}", self.code(name), self.moddef_link(moddef))));
+                    self.instances(moddef, depth + 1, results);
+                },
+                Component::ModInstVec(_loc, name, insts) => {
+                    let name = self.code(&format!("{name}[{}]", insts.len()));
+                    match insts.first().map(|inst| &**inst) {
+                        Some(Component::ModInst(_loc, _name, moddef, _params)) => {
+                            results.push((depth, format!("{name}: {}", self.moddef_link(moddef))));
+                            self.instances(moddef, depth + 1, results);
+                        },
+                        _ => results.push((depth, name)),
+                    }
+                },
+                Component::Mod(_loc, _doc, name, _type_args, _children, _wires, _whens, _assertions, _interfaces) => {
+                    results.push((depth, self.code(name)));
+                    self.instances(&child, depth + 1, results);
+                },
+                Component::Mem(_loc, name, typ, _dom, _latency, _ports) => {
+                    results.push((depth, format!("{}: {}", self.code(name), self.code(&format!("mem of {}", type_str(typ))))));
+                },
+                _ => (),
+            }
+        }
+    }
+
+    fn moddef_link(&self, moddef: &Arc<Component>) -> String {
+        let code = self.code(moddef.name());
+        match self.href(moddef.name(), |item| item.as_component().is_some_and(|component| Arc::ptr_eq(&component, moddef))) {
+            Some(href) => self.link(&code, &href),
+            None => code,
+        }
+    }
+
+    fn typ(&self, typ: &Type) -> String {
+        type_text(typ, &mut |typ, name| {
+            let href = self.href(name, |item| match (item, typ) {
+                (Item::EnumTypeDef(typedef), Type::Enum(other)) => Arc::ptr_eq(typedef, other),
+                (Item::StructTypeDef(typedef), Type::Struct(other)) => Arc::ptr_eq(typedef, other),
+                (Item::AltTypeDef(typedef), Type::Alt(other, _params)) => Arc::ptr_eq(typedef, other),
+                _ => false,
+            });
+            match href {
+                Some(href) => self.link(&self.escape(name), &href),
+                None => self.escape(name),
+            }
+        })
+    }
+
+    /// Where an item is documented, whether it's in this package or in one it imports.
+    fn href(&self, name: &str, is_item: impl Fn(&Item) -> bool) -> Option<String> {
+        if self.package.items().iter().any(|item| item.name() == name && is_item(item)) {
+            return Some(format!("#{name}"));
+        }
+        for (import_name, import) in self.package.imports() {
+            if import.item(name).is_some_and(|item| is_item(&item)) {
+                return Some(format!("{import_name}.{}#{name}", self.format.extension()));
+            }
+        }
+        None
+    }
+
+    fn heading(&mut self, level: usize, anchor: &str, text: &str) {
+        match self.format {
+            DocFormat::Html => {
+                self.out.push_str(&format!("<h{level} id=\"{}\">{}</h{level}>\n", self.escape(anchor), self.escape(text)));
+            },
+            DocFormat::Markdown => {
+                self.out.push_str(&format!("<a id=\"{anchor}\"></a>\n\n{} {}\n\n", "#".repeat(level), self.escape(text)));
+            },
+        }
+    }
+
+    fn paragraph(&mut self, text: &str) {
+        match self.format {
+            DocFormat::Html => self.out.push_str(&format!("<p>{text}</p>\n")),
+            DocFormat::Markdown => self.out.push_str(&format!("{text}\n\n")),
+        }
+    }
+
+    fn code_block(&mut self, code: &str) {
+        match self.format {
+            DocFormat::Html => self.out.push_str(&format!("<pre><code>{}</code></pre>\n", self.escape(code))),
+            DocFormat::Markdown => self.out.push_str(&format!("```bitsy\n{code}\n```\n\n")),
+        }
+    }
+
+    /// A doc comment. Markdown pages keep its formatting.
+    fn doc(&mut self, doc: Option<&str>) {
+        let Some(doc) = doc else { return };
+        match self.format {
+            DocFormat::Html => {
+                for paragraph in doc.split("\n\n").filter(|paragraph| !paragraph.trim().is_empty()) {
+                    self.paragraph(&self.escape(paragraph));
+                }
+            },
+            DocFormat::Markdown => self.paragraph(doc),
+        }
+    }
+
+    /// A doc comment, put on one line so it fits into a table.
+    fn summary(&self, doc: Option<&str>) -> String {
+        let doc = doc.unwrap_or_default().lines().map(str::trim).collect::<Vec<_>>().join(" ");
+        match self.format {
+            DocFormat::Html => self.escape(&doc),
+            DocFormat::Markdown => doc.replace('|', "\\|"),
+        }
+    }
+
+    fn table(&mut self, headers: &[&str], rows: Vec<Vec<String>>) {
+        match self.format {
+            DocFormat::Html => {
+                self.out.push_str("<table>\n<tr>");
+                for header in headers {
+                    self.out.push_str(&format!("<th>{header}</th>"));
+                }
+                self.out.push_str("</tr>\n");
+                for row in rows {
+                    self.out.push_str("<tr>");
+                    for cell in row {
+                        self.out.push_str(&format!("<td>{cell}</td>"));
+                    }
+                    self.out.push_str("</tr>\n");
+                }
+                self.out.push_str("</table>\n");
+            },
+            DocFormat::Markdown => {
+                self.out.push_str(&format!("| {} |\n", headers.join(" | ")));
+                self.out.push_str(&format!("|{}\n", " --- |".repeat(headers.len())));
+                for row in rows {
+                    self.out.push_str(&format!("| {} |\n", row.join(" | ")));
+                }
+                self.out.push('\n');
+            },
+        }
+    }
+
+    /// A nested list. Each entry is given with its depth.
+    fn list(&mut self, entries: &[(usize, String)]) {
+        match self.format {
+            DocFormat::Html => {
+                let mut depth = 0;
+                self.out.push_str("<ul>\n");
+                for (i, (entry_depth, entry)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        if *entry_depth > depth {
+                            self.out.push_str("\n<ul>\n");
+                        } else {
+                            self.out.push_str("</li>\n");
+                        }
+                    }
+                    while depth > *entry_depth {
+                        self.out.push_str("</ul>\n</li>\n");
+                        depth -= 1;
+                    }
+                    depth = *entry_depth;
+                    self.out.push_str(&format!("<li>{entry}"));
+                }
+                if !entries.is_empty() {
+                    self.out.push_str("</li>\n");
+                }
+                while depth > 0 {
+                    self.out.push_str("</ul>\n</li>\n");
+                    depth -= 1;
+                }
+                self.out.push_str("</ul>\n");
+            },
+            DocFormat::Markdown => {
+                for (depth, entry) in entries {
+                    self.out.push_str(&format!("{}- {entry}\n", "  ".repeat(*depth)));
+                }
+                self.out.push('\n');
+            },
+        }
+    }
+
+    fn code(&self, text: &str) -> String {
+        match self.format {
+            DocFormat::Html => format!("<code>{}</code>", self.escape(text)),
+            DocFormat::Markdown => format!("`{text}`"),
+        }
+    }
+
+    fn link(&self, text: &str, href: &str) -> String {
+        match self.format {
+            DocFormat::Html => format!("<a href=\"{}\">{text}</a>", self.escape(href)),
+            DocFormat::Markdown => format!("[{text}]({href})"),
+        }
+    }
+
+    fn escape(&self, text: &str) -> String {
+        match self.format {
+            DocFormat::Html => text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;"),
+            DocFormat::Markdown => text.to_string(),
+        }
+    }
+}
@@ -1,17 +1,60 @@
 use super::*;
+use crate::sim::Value;
 
 use std::sync::Arc;
 
 #[derive(Debug, Clone)]
 pub enum BitsyError {
     ExtHasNonPort(Span, Name),
-    DuplicateComponent(Arc<Component>),
-    MultipleDrivers(Span, Name),
+    /// A connection, `when`, `assert` or `cover` in an `ext mod`, along with which it is.
+    ExtHasStatement(Span, String),
+    /// A component declared twice, along with the first declaration.
+    DuplicateComponent(Arc<Component>, Arc<Component>),
+    /// A component driven twice, along with where it was first driven.
+    MultipleDrivers(Span, Name, Span),
     NoDrivers(Arc<Component>),
-    NoDriversPort(Arc<Component>, Arc<Component>),
+    /// A port of an instance which isn't driven, along with its path and its declaration. Eg, `cells.0.in`.
+    NoDriversPort(Arc<Component>, Path, Arc<Component>),
+    NotFullyDriven(Span, String),
     WrongWireType(Span, Name, WireType),
     IncomingPortDriven(Span, Name),
+    NotADomain(Span, String),
     NoSuchComponent(Span, String),
+    WrongParams(Span, Name, String),
+    ImportNotFound(Span, Name),
+    ImportCycle(Span, Vec<Name>),
+    /// Items in a package which depend on each other in a cycle, starting from the one at the span.
+    ItemCycle(Span, Vec<Name>),
+    /// A package file which couldn't be read, along with why.
+    CantReadFile(Span, String, String),
+    /// The width of a `Word` or `SInt` or the length of a `Vec` which is negative or too large.
+    InvalidWidth(Span, String),
+    /// A `port` declared with something other than a `shape`.
+    NotAShape(Span, Name),
+    /// Something other than an interface port used as one. Eg, in a bulk connection.
+    NotAnInterface(Span, String),
+    /// The two ends of a bulk connection which don't have matching ports.
+    InterfaceMismatch(Span, String),
+    /// A `mem` which is declared wrong, along with its name.
+    InvalidMem(Span, Name, String),
+    /// A `const` whose value can't be used, along with its name.
+    InvalidConst(Span, Name, String),
+    /// A `poke` or `expect` of a DUT which the testbench doesn't declare.
+    NoSuchDut(Span, Name),
+    /// A `poke` or `expect` of a port which the DUT doesn't have. Eg, `dut.nope`.
+    NoSuchPort(Span, String),
+    /// A `poke` or `expect` of a component which doesn't carry a value, such as a submodule.
+    NoValue(Span, String),
+    /// A `poke` of something other than an incoming port.
+    NotPokeable(Span, String),
+    /// A testbench `mod` declaration of a `mod` which doesn't exist.
+    UndefinedMod(Span, Name),
+    /// An `expect` which didn't hold when the testbench ran, along with the value expected and the actual value.
+    ExpectFailed(Span, String, Box<Value>, Box<Value>),
+    /// An `assert` which failed while a testbench ran, along with the path of its instance and the cycle.
+    AssertFailed(Span, String, u64),
+    /// An item used from another package which isn't `pub`.
+    NotPublic(Span, Name, Item),
     TypeError(TypeError),
     ParseError(Span, String),
     Unknown(Option<Span>, String),
@@ -44,7 +87,13 @@ impl std::fmt::Display for TypeError {
             TypeError::UndefinedReference(expr) => write!(f, "Undefiend reference: {expr:?}"),
             TypeError::NotExpectedType(type_expected, type_actual, _expr) => write!(f, "Not expected type: has type {type_actual:?} but expected {type_expected:?}."),
             TypeError::InvalidWord(expr) => write!(f, "Invalid literal: {expr:?}"),
-            TypeError::CantInferType(expr) => write!(f, "Can't infer type: {expr:?}"),
+            TypeError::CantInferType(expr) => {
+                if let Expr::Word(_span, _typ, None, n) = &**expr {
+                    write!(f, "Can't infer the width of the literal {n}")
+                } else {
+                    write!(f, "Can't infer type: {expr:?}")
+                }
+            },
             TypeError::Other(_expr, message) => write!(f, "{message}"),
         }
     }
@@ -54,10 +103,12 @@ impl std::fmt::Display for BitsyError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
             BitsyError::ExtHasNonPort(_span, name) => write!(f, "Ext declares a component other than an incoming or outgoing: {name}"),
-            BitsyError::DuplicateComponent(component) => write!(f, "Duplicate component: {}", component.name()),
-            BitsyError::MultipleDrivers(_span, name) => write!(f, "Component has multiple drivers: {name}."),
+            BitsyError::ExtHasStatement(_span, statement) => write!(f, "Ext may not contain {statement}"),
+            BitsyError::DuplicateComponent(component, _first) => write!(f, "Duplicate component: {}", component.name()),
+            BitsyError::MultipleDrivers(_span, name, _first) => write!(f, "Component has multiple drivers: {name}."),
             BitsyError::NoDrivers(component) => write!(f, "Component is not driven: {}", component.name()),
-            BitsyError::NoDriversPort(component, port) => write!(f, "Port is not driven: {}.{}", component.name(), port.name()),
+            BitsyError::NoDriversPort(_component, path, _port) => write!(f, "Port is not driven: {path}"),
+            BitsyError::NotFullyDriven(_span, parts) => write!(f, "Component is not driven in every part: {parts}"),
             BitsyError::WrongWireType(_span, name, wire_type) => {
                 let symbol = match wire_type {
                     WireType::Dom    => "$=",
@@ -68,7 +119,27 @@ impl std::fmt::Display for BitsyError {
                 write!(f, "Wrong wire type: {name} does not support {symbol}")
             },
             BitsyError::IncomingPortDriven(_span, name) => write!(f, "Incoming port is being driven from inside a mod, but shouldn't be: {name}"),
+            BitsyError::NotADomain(_span, s) => write!(f, "Not a clock domain: {s}"),
             BitsyError::NoSuchComponent(_span, s) => write!(f, "No such component: {s}"),
+            BitsyError::WrongParams(_span, name, message) => write!(f, "Wrong parameters for {name}: {message}"),
+            BitsyError::ImportNotFound(_span, name) => write!(f, "Package not found on the search path: {name}"),
+            BitsyError::ImportCycle(_span, cycle) => write!(f, "Import cycle: {}", cycle.join(" -> ")),
+            BitsyError::ItemCycle(_span, cycle) => write!(f, "Definition cycle: {}", cycle.join(" -> ")),
+            BitsyError::CantReadFile(_span, path, error) => write!(f, "Can't read {path}: {error}"),
+            BitsyError::InvalidWidth(_span, message) => write!(f, "Invalid width: {message}"),
+            BitsyError::NotAShape(_span, name) => write!(f, "Not a shape: {name}"),
+            BitsyError::NotAnInterface(_span, name) => write!(f, "Not an interface port: {name}"),
+            BitsyError::InterfaceMismatch(_span, message) => write!(f, "Interfaces don't match: {message}"),
+            BitsyError::InvalidMem(_span, name, message) => write!(f, "Invalid mem {name}: {message}"),
+            BitsyError::InvalidConst(_span, name, message) => write!(f, "Invalid const {name}: {message}"),
+            BitsyError::NoSuchDut(_span, dut) => write!(f, "No such DUT in testbench: {dut}"),
+            BitsyError::NoSuchPort(_span, path) => write!(f, "No such port: {path}"),
+            BitsyError::NoValue(_span, path) => write!(f, "{path} has no value"),
+            BitsyError::NotPokeable(_span, path) => write!(f, "Only incoming ports may be poked: {path}"),
+            BitsyError::UndefinedMod(_span, name) => write!(f, "Undefined reference to mod {name}"),
+            BitsyError::ExpectFailed(_span, path, expected, actual) => write!(f, "Expected {path} to be {expected}, but it was {actual}"),
+            BitsyError::AssertFailed(_span, path, clock_ticks) => write!(f, "Assertion failed in {path} on cycle {clock_ticks}"),
+            BitsyError::NotPublic(_span, name, _item) => write!(f, "{name} is not pub, so it can't be used from another package"),
             BitsyError::TypeError(type_error) => write!(f, "Type Error: {type_error}"),
             BitsyError::ParseError(_span, error) => write!(f, "{error}"),
             BitsyError::Unknown(_span, message) => write!(f, "{message}"),
@@ -80,16 +151,142 @@ impl HasSpan for BitsyError {
     fn span(&self) -> Span {
         match self {
             BitsyError::ExtHasNonPort(span, _name) => span.clone(),
-            BitsyError::DuplicateComponent(component) => component.span(),
-            BitsyError::MultipleDrivers(span, _name) => span.clone(),
+            BitsyError::ExtHasStatement(span, _statement) => span.clone(),
+            BitsyError::DuplicateComponent(component, _first) => component.span(),
+            BitsyError::MultipleDrivers(span, _name, _first) => span.clone(),
             BitsyError::NoDrivers(component) => component.span(),
-            BitsyError::NoDriversPort(component, _port) => component.span(),
+            BitsyError::NoDriversPort(component, _path, _port) => component.span(),
+            BitsyError::NotFullyDriven(span, _parts) => span.clone(),
             BitsyError::WrongWireType(span, _name, _wire_type) => span.clone(),
             BitsyError::IncomingPortDriven(span, _name) => span.clone(),
+            BitsyError::NotADomain(span, _name) => span.clone(),
             BitsyError::NoSuchComponent(span, _name) => span.clone(),
+            BitsyError::WrongParams(span, _name, _message) => span.clone(),
+            BitsyError::ImportNotFound(span, _name) => span.clone(),
+            BitsyError::ImportCycle(span, _cycle) => span.clone(),
+            BitsyError::ItemCycle(span, _cycle) => span.clone(),
+            BitsyError::CantReadFile(span, _path, _error) => span.clone(),
+            BitsyError::InvalidWidth(span, _message) => span.clone(),
+            BitsyError::NotAShape(span, _name) => span.clone(),
+            BitsyError::NotAnInterface(span, _name) => span.clone(),
+            BitsyError::InterfaceMismatch(span, _message) => span.clone(),
+            BitsyError::InvalidMem(span, _name, _message) => span.clone(),
+            BitsyError::InvalidConst(span, _name, _message) => span.clone(),
+            BitsyError::NoSuchDut(span, _dut) => span.clone(),
+            BitsyError::NoSuchPort(span, _path) => span.clone(),
+            BitsyError::NoValue(span, _path) => span.clone(),
+            BitsyError::NotPokeable(span, _path) => span.clone(),
+            BitsyError::UndefinedMod(span, _name) => span.clone(),
+            BitsyError::ExpectFailed(span, _path, _expected, _actual) => span.clone(),
+            BitsyError::AssertFailed(span, _path, _clock_ticks) => span.clone(),
+            BitsyError::NotPublic(span, _name, _item) => span.clone(),
             BitsyError::TypeError(type_error) => type_error.span(),
             BitsyError::ParseError(span, _error) => span.clone(),
             BitsyError::Unknown(span, _string) => span.clone().unwrap_or_else(|| Span::unknown()),
         }
     }
 }
+
+impl BitsyError {
+    /// A code which identifies the kind of error. Eg, `E0003`. See [`explain`].
+    pub fn code(&self) -> &'static str {
+        match self {
+            BitsyError::Unknown(_span, _message) => "E0000",
+            BitsyError::ParseError(_span, _error) => "E0001",
+            BitsyError::ExtHasNonPort(_span, _name) => "E0002",
+            BitsyError::DuplicateComponent(_component, _first) => "E0003",
+            BitsyError::MultipleDrivers(_span, _name, _first) => "E0004",
+            BitsyError::NoDrivers(_component) => "E0005",
+            BitsyError::NoDriversPort(_component, _path, _port) => "E0006",
+            BitsyError::NotFullyDriven(_span, _parts) => "E0007",
+            BitsyError::WrongWireType(_span, _name, _wire_type) => "E0008",
+            BitsyError::IncomingPortDriven(_span, _name) => "E0009",
+            BitsyError::NotADomain(_span, _name) => "E0010",
+            BitsyError::NoSuchComponent(_span, _name) => "E0011",
+            BitsyError::WrongParams(_span, _name, _message) => "E0012",
+            BitsyError::ImportNotFound(_span, _name) => "E0013",
+            BitsyError::ImportCycle(_span, _cycle) => "E0014",
+            BitsyError::NotPublic(_span, _name, _item) => "E0015",
+            BitsyError::CantReadFile(_span, _path, _error) => "E0016",
+            BitsyError::InvalidWidth(_span, _message) => "E0017",
+            BitsyError::NotAShape(_span, _name) => "E0018",
+            BitsyError::NotAnInterface(_span, _name) => "E0019",
+            BitsyError::InterfaceMismatch(_span, _message) => "E0020",
+            BitsyError::InvalidMem(_span, _name, _message) => "E0021",
+            BitsyError::NoSuchDut(_span, _dut) => "E0022",
+            BitsyError::NoSuchPort(_span, _path) => "E0023",
+            BitsyError::NoValue(_span, _path) => "E0024",
+            BitsyError::NotPokeable(_span, _path) => "E0025",
+            BitsyError::UndefinedMod(_span, _name) => "E0026",
+            BitsyError::ExpectFailed(_span, _path, _expected, _actual) => "E0027",
+            BitsyError::AssertFailed(_span, _path, _clock_ticks) => "E0028",
+            BitsyError::ExtHasStatement(_span, _statement) => "E0029",
+            BitsyError::ItemCycle(_span, _cycle) => "E0030",
+            BitsyError::InvalidConst(_span, _name, _message) => "E0031",
+            BitsyError::TypeError(type_error) => type_error.code(),
+        }
+    }
+
+    /// Other places in the source which help explain the error, each with a short note.
+    pub fn labels(&self) -> Vec<(Span, String)> {
+        match self {
+            BitsyError::DuplicateComponent(_component, first) => vec![(first.span(), format!("{} is first declared here", first.name()))],
+            BitsyError::MultipleDrivers(_span, name, first) => vec![(first.clone(), format!("{name} is first driven here"))],
+            BitsyError::NoDriversPort(_component, _path, port) => vec![(port.span(), "the port is declared here".to_string())],
+            BitsyError::NotPublic(_span, name, item) => vec![(item.span(), format!("{name} is defined here"))],
+            _ => vec![],
+        }
+    }
+}
+
+impl TypeError {
+    /// A code which identifies the kind of error. See [`BitsyError::code`].
+    pub fn code(&self) -> &'static str {
+        match self {
+            TypeError::UndefinedReference(_e) => "E0100",
+            TypeError::NotExpectedType(_type_expected, _type_actual, _e) => "E0101",
+            TypeError::InvalidWord(_e) => "E0102",
+            TypeError::CantInferType(_e) => "E0103",
+            TypeError::Other(_e, _msg) => "E0104",
+        }
+    }
+}
+
+/// A hole, such as `?foo`, along with what could be used to fill it.
+/// Holes aren't errors, and are reported for information only.
+#[derive(Debug, Clone)]
+pub struct HoleReport {
+    pub span: Span,
+    pub name: Option<String>,
+    /// The type the hole is expected to have, if typechecking got that far.
+    pub typ: Option<Type>,
+    /// The references in scope whose types fit the hole.
+    pub fits: Vec<(Path, Type)>,
+    /// The `fn`s which return a value which fits the hole.
+    pub fndefs: Vec<Arc<FnDef>>,
+}
+
+impl HasSpan for HoleReport {
+    fn span(&self) -> Span {
+        self.span.clone()
+    }
+}
+
+impl std::fmt::Display for HoleReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = self.name.clone().unwrap_or_default();
+        match &self.typ {
+            Some(typ) => write!(f, "Hole ?{name} has type {typ:?}")?,
+            None => write!(f, "Hole ?{name} has an unknown type")?,
+        }
+        if !self.fits.is_empty() {
+            let fits: Vec<String> = self.fits.iter().map(|(path, typ)| format!("{path} : {typ:?}")).collect();
+            write!(f, "\n  in scope: {}", fits.join(", "))?;
+        }
+        if !self.fndefs.is_empty() {
+            let fndefs: Vec<&str> = self.fndefs.iter().map(|fndef| fndef.name.as_str()).collect();
+            write!(f, "\n  fns: {}", fndefs.join(", "))?;
+        }
+        Ok(())
+    }
+}
@@ -41,6 +41,10 @@ pub enum Expr {
     TryCast(Span, OnceCell<Type>, Arc<Expr>),
     /// A word expression. Used to cast user-defined `enum` types to their bit values.
     ToWord(Span, OnceCell<Type>, Arc<Expr>),
+    /// Reinterpret a `Word[n]` as an `SInt[n]`. Eg, `as_signed(x)`.
+    AsSigned(Span, OnceCell<Type>, Arc<Expr>),
+    /// Reinterpret an `SInt[n]` as a `Word[n]`. Eg, `as_unsigned(x)`.
+    AsUnsigned(Span, OnceCell<Type>, Arc<Expr>),
     /// A vector constructor expression. Eg, `[0w2, 1w2, 2w2]`.
     Vec(Span, OnceCell<Type>, Vec<Arc<Expr>>),
     IdxField(Span, OnceCell<Type>, Arc<Expr>, String),
@@ -48,10 +52,15 @@ pub enum Expr {
     Idx(Span, OnceCell<Type>, Arc<Expr>, u64),
     /// A static index range. Eg, `foo[8..4]`.
     IdxRange(Span, OnceCell<Type>, Arc<Expr>, u64, u64),
-    /// A function call. Eg, `foo(x, y)`.
-    Call(Span, OnceCell<Type>, Arc<FnDef>, Vec<Arc<Expr>>),
+    /// A dynamic index. Eg, `foo[i]`.
+    IdxDyn(Span, OnceCell<Type>, Arc<Expr>, Arc<Expr>),
+    /// A function call. Eg, `foo(x, y)` or `foo::[8](x, y)`.
+    /// Holds the type parameters given at the call site,
+    /// and, once typechecked, the instance of the function it calls.
+    Call(Span, OnceCell<Type>, Arc<FnDef>, Vec<TypeParam>, OnceCell<Arc<FnDef>>, Vec<Arc<Expr>>),
     /// A hole. Eg, `?foo`.
-    Hole(Span, OnceCell<Type>, Option<String>),
+    /// Once typechecked, holds the context it was checked in, so it can be reported.
+    Hole(Span, OnceCell<Type>, Option<String>, OnceCell<Context<Path, Type>>),
 }
 
 /// A [`MatchArm`] is a case in a `match` expression.
@@ -78,9 +87,17 @@ pub struct MatchArm(pub Pat, pub Arc<Expr>);
 /// * `@Valid(n)`
 /// * `@Invalid`
 /// * `@Opcode::OP`
+/// * `@0b01w2`
+/// * `{ valid = @1w1, data = d }`
 #[derive(Clone, Debug)]
 pub enum Pat {
     At(String, Vec<Pat>),
+    /// A value of an `enum` type, qualified by the name of the type. Eg, `@State::Idle`.
+    Enum(String, String),
+    /// A `Word` literal. Eg, `@0b01w2`.
+    Word(Option<Width>, u64),
+    /// Destructures a `struct`. Fields which are left out match anything.
+    Struct(Vec<(String, Pat)>),
     Bind(String),
     Otherwise,
 }
@@ -94,6 +111,13 @@ impl Pat {
                     results.extend(pat.bound_vars());
                 }
             },
+            Pat::Enum(_typename, _value) => (),
+            Pat::Word(_width, _value) => (),
+            Pat::Struct(fields) => {
+                for (_field, pat) in fields {
+                    results.extend(pat.bound_vars());
+                }
+            },
             Pat::Bind(x) => results.push(x.clone()),
             Pat::Otherwise => (),
         }
@@ -103,6 +127,27 @@ impl Pat {
     }
 }
 
+impl std::fmt::Display for Pat {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Pat::At(ctor, pats) if pats.is_empty() => write!(f, "@{ctor}"),
+            Pat::At(ctor, pats) => {
+                let pats: Vec<String> = pats.iter().map(|pat| pat.to_string()).collect();
+                write!(f, "@{ctor}({})", pats.join(", "))
+            },
+            Pat::Enum(typename, value) => write!(f, "@{typename}::{value}"),
+            Pat::Word(Some(width), value) => write!(f, "@{value}w{width}"),
+            Pat::Word(None, value) => write!(f, "@{value}"),
+            Pat::Struct(fields) => {
+                let fields: Vec<String> = fields.iter().map(|(field, pat)| format!("{field} = {pat}")).collect();
+                write!(f, "{{ {} }}", fields.join(", "))
+            },
+            Pat::Bind(x) => write!(f, "{x}"),
+            Pat::Otherwise => write!(f, "_"),
+        }
+    }
+}
+
 impl MatchArm {
     fn free_vars(&self) -> Vec<Path> {
         let mut results = vec![];
@@ -140,12 +185,15 @@ impl HasSpan for Expr {
             Expr::Zext(span, _typ, _e) => span.clone(),
             Expr::TryCast(span, _typ, _e) => span.clone(),
             Expr::ToWord(span, _typ, _e) => span.clone(),
+            Expr::AsSigned(span, _typ, _e) => span.clone(),
+            Expr::AsUnsigned(span, _typ, _e) => span.clone(),
             Expr::Vec(span, _typ, _es) => span.clone(),
             Expr::IdxField(span, _typ, _e, _field) => span.clone(),
             Expr::Idx(span, _typ, _e, _i) => span.clone(),
             Expr::IdxRange(span, _typ, _e, _j, _i) => span.clone(),
-            Expr::Call(span, _typ, _fndef, _es) => span.clone(),
-            Expr::Hole(span, _typ, _opt_name) => span.clone(),
+            Expr::IdxDyn(span, _typ, _e, _i) => span.clone(),
+            Expr::Call(span, _typ, _fndef, _type_params, _instance, _es) => span.clone(),
+            Expr::Hole(span, _typ, _opt_name, _ctx) => span.clone(),
         }
     }
 }
@@ -167,13 +215,54 @@ pub enum BinOp {
     Add,
     AddCarry,
     Sub,
-//    SubBorrow,
+    SubBorrow,
+    Mul,
     And,
     Or,
     Xor,
+    /// Shift left.
+    Shl,
+    /// Logical shift right.
+    Shr,
+    /// Arithmetic shift right.
+    Sra,
     Eq,
     Neq,
     Lt,
+    Lte,
+    Gt,
+    Gte,
+    /// Signed less than.
+    Slt,
+    Slte,
+    Sgt,
+    Sgte,
+}
+
+impl BinOp {
+    /// Is this a comparison, resulting in a `Word[1]`?
+    pub fn is_comparison(&self) -> bool {
+        matches!(
+            self,
+            BinOp::Eq | BinOp::Neq | BinOp::Lt | BinOp::Lte | BinOp::Gt | BinOp::Gte | BinOp::Slt | BinOp::Slte | BinOp::Sgt | BinOp::Sgte
+        )
+    }
+
+    pub fn is_shift(&self) -> bool {
+        matches!(self, BinOp::Shl | BinOp::Shr | BinOp::Sra)
+    }
+
+    /// The variant of this operator used on `SInt` operands. Eg, `<` becomes signed less than.
+    pub fn signed(&self) -> BinOp {
+        match self {
+            BinOp::Lt => BinOp::Slt,
+            BinOp::Lte => BinOp::Slte,
+            BinOp::Gt => BinOp::Sgt,
+            BinOp::Gte => BinOp::Sgte,
+            BinOp::Shr => BinOp::Sra,
+            op => *op,
+        }
+    }
 }
 
 impl Expr {
@@ -227,7 +316,7 @@ impl Expr {
             },
             Expr::Match(_loc, _typ, e, arms) => {
                 callback(self);
-                callback(e);
+                e.with_subexprs(callback);
                 for MatchArm(_pat, arm_e) in arms {
                     arm_e.with_subexprs(callback);
                 }
@@ -260,6 +349,14 @@ impl Expr {
                 callback(self);
                 e.with_subexprs(callback);
             },
+            Expr::AsSigned(_loc, _typ, e) => {
+                callback(self);
+                e.with_subexprs(callback);
+            },
+            Expr::AsUnsigned(_loc, _typ, e) => {
+                callback(self);
+                e.with_subexprs(callback);
+            },
             Expr::Vec(_loc, _typ, es) => {
                 callback(self);
                 for e in es {
@@ -278,13 +375,18 @@ impl Expr {
                 callback(self);
                 e.with_subexprs(callback);
             },
-            Expr::Call(_loc, _typ, _fndef, es) => {
+            Expr::IdxDyn(_loc, _typ, e, i) => {
+                callback(self);
+                e.with_subexprs(callback);
+                i.with_subexprs(callback);
+            },
+            Expr::Call(_loc, _typ, _fndef, _type_params, _instance, es) => {
                 callback(self);
                 for e in es {
                     e.with_subexprs(callback);
                 }
             },
-            Expr::Hole(_loc, _typ, _name) => {
+            Expr::Hole(_loc, _typ, _name, _ctx) => {
                 callback(self);
             },
         }
@@ -372,6 +474,8 @@ impl Expr {
             },
             Expr::TryCast(_loc, _typ, e) => e.free_vars(),
             Expr::ToWord(_loc, _typ, e) => e.free_vars(),
+            Expr::AsSigned(_loc, _typ, e) => e.free_vars(),
+            Expr::AsUnsigned(_loc, _typ, e) => e.free_vars(),
             Expr::Vec(_loc, _typ, es) => {
                 let mut result = BTreeSet::new();
                 for e in es {
@@ -384,14 +488,15 @@ impl Expr {
             Expr::IdxField(_loc, _typ, e, _field) => e.free_vars(),
             Expr::Idx(_loc, _typ, e, _i) => e.free_vars(),
             Expr::IdxRange(_loc, _typ, e, _j, _i) => e.free_vars(),
-            Expr::Call(_loc, _typ, _fndef, es) => {
+            Expr::IdxDyn(_loc, _typ, e, i) => e.free_vars().union(&i.free_vars()).cloned().collect(),
+            Expr::Call(_loc, _typ, _fndef, _type_params, _instance, es) => {
                 let mut result = BTreeSet::new();
                 for e in es {
                     result.extend(e.free_vars())
                 }
                 result
             },
-            Expr::Hole(_loc, _typ, _name) => BTreeSet::new(),
+            Expr::Hole(_loc, _typ, _name, _ctx) => BTreeSet::new(),
         }
     }
 
@@ -422,12 +527,15 @@ impl Expr {
             Expr::Zext(_loc, typ, _e) => Some(typ),
             Expr::TryCast(_loc, typ, _e) => Some(typ),
             Expr::ToWord(_loc, typ, _e) => Some(typ),
+            Expr::AsSigned(_loc, typ, _e) => Some(typ),
+            Expr::AsUnsigned(_loc, typ, _e) => Some(typ),
             Expr::Vec(_loc, typ, _es) => Some(typ),
             Expr::Idx(_loc, typ, _e, _i) => Some(typ),
             Expr::IdxField(_loc, typ, _e, _field) => Some(typ),
             Expr::IdxRange(_loc, typ, _e, _j, _i) => Some(typ),
-            Expr::Call(_loc, typ, _fndef, _es) => Some(typ),
-            Expr::Hole(_loc, typ, _opt_name) => Some(typ),
+            Expr::IdxDyn(_loc, typ, _e, _i) => Some(typ),
+            Expr::Call(_loc, typ, _fndef, _type_params, _instance, _es) => Some(typ),
+            Expr::Hole(_loc, typ, _opt_name, _ctx) => Some(typ),
         }
     }
 }
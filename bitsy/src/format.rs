@@ -0,0 +1,769 @@
+use super::ast::*;
+use super::loc::{HasSpan, LineCol, Span};
+use super::error::BitsyError;
+use super::{BinOp, UnOp};
+
+use std::collections::VecDeque;
+
+/// Expressions which would run past this column are broken over several lines.
+const MAX_WIDTH: usize = 100;
+const INDENT: &str = "    ";
+
+/// Format the source of a package in the canonical layout, keeping its comments.
+pub fn format_package(package_text: &str) -> Result<String, Vec<BitsyError>> {
+    let package = parse_package_from_string(package_text)?;
+    Ok(format_parsed_package(&package, package_text))
+}
+
+/// Like [`format_package`], for a package which was already parsed from `package_text`.
+pub fn format_parsed_package(package: &Package, package_text: &str) -> String {
+    let mut formatter = Formatter::new(parse_comments(package_text));
+    formatter.package(package);
+    formatter.out
+}
+
+/// Lays out a package one line at a time.
+///
+/// Each comment is emitted just before the first declaration, `match` arm, or closing brace which follows it.
+/// A comment on the same line as the end of a declaration stays on that line.
+struct Formatter {
+    out: String,
+    indent: usize,
+    comments: VecDeque<Comment>,
+    /// Where the last declaration or comment to be emitted ended in the source.
+    last_end: Option<LineCol>,
+    /// Whether nothing has been emitted since the last `{`.
+    at_block_start: bool,
+    /// When set, expressions are laid out on a single line.
+    flat: bool,
+    /// Set when an expression can't be laid out on a single line. Eg, a `match`.
+    not_flat: bool,
+}
+
+impl Formatter {
+    fn new(comments: Vec<Comment>) -> Formatter {
+        Formatter {
+            out: String::new(),
+            indent: 0,
+            comments: comments.into(),
+            last_end: None,
+            at_block_start: true,
+            flat: false,
+            not_flat: false,
+        }
+    }
+
+    fn write(&mut self, s: &str) {
+        self.out.push_str(s);
+    }
+
+    fn newline(&mut self) {
+        self.out.push('\n');
+    }
+
+    fn start_line(&mut self) {
+        for _ in 0..self.indent {
+            self.out.push_str(INDENT);
+        }
+    }
+
+    fn line(&mut self, s: &str) {
+        self.start_line();
+        self.write(s);
+        self.newline();
+    }
+
+    fn blank_line(&mut self) {
+        if !self.out.is_empty() && !self.out.ends_with("\n\n") {
+            self.newline();
+        }
+    }
+
+    fn column(&self) -> usize {
+        let line_start = self.out.rfind('\n').map(|i| i + 1).unwrap_or(0);
+        self.out[line_start..].chars().count()
+    }
+
+    /// Begin something which starts at `start` in the source,
+    /// keeping one blank line before it if there was one in the source.
+    fn separate(&mut self, start: &LineCol) {
+        if let Some(end) = &self.last_end {
+            if !self.at_block_start && start.line() > end.line() + 1 {
+                self.blank_line();
+            }
+        }
+        self.at_block_start = false;
+    }
+
+    fn begin(&mut self, span: &Span) {
+        self.comments_before(Some(&span.start()));
+        self.separate(&span.start());
+        self.last_end = Some(span.start());
+    }
+
+    fn end(&mut self, span: &Span) {
+        self.last_end = Some(span.end());
+    }
+
+    /// Emit the comments which come before `pos` in the source, or all of them when `pos` is `None`.
+    fn comments_before(&mut self, pos: Option<&LineCol>) {
+        while let Some(comment) = self.comments.front() {
+            if let Some(pos) = pos {
+                if comment.span.start() >= *pos {
+                    break;
+                }
+            }
+            let comment = self.comments.pop_front().unwrap();
+            let start = comment.span.start();
+            let trailing = matches!(&self.last_end, Some(end) if end.line() == start.line());
+            if trailing && self.out.ends_with('\n') {
+                self.out.pop();
+                self.write(" ");
+                self.write(&comment.text);
+                self.newline();
+            } else {
+                self.separate(&start);
+                self.line(&comment.text);
+            }
+            self.last_end = Some(comment.span.end());
+        }
+    }
+
+    fn open_block(&mut self) {
+        self.write(" {");
+        self.newline();
+        self.indent += 1;
+        self.at_block_start = true;
+    }
+
+    fn close_block(&mut self, span: &Span) {
+        self.comments_before(Some(&span.end()));
+        self.indent -= 1;
+        self.at_block_start = false;
+        self.start_line();
+        self.write("}");
+    }
+
+    fn package(&mut self, package: &Package) {
+        for import in &package.imports {
+            self.begin(&import.span);
+            self.line(&format!("import {}", import.package));
+            self.end(&import.span);
+        }
+
+        for item in &package.items {
+            self.blank_line();
+            self.at_block_start = true;
+            self.item(item);
+        }
+
+        self.comments_before(None);
+    }
+
+    fn item(&mut self, item: &Item) {
+        let span = item.span();
+        self.begin(&span);
+        let vis = match item.visibility() {
+            Visibility::Pub => "pub ",
+            Visibility::Private => "",
+        };
+
+        self.start_line();
+        match item {
+            Item::ModDef(ModDef(_span, _doc, _visibility, name, type_args, decls)) => {
+                self.write(&format!("{vis}mod {name}{}", type_args_str(type_args)));
+                self.open_block();
+                self.decls(decls);
+            },
+            Item::ExtDef(ModDef(_span, _doc, _visibility, name, _type_args, decls)) => {
+                self.write(&format!("{vis}ext mod {name}"));
+                self.open_block();
+                self.decls(decls);
+            },
+            Item::EnumTypeDef(typedef) => {
+                let onehot = if typedef.onehot { "onehot " } else { "" };
+                self.write(&format!("{vis}{onehot}enum type {}", typedef.name));
+                if let Some(typ) = &typedef.typ {
+                    self.write(&format!(" of {}", type_str(typ)));
+                }
+                self.open_block();
+                for (name, value) in &typedef.values {
+                    self.begin(&name.span);
+                    match value {
+                        Some((span, _value)) => self.line(&format!("{name} = {};", span.source())),
+                        None => self.line(&format!("{name};")),
+                    }
+                    self.end(&name.span);
+                }
+            },
+            Item::StructTypeDef(typedef) => {
+                self.write(&format!("{vis}struct type {}", typedef.name));
+                self.open_block();
+                for (name, typ) in &typedef.fields {
+                    self.begin(&name.span);
+                    self.line(&format!("{name} of {};", type_str(typ)));
+                    self.end(&name.span);
+                }
+            },
+            Item::AltTypeDef(typedef) => {
+                self.write(&format!("{vis}alt type {}{}", typedef.name, type_args_str(&typedef.type_args)));
+                self.open_block();
+                for (name, typs) in &typedef.alts {
+                    self.begin(&name.span);
+                    let typs: Vec<String> = typs.iter().map(type_str).collect();
+                    self.line(&format!("{name}({});", typs.join(", ")));
+                    self.end(&name.span);
+                }
+            },
+            Item::FnDef(fndef) => {
+                let args: Vec<String> = fndef.args.iter().map(|(name, typ)| format!("{name} of {}", type_str(typ))).collect();
+                self.write(&format!(
+                    "{vis}fn {}{}({}) -> {}",
+                    fndef.name,
+                    type_args_str(&fndef.type_args),
+                    args.join(", "),
+                    type_str(&fndef.ret),
+                ));
+                self.open_block();
+                self.comments_before(Some(&fndef.body.span().start()));
+                self.start_line();
+                self.expr(&fndef.body, 0);
+                self.newline();
+                self.end(&fndef.body.span());
+            },
+            Item::TbDef(tbdef) => {
+                self.write(&format!("tb {}", tbdef.name));
+                self.open_block();
+                self.tb_statements(&tbdef.statements);
+            },
+            Item::ConstDef(constdef) => {
+                match &constdef.value {
+                    ConstValue::Nat(n) => self.write(&format!("{vis}const {} of Nat = {};", constdef.name, nat_str(n))),
+                    ConstValue::Expr(typ, e) => {
+                        self.write(&format!("{vis}const {} of {} = ", constdef.name, type_str(typ)));
+                        self.expr(e, 0);
+                        self.write(";");
+                    },
+                }
+                self.newline();
+                self.end(&span);
+                return;
+            },
+            Item::ShapeDef(shapedef) => {
+                self.write(&format!("{vis}shape {}", shapedef.name));
+                self.open_block();
+                for (name, direction, typ) in &shapedef.ports {
+                    self.begin(&name.span);
+                    let direction = match direction {
+                        Direction::Incoming => "incoming",
+                        Direction::Outgoing => "outgoing",
+                    };
+                    self.line(&format!("{direction} {name} of {};", type_str(typ)));
+                    self.end(&name.span);
+                }
+            },
+        }
+        self.close_block(&span);
+        self.newline();
+        self.end(&span);
+    }
+
+    fn decls(&mut self, decls: &[Decl]) {
+        for decl in decls {
+            self.decl(decl);
+        }
+    }
+
+    fn decl(&mut self, decl: &Decl) {
+        let span = decl.span();
+        self.begin(&span);
+        self.start_line();
+        match decl {
+            Decl::Mod(_span, _doc, name, decls) => {
+                self.write(&format!("mod {name}"));
+                self.open_block();
+                self.decls(decls);
+                self.close_block(&span);
+            },
+            Decl::ModInst(_span, name, moddef_name, params) => {
+                self.write(&format!("mod {name} of {moddef_name}{};", type_params_str(params)));
+            },
+            Decl::ModInstVec(_span, name, n, moddef_name, params) => {
+                self.write(&format!("mod {name}[{}] of {moddef_name}{};", nat_str(n), type_params_str(params)));
+            },
+            Decl::Dom(_span, name) => self.write(&format!("dom {name};")),
+            Decl::Incoming(_span, _doc, name, typ) => self.write(&format!("incoming {name} of {};", type_str(typ))),
+            Decl::Outgoing(_span, _doc, name, typ) => self.write(&format!("outgoing {name} of {};", type_str(typ))),
+            Decl::Node(_span, name, Some(typ)) => self.write(&format!("node {name} of {};", type_str(typ))),
+            Decl::Node(_span, name, None) => self.write(&format!("node {name} of _;")),
+            Decl::Reg(_span, _doc, name, typ, dom, reset) => {
+                self.write(&format!("reg {name} of {}", type_str(typ)));
+                if let Some(dom) = dom {
+                    self.write(&format!(" on {dom}"));
+                }
+                if let Some(reset) = reset {
+                    self.write(" reset ");
+                    self.expr(reset, 0);
+                }
+                self.write(";");
+            },
+            Decl::Mem(_span, name, typ, dom, latency, ports) => {
+                self.write(&format!("mem {name} of {}", type_str(typ)));
+                if let Some(dom) = dom {
+                    self.write(&format!(" on {dom}"));
+                }
+                if let Some(latency) = latency {
+                    self.write(&format!(" latency {latency}"));
+                }
+                self.open_block();
+                for port in ports {
+                    match port {
+                        MemPort::Read(name) => self.line(&format!("read {name};")),
+                        MemPort::Write(name, Some(mask)) => self.line(&format!("write {name} mask {mask};")),
+                        MemPort::Write(name, None) => self.line(&format!("write {name};")),
+                    }
+                }
+                self.close_block(&span);
+            },
+            Decl::Wire(_span, wire) => self.wire(wire),
+            Decl::When(_span, When(_when_span, cond, wires)) => {
+                self.write("when ");
+                self.expr(cond, 0);
+                self.open_block();
+                for wire in wires {
+                    self.begin(&wire.0);
+                    self.start_line();
+                    self.wire(wire);
+                    self.newline();
+                    self.end(&wire.0);
+                }
+                self.close_block(&span);
+            },
+            Decl::For(_span, var, start, end, decls) => {
+                self.write(&format!("for {var} in {}..{}", nat_str(start), nat_str(end)));
+                self.open_block();
+                self.decls(decls);
+                self.close_block(&span);
+            },
+            Decl::Port(_span, name, flip, shape) => {
+                let flip = if *flip { "flip " } else { "" };
+                self.write(&format!("port {name} of {flip}{shape};"));
+            },
+            Decl::Connect(_span, a, b) => self.write(&format!("{} <> {};", target_str(a), target_str(b))),
+            Decl::Assert(_span, e) => {
+                self.write("assert ");
+                self.expr(e, 0);
+                self.write(";");
+            },
+            Decl::Cover(_span, e) => {
+                self.write("cover ");
+                self.expr(e, 0);
+                self.write(";");
+            },
+        }
+        self.newline();
+        self.end(&span);
+    }
+
+    fn wire(&mut self, Wire(_span, target, e, wire_type): &Wire) {
+        let op = match wire_type {
+            WireType::Dom => "$=",
+            WireType::Direct => ":=",
+            WireType::Latch => "<=",
+            WireType::Proc => "<=!",
+        };
+        self.write(&format!("{} {op} ", target_str(target)));
+        self.expr(e, 0);
+        self.write(";");
+    }
+
+    fn tb_statements(&mut self, statements: &[TbStatement]) {
+        for statement in statements {
+            match statement {
+                TbStatement::Debug => self.line("debug;"),
+                TbStatement::Reset => self.line("reset;"),
+                TbStatement::Clock => self.line("clock;"),
+                TbStatement::ModInst(name, moddef_name) => {
+                    self.begin(&name.span);
+                    self.line(&format!("mod {name} of {moddef_name};"));
+                    self.end(&moddef_name.span);
+                },
+                TbStatement::Poke(span, dut, port, e) | TbStatement::Expect(span, dut, port, e) => {
+                    let (keyword, op) = match statement {
+                        TbStatement::Poke(..) => ("poke", "="),
+                        _ => ("expect", "=="),
+                    };
+                    self.begin(span);
+                    self.start_line();
+                    self.write(&format!("{keyword} {dut}.{port} {op} "));
+                    self.expr(e, 0);
+                    self.write(";");
+                    self.newline();
+                    self.end(span);
+                },
+                TbStatement::Loop(span, n, statements) => {
+                    self.begin(span);
+                    self.start_line();
+                    self.write(&format!("loop {n}"));
+                    self.open_block();
+                    self.tb_statements(statements);
+                    self.close_block(span);
+                    self.newline();
+                    self.end(span);
+                },
+            }
+            self.at_block_start = false;
+        }
+    }
+
+    /// Write an expression, with parentheses if it binds more loosely than `min_prec`. See [`prec`].
+    fn expr(&mut self, e: &Expr, min_prec: u8) {
+        if prec(e) < min_prec {
+            self.write("(");
+            self.expr(e, 0);
+            self.write(")");
+            return;
+        }
+
+        if !self.flat {
+            // A `let` in the place of a statement always puts its body on the next line.
+            let statement = self.column() == self.indent * INDENT.len();
+            if let Some(line) = flat(e) {
+                if self.column() + line.len() <= MAX_WIDTH && !(statement && matches!(e, Expr::Let(..))) {
+                    self.write(&line);
+                    return;
+                }
+            }
+        }
+
+        match e {
+            Expr::Ident(_span, id) => self.write(&id.to_string()),
+            Expr::Dot(_span, e, id) => {
+                self.expr(e, 9);
+                self.write(&format!(".{id}"));
+            },
+            Expr::Word(span, width, value) => {
+                // Keep the literal as it was written, such as in hex.
+                let text = span.source();
+                if !text.is_empty() {
+                    self.write(text);
+                } else if let Some(width) = width {
+                    self.write(&format!("{value}w{width}"));
+                } else {
+                    self.write(&format!("{value}"));
+                }
+            },
+            Expr::Enum(_span, typ, value) => self.write(&format!("{}::{value}", type_str(typ))),
+            Expr::Struct(_span, fields) => {
+                if fields.is_empty() {
+                    self.write("{}");
+                } else {
+                    self.write("{ ");
+                    for (i, (name, e)) in fields.iter().enumerate() {
+                        if i > 0 {
+                            self.write(", ");
+                        }
+                        self.write(&format!("{name} = "));
+                        self.expr(e, 0);
+                    }
+                    self.write(" }");
+                }
+            },
+            Expr::Vec(_span, es) => {
+                // `[e; n]` is expanded by the parser into `n` copies of `e`, which all share the same span.
+                let replicated = es.len() > 1 && es.iter().all(|e| same_span(&e.span(), &es[0].span()));
+                self.write("[");
+                if replicated {
+                    self.expr(&es[0], 0);
+                    self.write(&format!("; {}", es.len()));
+                } else {
+                    self.exprs(es);
+                }
+                self.write("]");
+            },
+            Expr::Call(_span, name, params, es) => {
+                self.write(&name.to_string());
+                if name.as_str().starts_with('@') && es.is_empty() {
+                    return;
+                }
+                if !params.is_empty() {
+                    self.write(&format!("::{}", type_params_str(params)));
+                }
+                self.write("(");
+                self.exprs(es);
+                self.write(")");
+            },
+            Expr::Let(_span, x, typ, e, b) => {
+                self.write(&format!("let {x}"));
+                if let Some(typ) = typ {
+                    self.write(&format!(" of {}", type_str(typ)));
+                }
+                self.write(" = ");
+                self.expr(e, 0);
+                self.write(";");
+                if self.flat {
+                    self.write(" ");
+                } else {
+                    self.newline();
+                    self.start_line();
+                }
+                self.expr(b, 0);
+            },
+            Expr::UnOp(_span, UnOp::Not, e) => {
+                self.write("!");
+                self.expr(e, 4);
+            },
+            Expr::BinOp(_span, op, e1, e2) => {
+                let p = binop_prec(*op);
+                // `||`, `^`, and `&&` associate to the right. The rest associate to the left.
+                let (p1, p2) = if p <= 2 { (p + 1, p) } else { (p, p + 1) };
+                self.expr(e1, p1);
+                self.write(&format!(" {} ", binop_str(*op)));
+                self.expr(e2, p2);
+            },
+            Expr::If(span, cond, e1, e2) => {
+                self.write("if ");
+                self.expr(cond, 0);
+                self.write(" {");
+                self.branch(e1);
+                let mut e2 = e2;
+                // The parser turns `else if` into an `if` in the `else` branch with the same span.
+                while let Expr::If(else_span, cond, e1, else_e2) = &**e2 {
+                    if !same_span(span, else_span) {
+                        break;
+                    }
+                    self.write("} else if ");
+                    self.expr(cond, 0);
+                    self.write(" {");
+                    self.branch(e1);
+                    e2 = else_e2;
+                }
+                self.write("} else {");
+                self.branch(e2);
+                self.write("}");
+            },
+            Expr::Match(span, e, arms) => {
+                if self.flat {
+                    self.not_flat = true;
+                    return;
+                }
+                self.write("match ");
+                self.expr(e, 0);
+                self.write(" {");
+                self.newline();
+                self.indent += 1;
+                self.at_block_start = true;
+                for MatchArm(pat, e) in arms {
+                    self.comments_before(Some(&e.span().start()));
+                    self.at_block_start = false;
+                    self.start_line();
+                    self.write(&format!("{} => ", pat_str(pat)));
+                    self.expr(e, 0);
+                    self.write(";");
+                    self.newline();
+                    self.end(&e.span());
+                }
+                self.close_block(span);
+            },
+            Expr::IdxField(_span, e, field) => {
+                self.expr(e, 9);
+                self.write(&format!("->{field}"));
+            },
+            Expr::Idx(_span, e, i) => {
+                self.expr(e, 9);
+                self.write(&format!("[{i}]"));
+            },
+            Expr::IdxRange(_span, e, j, i) => {
+                self.expr(e, 9);
+                self.write(&format!("[{j}..{i}]"));
+            },
+            Expr::IdxDyn(_span, e, i) => {
+                self.expr(e, 9);
+                self.write("[");
+                self.expr(i, 0);
+                self.write("]");
+            },
+            Expr::Hole(_span, Some(name)) => self.write(&format!("?{name}")),
+            Expr::Hole(_span, None) => self.write("?"),
+        }
+    }
+
+    fn exprs(&mut self, es: &[Expr]) {
+        for (i, e) in es.iter().enumerate() {
+            if i > 0 {
+                self.write(", ");
+            }
+            self.expr(e, 0);
+        }
+    }
+
+    /// Write one branch of an `if`, which ends just before its closing brace.
+    fn branch(&mut self, e: &Expr) {
+        if self.flat {
+            self.write(" ");
+            self.expr(e, 0);
+            self.write(" ");
+        } else {
+            self.newline();
+            self.indent += 1;
+            self.start_line();
+            self.expr(e, 0);
+            self.indent -= 1;
+            self.newline();
+            self.start_line();
+        }
+    }
+}
+
+/// The expression laid out on a single line, if it can be.
+fn flat(e: &Expr) -> Option<String> {
+    let mut formatter = Formatter::new(vec![]);
+    formatter.flat = true;
+    formatter.expr(e, 0);
+    if formatter.not_flat {
+        None
+    } else {
+        Some(formatter.out)
+    }
+}
+
+/// How tightly an expression binds, following the levels of the grammar.
+/// `let`, `if`, and `match` bind the loosest, and literals and references the tightest.
+fn prec(e: &Expr) -> u8 {
+    match e {
+        Expr::Let(..) | Expr::If(..) | Expr::Match(..) => 0,
+        Expr::BinOp(_span, op, _e1, _e2) => binop_prec(*op),
+        Expr::UnOp(..) => 3,
+        Expr::Call(..) => 8,
+        Expr::Dot(..) | Expr::IdxField(..) | Expr::Idx(..) | Expr::IdxRange(..) | Expr::IdxDyn(..) => 9,
+        Expr::Ident(..) | Expr::Word(..) | Expr::Enum(..) | Expr::Struct(..) | Expr::Vec(..) | Expr::Hole(..) => 10,
+    }
+}
+
+fn binop_prec(op: BinOp) -> u8 {
+    match op {
+        BinOp::Or | BinOp::Xor => 1,
+        BinOp::And => 2,
+        BinOp::Eq | BinOp::Neq | BinOp::Lt | BinOp::Lte | BinOp::Gt | BinOp::Gte => 4,
+        BinOp::Slt | BinOp::Slte | BinOp::Sgt | BinOp::Sgte => 4,
+        BinOp::Shl | BinOp::Shr | BinOp::Sra => 5,
+        BinOp::Add | BinOp::AddCarry | BinOp::Sub | BinOp::SubBorrow => 6,
+        BinOp::Mul => 7,
+    }
+}
+
+fn binop_str(op: BinOp) -> &'static str {
+    match op {
+        BinOp::Add => "+",
+        BinOp::AddCarry => "+%",
+        BinOp::Sub => "-",
+        BinOp::SubBorrow => "-%",
+        BinOp::Mul => "*",
+        BinOp::And => "&&",
+        BinOp::Or => "||",
+        BinOp::Xor => "^",
+        BinOp::Shl => "<<",
+        BinOp::Shr => ">>",
+        BinOp::Sra => ">>>",
+        BinOp::Eq => "==",
+        BinOp::Neq => "!=",
+        BinOp::Lt => "<",
+        BinOp::Lte => "<=",
+        BinOp::Gt => ">",
+        BinOp::Gte => ">=",
+        BinOp::Slt => "<+",
+        BinOp::Slte => "<=+",
+        BinOp::Sgt => ">+",
+        BinOp::Sgte => ">=+",
+    }
+}
+
+fn same_span(span1: &Span, span2: &Span) -> bool {
+    span1.start() == span2.start() && span1.end() == span2.end()
+}
+
+fn pat_str(pat: &crate::Pat) -> String {
+    match pat {
+        crate::Pat::Otherwise => "otherwise".to_string(),
+        crate::Pat::At(ctor, pats) if !pats.is_empty() => {
+            let pats: Vec<String> = pats.iter().map(pat_str).collect();
+            format!("@{ctor}({})", pats.join(", "))
+        },
+        crate::Pat::Struct(fields) if fields.is_empty() => "{}".to_string(),
+        crate::Pat::Struct(fields) => {
+            let fields: Vec<String> = fields.iter().map(|(field, pat)| format!("{field} = {}", pat_str(pat))).collect();
+            format!("{{ {} }}", fields.join(", "))
+        },
+        pat => pat.to_string(),
+    }
+}
+
+fn type_str(typ: &Type) -> String {
+    match typ {
+        Type::Word(_span, n) => format!("Word[{}]", nat_str(n)),
+        Type::SInt(_span, n) => format!("SInt[{}]", nat_str(n)),
+        Type::Vec(_span, typ, n) => format!("Vec[{}, {}]", type_str(typ), nat_str(n)),
+        Type::Valid(typ) => format!("Valid[{}]", type_str(typ)),
+        Type::TypeRef(name, params) => format!("{name}{}", type_params_str(params)),
+    }
+}
+
+fn type_params_str(params: &[TypeParam]) -> String {
+    if params.is_empty() {
+        return String::new();
+    }
+    let params: Vec<String> = params.iter().map(|param| match param {
+        TypeParam::Nat(n) => n.to_string(),
+        TypeParam::Type(typ) => type_str(typ),
+    }).collect();
+    format!("[{}]", params.join(", "))
+}
+
+fn type_args_str(type_args: &[(Ident, Kind)]) -> String {
+    if type_args.is_empty() {
+        return String::new();
+    }
+    let type_args: Vec<String> = type_args.iter().map(|(name, kind)| match kind {
+        Kind::Nat => format!("{name} of Nat"),
+        Kind::Type => format!("{name} of Type"),
+    }).collect();
+    format!("[{}]", type_args.join(", "))
+}
+
+fn nat_str(n: &Nat) -> String {
+    // Sums bind looser than products, and both associate to the left.
+    fn nat_prec(n: &Nat, min_prec: u8) -> String {
+        let (prec, s) = match n {
+            Nat::Lit(n) => (3, n.to_string()),
+            Nat::Param(name) => (3, name.to_string()),
+            Nat::Add(n, m) => (1, format!("{}+{}", nat_prec(n, 1), nat_prec(m, 2))),
+            Nat::Sub(n, m) => (1, format!("{}-{}", nat_prec(n, 1), nat_prec(m, 2))),
+            Nat::Mul(n, m) => (2, format!("{}*{}", nat_prec(n, 2), nat_prec(m, 3))),
+        };
+        if prec < min_prec {
+            format!("({s})")
+        } else {
+            s
+        }
+    }
+    nat_prec(n, 0)
+}
+
+fn target_str(target: &Target) -> String {
+    match target {
+        Target::Local(name) => name.to_string(),
+        Target::Nonlocal(name, port) => format!("{name}.{port}"),
+        Target::Indexed(name, i, port) => format!("{name}[{}].{port}", nat_str(i)),
+        Target::Nested(name, port, field) => format!("{name}.{port}.{field}"),
+        Target::Partial(target, Select::Field(field)) => format!("{}->{field}", target_str(target)),
+        Target::Partial(target, Select::Idx(i)) => format!("{}[{}]", target_str(target), nat_str(i)),
+        Target::Partial(target, Select::IdxRange(j, i)) => format!("{}[{}..{}]", target_str(target), nat_str(j), nat_str(i)),
+    }
+}
+
+/// A target is displayed as it's written in the source. Eg, `q.enq.valid`.
+impl std::fmt::Display for Target {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", target_str(self))
+    }
+}
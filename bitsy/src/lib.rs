@@ -16,6 +16,9 @@ mod resolve;
 mod component;
 mod circuit;
 mod parse;
+mod format;
+mod diagnostic;
+mod doc;
 mod expr;
 mod path;
 mod context;
@@ -32,6 +35,9 @@ pub use resolve::*;
 pub use component::*;
 pub use circuit::*;
 pub use parse::*;
+pub use format::*;
+pub use diagnostic::*;
+pub use doc::*;
 pub use expr::*;
 pub use path::*;
 pub use context::*;
@@ -8,14 +8,14 @@ type Pos = usize;
 #[derive(Clone, Debug)]
 pub struct SourceInfo {
     source: Source,
-    linelens: LineLens,
+    linelens: Arc<LineLens>,
 }
 
 impl SourceInfo {
     pub fn unknown() -> SourceInfo {
         SourceInfo {
             source: Source::Unknown,
-            linelens: LineLens::from(""),
+            linelens: Arc::new(LineLens::from("")),
         }
     }
 
@@ -25,15 +25,33 @@ impl SourceInfo {
 
     pub fn from_file(filepath: &std::path::Path, contents: &str) -> SourceInfo {
         SourceInfo {
-            source: Source::File(Arc::new(filepath.to_owned())),
-            linelens: LineLens::from(contents),
+            source: Source::File(Arc::new(filepath.to_owned()), Arc::new(contents.to_owned())),
+            linelens: Arc::new(LineLens::from(contents)),
         }
     }
 
     pub fn from_string(contents: &str) -> SourceInfo {
         SourceInfo {
             source: Source::String(Arc::new(contents.to_owned())),
-            linelens: LineLens::from(contents),
+            linelens: Arc::new(LineLens::from(contents)),
+        }
+    }
+
+    /// The name of the source, as it appears in diagnostics. Eg, `examples/top.bitsy`.
+    pub fn name(&self) -> String {
+        match &self.source {
+            Source::File(path, _contents) => path.display().to_string(),
+            Source::String(_contents) => "<string>".to_string(),
+            Source::Unknown => "<unknown>".to_string(),
+        }
+    }
+
+    /// The text of the source, if it is known.
+    pub fn text(&self) -> Option<&str> {
+        match &self.source {
+            Source::File(_path, contents) => Some(contents),
+            Source::String(contents) => Some(contents),
+            Source::Unknown => None,
         }
     }
 
@@ -52,7 +70,8 @@ impl SourceInfo {
 
 #[derive(Clone, Debug)]
 pub enum Source {
-    File(Arc<std::path::PathBuf>),
+    /// A file, along with its contents.
+    File(Arc<std::path::PathBuf>, Arc<String>),
     String(Arc<String>),
     Unknown,
 }
@@ -96,7 +115,7 @@ pub struct Span {
 impl std::fmt::Debug for Span {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
         match &self.source_info.source {
-            Source::File(path) => write!(f, "[{}-{}:{:?}]", self.start(), self.end(), path),
+            Source::File(path, _contents) => write!(f, "[{}-{}:{:?}]", self.start(), self.end(), path),
             Source::String(s) => write!(f, "[{}-{}:{:?}]", self.start(), self.end(), String::from_utf8_lossy(&s.as_bytes()[self.start..self.end])),
             Source::Unknown => write!(f, "[{}-{}]", self.start(), self.end()),
         }
@@ -106,7 +125,7 @@ impl std::fmt::Debug for Span {
 impl std::fmt::Display for Span {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
         match &self.source_info.source {
-            Source::File(_path) => write!(f, "[{}-{}]", self.start(), self.end()),
+            Source::File(_path, _contents) => write!(f, "[{}-{}]", self.start(), self.end()),
             Source::String(_s) => write!(f, "[{}-{}]", self.start(), self.end()),
             Source::Unknown => write!(f, "[{}-{}]", self.start(), self.end()),
         }
@@ -142,13 +161,16 @@ impl Span {
     }
 
     pub fn source(&self) -> &str {
-        if let Source::String(source) = &self.source_info.source {
-            &source[self.start..self.end]
-        } else {
-            ""
+        match self.source_info.text() {
+            Some(text) => &text[self.start..self.end],
+            None => "",
         }
     }
 
+    pub fn source_info(&self) -> &SourceInfo {
+        &self.source_info
+    }
+
     pub fn contains(&self, linecol: &LineCol) -> bool {
         &self.start() <= linecol && linecol <= &self.end()
     }
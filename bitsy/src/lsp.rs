@@ -4,6 +4,7 @@ use serde_json::{Value, json};
 use bitsy_lang::Package;
 use bitsy_lang::HasSpan;
 use bitsy_lang::LineCol;
+use bitsy_lang::{item_signature, component_signature};
 
 use std::sync::mpsc::channel;
 use std::thread;
@@ -46,6 +47,7 @@ pub(crate) fn run_lsp() {
                             "textDocument/didSave" => state.text_document_did_save(message),
                             "textDocument/hover" => state.text_document_hover(message),
                             "textDocument/definition" => state.text_document_definition(message),
+                            "textDocument/formatting" => state.text_document_formatting(message),
                             _ => (),
                         }
                     },
@@ -198,6 +200,7 @@ impl Buffer {
                             "end": { "line": end_line, "character": end_character },
                         },
                         "severity": 1, // ERROR
+                        "code": error.code(),
                         "message": message,
                     });
                     diagnostics.push(diagnostic);
@@ -235,6 +238,7 @@ impl Buffer {
                             "end": { "line": end_line, "character": end_character },
                         },
                         "severity": 1, // ERROR
+                        "code": error.code(),
                         "message": message,
                     });
                     diagnostics.push(diagnostic);
@@ -253,6 +257,46 @@ impl Buffer {
             },
         };
 
+        for moddef in self.package.unchecked_moddefs() {
+            let start_line = moddef.span().start().line() - 1;
+            let start_character = moddef.span().start().col() - 1;
+
+            let end_line = moddef.span().end().line() - 1;
+            let end_character = moddef.span().end().col() - 1;
+
+            let message = format!("The body of mod {} isn't checked until it's instantiated", moddef.name());
+
+            let diagnostic = json!({
+                "range": {
+                    "start": { "line": start_line, "character": start_character },
+                    "end": { "line": end_line, "character": end_character },
+                },
+                "severity": 2, // WARNING
+                "message": message,
+            });
+            diagnostics.push(diagnostic);
+        }
+
+        for hole in self.package.holes() {
+            let start_line = hole.span().start().line() - 1;
+            let start_character = hole.span().start().col() - 1;
+
+            let end_line = hole.span().end().line() - 1;
+            let end_character = hole.span().end().col() - 1;
+
+            let message = format!("{hole}");
+
+            let diagnostic = json!({
+                "range": {
+                    "start": { "line": start_line, "character": start_character },
+                    "end": { "line": end_line, "character": end_character },
+                },
+                "severity": 3, // INFORMATION
+                "message": message,
+            });
+            diagnostics.push(diagnostic);
+        }
+
         let message = json!({
             "jsonrpc": "2.0",
             "method": "textDocument/publishDiagnostics",
@@ -281,6 +325,7 @@ impl State {
                     "referencesProvider": true,
                     "documentHighlightProvider": true,
                     "documentSymbolProvider": true,
+                    "documentFormattingProvider": true,
 //                    "renameProvider": true,
                 },
             },
@@ -321,26 +366,30 @@ impl State {
         let uri = message["params"]["textDocument"]["uri"].as_str().unwrap().to_string();
         let buffer = self.buffer(&uri);
 
-        let mut hover_ident = None;
+        let mut hover = None;
 
         if let Ok(package) = bitsy_lang::load_package_from_string(&buffer.text) {
             for ident in package.idents().iter() {
                 if ident.span().contains(&linecol) {
-                    hover_ident = Some(ident.clone());
+                    hover = hover_text(&package, &ident.name, &linecol);
                 }
             }
         }
 
-        warn!("Hover: {linecol}: {:?}", hover_ident);
-        let response: Value = json!({
-            "jsonrpc": "2.0",
-            "id": message["id"],
-            "result": {
+        let result = match hover {
+            Some(hover) => json!({
                 "contents": {
                     "kind": "markdown",
-                    "value": "Please construct additional pylons",
+                    "value": hover,
                 },
-            },
+            }),
+            None => Value::Null,
+        };
+
+        let response: Value = json!({
+            "jsonrpc": "2.0",
+            "id": message["id"],
+            "result": result,
         });
 
         send_message(response);
@@ -390,6 +439,54 @@ impl State {
 
         send_message(response);
     }
+
+    fn text_document_formatting(&mut self, message: Value) {
+        let uri = message["params"]["textDocument"]["uri"].as_str().unwrap().to_string();
+        let buffer = self.buffer(&uri);
+
+        // Replace the whole buffer. The end position is clamped by the client.
+        let result = match bitsy_lang::format_package(&buffer.text) {
+            Ok(text) => json!([
+                {
+                    "range": {
+                        "start": { "line": 0, "character": 0 },
+                        "end": { "line": buffer.text.lines().count() + 1, "character": 0 },
+                    },
+                    "newText": text,
+                },
+            ]),
+            Err(_errors) => json!(null),
+        };
+
+        let response: Value = json!({
+            "jsonrpc": "2.0",
+            "id": message["id"],
+            "result": result,
+        });
+
+        send_message(response);
+    }
+}
+
+/// The signature and the doc comment of what the name refers to at `linecol`.
+/// This is either an item of the package or a port, reg, or instance of the mod the cursor is in.
+fn hover_text(package: &Package, name: &str, linecol: &LineCol) -> Option<String> {
+    let (signature, doc) = if let Some(item) = package.item(name) {
+        (item_signature(&item), item.doc().map(|doc| doc.to_string()))
+    } else {
+        let mut component = package.moddefs().into_iter().find(|moddef| moddef.span().contains(linecol))?;
+        while let Some(submod) = component.children().into_iter().find(|child| child.is_mod() && child.span().contains(linecol)) {
+            component = submod;
+        }
+        let child = component.child(name)?;
+        (component_signature(&child), child.doc().map(|doc| doc.to_string()))
+    };
+
+    let mut hover = format!("```bitsy\n{signature}\n```");
+    if let Some(doc) = doc {
+        hover.push_str(&format!("\n\n{doc}"));
+    }
+    Some(hover)
 }
 
 fn panic_handler(info: &std::panic::PanicInfo) {
@@ -9,12 +9,16 @@ mod testbench;
 use repl::*;
 use testbench::*;
 use clap::Parser;
+use clap::Subcommand;
 
 use std::collections::BTreeMap;
 
 #[derive(Parser, Debug)]
 #[command(name = "bitsy", author, version, about, long_about = None)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     filename: Option<String>,
 
     #[arg(short, long, default_value_t = false)]
@@ -31,6 +35,63 @@ struct Args {
 
     #[arg(short, long, default_value_t = false)]
     debug: bool,
+
+    /// Add a directory to the search path for imports.
+    #[arg(short = 'I', long = "include")]
+    include: Vec<String>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Run every `tb` testbench in a file.
+    Test {
+        filename: String,
+    },
+    /// Format files in place.
+    Fmt {
+        filenames: Vec<String>,
+
+        /// Don't write the files. Instead, list the ones which aren't formatted and fail if there are any.
+        #[arg(long, default_value_t = false)]
+        check: bool,
+    },
+    /// Explain an error code. Eg, `bitsy explain E0004`.
+    Explain {
+        code: String,
+    },
+    /// Write documentation pages for a file and the packages it imports.
+    Doc {
+        filename: String,
+
+        /// The directory to write the pages to.
+        #[arg(short, long, default_value = "doc")]
+        out: String,
+
+        /// Write Markdown instead of HTML.
+        #[arg(long, default_value_t = false)]
+        markdown: bool,
+    },
+}
+
+fn load_package(args: &Args, filename: &str) -> Result<Package, Vec<BitsyError>> {
+    let mut loader = Loader::new();
+    if let Some(dir) = std::path::Path::new(filename).parent() {
+        loader = loader.add_search_path(dir);
+    }
+    for dir in &args.include {
+        loader = loader.add_search_path(dir);
+    }
+    loader.load_package_from_file(filename)
+}
+
+fn report_warnings(package: &Package) {
+    for moddef in package.unchecked_moddefs() {
+        let message = format!("The body of mod {} isn't checked until it's instantiated", moddef.name());
+        eprintln!("{}", render_warning(&moddef.span(), &message));
+    }
+    for hole in package.holes() {
+        eprintln!("{}", render_warning(&hole.span(), &hole.to_string()));
+    }
 }
 
 fn main_compile(args: &Args) {
@@ -42,18 +103,19 @@ fn main_compile(args: &Args) {
         std::process::exit(1)
     });
 
-    let text = std::fs::read_to_string(&filename).unwrap().to_string();
-    let package = match bitsy_lang::load_package_from_string(&text) {
+    let package = match load_package(args, filename) {
         Ok(package) => package,
         Err(errors) => {
             for error in &errors {
-                eprintln!("{error:?}");
+                eprintln!("{}", render_error(error));
             }
             eprintln!("Circuit has {} errors.", errors.len());
             std::process::exit(1);
         },
     };
 
+    report_warnings(&package);
+
     let component_name = package.moddefs().first().map(|component| component.name().to_string()).unwrap();
     let top_name = match &args.top {
         Some(top_name) => top_name,
@@ -63,7 +125,7 @@ fn main_compile(args: &Args) {
     let _circuit = match package.top(&top_name) {
         Ok(circuit) => circuit,
         Err(error) => {
-            eprintln!("{error:?}");
+            eprintln!("{}", render_error(&error));
             eprintln!("Circuit has 1 errors.");
             std::process::exit(1);
         },
@@ -72,6 +134,123 @@ fn main_compile(args: &Args) {
     package.emit_mlir();
 }
 
+fn main_test(args: &Args, filename: &str) {
+    let package = match load_package(args, filename) {
+        Ok(package) => package,
+        Err(errors) => {
+            for error in &errors {
+                eprintln!("{}", render_error(error));
+            }
+            eprintln!("Circuit has {} errors.", errors.len());
+            std::process::exit(1);
+        },
+    };
+
+    report_warnings(&package);
+
+    let tbdefs = package.tbdefs();
+    let mut failed = 0;
+    for tbdef in &tbdefs {
+        match run_tb(&package, tbdef) {
+            Ok(()) => println!("tb {} ... ok", tbdef.name),
+            Err(errors) => {
+                println!("tb {} ... FAILED", tbdef.name);
+                for error in &errors {
+                    println!("{}", render_error(error));
+                }
+                failed += 1;
+            },
+        }
+    }
+
+    println!("{} passed; {failed} failed", tbdefs.len() - failed);
+    if failed > 0 {
+        std::process::exit(1);
+    }
+}
+
+fn main_fmt(filenames: &[String], check: bool) {
+    let mut unformatted = 0;
+    for filename in filenames {
+        let text = match std::fs::read_to_string(filename) {
+            Ok(text) => text,
+            Err(error) => {
+                let error = BitsyError::CantReadFile(Span::unknown(), filename.to_string(), error.to_string());
+                eprintln!("{}", render_error(&error));
+                std::process::exit(1);
+            },
+        };
+        // Parse with the file name, so that errors say where they are.
+        let source_info = SourceInfo::from_file(std::path::Path::new(filename), &text);
+        let package = match ast::parse_package(&source_info, &text) {
+            Ok(package) => package,
+            Err(errors) => {
+                for error in &errors {
+                    eprintln!("{}", render_error(error));
+                }
+                std::process::exit(1);
+            },
+        };
+        let formatted = format_parsed_package(&package, &text);
+
+        if formatted != text {
+            if check {
+                println!("{filename}");
+                unformatted += 1;
+            } else {
+                std::fs::write(filename, formatted).unwrap();
+            }
+        }
+    }
+
+    if unformatted > 0 {
+        std::process::exit(1);
+    }
+}
+
+fn main_doc(args: &Args, filename: &str, out: &str, markdown: bool) {
+    let package = match load_package(args, filename) {
+        Ok(package) => package,
+        Err(errors) => {
+            for error in &errors {
+                eprintln!("{}", render_error(error));
+            }
+            eprintln!("Circuit has {} errors.", errors.len());
+            std::process::exit(1);
+        },
+    };
+
+    let format = if markdown { DocFormat::Markdown } else { DocFormat::Html };
+    let name = std::path::Path::new(filename).file_stem().unwrap().to_string_lossy().to_string();
+
+    // Imported packages get their own pages, named after the import, so links between them resolve.
+    let mut pages = BTreeMap::new();
+    let mut stack = vec![(name, &package)];
+    while let Some((name, package)) = stack.pop() {
+        for (import_name, import) in package.imports() {
+            stack.push((import_name.to_string(), import));
+        }
+        pages.entry(name).or_insert(package);
+    }
+
+    std::fs::create_dir_all(out).unwrap();
+    for (name, package) in pages {
+        let path = std::path::Path::new(out).join(format!("{name}.{}", format.extension()));
+        std::fs::write(&path, doc_package(package, &name, format)).unwrap();
+        println!("{}", path.display());
+    }
+}
+
+fn main_explain(code: &str) {
+    match explain(&code.to_uppercase()) {
+        Some(explanation) => println!("{explanation}"),
+        None => {
+            eprintln!("No such error code: {code}");
+            std::process::exit(1);
+        },
+    }
+}
+
 fn main_run(args: &Args) {
     let filename = args.filename.as_ref();
     let filename: &String = filename.unwrap_or_else(|| {
@@ -81,19 +260,19 @@ fn main_run(args: &Args) {
         std::process::exit(1)
     });
 
-    let text = std::fs::read_to_string(&filename).unwrap().to_string();
-
-    let package = match bitsy_lang::load_package_from_string(&text) {
+    let package = match load_package(args, filename) {
         Ok(package) => package,
         Err(errors) => {
             for error in &errors {
-                eprintln!("{error:?}");
+                eprintln!("{}", render_error(error));
             }
             eprintln!("Circuit has {} errors.", errors.len());
             std::process::exit(1);
         },
     };
 
+    report_warnings(&package);
+
     let testbench_filename = args.tb.clone().or_else(|| testbench_for(&filename));
     let testbench = if args.debug {
         let command = TestbenchCommand::Debug;
@@ -117,7 +296,7 @@ fn main_run(args: &Args) {
     let circuit = match package.top(&top_name) {
         Ok(circuit) => circuit,
         Err(error) => {
-            eprintln!("{error:?}");
+            eprintln!("{}", render_error(&error));
             eprintln!("Circuit has 1 errors.");
             std::process::exit(1);
         },
@@ -133,6 +312,14 @@ fn main() {
     if args.lsp {
         lsp::run_lsp();
         std::process::exit(0);
+    } else if let Some(Command::Test { filename }) = &args.command {
+        main_test(&args, filename);
+    } else if let Some(Command::Fmt { filenames, check }) = &args.command {
+        main_fmt(filenames, *check);
+    } else if let Some(Command::Explain { code }) = &args.command {
+        main_explain(code);
+    } else if let Some(Command::Doc { filename, out, markdown }) = &args.command {
+        main_doc(&args, filename, out, *markdown);
     } else if args.compile {
         main_compile(&args);
     } else {
@@ -1,6 +1,8 @@
 use super::*;
 
+use std::collections::BTreeMap;
 use std::sync::Arc;
+use std::sync::Mutex;
 
 pub use ast::Ident;
 pub use ast::WireType;
@@ -13,17 +15,28 @@ pub type Name = String;
 pub struct Package {
     items: Vec<Item>,
     idents: Vec<Ident>,
+    imports: BTreeMap<Name, Package>,
+    /// The parametric mods whose bodies are resolved when they are instantiated. See [`resolve_instance`].
+    deferred: BTreeMap<Name, ast::ModDef>,
+    /// The parametric mods elaborated so far. See [`Package::elaborate`].
+    pub(crate) instances: Arc<Mutex<Vec<Instance>>>,
 }
 
 impl Package {
-    pub fn from(ast: &ast::Package) -> Result<Package, Vec<BitsyError>> {
-        let namespace = resolve::resolve(ast)?;
+    /// Resolve a parsed package.
+    /// The `imports` are the already-loaded packages named by its `import` statements.
+    pub fn from(ast: &ast::Package, imports: BTreeMap<Name, Package>) -> Result<Package, Vec<BitsyError>> {
+        let namespace = resolve::resolve(ast, &imports)?;
         let items = namespace.items().into_iter().map(|(_name, item)| item).collect();
         let idents = namespace.idents();
+        let deferred = namespace.deferred();
 
         let package = Package {
             items,
             idents,
+            imports,
+            deferred,
+            instances: Arc::default(),
         };
 
         package.check()?;
@@ -36,6 +49,9 @@ impl Package {
 
     pub fn top(&self, top_name: &str) -> Result<Circuit, BitsyError>  {
         if let Some(top) = self.moddef(top_name) {
+            if top.is_parametric() {
+                return Err(BitsyError::Unknown(None, format!("A parametric mod can't be the top: {top_name}")));
+            }
             Ok(Circuit(self.clone(), top))
         } else {
             Err(BitsyError::Unknown(None, format!("No such mod definition: {top_name}")))
@@ -50,7 +66,18 @@ impl Package {
         results
     }
 
+    /// The packages imported by this one, by name.
+    pub fn imports(&self) -> &BTreeMap<Name, Package> {
+        &self.imports
+    }
+
+    /// Look up an item by name.
+    /// Qualified names, such as `foo::Bar`, are looked up in the imported package `foo`.
     pub fn item(&self, name: &str) -> Option<Item> {
+        if let Some((package_name, rest)) = name.split_once("::") {
+            return self.imports.get(package_name)?.item(rest);
+        }
+
         for item in &self.items {
             if name == item.name() {
                 return Some(item.clone());
@@ -62,22 +89,37 @@ impl Package {
     pub fn moddefs(&self) -> Vec<Arc<Component>> {
         let mut results = vec![];
         for item in &self.items {
-            if let Item::ModDef(moddef) = &item {
+            if let Item::ModDef(_visibility, moddef) = &item {
                 results.push(moddef.clone());
-            } else if let Item::ExtDef(moddef) = &item {
+            } else if let Item::ExtDef(_visibility, moddef) = &item {
                 results.push(moddef.clone());
             }
         }
         results
     }
 
+    /// Every `tb` testbench.
+    pub fn tbdefs(&self) -> Vec<Arc<TbDef>> {
+        let mut results = vec![];
+        for item in &self.items {
+            if let Item::TbDef(tbdef) = &item {
+                results.push(tbdef.clone());
+            }
+        }
+        results
+    }
+
     pub fn moddef(&self, name: &str) -> Option<Arc<Component>> {
+        if name.contains("::") {
+            return self.item(name)?.as_component();
+        }
+
         for item in &self.items {
-            if let Item::ModDef(moddef) = &item {
+            if let Item::ModDef(_visibility, moddef) = &item {
                 if moddef.name() == name {
                     return Some(moddef.clone());
                 }
-            } else if let Item::ExtDef(moddef) = &item {
+            } else if let Item::ExtDef(_visibility, moddef) = &item {
                 if moddef.name() == name {
                     return Some(moddef.clone());
                 }
@@ -86,9 +128,20 @@ impl Package {
         None
     }
 
+    /// The package which defines `moddef` and its definition,
+    /// when the body of `moddef` is only resolved once it's instantiated. See [`resolve_instance`].
+    pub(crate) fn deferred_moddef(&self, moddef: &Arc<Component>) -> Option<(&Package, &ast::ModDef)> {
+        if let Some(ast) = self.deferred.get(moddef.name()) {
+            if self.moddef(moddef.name()).is_some_and(|local_moddef| Arc::ptr_eq(&local_moddef, moddef)) {
+                return Some((self, ast));
+            }
+        }
+        self.imports.values().find_map(|package| package.deferred_moddef(moddef))
+    }
+
     pub fn extdef(&self, name: &str) -> Option<Arc<Component>> {
         for item in &self.items {
-            if let Item::ExtDef(extdef) = &item {
+            if let Item::ExtDef(_visibility, extdef) = &item {
                 if extdef.name() == name {
                     return Some(extdef.clone());
                 }
@@ -108,6 +161,17 @@ impl Package {
         None
     }
 
+    pub fn alttypedef(&self, name: &str) -> Option<Arc<AltTypeDef>> {
+        for item in &self.items {
+            if let Item::AltTypeDef(typedef) = &item {
+                if typedef.name == name {
+                    return Some(typedef.clone());
+                }
+            }
+        }
+        None
+    }
+
     pub fn fndef(&self, name: &str) -> Option<Arc<FnDef>> {
         for item in &self.items {
             if let Item::FnDef(fndef) = &item {
@@ -129,105 +193,192 @@ impl Package {
         result
     }
 
-    /// Look at all components in scope, work out their type, and build a [`context::Context`] to assist in typechecking.
-    pub fn context_for(&self, component: Arc<Component>) -> Context<Path, Type> {
-        let mut ctx = vec![];
-        for (path, target) in self.visible_paths(component.clone()) {
-            let typ = self.type_of(target).unwrap();
-            ctx.push((path, typ));
+    /// Report every hole in the package, along with the references and `fn`s which fit it.
+    pub fn holes(&self) -> Vec<HoleReport> {
+        let mut exprs: Vec<Arc<Expr>> = vec![];
+        for fndef in self.fndefs() {
+            exprs.push(fndef.body.clone());
         }
-        Context::from(ctx)
-    }
-
-    pub(crate) fn visible_paths(&self, component: Arc<Component>) -> Vec<(Path, Arc<Component>)> {
-        let mut results = vec![];
-        for child in component.children() {
-            match &*child {
-                Component::Node(_span, name, _typ) => results.push((name.to_string().into(), child.clone())),
-                Component::Dom(_span, _name) => (),
-                Component::Incoming(_span, name, _typ) => results.push((name.to_string().into(), child.clone())),
-                Component::Outgoing(_span, name, _typ) => results.push((name.to_string().into(), child.clone())),
-                Component::Reg(_span, name, _typ, _reset) => results.push((name.to_string().into(), child.clone())),
-                Component::Mod(_span, name, _children, _wires, _whens) => {
-                    let mod_path: Path = name.to_string().into();
-                    for (path, component) in child.port_paths() {
-                        results.push((mod_path.join(path), component.clone()));
-                    }
-                },
-                Component::ModInst(_span, name, moddef) => {
-                    let mod_path: Path = name.to_string().into();
-                    for (path, component) in moddef.port_paths() {
-                        results.push((mod_path.join(path), component.clone()));
+        let mut moddefs = self.moddefs();
+        moddefs.extend(self.deferred_instances());
+        for moddef in moddefs {
+            let mut components = vec![moddef.clone()];
+            components.extend(moddef.submods());
+            for component in components {
+                for Wire(_loc, _target, _select, expr, _wiretype) in component.wires() {
+                    exprs.push(expr);
+                }
+                for When(cond, wires) in component.whens() {
+                    exprs.push(cond);
+                    for Wire(_loc, _target, _select, expr, _wiretype) in wires {
+                        exprs.push(expr);
                     }
-                },
-                Component::Ext(_span, name, _children) => {
-                    let ext_path: Path = name.to_string().into();
-                    for (path, component) in child.port_paths() {
-                        results.push((ext_path.join(path), component.clone()));
+                }
+                for Assertion(_loc, _kind, expr) in component.assertions() {
+                    exprs.push(expr);
+                }
+                for child in component.children() {
+                    if let Some(reset) = child.reset() {
+                        exprs.push(reset);
                     }
-                },
+                }
             }
         }
+
+        for tbdef in self.tbdefs() {
+            tb_exprs(&tbdef.statements, &mut exprs);
+        }
+
+        let mut results: Vec<HoleReport> = vec![];
+        for expr in exprs {
+            expr.with_subexprs(&mut |e: &Expr| {
+                if let Expr::Hole(span, typ, name, ctx) = e {
+                    // A deferred mod with several instances has the same holes in each.
+                    let seen = results.iter().any(|hole| hole.span.source_info().name() == span.source_info().name() && hole.span.start() == span.start());
+                    if !seen {
+                        results.push(self.hole_report(span.clone(), name.clone(), typ.get().cloned(), ctx.get().cloned()));
+                    }
+                }
+            });
+        }
         results
     }
 
+    fn hole_report(&self, span: Span, name: Option<String>, typ: Option<Type>, ctx: Option<Context<Path, Type>>) -> HoleReport {
+        let mut fits = vec![];
+        let mut fndefs = vec![];
+        if let Some(typ) = &typ {
+            let ctx = ctx.unwrap_or_else(Context::empty);
+            for (path, _typ) in ctx.iter() {
+                // Skip the references which are shadowed by a later `let`.
+                let path_typ = ctx.lookup(path).unwrap();
+                if path_typ.equals(typ) && !fits.iter().any(|(fit, _typ): &(Path, Type)| fit == path) {
+                    fits.push((path.clone(), path_typ));
+                }
+            }
+            for fndef in self.fndefs() {
+                if fndef.ret.unify(typ, &mut BTreeMap::new()) {
+                    fndefs.push(fndef);
+                }
+            }
+        }
+        HoleReport { span, name, typ, fits, fndefs }
+    }
+
+    /// Look at all components in scope, work out their type, and build a [`context::Context`] to assist in typechecking.
+    pub fn context_for(&self, component: Arc<Component>) -> Context<Path, Type> {
+        context_of(&component.children(), &|modinst| self.instance(modinst))
+    }
+
+    pub(crate) fn visible_paths(&self, component: Arc<Component>) -> Vec<(Path, Arc<Component>)> {
+        visible_paths(&component.children(), &|modinst| self.instance(modinst))
+    }
+
     pub fn type_of(&self, component: Arc<Component>) -> Option<Type> {
         match &*component {
-            Component::Mod(_span, _name, _children, _wires, _whens) => None,
-            Component::ModInst(_span, _name, _defname) => None,
-            Component::Ext(_span, _name, _children) => None,
+            Component::Mod(_span, _doc, _name, _type_args, _children, _wires, _whens, _assertions, _interfaces) => None,
+            Component::ModInst(_span, _name, _defname, _params) => None,
+            Component::ModInstVec(_span, _name, _insts) => None,
+            Component::Ext(_span, _doc, _name, _children) => None,
             Component::Node(_span, _name, typ) => Some(typ.clone()),
             Component::Dom(_span, _name) => None,
-            Component::Incoming(_span, _name, typ) => Some(typ.clone()),
-            Component::Outgoing(_span, _name, typ) => Some(typ.clone()),
-            Component::Reg(_span, _name, typ, _reset) => Some(typ.clone()),
+            Component::Incoming(_span, _doc, _name, typ) => Some(typ.clone()),
+            Component::Outgoing(_span, _doc, _name, typ) => Some(typ.clone()),
+            Component::Reg(_span, _doc, _name, typ, _dom, _reset) => Some(typ.clone()),
+            Component::Mem(_span, _name, _typ, _dom, _latency, _ports) => None,
         }
     }
 
+    /// Dot into the given path, relative to `component`.
+    /// Follow [`Component::ModInst`]s to the definitions they instantiate. See [`Package::instance`].
     pub(crate) fn component_from(&self, component: Arc<Component>, path: Path) -> Option<Arc<Component>> {
         let mut result: Arc<Component> = component;
         for part in path.split(".") {
-            if let Component::ModInst(_span, _name, moddef) = &*result {
-                result = moddef.clone();
-            }
-
-            if let Some(child) = result.child(part) {
-                result = child.clone();
+            if let Component::ModInst(_span, _name, _moddef, _params) = &*result {
+                result = self.instance(&result);
             }
+            result = result.child(part)?;
         }
         Some(result)
     }
 }
 
+/// The expressions of the `poke` and `expect` statements of a testbench.
+fn tb_exprs(statements: &[TbStatement], exprs: &mut Vec<Arc<Expr>>) {
+    for statement in statements {
+        match statement {
+            TbStatement::Poke(_span, _dut, _port, e) => exprs.push(e.clone()),
+            TbStatement::Expect(_span, _dut, _port, e) => exprs.push(e.clone()),
+            TbStatement::Loop(_span, _n, statements) => tb_exprs(statements, exprs),
+            TbStatement::Debug | TbStatement::Reset | TbStatement::Clock | TbStatement::ModInst(..) => (),
+        }
+    }
+}
+
 /// A top-level declaration in a [`Package`].
 #[derive(Debug, Clone)]
 pub enum Item {
-    ModDef(Arc<Component>),
-    ExtDef(Arc<Component>),
+    /// The [`Component`] of a mod doesn't know its visibility, since it's also used for mods nested in other mods.
+    ModDef(Visibility, Arc<Component>),
+    ExtDef(Visibility, Arc<Component>),
     EnumTypeDef(Arc<EnumTypeDef>),
     StructTypeDef(Arc<StructTypeDef>),
     AltTypeDef(Arc<AltTypeDef>),
     FnDef(Arc<FnDef>),
     TbDef(Arc<TbDef>),
+    ConstDef(Arc<ConstDef>),
+    ShapeDef(Arc<ShapeDef>),
 }
 
 impl Item {
     pub fn name(&self) -> &str {
         match self {
-            Item::ModDef(component) => component.name(),
-            Item::ExtDef(component) => component.name(),
+            Item::ModDef(_visibility, component) => component.name(),
+            Item::ExtDef(_visibility, component) => component.name(),
             Item::EnumTypeDef(typedef) => &typedef.name,
             Item::StructTypeDef(typedef) => &typedef.name,
             Item::AltTypeDef(typedef) => &typedef.name,
             Item::FnDef(typedef) => &typedef.name,
             Item::TbDef(tbdef) => &tbdef.name,
+            Item::ConstDef(constdef) => &constdef.name,
+            Item::ShapeDef(shapedef) => &shapedef.name,
+        }
+    }
+
+    /// Whether the item can be used from other packages.
+    pub fn visibility(&self) -> Visibility {
+        match self {
+            Item::ModDef(visibility, _component) => *visibility,
+            Item::ExtDef(visibility, _component) => *visibility,
+            Item::EnumTypeDef(typedef) => typedef.visibility,
+            Item::StructTypeDef(typedef) => typedef.visibility,
+            Item::AltTypeDef(typedef) => typedef.visibility,
+            Item::FnDef(fndef) => fndef.visibility,
+            Item::TbDef(_tbdef) => Visibility::Private,
+            Item::ConstDef(constdef) => constdef.visibility,
+            Item::ShapeDef(shapedef) => shapedef.visibility,
+        }
+    }
+
+    /// The text of the item's `///` doc comments.
+    pub fn doc(&self) -> Option<&str> {
+        match self {
+            Item::ModDef(_visibility, component) => component.doc(),
+            Item::ExtDef(_visibility, component) => component.doc(),
+            Item::EnumTypeDef(typedef) => typedef.doc.as_deref(),
+            Item::StructTypeDef(typedef) => typedef.doc.as_deref(),
+            Item::AltTypeDef(typedef) => typedef.doc.as_deref(),
+            Item::FnDef(fndef) => fndef.doc.as_deref(),
+            Item::TbDef(tbdef) => tbdef.doc.as_deref(),
+            Item::ConstDef(constdef) => constdef.doc.as_deref(),
+            Item::ShapeDef(shapedef) => shapedef.doc.as_deref(),
         }
     }
 
     pub fn is_moddef(&self) -> bool {
         match self {
-            Item::ModDef(_component) => true,
-            Item::ExtDef(_component) => true,
+            Item::ModDef(_visibility, _component) => true,
+            Item::ExtDef(_visibility, _component) => true,
             _ => false,
         }
     }
@@ -250,8 +401,8 @@ impl Item {
 
     pub fn as_component(&self) -> Option<Arc<Component>> {
         match self {
-            Item::ModDef(component) => Some(component.clone()),
-            Item::ExtDef(component) => Some(component.clone()),
+            Item::ModDef(_visibility, component) => Some(component.clone()),
+            Item::ExtDef(_visibility, component) => Some(component.clone()),
             _ => None,
         }
     }
@@ -262,39 +413,122 @@ impl Item {
             _ => None,
         }
     }
+
+    pub fn as_constdef(&self) -> Option<Arc<ConstDef>> {
+        match self {
+            Item::ConstDef(constdef) => Some(constdef.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn as_shapedef(&self) -> Option<Arc<ShapeDef>> {
+        match self {
+            Item::ShapeDef(shapedef) => Some(shapedef.clone()),
+            _ => None,
+        }
+    }
 }
 
 impl HasSpan for Item {
     fn span(&self) -> Span {
         match self {
-            Item::ModDef(component) => component.span(),
-            Item::ExtDef(component) => component.span(),
+            Item::ModDef(_visibility, component) => component.span(),
+            Item::ExtDef(_visibility, component) => component.span(),
             Item::EnumTypeDef(typedef) => typedef.span.clone(),
             Item::StructTypeDef(typedef) => typedef.span.clone(),
             Item::AltTypeDef(typedef) => typedef.span.clone(),
             Item::FnDef(typedef) => typedef.span.clone(),
             Item::TbDef(tbdef) => tbdef.span.clone(),
+            Item::ConstDef(constdef) => constdef.span.clone(),
+            Item::ShapeDef(shapedef) => shapedef.span.clone(),
         }
     }
 }
 
 /// [`Wire`]s drive the value of port, node, or register.
+///
+/// When a [`Select`] is given, the wire only drives that part of the target. Eg, `out->valid := 1;`.
 #[derive(Debug, Clone)]
-pub struct Wire(pub Span, pub Path, pub Arc<Expr>, pub WireType);
+pub struct Wire(pub Span, pub Path, pub Option<Select>, pub Arc<Expr>, pub WireType);
+
+/// The part of a component driven by a partial connection.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Select {
+    /// A field of a struct. Eg, `out->valid`.
+    Field(Name),
+    /// A bit of a `Word` or an element of a `Vec`. Eg, `bus[3]`.
+    Idx(u64),
+    /// A range of bits of a `Word`. Eg, `bus[8..4]`.
+    IdxRange(u64, u64),
+}
+
+impl Select {
+    /// The type of the selected part of a value of type `typ`.
+    pub fn type_of(&self, typ: &Type) -> Option<Type> {
+        match (self, typ) {
+            (Select::Field(field), Type::Struct(typedef)) => typedef.type_of_field(field),
+            (Select::Idx(i), Type::Word(n)) if i < n => Some(Type::word(1)),
+            (Select::Idx(i), Type::Vec(typ, n)) if i < n => Some(*typ.clone()),
+            (Select::IdxRange(j, i), Type::Word(n)) if i < j && j <= n => Some(Type::word(j - i)),
+            _ => None,
+        }
+    }
+
+    /// Break a selection down into single fields, bits, or elements.
+    pub fn atoms(&self) -> Vec<Select> {
+        match self {
+            Select::IdxRange(j, i) => (*i..*j).map(Select::Idx).collect(),
+            _ => vec![self.clone()],
+        }
+    }
+
+    /// Every field, bit, or element of a value of type `typ`, when it may be driven in parts.
+    pub fn atoms_of(typ: &Type) -> Option<Vec<Select>> {
+        match typ {
+            Type::Struct(typedef) => Some(typedef.fields.iter().map(|(name, _typ)| Select::Field(name.clone())).collect()),
+            Type::Word(n) => Some((0..*n).map(Select::Idx).collect()),
+            Type::Vec(_typ, n) => Some((0..*n).map(Select::Idx).collect()),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Select {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Select::Field(field) => write!(f, "->{field}"),
+            Select::Idx(i) => write!(f, "[{i}]"),
+            Select::IdxRange(j, i) => write!(f, "[{j}..{i}]"),
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct When(pub Arc<Expr>, pub Vec<Wire>);
 
+/// An `assert` or `cover` statement in a `mod`.
+/// Its condition is checked on every clock cycle during simulation.
+#[derive(Debug, Clone)]
+pub struct Assertion(pub Span, pub AssertionKind, pub Arc<Expr>);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssertionKind {
+    /// `assert cond;` fails when `cond` is false.
+    Assert,
+    /// `cover cond;` counts the cycles on which `cond` is true.
+    Cover,
+}
+
 impl Wire {
     pub fn new(span: Span, target: Path, expr: Arc<Expr>, typ: WireType) -> Wire {
         // TODO REMOVE THIS.
-        Wire(span, target, expr, typ)
+        Wire(span, target, None, expr, typ)
     }
 }
 
 impl HasSpan for Wire {
     fn span(&self) -> Span {
-        let Wire(span, _target, _expr, _wire_type) = self;
+        let Wire(span, _target, _select, _expr, _wire_type) = self;
         span.clone()
     }
 }
@@ -302,14 +536,16 @@ impl HasSpan for Wire {
 impl HasSpan for Component {
     fn span(&self) -> Span {
         match self {
-            Component::Mod(span, _name, _children, _wires, _whens) => span.clone(),
-            Component::ModInst(span, _name, _moddef) => span.clone(),
-            Component::Ext(span, _name, _children) => span.clone(),
+            Component::Mod(span, _doc, _name, _type_args, _children, _wires, _whens, _assertions, _interfaces) => span.clone(),
+            Component::ModInst(span, _name, _moddef, _params) => span.clone(),
+            Component::ModInstVec(span, _name, _insts) => span.clone(),
+            Component::Ext(span, _doc, _name, _children) => span.clone(),
             Component::Dom(span, _name) => span.clone(),
-            Component::Incoming(span, _name, _typ) => span.clone(),
-            Component::Outgoing(span, _name, _typ) => span.clone(),
+            Component::Incoming(span, _doc, _name, _typ) => span.clone(),
+            Component::Outgoing(span, _doc, _name, _typ) => span.clone(),
             Component::Node(span, _name, _typ) => span.clone(),
-            Component::Reg(span, _name, _typ, _expr) => span.clone(),
+            Component::Reg(span, _doc, _name, _typ, _dom, _expr) => span.clone(),
+            Component::Mem(span, _name, _typ, _dom, _latency, _ports) => span.clone(),
         }
     }
 }
@@ -1,18 +1,133 @@
 use super::*;
 
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
 pub fn load_package_from_file<P: AsRef<std::path::Path>>(path: P) -> Result<Package, Vec<BitsyError>> {
-    let package_text = std::fs::read_to_string(path.as_ref()).unwrap();
-    let source_info = SourceInfo::from_file(path.as_ref(), &package_text);
-    package_from_string(source_info, &package_text)
+    let mut loader = Loader::new();
+    if let Some(dir) = path.as_ref().parent() {
+        loader = loader.add_search_path(dir);
+    }
+    loader.load_package_from_file(path)
 }
 
 pub fn load_package_from_string(package_text: &str) -> Result<Package, Vec<BitsyError>> {
-    let source_info = SourceInfo::from_string(package_text);
-    package_from_string(source_info, package_text)
+    Loader::new().load_package_from_string(package_text)
+}
+
+/// A [`Loader`] loads packages, following their `import`s.
+///
+/// An `import foo` is satisfied by the first file named `foo.bitsy` found in the search path.
+/// Each package is loaded at most once per [`Loader`],
+/// so a package imported along two different routes shares the same definitions.
+#[derive(Debug, Default)]
+pub struct Loader {
+    search_path: Vec<PathBuf>,
+    loaded: RefCell<BTreeMap<Name, Package>>,
+    loading: RefCell<Vec<Name>>,
 }
 
-fn package_from_string(_source_info: SourceInfo, package_text: &str) -> Result<Package, Vec<BitsyError>> {
-    // TODO source info
-    let package_ast = crate::ast::parse_package_from_string(package_text)?;
-    Package::from(&package_ast)
+impl Loader {
+    pub fn new() -> Loader {
+        Loader::default()
+    }
+
+    /// Add a directory to the end of the search path.
+    pub fn add_search_path<P: AsRef<std::path::Path>>(mut self, dir: P) -> Self {
+        self.search_path.push(dir.as_ref().to_owned());
+        self
+    }
+
+    pub fn search_path(&self) -> &[PathBuf] {
+        &self.search_path
+    }
+
+    pub fn load_package_from_file<P: AsRef<std::path::Path>>(&self, path: P) -> Result<Package, Vec<BitsyError>> {
+        // The package being loaded counts towards import cycles, too.
+        let name = path.as_ref().file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+        self.loading.borrow_mut().push(name);
+        let result = self.load_file(path.as_ref(), &Span::unknown());
+        self.loading.borrow_mut().pop();
+        result
+    }
+
+    /// Load a package from a file. Failing to read it is reported at `span`, which is where it was imported.
+    fn load_file(&self, path: &std::path::Path, span: &Span) -> Result<Package, Vec<BitsyError>> {
+        let package_text = match std::fs::read_to_string(path) {
+            Ok(package_text) => package_text,
+            Err(error) => return Err(vec![BitsyError::CantReadFile(span.clone(), path.display().to_string(), error.to_string())]),
+        };
+        let source_info = SourceInfo::from_file(path, &package_text);
+        self.package_from_string(source_info, &package_text)
+    }
+
+    pub fn load_package_from_string(&self, package_text: &str) -> Result<Package, Vec<BitsyError>> {
+        let source_info = SourceInfo::from_string(package_text);
+        self.package_from_string(source_info, package_text)
+    }
+
+    fn package_from_string(&self, source_info: SourceInfo, package_text: &str) -> Result<Package, Vec<BitsyError>> {
+        let package_ast = crate::ast::parse_package(&source_info, package_text)?;
+        let imports = self.load_imports(&package_ast)?;
+        Package::from(&package_ast, imports)
+    }
+
+    fn load_imports(&self, package_ast: &ast::Package) -> Result<BTreeMap<Name, Package>, Vec<BitsyError>> {
+        let mut imports = BTreeMap::new();
+        let mut errors = vec![];
+
+        for import in &package_ast.imports {
+            match self.load_import(import) {
+                Ok(package) => {
+                    imports.insert(import.package.to_string(), package);
+                },
+                Err(errs) => errors.extend(errs),
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(imports)
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn load_import(&self, import: &ast::Import) -> Result<Package, Vec<BitsyError>> {
+        let name = import.package.to_string();
+
+        if let Some(package) = self.loaded.borrow().get(&name) {
+            return Ok(package.clone());
+        }
+
+        if let Some(start) = self.loading.borrow().iter().position(|loading_name| *loading_name == name) {
+            let mut cycle: Vec<Name> = self.loading.borrow()[start..].to_vec();
+            cycle.push(name);
+            return Err(vec![BitsyError::ImportCycle(import.span.clone(), cycle)]);
+        }
+
+        let filename = if let Some(filename) = self.find_package(&name) {
+            filename
+        } else {
+            return Err(vec![BitsyError::ImportNotFound(import.span.clone(), name)]);
+        };
+
+        self.loading.borrow_mut().push(name.clone());
+        let result = self.load_file(&filename, &import.span);
+        self.loading.borrow_mut().pop();
+
+        let package = result?;
+        self.loaded.borrow_mut().insert(name, package.clone());
+        Ok(package)
+    }
+
+    fn find_package(&self, name: &str) -> Option<PathBuf> {
+        for dir in &self.search_path {
+            let filename = dir.join(format!("{name}.bitsy"));
+            if filename.is_file() {
+                return Some(filename);
+            }
+        }
+        None
+    }
 }
@@ -32,7 +32,10 @@ impl Repl {
                     self.readline.add_history_entry(line.as_str()).unwrap();
                     return line;
                 },
-                Err(rustyline::error::ReadlineError::Eof) => std::process::exit(0),
+                Err(rustyline::error::ReadlineError::Eof) => {
+                    self.show_summary();
+                    std::process::exit(0)
+                },
                 Err(rustyline::error::ReadlineError::Interrupted) => (),
                 Err(e) => panic!("{e:?}"),
             }
@@ -45,6 +48,28 @@ impl Repl {
         for command in commands{
             self.exec_tb_command(command);
         }
+        self.show_summary();
+    }
+
+    /// Report the `assert` failures, `cover` hits, and holes evaluated during the simulation.
+    fn show_summary(&self) {
+        for (span, hole) in self.sim.holes_evaluated() {
+            eprintln!("{}", render_warning(&span, &format!("hole {hole} evaluated to X")));
+        }
+        let failures = self.sim.assert_failures();
+        if !failures.is_empty() {
+            println!("{} assertion failures", failures.len());
+            for failure in failures {
+                println!("    {} {} on cycle {}", failure.path, failure.span, failure.clock_ticks);
+            }
+        }
+        let covers = self.sim.covers();
+        if !covers.is_empty() {
+            println!("Coverage:");
+            for (path, span, count) in covers {
+                println!("    {path} {span}: {count}");
+            }
+        }
     }
 
     fn exec_tb_command(&mut self, command: TestbenchCommand) {
@@ -105,13 +130,29 @@ impl Repl {
                 // self.sim.set(terminal, value);
                 eprintln!("Not implemented");
             },
-            TestbenchCommand::Clock => {
+            TestbenchCommand::Clock(None) => {
                 if verbose {
                     println!("CLOCK");
                 }
                 self.sim.clock();
                 self.show_watches();
             },
+            TestbenchCommand::Clock(Some(dom)) => {
+                let abs_dom = if dom.is_absolute() {
+                    dom
+                } else {
+                    self.current_path.join(dom)
+                };
+
+                if verbose {
+                    println!("CLOCK {abs_dom}");
+                }
+                if let Err(error) = self.sim.clock_domain(abs_dom) {
+                    println!("Can't clock: {error}");
+                    return;
+                }
+                self.show_watches();
+            },
             TestbenchCommand::Reset => {
                 if verbose {
                     println!("RESET");
@@ -7,15 +7,24 @@ use once_cell::sync::OnceCell;
 use std::sync::Arc;
 use std::sync::Mutex;
 
-pub struct Namespace {
+/// The children, wires, `when` blocks, and assertions declared in the body of a `mod`.
+type ModBody = (Vec<Arc<Component>>, Vec<Wire>, Vec<When>, Vec<Assertion>);
+
+pub struct Namespace<'a> {
     items: BTreeMap<String, Item>,
+    imports: &'a BTreeMap<String, Package>,
     idents: Mutex<Vec<Ident>>,
+    type_args: Mutex<Vec<(String, Kind)>>,
+    locals: Mutex<BTreeSet<String>>,
+    interfaces: Mutex<BTreeMap<String, InterfacePort>>,
+    insts: Mutex<BTreeMap<String, Arc<Component>>>,
+    deferred: Mutex<BTreeMap<String, ast::ModDef>>,
 }
 
-pub fn resolve(package: &ast::Package) -> Result<Namespace, Vec<BitsyError>> {
-    let mut namespace = Namespace::new();
+pub fn resolve<'a>(package: &ast::Package, imports: &'a BTreeMap<String, Package>) -> Result<Namespace<'a>, Vec<BitsyError>> {
+    let mut namespace = Namespace::new(imports);
 
-    for item in order_items(package)? {
+    for item in order_items(package, imports)? {
         let item = namespace.resolve_item(item)?;
         namespace.add_item(item.name(), item.clone());
     }
@@ -23,11 +32,17 @@ pub fn resolve(package: &ast::Package) -> Result<Namespace, Vec<BitsyError>> {
     Ok(namespace)
 }
 
-impl Namespace {
-    fn new() -> Namespace {
+impl<'a> Namespace<'a> {
+    fn new(imports: &'a BTreeMap<String, Package>) -> Namespace<'a> {
         Namespace {
             items: BTreeMap::new(),
+            imports,
             idents: Mutex::new(Vec::new()),
+            type_args: Mutex::new(Vec::new()),
+            locals: Mutex::new(BTreeSet::new()),
+            interfaces: Mutex::new(BTreeMap::new()),
+            insts: Mutex::new(BTreeMap::new()),
+            deferred: Mutex::new(BTreeMap::new()),
         }
     }
 
@@ -40,6 +55,11 @@ impl Namespace {
         idents.to_vec()
     }
 
+    /// The parametric `mod`s whose bodies can only be resolved once they're instantiated. See [`resolve_instance`].
+    pub(crate) fn deferred(&self) -> BTreeMap<String, ast::ModDef> {
+        self.deferred.lock().unwrap().clone()
+    }
+
     fn add_item(&mut self, name: &str, item: Item) {
         let prev = self.items.insert(name.to_string(), item);
         assert!(prev.is_none());
@@ -50,15 +70,172 @@ impl Namespace {
         idents.push(ident.clone());
     }
 
+    /// Resolve with the type arguments of a parametric definition in scope.
+    fn with_type_args<T>(&self, type_args: &[(String, Kind)], f: impl FnOnce() -> T) -> T {
+        let saved = std::mem::replace(&mut *self.type_args.lock().unwrap(), type_args.to_vec());
+        let result = f();
+        *self.type_args.lock().unwrap() = saved;
+        result
+    }
+
+    /// Resolve with local names, such as components and `let` bindings, in scope.
+    /// These shadow any `const` definitions of the same name.
+    fn with_locals<T>(&self, names: impl IntoIterator<Item = String>, f: impl FnOnce() -> T) -> T {
+        let saved = self.locals.lock().unwrap().clone();
+        self.locals.lock().unwrap().extend(names);
+        let result = f();
+        *self.locals.lock().unwrap() = saved;
+        result
+    }
+
+    /// Resolve with the interface ports and the instances declared in the body of a `mod` in scope.
+    /// These are needed to resolve references to interface ports and bulk connections.
+    fn with_scope<T>(&self, decls: &[ast::Decl], f: impl FnOnce() -> Result<T, Vec<BitsyError>>) -> Result<T, Vec<BitsyError>> {
+        let mut interfaces = BTreeMap::new();
+        let mut insts = BTreeMap::new();
+        self.scan_scope(decls, &mut interfaces, &mut insts)?;
+
+        let saved_interfaces = std::mem::replace(&mut *self.interfaces.lock().unwrap(), interfaces);
+        let saved_insts = std::mem::replace(&mut *self.insts.lock().unwrap(), insts);
+        let result = f();
+        *self.interfaces.lock().unwrap() = saved_interfaces;
+        *self.insts.lock().unwrap() = saved_insts;
+        result
+    }
+
+    /// The interface ports declared in the body of a `mod`, to record on its [`Component::Mod`].
+    fn interfaces_of(&self, decls: &[ast::Decl]) -> Result<Vec<InterfacePort>, Vec<BitsyError>> {
+        let mut interfaces = BTreeMap::new();
+        self.scan_scope(decls, &mut interfaces, &mut BTreeMap::new())?;
+        Ok(interfaces.into_values().collect())
+    }
+
+    fn scan_scope(
+        &self,
+        decls: &[ast::Decl],
+        interfaces: &mut BTreeMap<String, InterfacePort>,
+        insts: &mut BTreeMap<String, Arc<Component>>,
+    ) -> Result<(), Vec<BitsyError>> {
+        for decl in decls {
+            match decl {
+                ast::Decl::Port(_loc, name, flipped, shape_name) => {
+                    let Some(shapedef) = self.item(shape_name.as_str()).and_then(|item| item.as_shapedef()) else {
+                        return Err(vec![BitsyError::NotAShape(shape_name.span.clone(), shape_name.to_string())]);
+                    };
+                    let interface = InterfacePort { name: name.to_string(), shapedef, flipped: *flipped };
+                    interfaces.insert(name.to_string(), interface);
+                },
+                ast::Decl::ModInst(_loc, name, moddef_name, _params) | ast::Decl::ModInstVec(_loc, name, _, moddef_name, _params) => {
+                    if let Some(moddef) = self.moddef(moddef_name.as_str()) {
+                        insts.insert(name.to_string(), moddef);
+                    }
+                },
+                ast::Decl::For(_loc, _var, _start, _end, decls) => self.scan_scope(decls, interfaces, insts)?,
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    fn is_interface(&self, name: &str) -> bool {
+        self.interfaces.lock().unwrap().contains_key(name)
+    }
+
+    /// The ports which make up an interface port, as the name of the port in the [`ShapeDef`],
+    /// the path of the flattened port, and whether the `mod` drives it.
+    ///
+    /// The interface ports of submodules are looked up on their definitions.
+    fn interface_ports(&self, span: &Span, target: &ast::Target) -> Result<Vec<(Name, Path, bool)>, Vec<BitsyError>> {
+        let (inst, port) = match target {
+            ast::Target::Local(id) => {
+                let Some(interface) = self.interfaces.lock().unwrap().get(id.as_str()).cloned() else {
+                    return Err(vec![BitsyError::NotAnInterface(span.clone(), id.to_string())]);
+                };
+                let mut results = vec![];
+                for (name, port_name, dir, _typ) in interface.ports() {
+                    results.push((name, port_name.into(), dir == Direction::Outgoing));
+                }
+                return Ok(results);
+            },
+            ast::Target::Nonlocal(id, port) => (id.to_string(), port),
+            ast::Target::Indexed(id, i, port) => (format!("{id}.{}", self.resolve_const_nat(span, i)?), port),
+            ast::Target::Nested(_, _, _) | ast::Target::Partial(_, _) => {
+                return Err(vec![BitsyError::NotAnInterface(span.clone(), target.to_string())]);
+            },
+        };
+
+        let inst_name = inst.split('.').next().unwrap();
+        let Some(moddef) = self.insts.lock().unwrap().get(inst_name).cloned() else {
+            return Err(vec![BitsyError::NoSuchComponent(span.clone(), inst_name.to_string())]);
+        };
+        let Some(interface) = moddef.interfaces().into_iter().find(|interface| interface.name == port.as_str()) else {
+            return Err(vec![BitsyError::NotAnInterface(span.clone(), format!("{inst}.{port}"))]);
+        };
+        let mut results = vec![];
+        for (name, port_name, dir, _typ) in interface.ports() {
+            // The incoming ports of a submodule are driven by the `mod` which instantiates it.
+            results.push((name, format!("{inst}.{port_name}").into(), dir == Direction::Incoming));
+        }
+        Ok(results)
+    }
+
+    /// Connect each port of one interface port to the port of the same name in another.
+    /// Whichever of the two is driven by the `mod` is driven by the other.
+    fn resolve_connect(&self, span: &Span, a: &ast::Target, b: &ast::Target) -> Result<Vec<Wire>, Vec<BitsyError>> {
+        let a_ports = self.interface_ports(span, a)?;
+        let b_ports = self.interface_ports(span, b)?;
+
+        let mut errors = vec![];
+        let mut wires = vec![];
+        for (name, a_path, a_driven) in &a_ports {
+            let Some((_name, b_path, b_driven)) = b_ports.iter().find(|(b_name, _path, _driven)| b_name == name) else {
+                errors.push(BitsyError::InterfaceMismatch(span.clone(), format!("no port {name} on both ends")));
+                continue;
+            };
+            let (target, source) = match (a_driven, b_driven) {
+                (true, false) => (a_path, b_path),
+                (false, true) => (b_path, a_path),
+                _ => {
+                    errors.push(BitsyError::InterfaceMismatch(span.clone(), format!("{a_path} and {b_path} have the same direction")));
+                    continue;
+                },
+            };
+            let expr = Arc::new(Expr::Reference(span.clone(), OnceCell::new(), source.clone()));
+            wires.push(Wire(span.clone(), target.clone(), None, expr, WireType::Direct));
+        }
+        for (name, _path, _driven) in &b_ports {
+            if !a_ports.iter().any(|(a_name, _path, _driven)| a_name == name) {
+                errors.push(BitsyError::InterfaceMismatch(span.clone(), format!("no port {name} on both ends")));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(wires)
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn type_arg(&self, name: &str) -> Option<Kind> {
+        let type_args = self.type_args.lock().unwrap();
+        type_args.iter().find(|(arg_name, _kind)| arg_name == name).map(|(_arg_name, kind)| kind.clone())
+    }
+
+    /// Look up an item by name.
+    /// Qualified names, such as `foo::Bar`, are looked up in the imported package `foo`.
     fn item(&self, name: &str) -> Option<Item> {
-        self.items.get(name).cloned()
+        if let Some((package_name, rest)) = name.split_once("::") {
+            self.imports.get(package_name)?.item(rest)
+        } else {
+            self.items.get(name).cloned()
+        }
     }
 
     fn moddef(&self, name: &str) -> Option<Arc<Component>> {
         let item = self.item(name);
-        if let Some(Item::ModDef(component)) = &item {
+        if let Some(Item::ModDef(_visibility, component)) = &item {
             Some(component.clone())
-        } else if let Some(Item::ExtDef(component)) = &item {
+        } else if let Some(Item::ExtDef(_visibility, component)) = &item {
             Some(component.clone())
         } else {
             None
@@ -74,38 +251,113 @@ impl Namespace {
         }
     }
 
+    fn constdef(&self, name: &str) -> Option<Arc<ConstDef>> {
+        if self.locals.lock().unwrap().contains(name) {
+            return None;
+        }
+        self.item(name)?.as_constdef()
+    }
+
+    fn const_nat(&self, name: &str) -> Option<u64> {
+        match self.constdef(name)?.value {
+            ConstValue::Nat(n) => Some(n),
+            ConstValue::Value(_, _) => None,
+        }
+    }
+
     fn resolve_item(&self, item: &ast::Item) -> Result<Item, Vec<BitsyError>> {
         Ok(match item {
-            ast::Item::ModDef(moddef) => Item::ModDef(self.resolve_moddef(moddef)?),
-            ast::Item::ExtDef(moddef) => Item::ExtDef(self.resolve_extmoddef(moddef)?),
+            ast::Item::ModDef(moddef) => Item::ModDef(moddef.2, self.resolve_moddef(moddef)?),
+            ast::Item::ExtDef(moddef) => Item::ExtDef(moddef.2, self.resolve_extmoddef(moddef)?),
             ast::Item::EnumTypeDef(typedef) => Item::EnumTypeDef(self.resolve_enum_typedef(typedef)?),
             ast::Item::StructTypeDef(typedef) => Item::StructTypeDef(self.resolve_struct_typedef(typedef)?),
             ast::Item::AltTypeDef(typedef) => Item::AltTypeDef(self.resolve_alt_typedef(typedef)?),
             ast::Item::FnDef(fndef) => Item::FnDef(self.resolve_fndef(fndef)?),
             ast::Item::TbDef(fndef) => Item::TbDef(self.resolve_tbdef(fndef)?),
+            ast::Item::ConstDef(constdef) => Item::ConstDef(self.resolve_constdef(constdef)?),
+            ast::Item::ShapeDef(shapedef) => Item::ShapeDef(self.resolve_shapedef(shapedef)?),
         })
     }
 
     fn resolve_moddef(&self, moddef: &ast::ModDef) -> Result<Arc<Component>, Vec<BitsyError>> {
-        let ast::ModDef(span, name, decls) = moddef;
+        let ast::ModDef(span, doc, _visibility, name, type_args, decls) = moddef;
+        let type_args: Vec<(String, Kind)> = type_args.iter().map(|(name, kind)| (name.to_string(), kind.clone())).collect();
         let decls_slice: &[&ast::Decl] = &decls.iter().collect::<Vec<_>>();
-        let (children, wires, whens) = self.resolve_decls(decls_slice)?;
-        Ok(Arc::new(Component::Mod(span.clone(), name.to_string(), children, wires, whens)))
+        let component_names = moddef_component_names(moddef)?;
+
+        // When the structure of the body depends on a `Nat` parameter, such as the bounds of a `for`,
+        // only the ports are resolved here. The rest waits until the `mod` is instantiated.
+        let nat_args: BTreeSet<&str> = type_args.iter().filter(|(_name, kind)| *kind == Kind::Nat).map(|(name, _kind)| name.as_str()).collect();
+        let decls_slice: &[&ast::Decl] = if structural_dependencies(decls).iter().any(|dependency| nat_args.contains(dependency.as_str())) {
+            self.deferred.lock().unwrap().insert(name.to_string(), moddef.clone());
+            &decls_slice.iter().copied().filter(|decl| decl.is_port_or_dom()).collect::<Vec<_>>()
+        } else {
+            decls_slice
+        };
+
+        let (children, wires, whens, assertions) = self.with_type_args(&type_args, || {
+            self.with_locals(component_names, || self.with_scope(decls, || self.resolve_decls(decls_slice)))
+        })?;
+        let interfaces = self.interfaces_of(decls)?;
+        Ok(Arc::new(Component::Mod(span.clone(), doc.clone(), name.to_string(), type_args, children, wires, whens, assertions, interfaces)))
     }
 
     fn resolve_extmoddef(&self, moddef: &ast::ModDef) -> Result<Arc<Component>, Vec<BitsyError>> {
-        let ast::ModDef(span, name, decls) = moddef;
+        let ast::ModDef(span, doc, _visibility, name, _type_args, decls) = moddef;
         let decls_slice: &[&ast::Decl] = &decls.iter().collect::<Vec<_>>();
-        let (children, wires, whens) = self.resolve_decls(decls_slice)?;
-        assert!(wires.is_empty());
-        assert!(whens.is_empty());
-        Ok(Arc::new(Component::Ext(span.clone(), name.to_string(), children)))
+        let (children, wires, whens, assertions) = self.with_scope(decls, || self.resolve_decls(decls_slice))?;
+        let mut errors = vec![];
+        for wire in &wires {
+            errors.push(BitsyError::ExtHasStatement(wire.span(), "a connection".to_string()));
+        }
+        for When(cond, _wires) in &whens {
+            errors.push(BitsyError::ExtHasStatement(cond.span(), "a when".to_string()));
+        }
+        for Assertion(span, kind, _cond) in &assertions {
+            let statement = match kind {
+                AssertionKind::Assert => "an assert",
+                AssertionKind::Cover => "a cover",
+            };
+            errors.push(BitsyError::ExtHasStatement(span.clone(), statement.to_string()));
+        }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        Ok(Arc::new(Component::Ext(span.clone(), doc.clone(), name.to_string(), children)))
     }
 
     fn resolve_enum_typedef(&self, typedef: &ast::EnumTypeDef) -> Result<Arc<EnumTypeDef>, Vec<BitsyError>> {
+        let width = match &typedef.typ {
+            None => None,
+            Some(typ) => match self.resolve_type(typ)? {
+                Type::Word(n) => Some(n),
+                typ => {
+                    let name = &typedef.name;
+                    return Err(vec![BitsyError::Unknown(Some(typedef.span.clone()), format!("Enum type {name} must be of Word[n], not {typ:?}"))]);
+                },
+            },
+        };
+
+        // Like C, a value without an explicit value is one more than the value before it.
+        let mut values = vec![];
+        let mut next_value = 0;
+        for (i, (name, val)) in typedef.values.iter().enumerate() {
+            let val = match val {
+                Some((_span, val)) => val.clone(),
+                None if typedef.onehot => WordLit(None, 1u64.checked_shl(i as u32).unwrap_or(0)),
+                None => WordLit(None, next_value),
+            };
+            next_value = val.value().wrapping_add(1);
+            values.push((name.to_string(), val));
+        }
+
         let package_typedef = Arc::new(EnumTypeDef {
             name: typedef.name.to_string(),
-            values: typedef.values.iter().map(|(name, val)| (name.to_string(), val.clone())).collect(),
+            visibility: typedef.visibility,
+            doc: typedef.doc.clone(),
+            values,
+            width,
+            onehot: typedef.onehot,
             span: typedef.span.clone(),
         });
         Ok(package_typedef)
@@ -119,6 +371,8 @@ impl Namespace {
 
         let package_typedef = Arc::new(StructTypeDef {
             name: typedef.name.to_string(),
+            visibility: typedef.visibility,
+            doc: typedef.doc.clone(),
             fields: fields.into_iter().collect(),
             span: typedef.span.clone(),
         });
@@ -126,18 +380,27 @@ impl Namespace {
     }
 
     fn resolve_alt_typedef(&self, typedef: &ast::AltTypeDef) -> Result<Arc<AltTypeDef>, Vec<BitsyError>> {
-        let mut alts: BTreeMap<String, Vec<Type>> = BTreeMap::new();
+        let type_args: Vec<(String, Kind)> = typedef.type_args.iter().map(|(name, kind)| (name.to_string(), kind.clone())).collect();
+
+        // The order of the alts is kept, since it determines their tags.
+        let mut alts: Vec<(String, Vec<Type>)> = vec![];
         for (name, typs) in &typedef.alts {
+            if alts.iter().any(|(other, _typs)| *other == name.to_string()) {
+                return Err(vec![BitsyError::Unknown(Some(name.span.clone()), format!("Duplicate alt in alt type {}: {name}", typedef.name))]);
+            }
             let mut alt_types = vec![];
             for typ in typs {
-                alt_types.push(self.resolve_type(typ)?);
+                alt_types.push(self.with_type_args(&type_args, || self.resolve_type(typ))?);
             }
-            alts.insert(name.to_string(), alt_types);
+            alts.push((name.to_string(), alt_types));
         }
 
         let package_typedef = Arc::new(AltTypeDef {
             name: typedef.name.to_string(),
-            alts: alts.into_iter().collect(),
+            visibility: typedef.visibility,
+            doc: typedef.doc.clone(),
+            type_args,
+            alts,
             span: typedef.span.clone(),
         });
         Ok(package_typedef)
@@ -149,52 +412,183 @@ impl Namespace {
             type_args.push((name.to_string(), kind.clone()));
         }
 
-        let mut args: Vec<(String, Type)> = vec![];
-        for (name, typ) in &fndef.args {
-            args.push((name.to_string(), self.resolve_type(typ)?));
+        self.with_type_args(&type_args.clone(), || {
+            let mut args: Vec<(String, Type)> = vec![];
+            for (name, typ) in &fndef.args {
+                args.push((name.to_string(), self.resolve_type(typ)?));
+            }
+
+            let package_typedef = Arc::new(FnDef {
+                span: fndef.span.clone(),
+                name: fndef.name.to_string(),
+                visibility: fndef.visibility,
+                doc: fndef.doc.clone(),
+                type_args,
+                args,
+                ret: self.resolve_type(&fndef.ret)?,
+                body: self.with_locals(fndef.args.iter().map(|(name, _typ)| name.to_string()), || {
+                    self.resolve_expr(&fndef.body, Context::empty())
+                })?,
+            });
+            Ok(package_typedef)
+        })
+    }
+
+    fn resolve_shapedef(&self, shapedef: &ast::ShapeDef) -> Result<Arc<ShapeDef>, Vec<BitsyError>> {
+        let mut ports = vec![];
+        for (name, dir, typ) in &shapedef.ports {
+            ports.push((name.to_string(), *dir, self.resolve_type(typ)?));
         }
 
-        let package_typedef = Arc::new(FnDef {
-            span: Span::unknown(),
-            name: fndef.name.to_string(),
-            type_args: type_args.into_iter().collect(),
-            args,
-            ret: self.resolve_type(&fndef.ret)?,
-            body: self.resolve_expr(&fndef.body, Context::empty())?,
-        });
-        Ok(package_typedef)
+        Ok(Arc::new(ShapeDef {
+            span: shapedef.span.clone(),
+            name: shapedef.name.to_string(),
+            visibility: shapedef.visibility,
+            doc: shapedef.doc.clone(),
+            ports,
+        }))
+    }
+
+    fn resolve_constdef(&self, constdef: &ast::ConstDef) -> Result<Arc<ConstDef>, Vec<BitsyError>> {
+        let value = match &constdef.value {
+            ast::ConstValue::Nat(n) => ConstValue::Nat(self.resolve_const_nat(&constdef.span, n)?),
+            ast::ConstValue::Expr(typ, e) => {
+                let typ = self.resolve_type(typ)?;
+                let e = self.resolve_expr(e, Context::empty())?;
+                if let Err(error) = e.typecheck(typ.clone(), Context::empty()) {
+                    return Err(vec![BitsyError::TypeError(error)]);
+                }
+                let value = e.eval_const();
+                if value.has_x() {
+                    let name = constdef.name.to_string();
+                    return Err(vec![BitsyError::InvalidConst(constdef.span.clone(), name, "its value is X, such as from a hole".to_string())]);
+                }
+                ConstValue::Value(typ, value)
+            },
+        };
+
+        Ok(Arc::new(ConstDef {
+            span: constdef.span.clone(),
+            name: constdef.name.to_string(),
+            visibility: constdef.visibility,
+            doc: constdef.doc.clone(),
+            value,
+        }))
     }
 
     fn resolve_tbdef(&self, tbdef: &ast::TbDef) -> Result<Arc<TbDef>, Vec<BitsyError>> {
-        let mut statements = vec![];
-        for statement in &tbdef.statements {
-            statements.push(self.resolve_tbstatement(statement)?);
-        }
+        // The DUTs declared so far, by name.
+        let mut duts = BTreeMap::new();
+        let statements = self.resolve_tbstatements(&tbdef.statements, &mut duts)?;
 
         Ok(Arc::new(TbDef {
             span: tbdef.span.clone(),
             name: tbdef.name.to_string(),
+            doc: tbdef.doc.clone(),
             statements,
         }))
     }
 
-    fn resolve_tbstatement(&self, statement: &ast::TbStatement) -> Result<TbStatement, Vec<BitsyError>> {
+    fn resolve_tbstatements(
+        &self,
+        statements: &[ast::TbStatement],
+        duts: &mut BTreeMap<Name, Arc<Component>>,
+    ) -> Result<Vec<TbStatement>, Vec<BitsyError>> {
+        let mut results = vec![];
+        for statement in statements {
+            results.push(self.resolve_tbstatement(statement, duts)?);
+        }
+        Ok(results)
+    }
+
+    fn resolve_tbstatement(
+        &self,
+        statement: &ast::TbStatement,
+        duts: &mut BTreeMap<Name, Arc<Component>>,
+    ) -> Result<TbStatement, Vec<BitsyError>> {
         Ok(match statement {
             ast::TbStatement::Debug => TbStatement::Debug,
             ast::TbStatement::Reset => TbStatement::Reset,
             ast::TbStatement::Clock => TbStatement::Clock,
             ast::TbStatement::ModInst(name, moddef_name) => {
-                let moddef = self.moddef(moddef_name.as_str()).unwrap();
+                let moddef = match self.moddef(moddef_name.as_str()) {
+                    Some(moddef) => moddef,
+                    None => return Err(vec![BitsyError::UndefinedMod(moddef_name.span(), moddef_name.to_string())]),
+                };
+                duts.insert(name.to_string(), moddef.clone());
                 TbStatement::ModInst(name.to_string(), moddef)
-            }
+            },
+            ast::TbStatement::Poke(span, dut, port, e) => {
+                let e = self.resolve_tb_expr(span, duts, dut, port, e, true)?;
+                TbStatement::Poke(span.clone(), dut.to_string(), port.to_string(), e)
+            },
+            ast::TbStatement::Expect(span, dut, port, e) => {
+                let e = self.resolve_tb_expr(span, duts, dut, port, e, false)?;
+                TbStatement::Expect(span.clone(), dut.to_string(), port.to_string(), e)
+            },
+            ast::TbStatement::Loop(span, n, statements) => {
+                TbStatement::Loop(span.clone(), *n, self.resolve_tbstatements(statements, duts)?)
+            },
         })
     }
 
+    /// Fold the value given to a `poke` or an `expect`, checking it against the type of the port.
+    fn resolve_tb_expr(
+        &self,
+        span: &Span,
+        duts: &BTreeMap<Name, Arc<Component>>,
+        dut: &ast::Ident,
+        port: &ast::Ident,
+        e: &ast::Expr,
+        poke: bool,
+    ) -> Result<Arc<Expr>, Vec<BitsyError>> {
+        let Some(moddef) = duts.get(dut.as_str()) else {
+            return Err(vec![BitsyError::NoSuchDut(span.clone(), dut.to_string())]);
+        };
+        let Some(component) = moddef.child(port.as_str()) else {
+            return Err(vec![BitsyError::NoSuchPort(span.clone(), format!("{dut}.{port}"))]);
+        };
+        if poke && !component.is_incoming_port() {
+            return Err(vec![BitsyError::NotPokeable(span.clone(), format!("{dut}.{port}"))]);
+        }
+        let Some(typ) = component.type_of() else {
+            return Err(vec![BitsyError::NoValue(span.clone(), format!("{dut}.{port}"))]);
+        };
+
+        let e = self.resolve_expr(e, Context::empty())?;
+        if let Err(error) = e.typecheck(typ, Context::empty()) {
+            return Err(vec![BitsyError::TypeError(error)]);
+        }
+        Ok(e)
+    }
+
     fn resolve_type(&self, typ: &ast::Type) -> Result<Type, Vec<BitsyError>> {
         Ok(match typ {
-            ast::Type::Word(n) => Type::word(*n),
-            ast::Type::Vec(t, n) => Type::vec(self.resolve_type(t)?, *n),
+            ast::Type::Word(span, n) => {
+                match self.resolve_width(span, n)? {
+                    Nat::Lit(n) => Type::word(n),
+                    n => Type::GenericWord(n),
+                }
+            },
+            ast::Type::SInt(span, n) => {
+                match self.resolve_width(span, n)? {
+                    Nat::Lit(n) => Type::SInt(n),
+                    n => Type::GenericSInt(n),
+                }
+            },
+            ast::Type::Vec(span, t, n) => {
+                match self.resolve_width(span, n)? {
+                    Nat::Lit(n) => Type::vec(self.resolve_type(t)?, n),
+                    n => Type::GenericVec(Box::new(self.resolve_type(t)?), n),
+                }
+            },
             ast::Type::Valid(t) => Type::valid(self.resolve_type(t)?),
+            ast::Type::TypeRef(r, params) if self.type_arg(r.as_str()).is_some() => {
+                if self.type_arg(r.as_str()) != Some(Kind::Type) || !params.is_empty() {
+                    return Err(vec![BitsyError::Unknown(Some(r.span.clone()), format!("Not a type: {r}"))]);
+                }
+                Type::Var(r.to_string())
+            },
             ast::Type::TypeRef(r, params) => {
                 // TODO add params to Type::Enum Struct Alt.
                 self.add_ident(r);
@@ -205,7 +599,14 @@ impl Namespace {
                 match self.item(r.as_str()) {
                     Some(Item::EnumTypeDef(typedef)) => Type::Enum(typedef.clone()),
                     Some(Item::StructTypeDef(typedef)) => Type::Struct(typedef.clone()),
-                    Some(Item::AltTypeDef(typedef)) => Type::Alt(typedef.clone(), resolved_params),
+                    Some(Item::AltTypeDef(typedef)) => {
+                        if resolved_params.len() != typedef.type_args.len() {
+                            let expected = typedef.type_args.len();
+                            let found = resolved_params.len();
+                            return Err(vec![BitsyError::Unknown(Some(r.span.clone()), format!("Alt type {r} expects {expected} parameters, but found {found}"))]);
+                        }
+                        Type::Alt(typedef.clone(), resolved_params)
+                    },
                     Some(_) => return Err(vec![BitsyError::Unknown(None, format!("Not a type definition: {r}"))]),
                     None => return Err(vec![BitsyError::Unknown(None, format!("Type definition not found: {r}"))]),
                 }
@@ -215,46 +616,130 @@ impl Namespace {
 
     fn resolve_type_param(&self, typ: &ast::TypeParam) -> Result<TypeParam, Vec<BitsyError>> {
         Ok(match typ {
-            ast::TypeParam::Nat(n) => TypeParam::Nat(*n),
+            ast::TypeParam::Nat(n) => TypeParam::Nat(Nat::Lit(*n)),
+            // A bare identifier parses as a type, but it may name a `Nat` parameter instead.
+            ast::TypeParam::Type(ast::Type::TypeRef(r, params)) if params.is_empty() && self.type_arg(r.as_str()) == Some(Kind::Nat) => {
+                TypeParam::Nat(Nat::Param(r.to_string()))
+            },
+            ast::TypeParam::Type(ast::Type::TypeRef(r, params)) if params.is_empty() && self.const_nat(r.as_str()).is_some() => {
+                self.add_ident(r);
+                TypeParam::Nat(Nat::Lit(self.const_nat(r.as_str()).unwrap()))
+            },
             ast::TypeParam::Type(typ) => TypeParam::Type(self.resolve_type(typ)?),
         })
     }
 
-    fn resolve_decls(&self, decls: &[&ast::Decl]) -> Result<(Vec<Arc<Component>>, Vec<Wire>, Vec<When>), Vec<BitsyError>> {
+    /// Resolve the width or length of a type, at `span`.
+    /// Arithmetic on constants is evaluated, so that a width like `Word[2*4]` is concrete.
+    fn resolve_width(&self, span: &Span, n: &ast::Nat) -> Result<Nat, Vec<BitsyError>> {
+        let n = self.resolve_nat(n)?.subst(&BTreeMap::new());
+        if n.is_negative() {
+            return Err(vec![BitsyError::InvalidWidth(span.clone(), format!("{n} is negative"))]);
+        }
+        if n.is_overflow() {
+            return Err(vec![BitsyError::InvalidWidth(span.clone(), format!("{n} is too large"))]);
+        }
+        Ok(n)
+    }
+
+    fn resolve_nat(&self, n: &ast::Nat) -> Result<Nat, Vec<BitsyError>> {
+        Ok(match n {
+            ast::Nat::Lit(n) => Nat::Lit(*n),
+            ast::Nat::Param(r) if self.type_arg(r.as_str()).is_none() && self.const_nat(r.as_str()).is_some() => {
+                self.add_ident(r);
+                Nat::Lit(self.const_nat(r.as_str()).unwrap())
+            },
+            ast::Nat::Param(r) => {
+                if self.type_arg(r.as_str()) != Some(Kind::Nat) {
+                    return Err(vec![BitsyError::Unknown(Some(r.span.clone()), format!("Not a Nat parameter: {r}"))]);
+                }
+                Nat::Param(r.to_string())
+            },
+            ast::Nat::Add(n, m) => Nat::Add(Box::new(self.resolve_nat(n)?), Box::new(self.resolve_nat(m)?)),
+            ast::Nat::Sub(n, m) => Nat::Sub(Box::new(self.resolve_nat(n)?), Box::new(self.resolve_nat(m)?)),
+            ast::Nat::Mul(n, m) => Nat::Mul(Box::new(self.resolve_nat(n)?), Box::new(self.resolve_nat(m)?)),
+        })
+    }
+
+    fn resolve_decls(&self, decls: &[&ast::Decl]) -> Result<ModBody, Vec<BitsyError>> {
+        let mut inferred_nodes = vec![];
+        let (mut children, wires, whens, assertions) = self.resolve_decls_inferring(decls, &mut inferred_nodes)?;
+        self.infer_node_types(&mut children, &wires, inferred_nodes)?;
+        Ok((children, wires, whens, assertions))
+    }
+
+    /// Resolve the declarations in the body of a `mod`,
+    /// setting aside each `node x of _;` in `inferred_nodes` so its type can be inferred once the whole body is known.
+    fn resolve_decls_inferring(&self, decls: &[&ast::Decl], inferred_nodes: &mut Vec<(Span, Name)>) -> Result<ModBody, Vec<BitsyError>> {
         let mut children = vec![];
         let mut wires = vec![];
         let mut whens = vec![];
+        let mut assertions = vec![];
 
         for decl in decls {
             match decl {
-                ast::Decl::Mod(span, name, decls) => {
-                    let (inner_children, wires, whens) = self.resolve_decls(&decls.iter().collect::<Vec<_>>())?;
-                    let child = Component::Mod(span.clone(), name.to_string(), inner_children, wires, whens);
+                ast::Decl::Mod(span, doc, name, decls) => {
+                    let component_names = moddef_component_names_anonymous(decls)?;
+                    let (inner_children, wires, whens, assertions) = self.with_locals(component_names, || {
+                        self.with_scope(decls, || self.resolve_decls(&decls.iter().collect::<Vec<_>>()))
+                    })?;
+                    let interfaces = self.interfaces_of(decls)?;
+                    let child = Component::Mod(span.clone(), doc.clone(), name.to_string(), vec![], inner_children, wires, whens, assertions, interfaces);
                     children.push(Arc::new(child));
                 },
-                ast::Decl::ModInst(span, name, moddef_name) => {
+                ast::Decl::ModInst(span, name, moddef_name, params) => {
                     self.add_ident(moddef_name);
                     let moddef = self.moddef(moddef_name.as_str()).unwrap();
-                    let child = Component::ModInst(span.clone(), name.to_string(), moddef);
+                    let mut resolved_params = vec![];
+                    for param in params {
+                        resolved_params.push(self.resolve_type_param(param)?);
+                    }
+                    let child = Component::ModInst(span.clone(), name.to_string(), moddef, resolved_params);
+                    children.push(Arc::new(child));
+                },
+                ast::Decl::ModInstVec(span, name, n, moddef_name, params) => {
+                    self.add_ident(moddef_name);
+                    let moddef = self.moddef(moddef_name.as_str()).unwrap();
+                    let mut resolved_params = vec![];
+                    for param in params {
+                        resolved_params.push(self.resolve_type_param(param)?);
+                    }
+                    let insts = (0..self.resolve_const_nat(span, n)?).map(|i| {
+                        Arc::new(Component::ModInst(span.clone(), i.to_string(), moddef.clone(), resolved_params.clone()))
+                    }).collect();
+                    let child = Component::ModInstVec(span.clone(), name.to_string(), insts);
                     children.push(Arc::new(child));
                 },
                 ast::Decl::Dom(span, name) => {
                     let child = Component::Dom(span.clone(), name.to_string());
                     children.push(Arc::new(child));
                 },
-                ast::Decl::Incoming(span, name, typ) => {
-                    let child = Component::Incoming(span.clone(), name.to_string(), self.resolve_type(typ)?);
+                ast::Decl::Incoming(span, doc, name, typ) => {
+                    let child = Component::Incoming(span.clone(), doc.clone(), name.to_string(), self.resolve_type(typ)?);
                     children.push(Arc::new(child));
                 },
-                ast::Decl::Outgoing(span, name, typ) => {
-                    let child = Component::Outgoing(span.clone(), name.to_string(), self.resolve_type(typ)?);
+                ast::Decl::Outgoing(span, doc, name, typ) => {
+                    let child = Component::Outgoing(span.clone(), doc.clone(), name.to_string(), self.resolve_type(typ)?);
                     children.push(Arc::new(child));
                 },
-                ast::Decl::Node(span, name, typ) => {
+                ast::Decl::Node(span, name, Some(typ)) => {
                     let child = Component::Node(span.clone(), name.to_string(), self.resolve_type(typ)?);
                     children.push(Arc::new(child));
                 },
-                ast::Decl::Reg(span, name, typ, reset) => {
+                ast::Decl::Node(span, name, None) => inferred_nodes.push((span.clone(), name.to_string())),
+                // An interface port is flattened into one incoming or outgoing port for each port of its shape.
+                ast::Decl::Port(span, name, _flipped, _shape_name) => {
+                    let interface = self.interfaces.lock().unwrap()[name.as_str()].clone();
+                    for (_name, port_name, dir, typ) in interface.ports() {
+                        let child = match dir {
+                            Direction::Incoming => Component::Incoming(span.clone(), None, port_name, typ),
+                            Direction::Outgoing => Component::Outgoing(span.clone(), None, port_name, typ),
+                        };
+                        children.push(Arc::new(child));
+                    }
+                },
+                ast::Decl::Connect(span, a, b) => wires.extend(self.resolve_connect(span, a, b)?),
+                ast::Decl::Reg(span, doc, name, typ, dom, reset) => {
                     let reset_e = if let Some(e) = reset {
                         Some(self.resolve_expr(&e, Context::empty())?)
                     } else {
@@ -262,16 +747,34 @@ impl Namespace {
                     };
                     let child = Component::Reg(
                         span.clone(),
+                        doc.clone(),
                         name.to_string(),
                         self.resolve_type(typ)?,
+                        dom.as_ref().map(|dom| dom.to_string()),
                         reset_e,
                     );
                     children.push(Arc::new(child));
                 },
+                ast::Decl::Mem(span, name, typ, dom, latency, ports) => {
+                    let typ = self.resolve_type(typ)?;
+                    if !matches!(typ, Type::Vec(_, _) | Type::GenericVec(_, _)) {
+                        return Err(vec![BitsyError::InvalidMem(span.clone(), name.to_string(), format!("its type must be a Vec, not {typ:?}"))]);
+                    }
+                    let ports = ports.iter().map(|port| match port {
+                        ast::MemPort::Read(name) => MemPort::Read(name.to_string()),
+                        ast::MemPort::Write(name, mask) => MemPort::Write(name.to_string(), *mask),
+                    }).collect();
+                    // Reads take a cycle unless the latency is given.
+                    let dom = dom.as_ref().map(|dom| dom.to_string());
+                    let child = Component::Mem(span.clone(), name.to_string(), typ, dom, latency.unwrap_or(1), ports);
+                    children.push(Arc::new(child));
+                },
                 ast::Decl::Wire(span, ast::Wire(_loc, target, expr, wire_type)) => {
+                    let (target, select) = self.resolve_target(span, target)?;
                     let wire = Wire(
                         span.clone(),
-                        target_to_path(target),
+                        target,
+                        select,
                         self.resolve_expr(expr, Context::empty())?,
                         wire_type.clone(),
                     );
@@ -282,9 +785,11 @@ impl Namespace {
                     let package_cond = self.resolve_expr(&*cond, Context::empty())?;
 
                     for ast::Wire(span, target, expr, wire_type) in wires {
+                        let (target, select) = self.resolve_target(span, target)?;
                         let package_wire = Wire(
                             span.clone(),
-                            target_to_path(target),
+                            target,
+                            select,
                             self.resolve_expr(expr, Context::empty())?,
                             wire_type.clone(),
                         );
@@ -293,24 +798,169 @@ impl Namespace {
                     let package_when = When(package_cond, package_wires);
                     whens.push(package_when);
                 },
+                ast::Decl::Assert(span, cond) => {
+                    let cond = self.resolve_expr(cond, Context::empty())?;
+                    assertions.push(Assertion(span.clone(), AssertionKind::Assert, cond));
+                },
+                ast::Decl::Cover(span, cond) => {
+                    let cond = self.resolve_expr(cond, Context::empty())?;
+                    assertions.push(Assertion(span.clone(), AssertionKind::Cover, cond));
+                },
+                ast::Decl::For(span, var, start, end, decls) => {
+                    let start = self.resolve_const_nat(span, start)?;
+                    let end = self.resolve_const_nat(span, end)?;
+                    for i in start..end {
+                        let unrolled: Vec<ast::Decl> = decls.iter().map(|decl| unroll_decl(decl, var.as_str(), i)).collect();
+                        let unrolled: Vec<&ast::Decl> = unrolled.iter().collect();
+                        let (inner_children, inner_wires, inner_whens, inner_assertions) = self.resolve_decls_inferring(&unrolled, inferred_nodes)?;
+                        children.extend(inner_children);
+                        wires.extend(inner_wires);
+                        whens.extend(inner_whens);
+                        assertions.extend(inner_assertions);
+                    }
+                },
+            }
+        }
+
+        Ok((children, wires, whens, assertions))
+    }
+
+    /// Work out the type of each `node x of _;` from the wire which drives it.
+    /// A node's driver may refer to other such nodes, so this goes around until no more can be worked out.
+    fn infer_node_types(&self, children: &mut Vec<Arc<Component>>, wires: &[Wire], mut nodes: Vec<(Span, Name)>) -> Result<(), Vec<BitsyError>> {
+        let driver_of = |name: &str| {
+            wires.iter().find(|Wire(_loc, target, select, _expr, wire_type)| {
+                *wire_type == WireType::Direct && select.is_none() && target.to_string() == name
+            })
+        };
+
+        while !nodes.is_empty() {
+            let ctx = context_of(children, &Component::instantiated);
+            let mut remaining = vec![];
+            for (span, name) in nodes.iter() {
+                let typ = driver_of(name).and_then(|Wire(_loc, _target, _select, expr, _wire_type)| expr.typeinfer(ctx.clone()));
+                if let Some(typ) = typ {
+                    children.push(Arc::new(Component::Node(span.clone(), name.clone(), typ)));
+                } else {
+                    remaining.push((span.clone(), name.clone()));
+                }
+            }
+
+            if remaining.len() == nodes.len() {
+                let errors = remaining.into_iter().map(|(span, name)| {
+                    if let Some(Wire(_loc, _target, _select, expr, _wire_type)) = driver_of(&name) {
+                        let expr = expr.unsized_literal().unwrap_or_else(|| expr.clone());
+                        BitsyError::TypeError(TypeError::CantInferType(expr))
+                    } else {
+                        BitsyError::Unknown(Some(span), format!("Can't infer the type of node {name}: it has no driver"))
+                    }
+                }).collect();
+                return Err(errors);
             }
+            nodes = remaining;
         }
+        Ok(())
+    }
+
+    /// Resolve a [`ast::Nat`] which must be known before elaboration, such as the bounds of a `for`.
+    fn resolve_const_nat(&self, span: &Span, n: &ast::Nat) -> Result<u64, Vec<BitsyError>> {
+        match self.resolve_nat(n)?.subst(&BTreeMap::new()) {
+            Nat::Lit(n) => Ok(n),
+            n if n.is_overflow() => Err(vec![BitsyError::InvalidWidth(span.clone(), format!("{n} is too large"))]),
+            n => Err(vec![BitsyError::Unknown(Some(span.clone()), format!("Not a constant: {n}"))]),
+        }
+    }
 
-        Ok((children, wires, whens))
+    /// The value of an index made of literals and `Nat` constants. Eg, the `n-1` in `cells[n-1].out`.
+    fn const_index(&self, e: &ast::Expr) -> Option<u64> {
+        match e {
+            ast::Expr::Word(_span, None, n) => Some(*n),
+            ast::Expr::Ident(_span, id) => self.const_nat(id.as_str()),
+            ast::Expr::BinOp(_span, BinOp::Add, e1, e2) => self.const_index(e1)?.checked_add(self.const_index(e2)?),
+            ast::Expr::BinOp(_span, BinOp::Sub, e1, e2) => self.const_index(e1)?.checked_sub(self.const_index(e2)?),
+            ast::Expr::BinOp(_span, BinOp::Mul, e1, e2) => self.const_index(e1)?.checked_mul(self.const_index(e2)?),
+            _ => None,
+        }
+    }
+
+    fn resolve_target(&self, span: &Span, target: &ast::Target) -> Result<(Path, Option<Select>), Vec<BitsyError>> {
+        Ok(match target {
+            ast::Target::Local(id) => (id.as_str().into(), None),
+            ast::Target::Nonlocal(id1, id2) if self.is_interface(id1.as_str()) => (format!("{id1}_{id2}").into(), None),
+            ast::Target::Nonlocal(id1, id2) => (format!("{}.{}", id1.as_str(), id2.as_str()).into(), None),
+            ast::Target::Nested(id1, id2, id3) => (format!("{id1}.{id2}_{id3}").into(), None),
+            ast::Target::Indexed(id1, i, id2) => {
+                let i = self.resolve_const_nat(&id1.span, i)?;
+                (format!("{}.{i}.{}", id1.as_str(), id2.as_str()).into(), None)
+            },
+            ast::Target::Partial(target, select) => {
+                let (path, None) = self.resolve_target(span, target)? else {
+                    unreachable!("The grammar only allows one selection")
+                };
+                let select = match select {
+                    ast::Select::Field(field) => Select::Field(field.to_string()),
+                    ast::Select::Idx(i) => Select::Idx(self.resolve_const_nat(span, i)?),
+                    ast::Select::IdxRange(j, i) => Select::IdxRange(self.resolve_const_nat(span, j)?, self.resolve_const_nat(span, i)?),
+                };
+                (path, Some(select))
+            },
+        })
     }
 
     fn resolve_expr(&self, expr: &ast::Expr, ctx: Context<String, Type>) -> Result<Arc<Expr>, Vec<BitsyError>> {
         Ok(Arc::new(match expr {
             ast::Expr::Ident(span, id) => {
                 self.add_ident(id);
+                if let Some(constdef) = self.constdef(id.as_str()) {
+                    return Ok(constdef.to_expr(span));
+                }
                 Expr::Reference(span.clone(), OnceCell::new(), id.to_string().into())
             },
             ast::Expr::Dot(span, e, x) => {
-                if let ast::Expr::Ident(_loc, id) = &**e {
-                    self.add_ident(id);
-                    Expr::Reference(span.clone(), OnceCell::new(), format!("{id}.{x}").into())
-                } else {
-                    panic!()
+                match &**e {
+                    // A port of an interface port. Eg, `enq.valid`.
+                    ast::Expr::Ident(_loc, id) if self.is_interface(id.as_str()) => {
+                        self.add_ident(id);
+                        Expr::Reference(span.clone(), OnceCell::new(), format!("{id}_{x}").into())
+                    },
+                    ast::Expr::Ident(_loc, id) => {
+                        self.add_ident(id);
+                        Expr::Reference(span.clone(), OnceCell::new(), format!("{id}.{x}").into())
+                    },
+                    // A port of an interface port of a submodule. Eg, `q.deq.valid`.
+                    ast::Expr::Dot(_loc, inst, port) => {
+                        let Expr::Reference(_loc, _typ, path) = &*self.resolve_expr(&ast::Expr::Dot(span.clone(), inst.clone(), port.clone()), ctx)? else {
+                            unreachable!()
+                        };
+                        Expr::Reference(span.clone(), OnceCell::new(), format!("{path}_{x}").into())
+                    },
+                    // A port of one of a vector of instances. Eg, `lanes[0].out`.
+                    ast::Expr::Idx(_loc, inst, i) => {
+                        if let ast::Expr::Ident(_loc, id) = &**inst {
+                            self.add_ident(id);
+                            Expr::Reference(span.clone(), OnceCell::new(), format!("{id}.{i}.{x}").into())
+                        } else {
+                            let message = format!("Only a vector of instances may be indexed to reach a port: {x}");
+                            return Err(vec![BitsyError::Unknown(Some(span.clone()), message)]);
+                        }
+                    },
+                    // Eg, `cells[n-1].out`, where `n` is a `const` or the parameter of an instance being resolved.
+                    ast::Expr::IdxDyn(loc, inst, i) => {
+                        match (&**inst, self.const_index(i)) {
+                            (ast::Expr::Ident(_loc, id), Some(i)) => {
+                                self.add_ident(id);
+                                Expr::Reference(span.clone(), OnceCell::new(), format!("{id}.{i}.{x}").into())
+                            },
+                            _ => {
+                                let message = "Instances must be indexed by a constant".to_string();
+                                return Err(vec![BitsyError::Unknown(Some(loc.clone()), message)]);
+                            },
+                        }
+                    },
+                    _ => {
+                        let message = format!("Only submodules and interface ports have ports: {x}");
+                        return Err(vec![BitsyError::Unknown(Some(span.clone()), message)]);
+                    },
                 }
             },
             ast::Expr::Word(span, w, n) => Expr::Word(span.clone(), OnceCell::new(), *w, *n),
@@ -349,6 +999,8 @@ impl Namespace {
                     "zext" => Expr::Zext(span.clone(), OnceCell::new(), package_es[0].clone()),
                     "trycast" => Expr::TryCast(span.clone(), OnceCell::new(), package_es[0].clone()),
                     "word" => Expr::ToWord(span.clone(), OnceCell::new(), package_es[0].clone()),
+                    "as_signed" => Expr::AsSigned(span.clone(), OnceCell::new(), package_es[0].clone()),
+                    "as_unsigned" => Expr::AsUnsigned(span.clone(), OnceCell::new(), package_es[0].clone()),
                     "@Valid" => Expr::Ctor(span.clone(), OnceCell::new(), "Valid".to_string(), package_es),
                     "@Invalid" => Expr::Ctor(span.clone(), OnceCell::new(), "Invalid".to_string(), vec![]),
                     fnname => {
@@ -364,7 +1016,11 @@ impl Namespace {
                             for expr in es {
                                 package_es.push(self.resolve_expr(expr, ctx.clone())?);
                             }
-                            Expr::Call(span.clone(), OnceCell::new(), fndef, package_es)
+                            let mut package_type_params = vec![];
+                            for type_param in type_params {
+                                package_type_params.push(self.resolve_type_param(type_param)?);
+                            }
+                            Expr::Call(span.clone(), OnceCell::new(), fndef, package_type_params, OnceCell::new(), package_es)
                         } else {
                             panic!("Unknown call: {func}")
                         }
@@ -373,7 +1029,7 @@ impl Namespace {
             },
             ast::Expr::Let(span, x, type_ascription, e, b) => {
                 let package_e = self.resolve_expr(e, ctx.clone())?;
-                let package_b = self.resolve_expr(b, ctx)?;
+                let package_b = self.with_locals([x.to_string()], || self.resolve_expr(b, ctx))?;
                 let package_ascription = if let Some(typ) = type_ascription {
                     Some(self.resolve_type(&typ)?)
                 } else {
@@ -401,7 +1057,7 @@ impl Namespace {
                 let package_e = self.resolve_expr(e, ctx.clone())?;
                 let mut package_arms: Vec<MatchArm> = vec![];
                 for ast::MatchArm(pat, expr) in arms {
-                    let package_expr = self.resolve_expr(expr, ctx.clone())?;
+                    let package_expr = self.with_locals(pat.bound_vars(), || self.resolve_expr(expr, ctx.clone()))?;
                     package_arms.push(MatchArm(pat.clone(), package_expr))
                 }
                 Expr::Match(span.clone(), OnceCell::new(), package_e, package_arms)
@@ -418,14 +1074,83 @@ impl Namespace {
             ast::Expr::IdxRange(span, e, j, i) => {
                 Expr::IdxRange(span.clone(), OnceCell::new(), self.resolve_expr(&e, ctx)?, *j, *i)
             },
+            ast::Expr::IdxDyn(span, e, i) => {
+                Expr::IdxDyn(span.clone(), OnceCell::new(), self.resolve_expr(e, ctx.clone())?, self.resolve_expr(i, ctx)?)
+            },
             ast::Expr::Hole(span, name) => {
-                Expr::Hole(span.clone(), OnceCell::new(), name.clone().map(|name| name.to_string()))
+                Expr::Hole(span.clone(), OnceCell::new(), name.clone().map(|name| name.to_string()), OnceCell::new())
             },
         }))
     }
 
 }
 
+/// Resolve the body of a parametric `mod` whose structure depends on its `Nat` parameters,
+/// now that their values are known. See [`Package::elaborate`].
+///
+/// The `Nat` parameters are resolved as if they were `const`s, so that `for i in 0..n` can be unrolled.
+pub(crate) fn resolve_instance(package: &Package, moddef: &ast::ModDef, params: &[TypeParam]) -> Result<Arc<Component>, Vec<BitsyError>> {
+    let ast::ModDef(span, doc, _visibility, name, type_args, decls) = moddef;
+    let mut namespace = Namespace::new(package.imports());
+    namespace.items = package.items().into_iter().map(|item| (item.name().to_string(), item)).collect();
+
+    let mut type_params = BTreeMap::new();
+    let mut remaining_type_args = vec![];
+    for ((arg_name, kind), param) in type_args.iter().zip(params.iter()) {
+        if let TypeParam::Nat(Nat::Lit(n)) = param {
+            let constdef = ConstDef {
+                span: arg_name.span.clone(),
+                name: arg_name.to_string(),
+                visibility: Visibility::Private,
+                doc: None,
+                value: ConstValue::Nat(*n),
+            };
+            namespace.items.insert(arg_name.to_string(), Item::ConstDef(Arc::new(constdef)));
+        } else {
+            type_params.insert(arg_name.to_string(), param.clone());
+            remaining_type_args.push((arg_name.to_string(), kind.clone()));
+        }
+    }
+
+    let decls_slice: &[&ast::Decl] = &decls.iter().collect::<Vec<_>>();
+    let component_names = moddef_component_names(moddef)?;
+    let (children, wires, whens, assertions) = namespace.with_type_args(&remaining_type_args, || {
+        namespace.with_locals(component_names, || namespace.with_scope(decls, || namespace.resolve_decls(decls_slice)))
+    })?;
+    let interfaces = namespace.interfaces_of(decls)?;
+    let component = Component::Mod(span.clone(), doc.clone(), name.to_string(), vec![], children, wires, whens, assertions, interfaces);
+    Ok(component.subst(&type_params))
+}
+
+/// The names used in the widths which decide the structure of the body of a `mod`:
+/// the bounds of each `for`, the length of each vector of instances, and the indexes of the targets of wires.
+fn structural_dependencies(decls: &[ast::Decl]) -> Vec<ast::Ident> {
+    let mut results = vec![];
+    for decl in decls {
+        match decl {
+            ast::Decl::Mod(_loc, _doc, _name, decls) => results.extend(structural_dependencies(decls)),
+            ast::Decl::ModInstVec(_loc, _name, n, _moddef_name, _params) => results.extend(nat_dependencies(n)),
+            ast::Decl::Wire(_loc, ast::Wire(_span, target, _expr, _wire_type)) => results.extend(target_dependencies(target)),
+            ast::Decl::When(_loc, ast::When(_span, _cond, wires)) => {
+                for ast::Wire(_span, target, _expr, _wire_type) in wires {
+                    results.extend(target_dependencies(target));
+                }
+            },
+            ast::Decl::Connect(_loc, a, b) => {
+                results.extend(target_dependencies(a));
+                results.extend(target_dependencies(b));
+            },
+            ast::Decl::For(_loc, _var, start, end, decls) => {
+                results.extend(nat_dependencies(start));
+                results.extend(nat_dependencies(end));
+                results.extend(structural_dependencies(decls));
+            },
+            _ => (),
+        }
+    }
+    results
+}
+
 fn item_dependencies(item: &ast::Item) -> Result<Vec<ast::Ident>, Vec<BitsyError>> {
     match item {
         ast::Item::ModDef(moddef) => moddef_dependencies(moddef),
@@ -435,47 +1160,72 @@ fn item_dependencies(item: &ast::Item) -> Result<Vec<ast::Ident>, Vec<BitsyError
         ast::Item::AltTypeDef(typedef) => altypedef_dependencies(typedef),
         ast::Item::FnDef(typedef) => fndef_dependencies(typedef),
         ast::Item::TbDef(typedef) => tbdef_dependencies(typedef),
+        ast::Item::ConstDef(constdef) => constdef_dependencies(constdef),
+        ast::Item::ShapeDef(shapedef) => {
+            let mut results = vec![];
+            for (_name, _dir, typ) in &shapedef.ports {
+                results.extend(type_dependencies(typ)?);
+            }
+            Ok(results)
+        },
     }
 }
 
 fn moddef_dependencies(moddef: &ast::ModDef) -> Result<Vec<ast::Ident>, Vec<BitsyError>> {
     let mut results = vec![];
     let component_names = moddef_component_names(moddef)?;
-    let ast::ModDef(_loc, _name, decls) = moddef;
+    let ast::ModDef(_loc, _doc, _visibility, _name, type_args, decls) = moddef;
     for decl in decls {
         results.extend(decl_dependencies(decl, &component_names)?.into_iter());
     }
+    // References to the type arguments are not references to other items.
+    results.retain(|dependency| !type_args.iter().any(|(type_arg, _kind)| type_arg.name == dependency.name));
     Ok(results)
 }
 
 fn moddef_component_names(moddef: &ast::ModDef) -> Result<BTreeSet<String>, Vec<BitsyError>> {
     let mut result = BTreeSet::new();
-    let ast::ModDef(_loc, _name, decls) = moddef;
+    let ast::ModDef(_loc, _doc, _visibility, _name, _type_args, decls) = moddef;
     for decl in decls {
         match decl {
-            ast::Decl::Mod(_loc, name, _decls) => {
+            ast::Decl::Mod(_loc, _doc, name, _decls) => {
                 result.insert(name.to_string());
             },
-            ast::Decl::ModInst(_loc, name, _moddef_name) => {
+            ast::Decl::ModInst(_loc, name, _moddef_name, _params) => {
+                result.insert(name.to_string());
+            },
+            ast::Decl::ModInstVec(_loc, name, _n, _moddef_name, _params) => {
                 result.insert(name.to_string());
             },
             ast::Decl::Dom(_loc, name) => {
                 result.insert(name.to_string());
             },
-            ast::Decl::Incoming(_loc, name, _typ) => {
+            ast::Decl::Incoming(_loc, _doc, name, _typ) => {
                 result.insert(name.to_string());
             },
-            ast::Decl::Outgoing(_loc, name, _typ) => {
+            ast::Decl::Outgoing(_loc, _doc, name, _typ) => {
                 result.insert(name.to_string());
             },
             ast::Decl::Node(_loc, name, _typ) => {
                 result.insert(name.to_string());
             },
-            ast::Decl::Reg(_loc, name, _typ, _reset) => {
+            ast::Decl::Reg(_loc, _doc, name, _typ, _dom, _reset) => {
                 result.insert(name.to_string());
             },
+            ast::Decl::Mem(_loc, name, _typ, _dom, _latency, _ports) => {
+                result.insert(name.to_string());
+            },
+            ast::Decl::Port(_loc, name, _flipped, _shape_name) => {
+                result.insert(name.to_string());
+            },
+            ast::Decl::Connect(_loc, _a, _b) => (),
             ast::Decl::Wire(_loc, _wire) => (),
             ast::Decl::When(_loc, _when) => (),
+            ast::Decl::Assert(_loc, _cond) => (),
+            ast::Decl::Cover(_loc, _cond) => (),
+            ast::Decl::For(_loc, _var, _start, _end, decls) => {
+                result.extend(moddef_component_names_anonymous(decls)?);
+            },
         }
     }
     Ok(result)
@@ -485,29 +1235,44 @@ fn moddef_component_names_anonymous(decls: &[ast::Decl]) -> Result<BTreeSet<Stri
     let mut result = BTreeSet::new();
     for decl in decls {
         match decl {
-            ast::Decl::Mod(_loc, name, _decls) => {
+            ast::Decl::Mod(_loc, _doc, name, _decls) => {
+                result.insert(name.to_string());
+            },
+            ast::Decl::ModInst(_loc, name, _moddef_name, _params) => {
                 result.insert(name.to_string());
             },
-            ast::Decl::ModInst(_loc, name, _moddef_name) => {
+            ast::Decl::ModInstVec(_loc, name, _n, _moddef_name, _params) => {
                 result.insert(name.to_string());
             },
             ast::Decl::Dom(_loc, name) => {
                 result.insert(name.to_string());
             },
-            ast::Decl::Incoming(_loc, name, _typ) => {
+            ast::Decl::Incoming(_loc, _doc, name, _typ) => {
                 result.insert(name.to_string());
             },
-            ast::Decl::Outgoing(_loc, name, _typ) => {
+            ast::Decl::Outgoing(_loc, _doc, name, _typ) => {
                 result.insert(name.to_string());
             },
             ast::Decl::Node(_loc, name, _typ) => {
                 result.insert(name.to_string());
             },
-            ast::Decl::Reg(_loc, name, _typ, _reset) => {
+            ast::Decl::Reg(_loc, _doc, name, _typ, _dom, _reset) => {
                 result.insert(name.to_string());
             },
+            ast::Decl::Mem(_loc, name, _typ, _dom, _latency, _ports) => {
+                result.insert(name.to_string());
+            },
+            ast::Decl::Port(_loc, name, _flipped, _shape_name) => {
+                result.insert(name.to_string());
+            },
+            ast::Decl::Connect(_loc, _a, _b) => (),
             ast::Decl::Wire(_loc, _wire) => (),
             ast::Decl::When(_loc, _when) => (),
+            ast::Decl::Assert(_loc, _cond) => (),
+            ast::Decl::Cover(_loc, _cond) => (),
+            ast::Decl::For(_loc, _var, _start, _end, decls) => {
+                result.extend(moddef_component_names_anonymous(decls)?);
+            },
         }
     }
     Ok(result)
@@ -516,33 +1281,66 @@ fn moddef_component_names_anonymous(decls: &[ast::Decl]) -> Result<BTreeSet<Stri
 fn decl_dependencies(decl: &ast::Decl, component_names: &BTreeSet<String>) -> Result<Vec<ast::Ident>, Vec<BitsyError>> {
     let mut results = vec![];
     match decl {
-        ast::Decl::Mod(_loc, _name, decls) => {
+        ast::Decl::Mod(_loc, _doc, _name, decls) => {
             let component_names = moddef_component_names_anonymous(&*decls)?;
             for decl in decls {
                 results.extend(decl_dependencies(decl, &component_names)?.into_iter());
             }
         },
-        ast::Decl::ModInst(_loc, _name, moddef_name) => results.push(moddef_name.clone()),
+        ast::Decl::ModInst(_loc, _name, moddef_name, params) | ast::Decl::ModInstVec(_loc, _name, _, moddef_name, params) => {
+            if let ast::Decl::ModInstVec(_loc, _name, n, _moddef_name, _params) = decl {
+                results.extend(nat_dependencies(n));
+            }
+            results.push(moddef_name.clone());
+            for param in params {
+                if let ast::TypeParam::Type(typ) = param {
+                    results.extend(type_dependencies(typ)?.into_iter());
+                }
+            }
+        },
         ast::Decl::Dom(_loc, _name) => (),
-        ast::Decl::Incoming(_loc, _name, typ) => results.extend(type_dependencies(typ)?),
-        ast::Decl::Outgoing(_loc, _name, typ) => results.extend(type_dependencies(typ)?),
-        ast::Decl::Node(_loc, _name, typ) => results.extend(type_dependencies(typ)?),
-        ast::Decl::Reg(_loc, _name, typ, reset) => {
+        ast::Decl::Incoming(_loc, _doc, _name, typ) => results.extend(type_dependencies(typ)?),
+        ast::Decl::Outgoing(_loc, _doc, _name, typ) => results.extend(type_dependencies(typ)?),
+        ast::Decl::Node(_loc, _name, Some(typ)) => results.extend(type_dependencies(typ)?),
+        ast::Decl::Node(_loc, _name, None) => (),
+        ast::Decl::Reg(_loc, _doc, _name, typ, _dom, reset) => {
             results.extend(type_dependencies(typ)?.into_iter());
             if let Some(expr) = reset {
                 results.extend(expr_dependencies(expr, component_names)?.into_iter());
             }
         },
+        ast::Decl::Mem(_loc, _name, typ, _dom, _latency, _ports) => results.extend(type_dependencies(typ)?),
         ast::Decl::Wire(_loc, wire) => {
-            let ast::Wire(_loc2, _target, expr, _wire_type) = wire;
+            let ast::Wire(_loc2, target, expr, _wire_type) = wire;
+            results.extend(target_dependencies(target));
             results.extend(expr_dependencies(expr, component_names)?.into_iter())
         },
         ast::Decl::When(_loc, ast::When(_span, cond, wires)) => {
             results.extend(expr_dependencies(cond, component_names)?.into_iter());
-            for ast::Wire(_loc2, _target, expr, _wire_type) in wires {
+            for ast::Wire(_loc2, target, expr, _wire_type) in wires {
+                results.extend(target_dependencies(target));
                 results.extend(expr_dependencies(expr, component_names)?.into_iter())
             }
         },
+        ast::Decl::Assert(_loc, cond) | ast::Decl::Cover(_loc, cond) => {
+            results.extend(expr_dependencies(cond, component_names)?);
+        },
+        ast::Decl::Port(_loc, _name, _flipped, shape_name) => results.push(shape_name.clone()),
+        ast::Decl::Connect(_loc, a, b) => {
+            results.extend(target_dependencies(a));
+            results.extend(target_dependencies(b));
+        },
+        ast::Decl::For(_loc, var, start, end, decls) => {
+            results.extend(nat_dependencies(start));
+            results.extend(nat_dependencies(end));
+            let mut component_names = component_names.clone();
+            component_names.insert(var.to_string());
+            for decl in decls {
+                results.extend(decl_dependencies(decl, &component_names)?);
+            }
+            // The loop variable may appear in types, such as `Word[i + 1]`.
+            results.retain(|dependency| dependency.name != var.name);
+        },
     }
     Ok(results.into_iter().collect())
 }
@@ -562,6 +1360,8 @@ fn altypedef_dependencies(typedef: &ast::AltTypeDef) -> Result<Vec<ast::Ident>,
             deps.extend(type_dependencies(typ)?.into_iter())
         }
     }
+    // References to the type arguments are not references to other items.
+    deps.retain(|dependency| !typedef.type_args.iter().any(|(type_arg, _kind)| type_arg.name == dependency.name));
     Ok(deps.into_iter().collect())
 }
 
@@ -574,31 +1374,87 @@ fn fndef_dependencies(typedef: &ast::FnDef) -> Result<Vec<ast::Ident>, Vec<Bitsy
     }
 
     result.extend(expr_dependencies(&typedef.body, &arguments)?.into_iter());
+    // References to the type arguments are not references to other items.
+    result.retain(|dependency| !typedef.type_args.iter().any(|(type_arg, _kind)| type_arg.name == dependency.name));
     Ok(result)
 }
 
 fn tbdef_dependencies(tbdef: &ast::TbDef) -> Result<Vec<ast::Ident>, Vec<BitsyError>> {
     let mut results = vec![];
+    tbstatements_dependencies(&tbdef.statements, &mut results);
+    Ok(results)
+}
 
-    for statement in &tbdef.statements {
+fn tbstatements_dependencies(statements: &[ast::TbStatement], results: &mut Vec<ast::Ident>) {
+    for statement in statements {
         match statement {
             ast::TbStatement::ModInst(_name, moddef_name) => results.push(moddef_name.clone()),
+            ast::TbStatement::Loop(_span, _n, statements) => tbstatements_dependencies(statements, results),
             _ => (),
         }
     }
+}
 
-    Ok(results)
+fn constdef_dependencies(constdef: &ast::ConstDef) -> Result<Vec<ast::Ident>, Vec<BitsyError>> {
+    match &constdef.value {
+        ast::ConstValue::Nat(n) => Ok(nat_dependencies(n)),
+        ast::ConstValue::Expr(typ, e) => {
+            let mut results = type_dependencies(typ)?;
+            results.extend(expr_dependencies(e, &BTreeSet::new())?);
+            Ok(results)
+        },
+    }
 }
 
 fn type_dependencies(typ: &ast::Type) -> Result<Vec<ast::Ident>, Vec<BitsyError>> {
     match typ {
-        ast::Type::Word(_n) => Ok(Vec::new()),
-        ast::Type::Vec(t, _n) => type_dependencies(t),
+        ast::Type::Word(_span, n) => Ok(nat_dependencies(n)),
+        ast::Type::SInt(_span, n) => Ok(nat_dependencies(n)),
+        ast::Type::Vec(_span, t, n) => {
+            let mut results = type_dependencies(t)?;
+            results.extend(nat_dependencies(n));
+            Ok(results)
+        },
         ast::Type::Valid(t) => type_dependencies(t),
         ast::Type::TypeRef(r, _params) => Ok(vec![r.clone()]),
     }
 }
 
+/// The parameters named in the indexes of a [`ast::Target`].
+fn target_dependencies(target: &ast::Target) -> Vec<ast::Ident> {
+    match target {
+        ast::Target::Local(_id) => Vec::new(),
+        ast::Target::Nonlocal(_id, _port) => Vec::new(),
+        ast::Target::Indexed(_id, i, _port) => nat_dependencies(i),
+        ast::Target::Nested(_id, _port, _field) => Vec::new(),
+        ast::Target::Partial(target, select) => {
+            let mut results = target_dependencies(target);
+            match select {
+                ast::Select::Field(_field) => (),
+                ast::Select::Idx(i) => results.extend(nat_dependencies(i)),
+                ast::Select::IdxRange(j, i) => {
+                    results.extend(nat_dependencies(j));
+                    results.extend(nat_dependencies(i));
+                },
+            }
+            results
+        },
+    }
+}
+
+/// The parameters named in a [`ast::Nat`]. These are either type arguments or `const` definitions.
+fn nat_dependencies(n: &ast::Nat) -> Vec<ast::Ident> {
+    match n {
+        ast::Nat::Lit(_n) => Vec::new(),
+        ast::Nat::Param(r) => vec![r.clone()],
+        ast::Nat::Add(n, m) | ast::Nat::Sub(n, m) | ast::Nat::Mul(n, m) => {
+            let mut results = nat_dependencies(n);
+            results.extend(nat_dependencies(m));
+            results
+        },
+    }
+}
+
 fn expr_dependencies(expr: &ast::Expr, shadowed: &BTreeSet<String>) -> Result<Vec<ast::Ident>, Vec<BitsyError>> {
     Ok(match expr {
         ast::Expr::Ident(_loc, ident) => {
@@ -611,7 +1467,13 @@ fn expr_dependencies(expr: &ast::Expr, shadowed: &BTreeSet<String>) -> Result<Ve
         ast::Expr::Dot(_loc, e, _x) => expr_dependencies(e, shadowed)?,
         ast::Expr::Word(_loc, _w, _v) => Vec::new(),
         ast::Expr::Enum(_loc, typ, _value) => type_dependencies(typ)?,
-        ast::Expr::Struct(_loc, _fields) => Vec::new(),
+        ast::Expr::Struct(_loc, fields) => {
+            let mut results = Vec::new();
+            for (_name, e) in fields {
+                results.extend(expr_dependencies(e, shadowed)?);
+            }
+            results
+        },
         ast::Expr::Vec(_loc, es) => {
             let mut results = Vec::new();
             for e in es {
@@ -620,8 +1482,12 @@ fn expr_dependencies(expr: &ast::Expr, shadowed: &BTreeSet<String>) -> Result<Ve
             results
         },
         ast::Expr::Call(_loc, func, type_params, es) => {
-            // TODO check type_params
             let mut results = Vec::new();
+            for type_param in type_params {
+                if let ast::TypeParam::Type(typ) = type_param {
+                    results.extend(type_dependencies(typ)?.into_iter());
+                }
+            }
             #[rustfmt::skip]
             const SPECIALS: &[&str] = &[
                 "cat",
@@ -630,6 +1496,8 @@ fn expr_dependencies(expr: &ast::Expr, shadowed: &BTreeSet<String>) -> Result<Ve
                 "zext",
                 "trycast",
                 "word",
+                "as_signed",
+                "as_unsigned",
                 "@Valid",
                 "@Invalid",
             ];
@@ -682,18 +1550,176 @@ fn expr_dependencies(expr: &ast::Expr, shadowed: &BTreeSet<String>) -> Result<Ve
         ast::Expr::IdxField(_loc, e, _field) => expr_dependencies(e, shadowed)?,
         ast::Expr::Idx(_loc, e, _i) => expr_dependencies(e, shadowed)?,
         ast::Expr::IdxRange(_loc, e, _j, _i) => expr_dependencies(e, shadowed)?,
+        ast::Expr::IdxDyn(_loc, e, i) => {
+            let mut results = Vec::new();
+            for e in &[e, i] {
+                results.extend(expr_dependencies(e, shadowed)?);
+            }
+            results
+        },
         ast::Expr::Hole(_loc, _name) => Vec::new(),
     })
 }
 
-fn target_to_path(target: &ast::Target) -> Path {
+/// Substitute the value `i` for the variable of a `for` into one of its declarations.
+fn unroll_decl(decl: &ast::Decl, var: &str, i: u64) -> ast::Decl {
+    match decl {
+        ast::Decl::Mod(span, doc, name, decls) => {
+            ast::Decl::Mod(span.clone(), doc.clone(), name.clone(), decls.iter().map(|decl| unroll_decl(decl, var, i)).collect())
+        },
+        ast::Decl::ModInst(_span, _name, _moddef_name, _params) => decl.clone(),
+        ast::Decl::ModInstVec(span, name, n, moddef_name, params) => {
+            ast::Decl::ModInstVec(span.clone(), name.clone(), unroll_nat(n, var, i), moddef_name.clone(), params.clone())
+        },
+        ast::Decl::Dom(_span, _name) => decl.clone(),
+        ast::Decl::Incoming(span, doc, name, typ) => ast::Decl::Incoming(span.clone(), doc.clone(), name.clone(), unroll_type(typ, var, i)),
+        ast::Decl::Outgoing(span, doc, name, typ) => ast::Decl::Outgoing(span.clone(), doc.clone(), name.clone(), unroll_type(typ, var, i)),
+        ast::Decl::Node(span, name, typ) => ast::Decl::Node(span.clone(), name.clone(), typ.as_ref().map(|typ| unroll_type(typ, var, i))),
+        ast::Decl::Reg(span, doc, name, typ, dom, reset) => {
+            ast::Decl::Reg(
+                span.clone(),
+                doc.clone(),
+                name.clone(),
+                unroll_type(typ, var, i),
+                dom.clone(),
+                reset.as_ref().map(|reset| Box::new(unroll_expr(reset, var, i))),
+            )
+        },
+        ast::Decl::Mem(span, name, typ, dom, latency, ports) => {
+            ast::Decl::Mem(span.clone(), name.clone(), unroll_type(typ, var, i), dom.clone(), *latency, ports.clone())
+        },
+        ast::Decl::Wire(span, wire) => ast::Decl::Wire(span.clone(), unroll_wire(wire, var, i)),
+        ast::Decl::Port(_span, _name, _flipped, _shape_name) => decl.clone(),
+        ast::Decl::Assert(span, cond) => ast::Decl::Assert(span.clone(), Box::new(unroll_expr(cond, var, i))),
+        ast::Decl::Cover(span, cond) => ast::Decl::Cover(span.clone(), Box::new(unroll_expr(cond, var, i))),
+        ast::Decl::Connect(span, a, b) => ast::Decl::Connect(span.clone(), unroll_target(a, var, i), unroll_target(b, var, i)),
+        ast::Decl::When(span, ast::When(when_span, cond, wires)) => {
+            ast::Decl::When(
+                span.clone(),
+                ast::When(
+                    when_span.clone(),
+                    Box::new(unroll_expr(cond, var, i)),
+                    wires.iter().map(|wire| unroll_wire(wire, var, i)).collect(),
+                ),
+            )
+        },
+        ast::Decl::For(span, inner_var, start, end, decls) => {
+            // An inner loop over the same variable shadows it.
+            let decls = if inner_var.as_str() == var {
+                decls.clone()
+            } else {
+                decls.iter().map(|decl| unroll_decl(decl, var, i)).collect()
+            };
+            ast::Decl::For(span.clone(), inner_var.clone(), unroll_nat(start, var, i), unroll_nat(end, var, i), decls)
+        },
+    }
+}
+
+fn unroll_wire(wire: &ast::Wire, var: &str, i: u64) -> ast::Wire {
+    let ast::Wire(span, target, expr, wire_type) = wire;
+    ast::Wire(span.clone(), unroll_target(target, var, i), Box::new(unroll_expr(expr, var, i)), wire_type.clone())
+}
+
+fn unroll_target(target: &ast::Target, var: &str, i: u64) -> ast::Target {
     match target {
-        ast::Target::Local(id) => id.as_str().into(),
-        ast::Target::Nonlocal(id1, id2) => format!("{}.{}", id1.as_str(), id2.as_str()).into(),
+        ast::Target::Local(_id) => target.clone(),
+        ast::Target::Nonlocal(_id, _port) => target.clone(),
+        ast::Target::Indexed(id, n, port) => ast::Target::Indexed(id.clone(), unroll_nat(n, var, i), port.clone()),
+        ast::Target::Nested(_id, _port, _field) => target.clone(),
+        ast::Target::Partial(target, select) => {
+            let select = match select {
+                ast::Select::Field(_field) => select.clone(),
+                ast::Select::Idx(n) => ast::Select::Idx(unroll_nat(n, var, i)),
+                ast::Select::IdxRange(n, m) => ast::Select::IdxRange(unroll_nat(n, var, i), unroll_nat(m, var, i)),
+            };
+            ast::Target::Partial(Box::new(unroll_target(target, var, i)), select)
+        },
     }
 }
 
-fn order_items(package: &ast::Package) -> Result<Vec<&ast::Item>, Vec<BitsyError>> {
+fn unroll_nat(n: &ast::Nat, var: &str, i: u64) -> ast::Nat {
+    match n {
+        ast::Nat::Lit(_n) => n.clone(),
+        ast::Nat::Param(x) if x.as_str() == var => ast::Nat::Lit(i),
+        ast::Nat::Param(_x) => n.clone(),
+        ast::Nat::Add(n, m) => ast::Nat::Add(Box::new(unroll_nat(n, var, i)), Box::new(unroll_nat(m, var, i))),
+        ast::Nat::Sub(n, m) => ast::Nat::Sub(Box::new(unroll_nat(n, var, i)), Box::new(unroll_nat(m, var, i))),
+        ast::Nat::Mul(n, m) => ast::Nat::Mul(Box::new(unroll_nat(n, var, i)), Box::new(unroll_nat(m, var, i))),
+    }
+}
+
+fn unroll_type(typ: &ast::Type, var: &str, i: u64) -> ast::Type {
+    match typ {
+        ast::Type::Word(span, n) => ast::Type::Word(span.clone(), unroll_nat(n, var, i)),
+        ast::Type::SInt(span, n) => ast::Type::SInt(span.clone(), unroll_nat(n, var, i)),
+        ast::Type::Vec(span, typ, n) => ast::Type::Vec(span.clone(), Box::new(unroll_type(typ, var, i)), unroll_nat(n, var, i)),
+        ast::Type::Valid(typ) => ast::Type::Valid(Box::new(unroll_type(typ, var, i))),
+        ast::Type::TypeRef(name, params) => {
+            let params = params.iter().map(|param| match param {
+                ast::TypeParam::Nat(n) => ast::TypeParam::Nat(*n),
+                ast::TypeParam::Type(typ) => ast::TypeParam::Type(unroll_type(typ, var, i)),
+            }).collect();
+            ast::Type::TypeRef(name.clone(), params)
+        },
+    }
+}
+
+/// The variable of a `for` becomes a literal without a width.
+/// Arithmetic on literals is evaluated, so that indexes like `lanes[i+1]` are static.
+fn unroll_expr(expr: &ast::Expr, var: &str, i: u64) -> ast::Expr {
+    let unroll = |e: &ast::Expr| Box::new(unroll_expr(e, var, i));
+    match expr {
+        ast::Expr::Ident(span, x) if x.as_str() == var => ast::Expr::Word(span.clone(), None, i),
+        ast::Expr::Ident(_span, _x) => expr.clone(),
+        ast::Expr::Dot(span, e, x) => ast::Expr::Dot(span.clone(), unroll(e), x.clone()),
+        ast::Expr::Word(_span, _w, _n) => expr.clone(),
+        ast::Expr::Enum(_span, _typ, _value) => expr.clone(),
+        ast::Expr::Struct(span, fields) => {
+            ast::Expr::Struct(span.clone(), fields.iter().map(|(name, e)| (name.clone(), unroll(e))).collect())
+        },
+        ast::Expr::Vec(span, es) => ast::Expr::Vec(span.clone(), es.iter().map(|e| unroll_expr(e, var, i)).collect()),
+        ast::Expr::Call(span, name, params, es) => {
+            ast::Expr::Call(span.clone(), name.clone(), params.clone(), es.iter().map(|e| unroll_expr(e, var, i)).collect())
+        },
+        ast::Expr::Let(span, x, typ, e, b) => {
+            let b = if x.as_str() == var { b.clone() } else { unroll(b) };
+            ast::Expr::Let(span.clone(), x.clone(), typ.as_ref().map(|typ| unroll_type(typ, var, i)), unroll(e), b)
+        },
+        ast::Expr::UnOp(span, op, e) => ast::Expr::UnOp(span.clone(), *op, unroll(e)),
+        ast::Expr::BinOp(span, op, e1, e2) => {
+            match (op, unroll_expr(e1, var, i), unroll_expr(e2, var, i)) {
+                (BinOp::Add, ast::Expr::Word(_, None, n), ast::Expr::Word(_, None, m)) if n.checked_add(m).is_some() => ast::Expr::Word(span.clone(), None, n + m),
+                (BinOp::Sub, ast::Expr::Word(_, None, n), ast::Expr::Word(_, None, m)) if n >= m => ast::Expr::Word(span.clone(), None, n - m),
+                (BinOp::Mul, ast::Expr::Word(_, None, n), ast::Expr::Word(_, None, m)) if n.checked_mul(m).is_some() => ast::Expr::Word(span.clone(), None, n * m),
+                (op, e1, e2) => ast::Expr::BinOp(span.clone(), *op, Box::new(e1), Box::new(e2)),
+            }
+        },
+        ast::Expr::If(span, cond, e1, e2) => ast::Expr::If(span.clone(), unroll(cond), unroll(e1), unroll(e2)),
+        ast::Expr::Match(span, e, arms) => {
+            let arms = arms.iter().map(|ast::MatchArm(pat, e)| {
+                if pat.bound_vars().iter().any(|x| x == var) {
+                    ast::MatchArm(pat.clone(), e.clone())
+                } else {
+                    ast::MatchArm(pat.clone(), unroll(e))
+                }
+            }).collect();
+            ast::Expr::Match(span.clone(), unroll(e), arms)
+        },
+        ast::Expr::IdxField(span, e, field) => ast::Expr::IdxField(span.clone(), unroll(e), field.clone()),
+        ast::Expr::Idx(span, e, j) => ast::Expr::Idx(span.clone(), unroll(e), *j),
+        ast::Expr::IdxRange(span, e, j, k) => ast::Expr::IdxRange(span.clone(), unroll(e), *j, *k),
+        ast::Expr::IdxDyn(span, e, j) => {
+            match unroll_expr(j, var, i) {
+                ast::Expr::Word(_span, None, j) => ast::Expr::Idx(span.clone(), unroll(e), j),
+                j => ast::Expr::IdxDyn(span.clone(), unroll(e), Box::new(j)),
+            }
+        },
+        ast::Expr::Hole(_span, _name) => expr.clone(),
+    }
+}
+
+
+fn order_items<'a>(package: &'a ast::Package, imports: &BTreeMap<String, Package>) -> Result<Vec<&'a ast::Item>, Vec<BitsyError>> {
     use petgraph::graph::{DiGraph, NodeIndex};
     use petgraph::algo::toposort;
 
@@ -714,7 +1740,16 @@ fn order_items(package: &ast::Package) -> Result<Vec<&ast::Item>, Vec<BitsyError
         items.insert(item.name().to_string(), (node, item));
 
         for item_dependency in item_dependencies(item)? {
-            if let Some((dependency, _item)) = items.get(&item_dependency.name) {
+            if let Some((package_name, rest)) = item_dependency.name.split_once("::") {
+                // Imported items are already resolved, so they don't take part in the ordering.
+                match imports.get(package_name).and_then(|package| package.item(rest)) {
+                    None => errors.push(BitsyError::Unknown(Some(item_dependency.span.clone()), format!("{item_dependency} not found"))),
+                    Some(item) if item.visibility() == Visibility::Private => {
+                        errors.push(BitsyError::NotPublic(item_dependency.span.clone(), item_dependency.name.clone(), item));
+                    },
+                    Some(_item) => (),
+                }
+            } else if let Some((dependency, _item)) = items.get(&item_dependency.name) {
                 graph.add_edge(node, *dependency, ());
             } else {
                 errors.push(BitsyError::Unknown(Some(item_dependency.span.clone()), format!("{item_dependency} not found")));
@@ -726,7 +1761,39 @@ fn order_items(package: &ast::Package) -> Result<Vec<&ast::Item>, Vec<BitsyError
         return Err(errors);
     }
 
-    let mut sorted: Vec<NodeIndex> = toposort(&graph, None).unwrap();
+    let mut sorted: Vec<NodeIndex> = match toposort(&graph, None) {
+        Ok(sorted) => sorted,
+        Err(cycle) => {
+            // Search the dependencies for the shortest way back to the item where the cycle was found.
+            let start = cycle.node_id();
+            let mut parents: BTreeMap<NodeIndex, NodeIndex> = BTreeMap::new();
+            let mut queue = std::collections::VecDeque::from([start]);
+            while let Some(node) = queue.pop_front() {
+                for next in graph.neighbors(node) {
+                    if let std::collections::btree_map::Entry::Vacant(entry) = parents.entry(next) {
+                        entry.insert(node);
+                        queue.push_back(next);
+                    }
+                }
+                if parents.contains_key(&start) {
+                    break;
+                }
+            }
+
+            let mut path = vec![start];
+            let mut node = parents[&start];
+            while node != start {
+                path.push(node);
+                node = parents[&node];
+            }
+            path.push(start);
+            path.reverse();
+
+            let (_node, item) = items[&name_by_node[&start]];
+            let cycle = path.iter().map(|node| name_by_node[node].clone()).collect();
+            return Err(vec![BitsyError::ItemCycle(item.span(), cycle)]);
+        },
+    };
     sorted.reverse();
 
     let mut results = vec![];
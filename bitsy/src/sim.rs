@@ -4,14 +4,18 @@ use super::*;
 mod tests;
 mod value;
 mod eval;
+mod tb;
 pub mod ext;
 
 pub use value::Value;
+pub use tb::run_tb;
 use ext::*;
 
 use std::collections::BTreeMap;
 use std::collections::BTreeSet;
+use std::collections::VecDeque;
 use std::sync::Arc;
+use std::sync::Mutex;
 use std::time::Duration;
 use std::time::SystemTime;
 
@@ -20,18 +24,66 @@ pub type CombId = usize;
 pub type ExtInstId = usize;
 pub type ExtId = usize;
 pub type RegId = usize;
+pub type MemId = usize;
 
 #[derive(Debug)]
 pub struct RegInfo {
     set_net_id: NetId,
     val_net_id: NetId,
     reset: Option<Arc<Expr>>,
+    dom: Option<Path>,
+}
+
+/// A `mem`, along with the nets of its ports. See [`MemPort`].
+#[derive(Debug)]
+pub struct MemInfo {
+    path: Path,
+    dom: Option<Path>,
+    typ: Type,
+    depth: usize,
+    latency: u64,
+    /// The `addr` and `data` of each read port.
+    read_ports: Vec<(NetId, NetId)>,
+    write_ports: Vec<MemWritePort>,
+}
+
+/// The `en`, `addr`, and `data` of a write port of a `mem`, and its `mask`, along with the width of the mask.
+type MemWritePort = (NetId, NetId, NetId, Option<(NetId, Width)>);
+
+/// An `assert` whose condition was false when the clock ticked.
+#[derive(Debug, Clone)]
+pub struct AssertFailure {
+    pub span: Span,
+    /// The instance the `assert` belongs to.
+    pub path: Path,
+    pub clock_ticks: u64,
+}
+
+/// A request to the simulator which doesn't make sense for the circuit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SimError {
+    NoSuchDomain(Path),
+    NoSuchMem(Path),
+    /// An address past the end of a `mem`, along with the path of the `mem`.
+    AddrOutOfRange(Path, usize),
+}
+
+impl std::fmt::Display for SimError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            SimError::NoSuchDomain(dom) => write!(f, "No such domain: {dom}"),
+            SimError::NoSuchMem(path) => write!(f, "No such mem: {path}"),
+            SimError::AddrOutOfRange(path, addr) => write!(f, "Address {addr} is out of range for {path}"),
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct Dependents {
     pub combs: Vec<CombId>,
     pub ext_inst_ports: Vec<(ExtInstId, PortName)>,
+    /// The read ports of the `mem`s with no latency whose address is on this net.
+    pub mem_read_ports: Vec<(MemId, usize)>,
 }
 
 #[derive(Debug)]
@@ -39,12 +91,15 @@ pub struct SimCircuit {
     pub nets: Vec<Net>, // indexed by NetId
     pub combs: Vec<Comb>, // indexed by NetId
     pub regs: Vec<RegInfo>, // indexed by RegId
+    pub mems: Vec<MemInfo>, // indexed by MemId
+    pub assertions: Vec<(Path, Assertion)>,
 
     pub dependents: Vec<Dependents>, // indexed by NetId
 
     pub net_id_by_ext_port: BTreeMap<(ExtInstId, PortName), NetId>,
 
     pub net_id_by_path: BTreeMap<Path, NetId>,
+    pub root_dom_by_path: BTreeMap<Path, Path>,
     pub ext_inst_id_by_path: BTreeMap<Path, ExtInstId>,
     pub path_by_ext_inst_id: BTreeMap<ExtInstId, Path>,
 }
@@ -83,12 +138,55 @@ fn make_regs(circuit: &Circuit, net_id_by_path: &BTreeMap<Path, NetId>) -> Vec<R
         .map(|path| {
             let set_net_id = net_id_by_path[&path.set()];
             let val_net_id = net_id_by_path[&path.clone()];
-            let reset = circuit.reset_for_reg(path);
+            let reset = circuit.reset_for_reg(path.clone());
+            let dom = circuit.dom_for(path);
 
             RegInfo {
                 set_net_id,
                 val_net_id,
                 reset,
+                dom,
+            }
+        })
+        .collect()
+}
+
+fn make_mems(circuit: &Circuit, net_id_by_path: &BTreeMap<Path, NetId>) -> Vec<MemInfo> {
+    circuit
+        .mems()
+        .into_iter()
+        .map(|(path, mem)| {
+            let Component::Mem(_loc, _name, Type::Vec(typ, depth), _dom, latency, ports) = &*mem else {
+                unreachable!("Every mem has a Vec type once it is elaborated")
+            };
+            let net_id = |port: String| net_id_by_path[&path.join(port.into())];
+
+            let mut read_ports = vec![];
+            let mut write_ports = vec![];
+            for port in ports {
+                match port {
+                    MemPort::Read(name) => {
+                        read_ports.push((net_id(format!("{name}_addr")), net_id(format!("{name}_data"))));
+                    },
+                    MemPort::Write(name, mask) => {
+                        write_ports.push((
+                            net_id(format!("{name}_en")),
+                            net_id(format!("{name}_addr")),
+                            net_id(format!("{name}_data")),
+                            mask.map(|mask| (net_id(format!("{name}_mask")), mask)),
+                        ));
+                    },
+                }
+            }
+
+            MemInfo {
+                path: path.clone(),
+                dom: circuit.dom_for(path.clone()),
+                typ: *typ.clone(),
+                depth: *depth as usize,
+                latency: *latency,
+                read_ports,
+                write_ports,
             }
         })
         .collect()
@@ -97,6 +195,7 @@ fn make_regs(circuit: &Circuit, net_id_by_path: &BTreeMap<Path, NetId>) -> Vec<R
 fn make_combs(circuit: &Circuit, net_id_by_path: &BTreeMap<Path, NetId>) -> Vec<Comb> {
     /*
         Created from the Wires of the Circuit.
+        Domain wires don't carry values, so they are skipped.
         Look at the WireType and decide if we need to capture the val or set terminal.
         Converts everything to nets.
         Don't add a comb when the two point at the same net.
@@ -105,14 +204,16 @@ fn make_combs(circuit: &Circuit, net_id_by_path: &BTreeMap<Path, NetId>) -> Vec<
     circuit
         .wires()
         .iter()
+        .filter(|(_path, Wire(_loc, _target, _select, _expr, wiretype))| *wiretype != WireType::Dom)
         .cloned()
-        .map(|(path, Wire(_loc, target, expr, wiretype))| {
+        .map(|(path, Wire(_loc, target, _select, expr, wiretype))| {
             let abs_target = path.clone().join(target);
             let abs_expr = expr.rebase(path.clone());
             let target_net_id = match wiretype {
                 WireType::Direct => net_id_by_path[&abs_target],
                 WireType::Latch => net_id_by_path[&abs_target.set()],
-                _ => todo!(), // Proc Wires not handled
+                WireType::Proc => unreachable!("Proc Wires are lowered to Latch Wires by Component::netlist_wires()"),
+                WireType::Dom => unreachable!(),
             };
             (target_net_id, abs_expr.references_to_nets(&net_id_by_path), wiretype)
         })
@@ -129,6 +230,17 @@ fn make_combs(circuit: &Circuit, net_id_by_path: &BTreeMap<Path, NetId>) -> Vec<
         .collect()
 }
 
+fn make_assertions(circuit: &Circuit, net_id_by_path: &BTreeMap<Path, NetId>) -> Vec<(Path, Assertion)> {
+    circuit
+        .assertions()
+        .into_iter()
+        .map(|(path, Assertion(span, kind, expr))| {
+            let abs_expr = expr.rebase(path.clone()).references_to_nets(net_id_by_path);
+            (path, Assertion(span, kind, abs_expr))
+        })
+        .collect()
+}
+
 fn make_ext_inst_id_by_path(
     circuit: &Circuit,
     net_id_by_path: &BTreeMap<Path, NetId>,
@@ -143,7 +255,7 @@ fn make_ext_inst_id_by_path(
         path_by_ext_inst_id.insert(ext_id, path.clone());
 
         for child in ext_component.children() {
-            if let Component::Incoming(_loc, name, _typ) = &*child {
+            if let Component::Incoming(_loc, _doc, name, _typ) = &*child {
                 let incoming_path = path.join(name.to_string().into());
                 let net_id = net_id_by_path[&incoming_path];
                 ext_dependencies[net_id].push((ext_id, name.to_string()));
@@ -160,6 +272,7 @@ fn make_dependents(
     combs: &[Comb],
     net_id_by_path: &BTreeMap<Path, NetId>,
     ext_id_by_path: &BTreeMap<Path, ExtInstId>,
+    mems: &[MemInfo],
 ) -> Vec<Dependents> {
     net_ids.iter()
     .map(|net_id| {
@@ -168,10 +281,10 @@ fn make_dependents(
         for (path, ext_component) in circuit.exts() {
             let ext_id = ext_id_by_path[&path];
             match &*ext_component {
-                Component::Ext(_loc, _name, children) => {
+                Component::Ext(_loc, _doc, _name, children) => {
                     for child in children {
                         match &**child {
-                            Component::Incoming(_loc, name, _typ) => {
+                            Component::Incoming(_loc, _doc, name, _typ) => {
                                 let port_path = path.join(name.clone().into());
                                 let port_net_id = net_id_by_path[&port_path];
                                 if  port_net_id == *net_id {
@@ -186,9 +299,21 @@ fn make_dependents(
             }
         }
 
+        let mut mem_read_ports: Vec<(MemId, usize)> = vec![];
+        for (mem_id, mem) in mems.iter().enumerate() {
+            if mem.latency == 0 {
+                for (port, (addr_net_id, _data_net_id)) in mem.read_ports.iter().enumerate() {
+                    if addr_net_id == net_id {
+                        mem_read_ports.push((mem_id, port));
+                    }
+                }
+            }
+        }
+
         let dependents = Dependents {
             combs,
             ext_inst_ports: ext_ports,
+            mem_read_ports,
         };
         dependents
     })
@@ -205,10 +330,10 @@ fn make_net_id_by_ext_port(
     for (path, ext_component) in circuit.exts() {
         let ext_inst_id = ext_inst_id_by_path[&path];
         match &*ext_component {
-            Component::Ext(_loc, _name, children) => {
+            Component::Ext(_loc, _doc, _name, children) => {
                 for child in children {
                     match &**child {
-                        Component::Outgoing(_loc, name, _typ) => {
+                        Component::Outgoing(_loc, _doc, name, _typ) => {
                             let port_path = path.join(name.clone().into());
                             let net_id = net_id_by_path[&port_path];
                             net_id_by_ext_port.insert((ext_inst_id, name.clone()), net_id);
@@ -229,7 +354,10 @@ impl SimCircuit {
         let net_ids: Vec<NetId> = (0..nets.len()).into_iter().collect();
         let net_id_by_path: BTreeMap<Path, NetId> = make_net_id_by_path(&circuit, &nets);
         let regs: Vec<RegInfo> = make_regs(&circuit, &net_id_by_path);
+        let mems: Vec<MemInfo> = make_mems(circuit, &net_id_by_path);
+        let root_dom_by_path: BTreeMap<Path, Path> = circuit.doms().into_iter().map(|dom| (dom.clone(), circuit.root_dom(dom))).collect();
         let combs: Vec<Comb> = make_combs(&circuit, &net_id_by_path);
+        let assertions: Vec<(Path, Assertion)> = make_assertions(circuit, &net_id_by_path);
         let (ext_inst_id_by_path, path_by_ext_inst_id) = make_ext_inst_id_by_path(&circuit, &net_id_by_path, &nets);
 
         let dependents: Vec<Dependents> = make_dependents(
@@ -238,6 +366,7 @@ impl SimCircuit {
             &combs,
             &net_id_by_path,
             &ext_inst_id_by_path,
+            &mems,
         );
 
         let net_id_by_ext_port = make_net_id_by_ext_port(
@@ -250,11 +379,14 @@ impl SimCircuit {
             nets,
             combs,
             regs,
+            mems,
+            assertions,
 
             dependents,
             net_id_by_ext_port,
 
             net_id_by_path,
+            root_dom_by_path,
             ext_inst_id_by_path,
             path_by_ext_inst_id,
         }
@@ -273,6 +405,11 @@ pub struct Sim {
     clock_ticks: u64,
     start_time: SystemTime,
     clock_freq_cap: Option<f64>,
+    assert_failures: Vec<AssertFailure>,
+    cover_counts: Vec<u64>, // indexed like SimCircuit::assertions
+    mem_contents: Vec<Vec<Value>>, // indexed by MemId
+    mem_pipelines: Vec<Vec<VecDeque<Value>>>, // indexed by MemId, then by read port
+    holes_evaluated: Mutex<BTreeMap<(String, LineCol), (Span, String)>>, // keyed by where the hole is
 }
 
 impl Sim {
@@ -282,6 +419,15 @@ impl Sim {
         let net_values: Vec<Value> = net_ids.iter().map(|_net| Value::X).collect();
 
         let ext_id_by_ext_inst_id: BTreeMap<ExtInstId, ExtId> = BTreeMap::new();
+        let cover_counts = vec![0; sim_circuit.assertions.len()];
+
+        // Memories start out zeroed.
+        // A read which takes `latency` cycles passes through `latency - 1` values on the way.
+        let mem_contents = sim_circuit.mems.iter().map(|mem| vec![Value::from_bits(&mem.typ, 0); mem.depth]).collect();
+        let mem_pipelines = sim_circuit.mems.iter().map(|mem| {
+            let pipeline: VecDeque<Value> = (1..mem.latency).map(|_i| Value::X).collect();
+            vec![pipeline; mem.read_ports.len()]
+        }).collect();
 
         let mut sim = Sim {
             sim_circuit,
@@ -291,6 +437,11 @@ impl Sim {
             start_time: SystemTime::now(),
             clock_ticks: 0,
             clock_freq_cap: None,
+            assert_failures: vec![],
+            cover_counts,
+            mem_contents,
+            mem_pipelines,
+            holes_evaluated: Mutex::new(BTreeMap::new()),
         };
 
         for (ext_inst_id, (path, ext_component)) in circuit.exts().iter().enumerate() {
@@ -352,6 +503,10 @@ impl Sim {
                 self.poke_net(net_id, updated_value);
             }
         }
+
+        for (mem_id, port) in dependents.mem_read_ports.iter() {
+            self.update_mem_read_port(*mem_id, *port);
+        }
     }
 
     pub(crate) fn peek_net(&self, net_id: NetId) -> Value {
@@ -406,20 +561,188 @@ impl Sim {
 //            eprintln!("CPS: {:.2}", self.clocks_per_second());
 //        }
 
+        self.clock_edge(None);
+    }
+
+    /// Tick the clock of a `dom`.
+    /// Only the registers and `mem`s declared `on` that domain, or on a domain wired to it with `$=`, are clocked.
+    ///
+    /// [`Sim::clock`] ticks the implicit clock, which clocks every register and `mem` without a domain.
+    pub fn clock_domain<P: Into<Path>>(&mut self, dom: P) -> Result<(), SimError> {
+        let dom: Path = dom.into();
+        let Some(root_dom) = self.sim_circuit.root_dom_by_path.get(&dom).cloned() else {
+            return Err(SimError::NoSuchDomain(dom));
+        };
+        self.clock_edge(Some(&root_dom));
+        Ok(())
+    }
+
+    /// Everything which happens on a tick of the clock of the root domain `dom`,
+    /// or of the implicit clock when it is `None`.
+    ///
+    /// The assertions are checked against the values from before the tick.
+    /// The `ext`s are on the implicit clock.
+    fn clock_edge(&mut self, dom: Option<&Path>) {
+        self.check_assertions();
+        let mem_reads = self.clock_mems(dom);
+        self.latch_regs(dom);
+        for (data_net_id, value) in mem_reads {
+            self.poke_net(data_net_id, value);
+        }
+        self.update_mem_reads();
+
+        if dom.is_none() {
+            for (ext_inst_id, path) in &self.sim_circuit.path_by_ext_inst_id {
+                let ext_id = self.ext_id_by_ext_inst_id[ext_inst_id];
+                let ext = &mut self.exts[ext_id];
+                ext.clock(path.clone());
+            }
+        }
+    }
+
+    /// Perform the writes to every `mem` on the domain `dom` and advance its reads.
+    /// Returns the values read, which are driven onto the read ports once the registers latch.
+    fn clock_mems(&mut self, dom: Option<&Path>) -> Vec<(NetId, Value)> {
+        let mut reads = vec![];
+        for (mem_id, mem) in self.sim_circuit.clone().mems.iter().enumerate() {
+            if mem.dom.as_ref() != dom {
+                continue;
+            }
+            // Reads see the contents from before the writes.
+            if mem.latency > 0 {
+                for (port, (addr_net_id, data_net_id)) in mem.read_ports.iter().enumerate() {
+                    let value = self.read_mem(mem_id, *addr_net_id);
+                    let pipeline = &mut self.mem_pipelines[mem_id][port];
+                    pipeline.push_back(value);
+                    reads.push((*data_net_id, pipeline.pop_front().unwrap()));
+                }
+            }
+
+            for (en_net_id, addr_net_id, data_net_id, mask) in &mem.write_ports {
+                if self.peek_net(*en_net_id) != Value::Word(1, 1) {
+                    continue;
+                }
+                let Some(addr) = self.peek_net(*addr_net_id).to_u64() else { continue };
+                let Some(old) = self.mem_contents[mem_id].get(addr as usize).cloned() else { continue };
+                let data = self.peek_net(*data_net_id);
+                let new = match mask {
+                    None => data,
+                    Some((mask_net_id, mask_width)) => masked_write(old, data, self.peek_net(*mask_net_id), *mask_width),
+                };
+                self.mem_contents[mem_id][addr as usize] = new;
+            }
+        }
+        reads
+    }
+
+    fn read_mem(&self, mem_id: MemId, addr_net_id: NetId) -> Value {
+        let contents = &self.mem_contents[mem_id];
+        match self.peek_net(addr_net_id).to_u64() {
+            Some(addr) if (addr as usize) < contents.len() => contents[addr as usize].clone(),
+            _ => Value::X,
+        }
+    }
+
+    /// Drive the data of a read port of a `mem` with no latency from its current address.
+    fn update_mem_read_port(&mut self, mem_id: MemId, port: usize) {
+        let (addr_net_id, data_net_id) = self.sim_circuit.mems[mem_id].read_ports[port];
+        let value = self.read_mem(mem_id, addr_net_id);
+        self.poke_net(data_net_id, value);
+    }
+
+    /// Drive the data of every read port of the `mem`s with no latency, after their contents change.
+    fn update_mem_reads(&mut self) {
+        for (mem_id, mem) in self.sim_circuit.clone().mems.iter().enumerate() {
+            if mem.latency == 0 {
+                for port in 0..mem.read_ports.len() {
+                    self.update_mem_read_port(mem_id, port);
+                }
+            }
+        }
+    }
+
+    /// The [`MemId`] of the `mem` at `path`, after checking that `addr` is one of its addresses.
+    fn mem_id(&self, path: Path, addr: usize) -> Result<MemId, SimError> {
+        let Some(mem_id) = self.sim_circuit.mems.iter().position(|mem| mem.path == path) else {
+            return Err(SimError::NoSuchMem(path));
+        };
+        if addr >= self.mem_contents[mem_id].len() {
+            return Err(SimError::AddrOutOfRange(path, addr));
+        }
+        Ok(mem_id)
+    }
+
+    /// The value at address `addr` of the `mem` at `path`.
+    pub fn peek_mem<P: Into<Path>>(&self, path: P, addr: usize) -> Result<Value, SimError> {
+        let mem_id = self.mem_id(path.into(), addr)?;
+        Ok(self.mem_contents[mem_id][addr].clone())
+    }
+
+    /// Set the value at address `addr` of the `mem` at `path`. Eg, to load a program.
+    pub fn poke_mem<P: Into<Path>>(&mut self, path: P, addr: usize, value: Value) -> Result<(), SimError> {
+        let mem_id = self.mem_id(path.into(), addr)?;
+        self.mem_contents[mem_id][addr] = value;
+        self.update_mem_reads();
+        Ok(())
+    }
+
+    /// Evaluate every `assert` and `cover` against the values of the current cycle.
+    /// A condition which is `X` neither fails nor counts.
+    fn check_assertions(&mut self) {
+        for (i, (path, Assertion(span, kind, expr))) in self.sim_circuit.clone().assertions.iter().enumerate() {
+            let value = expr.eval(self);
+            match (kind, value) {
+                (AssertionKind::Assert, Value::Word(1, 0)) => {
+                    self.assert_failures.push(AssertFailure {
+                        span: span.clone(),
+                        path: path.clone(),
+                        clock_ticks: self.clock_ticks,
+                    });
+                },
+                (AssertionKind::Cover, Value::Word(1, 1)) => self.cover_counts[i] += 1,
+                _ => (),
+            }
+        }
+    }
+
+    /// Every `assert` which has failed so far.
+    pub fn assert_failures(&self) -> &[AssertFailure] {
+        &self.assert_failures
+    }
+
+    /// The holes which have been evaluated so far, and where they are, in the order they appear. Eg, `?foo`.
+    pub fn holes_evaluated(&self) -> Vec<(Span, String)> {
+        self.holes_evaluated.lock().unwrap().values().cloned().collect()
+    }
+
+    /// Record that the hole at `span` was evaluated.
+    pub(crate) fn hole_evaluated(&self, span: &Span, hole: String) {
+        let key = (span.source_info().name(), span.start());
+        self.holes_evaluated.lock().unwrap().entry(key).or_insert((span.clone(), hole));
+    }
+
+    /// For each `cover`, the instance it belongs to, its location, and the number of cycles its condition held.
+    pub fn covers(&self) -> Vec<(Path, Span, u64)> {
+        let mut results = vec![];
+        for (i, (path, Assertion(span, kind, _expr))) in self.sim_circuit.assertions.iter().enumerate() {
+            if *kind == AssertionKind::Cover {
+                results.push((path.clone(), span.clone(), self.cover_counts[i]));
+            }
+        }
+        results
+    }
+
+    fn latch_regs(&mut self, dom: Option<&Path>) {
         let mut updates = vec![];
         for reginfo in &self.sim_circuit.clone().regs {
-            let value = self.peek_net(reginfo.set_net_id);
-            updates.push((reginfo.val_net_id, value));
+            if reginfo.dom.as_ref() == dom {
+                let value = self.peek_net(reginfo.set_net_id);
+                updates.push((reginfo.val_net_id, value));
+            }
         }
         for (val_net_id, value) in updates {
             self.poke_net(val_net_id, value);
         }
-
-        for (ext_inst_id, path) in &self.sim_circuit.path_by_ext_inst_id {
-            let ext_id = self.ext_id_by_ext_inst_id[ext_inst_id];
-            let ext = &mut self.exts[ext_id];
-            ext.clock(path.clone());
-        }
     }
 
     pub fn reset(&mut self) {
@@ -442,6 +765,23 @@ impl Sim {
     }
 }
 
+/// Write `data` over `old` in the slices of the word which are enabled by `mask`.
+fn masked_write(old: Value, data: Value, mask: Value, mask_width: Width) -> Value {
+    match (old, data, mask) {
+        (Value::Word(width, old), Value::Word(_width, data), Value::Word(_mask_width, mask)) => {
+            let slice_width = width / mask_width;
+            let mut bits = 0;
+            for i in 0..mask_width {
+                if mask >> i & 1 == 1 {
+                    bits |= (u64::MAX >> (64 - slice_width)) << (i * slice_width);
+                }
+            }
+            Value::Word(width, (old & !bits) | (data & bits))
+        },
+        _ => Value::X,
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Comb(NetId, Arc<Expr>);
 
@@ -467,10 +807,11 @@ impl std::fmt::Debug for Sim {
 pub fn nets(circuit: &Circuit) -> Vec<Net> {
     let mut immediate_driver_for: BTreeMap<Path, Path> = BTreeMap::new();
 
-    for (path, Wire(_loc, target, expr, wire_type)) in circuit.wires() {
+    for (path, Wire(_loc, target, _select, expr, wire_type)) in circuit.wires() {
         let abs_expr = expr.rebase(path.clone());
         let target_terminal: Path = match wire_type {
-            WireType::Dom    => path.join(target).clone(),
+            // Clock domains don't carry values.
+            WireType::Dom    => continue,
             WireType::Direct => path.join(target).clone(),
             WireType::Latch  => path.join(target).set(),
             WireType::Proc   => path.join(target).set(),
@@ -645,19 +986,26 @@ impl Expr {
             Expr::Zext(loc, typ, e) => Expr::Zext(loc.clone(), typ.clone(), e.rebase_rec(current_path, shadowed)),
             Expr::TryCast(loc, typ, e) => Expr::TryCast(loc.clone(), typ.clone(), e.rebase_rec(current_path, shadowed)),
             Expr::ToWord(loc, typ, e) => Expr::ToWord(loc.clone(), typ.clone(), e.rebase_rec(current_path, shadowed)),
+            Expr::AsSigned(loc, typ, e) => Expr::AsSigned(loc.clone(), typ.clone(), e.rebase_rec(current_path, shadowed)),
+            Expr::AsUnsigned(loc, typ, e) => Expr::AsUnsigned(loc.clone(), typ.clone(), e.rebase_rec(current_path, shadowed)),
             Expr::Vec(loc, typ, es) => Expr::Vec(loc.clone(), typ.clone(), es.iter().map(|e| e.rebase_rec(current_path.clone(), shadowed)).collect()),
             Expr::IdxField(loc, typ, e, field) => Expr::IdxField(loc.clone(), typ.clone(), e.rebase_rec(current_path, shadowed), field.clone()),
             Expr::Idx(loc, typ, e, i) => Expr::Idx(loc.clone(), typ.clone(), e.rebase_rec(current_path, shadowed), *i),
             Expr::IdxRange(loc, typ, e, j, i) => Expr::IdxRange(loc.clone(), typ.clone(), e.rebase_rec(current_path, shadowed), *j, *i),
-            Expr::Call(loc, typ, fndef, es) => {
+            Expr::IdxDyn(loc, typ, e, i) => {
+                Expr::IdxDyn(loc.clone(), typ.clone(), e.rebase_rec(current_path.clone(), shadowed), i.rebase_rec(current_path, shadowed))
+            },
+            Expr::Call(loc, typ, fndef, type_params, instance, es) => {
                 Expr::Call(
                     loc.clone(),
                     typ.clone(),
                     fndef.clone(),
+                    type_params.clone(),
+                    instance.clone(),
                     es.iter().map(|e| e.rebase_rec(current_path.clone(), shadowed)).collect(),
                 )
             },
-            Expr::Hole(loc, typ, name) => Expr::Hole(loc.clone(), typ.clone(), name.clone()),
+            Expr::Hole(loc, typ, name, ctx) => Expr::Hole(loc.clone(), typ.clone(), name.clone(), ctx.clone()),
         })
     }
 
@@ -755,19 +1103,31 @@ impl Expr {
             Expr::Zext(loc, typ, e) => Expr::Zext(loc.clone(), typ.clone(), e.references_to_nets_rec(net_id_by_path, shadowed)),
             Expr::TryCast(loc, typ, e) => Expr::TryCast(loc.clone(), typ.clone(), e.references_to_nets_rec(net_id_by_path, shadowed)),
             Expr::ToWord(loc, typ, e) => Expr::ToWord(loc.clone(), typ.clone(), e.references_to_nets_rec(net_id_by_path, shadowed)),
+            Expr::AsSigned(loc, typ, e) => Expr::AsSigned(loc.clone(), typ.clone(), e.references_to_nets_rec(net_id_by_path, shadowed)),
+            Expr::AsUnsigned(loc, typ, e) => Expr::AsUnsigned(loc.clone(), typ.clone(), e.references_to_nets_rec(net_id_by_path, shadowed)),
             Expr::Vec(loc, typ, es) => Expr::Vec(loc.clone(), typ.clone(), es.iter().map(|e| e.references_to_nets_rec(net_id_by_path, shadowed)).collect()),
             Expr::IdxField(loc, typ, e, field) => Expr::IdxField(loc.clone(), typ.clone(), e.references_to_nets_rec(net_id_by_path, shadowed), field.clone()),
             Expr::Idx(loc, typ, e, i) => Expr::Idx(loc.clone(), typ.clone(), e.references_to_nets_rec(net_id_by_path, shadowed), *i),
             Expr::IdxRange(loc, typ, e, j, i) => Expr::IdxRange(loc.clone(), typ.clone(), e.references_to_nets_rec(net_id_by_path, shadowed), *j, *i),
-            Expr::Call(loc, typ, fndef, es) => {
+            Expr::IdxDyn(loc, typ, e, i) => {
+                Expr::IdxDyn(
+                    loc.clone(),
+                    typ.clone(),
+                    e.references_to_nets_rec(net_id_by_path, shadowed),
+                    i.references_to_nets_rec(net_id_by_path, shadowed),
+                )
+            },
+            Expr::Call(loc, typ, fndef, type_params, instance, es) => {
                 Expr::Call(
                     loc.clone(),
                     typ.clone(),
                     fndef.clone(),
+                    type_params.clone(),
+                    instance.clone(),
                     es.iter().map(|e| e.references_to_nets_rec(net_id_by_path, shadowed)).collect(),
                 )
             },
-            Expr::Hole(loc, typ, name) => Expr::Hole(loc.clone(), typ.clone(), name.clone()),
+            Expr::Hole(loc, typ, name, ctx) => Expr::Hole(loc.clone(), typ.clone(), name.clone(), ctx.clone()),
         })
     }
 
@@ -790,12 +1150,15 @@ impl Expr {
             Expr::Zext(_loc, _typ, e) => e.depends_on_net(net_id),
             Expr::TryCast(_loc, _typ, e) => e.depends_on_net(net_id),
             Expr::ToWord(_loc, _typ, e) => e.depends_on_net(net_id),
+            Expr::AsSigned(_loc, _typ, e) => e.depends_on_net(net_id),
+            Expr::AsUnsigned(_loc, _typ, e) => e.depends_on_net(net_id),
             Expr::Vec(_loc, _typ, es) => es.iter().any(|e| e.depends_on_net(net_id)),
             Expr::IdxField(_loc, _typ, e, _field) => e.depends_on_net(net_id),
             Expr::Idx(_loc, _typ, e, _i) => e.depends_on_net(net_id),
             Expr::IdxRange(_loc, _typ, e, _j, _i) => e.depends_on_net(net_id),
-            Expr::Call(_loc, _typ, _fndef, es) => es.iter().any(|e| e.depends_on_net(net_id)),
-            Expr::Hole(_loc, _typ, _name) => false,
+            Expr::IdxDyn(_loc, _typ, e, i) => e.depends_on_net(net_id) || i.depends_on_net(net_id),
+            Expr::Call(_loc, _typ, _fndef, _type_params, _instance, es) => es.iter().any(|e| e.depends_on_net(net_id)),
+            Expr::Hole(_loc, _typ, _name, _ctx) => false,
         }
     }
 
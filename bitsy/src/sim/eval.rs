@@ -1,27 +1,34 @@
 use super::*;
 use crate::sim::Sim;
 use crate::sim::Value;
+use crate::sim::value::to_signed;
 
 impl Expr {
     pub fn eval(&self, bitsy: &Sim) -> Value {
-        self.eval_with_ctx(bitsy, Context::empty())
+        self.eval_with_ctx(Some(bitsy), Context::empty())
     }
 
-    fn eval_with_ctx(&self, bitsy: &Sim, ctx: Context<Path, Value>) -> Value {
+    /// Evaluate an expression which refers to no components, such as the value of a `const`.
+    pub(crate) fn eval_const(&self) -> Value {
+        self.eval_with_ctx(None, Context::empty())
+    }
+
+    fn eval_with_ctx(&self, bitsy: Option<&Sim>, ctx: Context<Path, Value>) -> Value {
         match self {
             Expr::Reference(_loc, _typ, path) => {
                 if let Some(value) = ctx.lookup(path) {
                     value.clone()
                 } else {
-                    bitsy.peek(path.clone())
+                    // Without a simulation, as for a `const`, a component has no value.
+                    bitsy.map(|bitsy| bitsy.peek(path.clone())).unwrap_or(Value::X)
                 }
             }
-            Expr::Net(_loc, _typ, netid) => bitsy.peek_net(*netid),
+            Expr::Net(_loc, _typ, netid) => bitsy.map(|bitsy| bitsy.peek_net(*netid)).unwrap_or(Value::X),
             Expr::Word(_loc, typ, _width, value) => {
-                if let Type::Word(width) = typ.get().unwrap() {
-                    Value::Word(*width, *value)
-                } else {
-                    unreachable!()
+                match typ.get().unwrap() {
+                    Type::Word(width) => Value::Word(*width, *value),
+                    Type::SInt(width) => Value::SInt(*width, *value),
+                    _ => unreachable!(),
                 }
             },
             Expr::Enum(_loc, _typ, typedef, name) => Value::Enum(typedef.clone(), name.clone()),
@@ -45,27 +52,23 @@ impl Expr {
             Expr::UnOp(_loc, _typ, op, e) => {
                 match (op, e.eval_with_ctx(bitsy, ctx.clone())) {
                     (UnOp::Not, Value::Word(n, v)) => Value::Word(n, (!v) & ((1 << n) - 1)),
+                    (UnOp::Not, Value::SInt(n, v)) => Value::SInt(n, (!v) & ((1 << n) - 1)),
                     _ => Value::X,
                 }
             },
             Expr::BinOp(_loc, _typ, op, e1, e2) => {
-                match (op, e1.eval_with_ctx(bitsy, ctx.clone()), e2.eval_with_ctx(bitsy, ctx.clone())) {
-                    (BinOp::Add, Value::X, _other) => Value::X,
-                    (BinOp::Add, _other, Value::X) => Value::X,
-                    (BinOp::Add, Value::Word(w, a),  Value::Word(_w, b)) => Value::Word(w, a.wrapping_add(b) % (1 << w)),
-                    (BinOp::AddCarry, Value::Word(w, a),  Value::Word(_w, b)) => {
-                        let new_w = w + 1;
-                        Value::Word(new_w, a.wrapping_add(b) % (1 << new_w))
-                    },
-                    (BinOp::Sub, Value::Word(w, a),  Value::Word(_w, b)) => Value::Word(w, a.wrapping_sub(b) % (1 << w)),
-                    (BinOp::And, Value::Word(w, a),  Value::Word(_w, b)) => Value::Word(w, a & b),
-                    (BinOp::Or,  Value::Word(w, a),  Value::Word(_w, b)) => Value::Word(w, a | b),
-                    (BinOp::Eq,  Value::Word(_w, a), Value::Word(_v, b)) => (a == b).into(),
-                    (BinOp::Eq,  Value::Enum(_typedef, a), Value::Enum(_typedef2, b)) => (a == b).into(),
-                    (BinOp::Lt,  Value::Word(_w, a), Value::Word(_v, b)) => (a < b).into(),
-                    (BinOp::Neq, Value::Word(_w, a), Value::Word(_v, b)) => (a != b).into(),
-                    (BinOp::Xor, Value::Word(n, a),  Value::Word(_m, b)) => Value::Word(n, a ^ b),
-                    _ => Value::X,
+                let v1 = e1.eval_with_ctx(bitsy, ctx.clone());
+                let v2 = e2.eval_with_ctx(bitsy, ctx.clone());
+                // An SInt has the same bits as a Word, but compares and shifts right as signed.
+                if let Value::SInt(_w, _n) = v1 {
+                    let v = eval_binop(op.signed(), v1.as_unsigned(), v2.as_unsigned());
+                    if op.is_comparison() {
+                        v
+                    } else {
+                        v.as_signed()
+                    }
+                } else {
+                    eval_binop(*op, v1, v2)
                 }
             },
             Expr::If(_loc, _typ, cond, e1, e2) => {
@@ -79,19 +82,21 @@ impl Expr {
                     _ => Value::X,
                 }
             },
-            Expr::Match(loc, _typ, subject, arms) => {
+            Expr::Match(_loc, _typ, subject, arms) => {
                 let subject_value = subject.eval_with_ctx(bitsy, ctx.clone());
                 if subject_value.is_x() {
                     return Value::X;
                 }
 
                 for MatchArm(pat, e) in arms {
-                    if let Some(new_ctx) = pat.bind(&subject_value, ctx.clone()) {
-                        let e_value = e.eval_with_ctx(bitsy, new_ctx);
-                        return e_value;
+                    match pat.bind(&subject_value, ctx.clone()) {
+                        Binding::Match(new_ctx) => return e.eval_with_ctx(bitsy, new_ctx),
+                        Binding::NoMatch => (),
+                        // Which arm is taken depends on an X.
+                        Binding::X => return Value::X,
                     }
                 }
-                panic!("No match arm matched: {loc:?}")
+                Value::X
             },
             Expr::Mux(_loc, _typ, cond, e1, e2) => {
                 let cond_v = cond.eval_with_ctx(bitsy, ctx.clone());
@@ -127,12 +132,12 @@ impl Expr {
                 }
             },
             Expr::Sext(loc, typ, e) => {
-                let n = if let Type::Word(n) = typ.get().unwrap() {
-                    n
-                } else {
-                    unreachable!()
+                let (n, signed) = match typ.get().unwrap() {
+                    Type::Word(n) => (n, false),
+                    Type::SInt(n) => (n, true),
+                    _ => unreachable!(),
                 };
-                match e.eval_with_ctx(bitsy, ctx.clone()) {
+                let v = match e.eval_with_ctx(bitsy, ctx.clone()).as_unsigned() {
                     Value::X => Value::X,
                     Value::Word(0, _x) => panic!("Can't sext a Word[0] {loc:?}"),
                     Value::Word(w, x) => {
@@ -151,6 +156,11 @@ impl Expr {
                     Value::Vec(_vs) => panic!("Can't sext a Vec {loc:?}"),
                     Value::Enum(typedef, _name) => panic!("Can't sext a {} {loc:?}", typedef.name()),
                     _ => panic!("Can't sext {self:?} {loc:?}"),
+                };
+                if signed {
+                    v.as_signed()
+                } else {
+                    v
                 }
             },
             Expr::Zext(loc, typ, e) => {
@@ -196,6 +206,8 @@ impl Expr {
                 }
                 return Value::Ctor("Invalid".to_string(), vec![]);
             },
+            Expr::AsSigned(_loc, _typ, e) => e.eval_with_ctx(bitsy, ctx.clone()).as_signed(),
+            Expr::AsUnsigned(_loc, _typ, e) => e.eval_with_ctx(bitsy, ctx.clone()).as_unsigned(),
             Expr::ToWord(loc, _typ, e) => {
                 let v = e.eval_with_ctx(bitsy, ctx.clone());
                 match &v {
@@ -282,7 +294,28 @@ impl Expr {
                     panic!("Can't index into value: {value:?}")
                 }
             },
-            Expr::Call(_loc, _typ, fndef, es) => {
+            Expr::IdxDyn(_loc, _typ, e, i) => {
+                // An index which is out of range evaluates to X.
+                let index = match i.eval_with_ctx(bitsy, ctx.clone()) {
+                    Value::X => return Value::X,
+                    Value::Word(_width, index) => index,
+                    index => panic!("Index with invalid value: {index:?}"),
+                };
+                match e.eval_with_ctx(bitsy, ctx.clone()) {
+                    Value::X => Value::X,
+                    Value::Word(width, val) => {
+                        if index < width {
+                            Value::Word(1, (val >> index) & 1)
+                        } else {
+                            Value::X
+                        }
+                    },
+                    Value::Vec(vs) => vs.get(index as usize).cloned().unwrap_or(Value::X),
+                    value => panic!("Index with invalid value: {value:?}"),
+                }
+            },
+            Expr::Call(_loc, _typ, fndef, _type_params, instance, es) => {
+                let fndef = instance.get().unwrap_or(fndef);
                 assert_eq!(fndef.args.len(), es.len());
                 let mut new_ctx = ctx.clone();
 
@@ -293,52 +326,140 @@ impl Expr {
 
                 fndef.body.eval_with_ctx(bitsy, new_ctx)
             },
-            Expr::Hole(loc, _typ, opt_name) => {
-                let name = opt_name.clone().unwrap_or_default();
-                eprintln!("{loc} EVALUATED A HOLE: ?{name}: {ctx}");
+            Expr::Hole(loc, _typ, opt_name, _hole_ctx) => {
+                // A hole has no value, so it evaluates to X. See Sim::holes_evaluated.
+                if let Some(bitsy) = bitsy {
+                    bitsy.hole_evaluated(loc, format!("?{}", opt_name.clone().unwrap_or_default()));
+                }
                 Value::X
             },
         }
     }
 }
 
+/// The result of matching a [`Value`] against a [`Pat`].
+enum Binding {
+    /// The value matches, with the context extended by the variables the pattern binds.
+    Match(Context<Path, Value>),
+    NoMatch,
+    /// Whether the value matches depends on a part of it which is X.
+    X,
+}
+
+impl Binding {
+    /// Combine the results of matching the parts of a value, such as the fields of a struct.
+    /// A part which doesn't match decides the result, even when another part is X.
+    fn and_then(self, f: impl FnOnce(Context<Path, Value>) -> Binding) -> Binding {
+        match self {
+            Binding::Match(ctx) => f(ctx),
+            Binding::NoMatch => Binding::NoMatch,
+            Binding::X => match f(Context::empty()) {
+                Binding::NoMatch => Binding::NoMatch,
+                _ => Binding::X,
+            },
+        }
+    }
+}
+
 impl Pat {
-    fn bind(&self, v: &Value, ctx: Context<Path, Value>) -> Option<Context<Path, Value>> {
+    fn bind(&self, v: &Value, ctx: Context<Path, Value>) -> Binding {
         match self {
             Pat::At(ctor, pats) => {
                 match v {
-                    Value::X => None,
-                    Value::Word(_w, _n) => None,
-                    Value::Vec(_vs) => None,
+                    Value::X => Binding::X,
+                    Value::Word(_w, _n) => Binding::NoMatch,
+                    Value::SInt(_w, _n) => Binding::NoMatch,
+                    Value::Vec(_vs) => Binding::NoMatch,
                     Value::Ctor(v_ctor, vs) => {
                         if ctor == v_ctor {
                             assert_eq!(pats.len(), vs.len());
-                            let mut ctx_result = ctx.clone();
+                            let mut result = Binding::Match(ctx);
                             for (pat, v) in pats.iter().zip(vs.iter()) {
-                                if let Some(ctx) = pat.bind(v, ctx_result.clone()) {
-                                    ctx_result = ctx;
-                                } else {
-                                    return None;
-                                }
+                                result = result.and_then(|ctx| pat.bind(v, ctx));
                             }
-                            Some(ctx_result)
+                            result
                         } else {
-                            None
+                            Binding::NoMatch
                         }
                     },
                     Value::Enum(_typ, val) => {
                         assert_eq!(pats.len(), 0);
                         if ctor == val {
-                            Some(ctx)
+                            Binding::Match(ctx)
                         } else {
-                            None
+                            Binding::NoMatch
                         }
                     },
-                    Value::Struct(_typ, _fields) => None,
+                    Value::Struct(_typ, _fields) => Binding::NoMatch,
                 }
             },
-            Pat::Bind(x) => Some(ctx.extend(x.clone().into(), v.clone())),
-            Pat::Otherwise => Some(ctx),
+            Pat::Enum(_typename, value) => match v {
+                Value::X => Binding::X,
+                Value::Enum(_typ, val) if val == value => Binding::Match(ctx),
+                _ => Binding::NoMatch,
+            },
+            Pat::Word(_width, value) => match v {
+                Value::X => Binding::X,
+                Value::Word(_w, n) if n == value => Binding::Match(ctx),
+                Value::SInt(_w, n) if n == value => Binding::Match(ctx),
+                _ => Binding::NoMatch,
+            },
+            Pat::Struct(fields) => match v {
+                Value::X => Binding::X,
+                Value::Struct(_typ, field_values) => {
+                    let mut result = Binding::Match(ctx);
+                    for (field, pat) in fields {
+                        let Some((_name, v)) = field_values.iter().find(|(name, _v)| name == field) else {
+                            return Binding::NoMatch;
+                        };
+                        result = result.and_then(|ctx| pat.bind(v, ctx));
+                    }
+                    result
+                },
+                _ => Binding::NoMatch,
+            },
+            Pat::Bind(x) => Binding::Match(ctx.extend(x.clone().into(), v.clone())),
+            Pat::Otherwise => Binding::Match(ctx),
         }
     }
 }
+
+/// Evaluate a binary operation on `Word`s.
+fn eval_binop(op: BinOp, v1: Value, v2: Value) -> Value {
+    match (op, v1, v2) {
+        (BinOp::Add, Value::X, _other) => Value::X,
+        (BinOp::Add, _other, Value::X) => Value::X,
+        (BinOp::Add, Value::Word(w, a),  Value::Word(_w, b)) => Value::Word(w, a.wrapping_add(b) % (1 << w)),
+        (BinOp::AddCarry, Value::Word(w, a),  Value::Word(_w, b)) => {
+            let new_w = w + 1;
+            Value::Word(new_w, a.wrapping_add(b) % (1 << new_w))
+        },
+        (BinOp::Sub, Value::Word(w, a),  Value::Word(_w, b)) => Value::Word(w, a.wrapping_sub(b) % (1 << w)),
+        (BinOp::SubBorrow, Value::Word(w, a),  Value::Word(_w, b)) => {
+            let new_w = w + 1;
+            Value::Word(new_w, a.wrapping_sub(b) % (1 << new_w))
+        },
+        (BinOp::Mul, Value::Word(w, a),  Value::Word(_w, b)) => Value::Word(w, a.wrapping_mul(b) % (1 << w)),
+        (BinOp::Shl, Value::Word(w, a),  Value::Word(_w, b)) => Value::Word(w, if b < w { (a << b) % (1 << w) } else { 0 }),
+        (BinOp::Shr, Value::Word(w, a),  Value::Word(_w, b)) => Value::Word(w, if b < w { a >> b } else { 0 }),
+        (BinOp::Sra, Value::Word(w, a),  Value::Word(_w, b)) => {
+            let b = b.min(w.saturating_sub(1));
+            Value::Word(w, ((to_signed(w, a) >> b) as u64) % (1 << w))
+        },
+        (BinOp::And, Value::Word(w, a),  Value::Word(_w, b)) => Value::Word(w, a & b),
+        (BinOp::Or,  Value::Word(w, a),  Value::Word(_w, b)) => Value::Word(w, a | b),
+        (BinOp::Eq,  Value::Word(_w, a), Value::Word(_v, b)) => (a == b).into(),
+        (BinOp::Eq,  Value::Enum(_typedef, a), Value::Enum(_typedef2, b)) => (a == b).into(),
+        (BinOp::Lt,  Value::Word(_w, a), Value::Word(_v, b)) => (a < b).into(),
+        (BinOp::Lte, Value::Word(_w, a), Value::Word(_v, b)) => (a <= b).into(),
+        (BinOp::Gt,  Value::Word(_w, a), Value::Word(_v, b)) => (a > b).into(),
+        (BinOp::Gte, Value::Word(_w, a), Value::Word(_v, b)) => (a >= b).into(),
+        (BinOp::Slt,  Value::Word(w, a), Value::Word(v, b)) => (to_signed(w, a) < to_signed(v, b)).into(),
+        (BinOp::Slte, Value::Word(w, a), Value::Word(v, b)) => (to_signed(w, a) <= to_signed(v, b)).into(),
+        (BinOp::Sgt,  Value::Word(w, a), Value::Word(v, b)) => (to_signed(w, a) > to_signed(v, b)).into(),
+        (BinOp::Sgte, Value::Word(w, a), Value::Word(v, b)) => (to_signed(w, a) >= to_signed(v, b)).into(),
+        (BinOp::Neq, Value::Word(_w, a), Value::Word(_v, b)) => (a != b).into(),
+        (BinOp::Xor, Value::Word(n, a),  Value::Word(_m, b)) => Value::Word(n, a ^ b),
+        _ => Value::X,
+    }
+}
@@ -0,0 +1,82 @@
+use super::*;
+
+/// Run a `tb` testbench.
+/// Returns an error for every `expect` which didn't hold and every `assert` which failed in one of its DUTs.
+pub fn run_tb(package: &Package, tbdef: &TbDef) -> Result<(), Vec<BitsyError>> {
+    let mut run = TbRun {
+        package: package.clone(),
+        duts: BTreeMap::new(),
+        errors: vec![],
+    };
+    run.exec(&tbdef.statements);
+
+    for (dut, sim) in &run.duts {
+        for failure in sim.assert_failures() {
+            run.errors.push(BitsyError::AssertFailed(failure.span.clone(), dut_path(dut, &failure.path), failure.clock_ticks));
+        }
+    }
+
+    if run.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(run.errors)
+    }
+}
+
+struct TbRun {
+    package: Package,
+    duts: BTreeMap<Name, Sim>,
+    errors: Vec<BitsyError>,
+}
+
+impl TbRun {
+    fn exec(&mut self, statements: &[TbStatement]) {
+        for statement in statements {
+            match statement {
+                TbStatement::Debug => self.show(),
+                TbStatement::Reset => self.duts.values_mut().for_each(|sim| sim.reset()),
+                TbStatement::Clock => self.duts.values_mut().for_each(|sim| sim.clock()),
+                TbStatement::ModInst(name, moddef) => {
+                    let circuit = Circuit(self.package.clone(), moddef.clone());
+                    self.duts.insert(name.clone(), Sim::new(&circuit, vec![]));
+                },
+                TbStatement::Poke(_span, dut, port, e) => {
+                    let value = e.eval(&self.duts[dut]);
+                    let sim = self.duts.get_mut(dut).unwrap();
+                    sim.poke(format!("top.{port}"), value);
+                },
+                TbStatement::Expect(span, dut, port, e) => {
+                    let expected = e.eval(&self.duts[dut]);
+                    let actual = self.duts[dut].peek(format!("top.{port}"));
+                    if actual != expected {
+                        self.errors.push(BitsyError::ExpectFailed(span.clone(), format!("{dut}.{port}"), Box::new(expected), Box::new(actual)));
+                    }
+                },
+                TbStatement::Loop(_span, n, statements) => {
+                    for _i in 0..*n {
+                        self.exec(statements);
+                    }
+                },
+            }
+        }
+    }
+
+    /// Print the value of every net of every DUT.
+    fn show(&self) {
+        for (dut, sim) in &self.duts {
+            for (net_id, value) in sim.net_values() {
+                let terminals: Vec<String> = sim.net(net_id)
+                    .terminals()
+                    .iter()
+                    .map(|terminal| dut_path(dut, terminal))
+                    .collect();
+                println!("{:>5}   {}", format!("{value:?}"), terminals.join(" "));
+            }
+        }
+    }
+}
+
+/// Rename a path in the simulation of a DUT, such as `top.out`, after the DUT, such as `dut.out`.
+fn dut_path(dut: &str, path: &Path) -> String {
+    format!("{dut}{}", &path["top".len()..])
+}
@@ -1,7 +1,7 @@
 use crate::Path;
 use super::ext::monitor::Monitor;
 use crate::load_package_from_string;
-use crate::sim::{Sim, Value};
+use crate::sim::{Sim, SimError, Value};
 use std::collections::BTreeSet;
 
 #[test]
@@ -181,6 +181,153 @@ fn counter() {
     assert_eq!(bitsy.peek("top.out"), Value::Word(4, 0));
 }
 
+#[test]
+fn when() {
+    let top = load_package_from_string("
+        mod Top {
+            incoming en of Word[1];
+            incoming clr of Word[1];
+            incoming d of Word[4];
+            outgoing out of Word[4];
+            reg r of Word[4] reset 0w4;
+            out := r;
+
+            when en {
+                r <=! d;
+            }
+            when clr {
+                r <=! 0w4;
+            }
+        }
+    ").unwrap();
+    let top = top.top("Top").unwrap();
+
+    let mut bitsy = Sim::new(&top, vec![]);
+    bitsy.reset();
+
+    bitsy.poke("top.en", true.into());
+    bitsy.poke("top.clr", false.into());
+    bitsy.poke("top.d", Value::Word(4, 5));
+    bitsy.clock();
+    assert_eq!(bitsy.peek("top.out"), Value::Word(4, 5));
+
+    // Holds its value when no when applies.
+    bitsy.poke("top.en", false.into());
+    bitsy.poke("top.d", Value::Word(4, 9));
+    bitsy.clock();
+    assert_eq!(bitsy.peek("top.out"), Value::Word(4, 5));
+
+    // The last assignment wins.
+    bitsy.poke("top.en", true.into());
+    bitsy.poke("top.clr", true.into());
+    bitsy.clock();
+    assert_eq!(bitsy.peek("top.out"), Value::Word(4, 0));
+}
+
+#[test]
+fn clock_domains() {
+    let top = load_package_from_string("
+        mod Top {
+            dom fast;
+            dom slow;
+            outgoing a of Word[4];
+            outgoing b of Word[4];
+            outgoing c of Word[4];
+            reg r of Word[4] on fast reset 0;
+            reg t of Word[4] reset 0;
+            r <= r + 1;
+            t <= t + 1;
+            a := r;
+            b := sub.out;
+            c := t;
+
+            mod sub of Counter;
+            sub.clk $= slow;
+        }
+
+        mod Counter {
+            dom clk;
+            outgoing out of Word[4];
+            reg q of Word[4] on clk reset 0;
+            q <= q + 1;
+            out := q;
+        }
+    ").unwrap();
+    let top = top.top("Top").unwrap();
+
+    let mut bitsy = Sim::new(&top, vec![]);
+    bitsy.reset();
+
+    bitsy.clock_domain("top.fast").unwrap();
+    bitsy.clock_domain("top.fast").unwrap();
+    bitsy.clock_domain("top.slow").unwrap();
+    bitsy.clock();
+    bitsy.clock();
+    bitsy.clock();
+    assert_eq!(bitsy.peek("top.a"), Value::Word(4, 2));
+    assert_eq!(bitsy.peek("top.b"), Value::Word(4, 1));
+    assert_eq!(bitsy.peek("top.c"), Value::Word(4, 3));
+
+    // A domain wired to another with `$=` ticks along with it.
+    bitsy.clock_domain("top.sub.clk").unwrap();
+    assert_eq!(bitsy.peek("top.b"), Value::Word(4, 2));
+
+    assert_eq!(bitsy.clock_domain("top.medium"), Err(SimError::NoSuchDomain("top.medium".into())));
+}
+
+#[test]
+fn assertions() {
+    let top = load_package_from_string("
+        mod Top {
+            mod counter of Counter;
+        }
+
+        mod Counter {
+            outgoing out of Word[4];
+            reg c of Word[4] reset 0;
+            c <= c + 1;
+            out := c;
+
+            assert c < 3;
+            cover c == 1;
+        }
+    ").unwrap();
+    let top = top.top("Top").unwrap();
+
+    let mut bitsy = Sim::new(&top, vec![]);
+    bitsy.reset();
+    for _ in 0..4 {
+        bitsy.clock();
+    }
+
+    let failures = bitsy.assert_failures();
+    assert_eq!(failures.len(), 1);
+    assert_eq!(failures[0].path, "top.counter".into());
+    assert_eq!(failures[0].clock_ticks, 4);
+
+    let covers = bitsy.covers();
+    assert_eq!(covers.len(), 1);
+    assert_eq!(covers[0].2, 1);
+
+    // Assertions are checked when a single domain is clocked, too.
+    let top = load_package_from_string("
+        mod Top {
+            dom clk;
+            reg c of Word[4] on clk reset 0;
+            c <= c + 1;
+            assert c < 1;
+        }
+    ").unwrap();
+    let top = top.top("Top").unwrap();
+
+    let mut bitsy = Sim::new(&top, vec![]);
+    bitsy.reset();
+    bitsy.clock_domain("top.clk").unwrap();
+    assert!(bitsy.assert_failures().is_empty());
+    bitsy.clock_domain("top.clk").unwrap();
+    assert_eq!(bitsy.assert_failures().len(), 1);
+}
+
 #[test]
 fn monitor() {
     let top = load_package_from_string("
@@ -212,3 +359,91 @@ fn monitor() {
     bitsy.clock();
 }
 
+
+#[test]
+fn signed() {
+    let top = load_package_from_string("
+        mod Top {
+            incoming a of SInt[8];
+            incoming b of SInt[8];
+            incoming w of Word[4];
+            outgoing lt of Word[1];
+            outgoing ult of Word[1];
+            outgoing shr of SInt[8];
+            outgoing wide of SInt[16];
+            outgoing sum of SInt[8];
+            outgoing bits of Word[8];
+            outgoing s of SInt[4];
+            outgoing long of SInt[64];
+            outgoing wide_word of Word[64];
+
+            lt := a < b;
+            ult := as_unsigned(a) < as_unsigned(b);
+            shr := a >> 1;
+            wide := sext(a);
+            sum := a + b;
+            bits := as_unsigned(a);
+            s := as_signed(w);
+            long := 5;
+            wide_word := 5;
+        }
+    ").unwrap();
+    let top = top.top("Top").unwrap();
+
+    let mut bitsy = Sim::new(&top, vec![]);
+    bitsy.poke("top.a", Value::SInt(8, 0xfe));
+    bitsy.poke("top.b", Value::SInt(8, 3));
+    bitsy.poke("top.w", Value::Word(4, 0b1001));
+
+    assert_eq!(bitsy.peek("top.lt"), Value::Word(1, 1));
+    assert_eq!(bitsy.peek("top.ult"), Value::Word(1, 0));
+    assert_eq!(bitsy.peek("top.shr"), Value::SInt(8, 0xff));
+    assert_eq!(bitsy.peek("top.wide"), Value::SInt(16, 0xfffe));
+    assert_eq!(bitsy.peek("top.sum"), Value::SInt(8, 1));
+    assert_eq!(bitsy.peek("top.bits"), Value::Word(8, 0xfe));
+    assert_eq!(bitsy.peek("top.s").to_i64(), Some(-7));
+    assert_eq!(bitsy.peek("top.a").to_string(), "-2s8");
+    assert_eq!(bitsy.peek("top.long"), Value::SInt(64, 5));
+    assert_eq!(bitsy.peek("top.wide_word"), Value::Word(64, 5));
+
+    let errors = load_package_from_string("
+        mod Top {
+            incoming a of SInt[8];
+            outgoing out of Word[8];
+            out := a;
+        }
+    ").unwrap_err();
+    assert_eq!(errors[0].to_string(), "Type Error: Not expected type: has type SInt[8] but expected Word[8].");
+}
+
+#[test]
+fn match_x() {
+    let top = load_package_from_string("
+        struct type Pair {
+            a of Word[1];
+            b of Word[1];
+        }
+
+        mod Top {
+            incoming in of Word[1];
+            outgoing out of Word[2];
+            reg r of Word[1];
+            node s of Pair;
+            r <= in;
+            s := { a = r, b = 1 };
+            out := match s {
+                { a = _, b = @0w1 } => 2;
+                { a = @0w1, b = _ } => 0;
+                { a = @1w1, b = _ } => 1;
+            };
+        }
+    ").unwrap();
+    let top = top.top("Top").unwrap();
+
+    let mut bitsy = Sim::new(&top, vec![]);
+    assert_eq!(bitsy.peek("top.out"), Value::X);
+
+    bitsy.poke("top.in", Value::Word(1, 1));
+    bitsy.clock();
+    assert_eq!(bitsy.peek("top.out"), Value::Word(2, 1));
+}
@@ -1,4 +1,8 @@
 use crate::types::*;
+use crate::expr::Expr;
+use crate::loc::Span;
+use once_cell::sync::OnceCell;
+use std::sync::Arc;
 
 /// A value used in the simulator (see [`crate::sim::Sim`]).
 #[derive(Clone, Default)]
@@ -8,6 +12,8 @@ pub enum Value {
     X,
     /// An element of `Word[n]`.
     Word(Width, u64),
+    /// An element of `SInt[n]`. Holds the two's complement bits.
+    SInt(Width, u64),
     /// An element of `Vec[T, n]`.
     Vec(Vec<Value>),
     /// An element of `Valid[T]`.
@@ -23,6 +29,7 @@ impl PartialEq for Value {
         match (self, other) {
             (Value::X, Value::X) => true,
             (Value::Word(w1, v1), Value::Word(w2, v2)) => w1 == w2 && v1 == v2,
+            (Value::SInt(w1, v1), Value::SInt(w2, v2)) => w1 == w2 && v1 == v2,
             (Value::Vec(vs1), Value::Vec(vs2)) => {
                 assert_eq!(vs1.len(), vs2.len());
                 for (v1, v2) in vs1.iter().zip(vs2.iter()) {
@@ -71,10 +78,24 @@ impl Value {
         }
     }
 
+    /// Whether this is X, or has an X anywhere inside of it. Eg, `[1w8, X]`.
+    pub fn has_x(&self) -> bool {
+        match self {
+            Value::X => true,
+            Value::Word(_w, _n) => false,
+            Value::SInt(_w, _n) => false,
+            Value::Vec(vs) => vs.iter().any(|v| v.has_x()),
+            Value::Ctor(_name, vs) => vs.iter().any(|v| v.has_x()),
+            Value::Enum(_typ, _name) => false,
+            Value::Struct(_typ, fields) => fields.iter().any(|(_name, v)| v.has_x()),
+        }
+    }
+
     pub fn to_u64(&self) -> Option<u64> {
         match self {
             Value::X => None,
             Value::Word(w, n) => Some(n & ((1 << w) - 1)),
+            Value::SInt(w, n) => Some(n & ((1 << w) - 1)),
             Value::Vec(_vs) => panic!(),
             Value::Enum(_typedef, _name) => panic!(),
             Value::Struct(_typedef, _fields) => panic!(),
@@ -82,6 +103,30 @@ impl Value {
         }
     }
 
+    /// The value of an `SInt[n]` as a signed integer.
+    pub fn to_i64(&self) -> Option<i64> {
+        match self {
+            Value::SInt(w, n) => Some(to_signed(*w, *n)),
+            _ => None,
+        }
+    }
+
+    /// Reinterpret a `Word[n]` as an `SInt[n]`.
+    pub fn as_signed(&self) -> Value {
+        match self {
+            Value::Word(w, n) => Value::SInt(*w, *n),
+            _ => self.clone(),
+        }
+    }
+
+    /// Reinterpret an `SInt[n]` as a `Word[n]`.
+    pub fn as_unsigned(&self) -> Value {
+        match self {
+            Value::SInt(w, n) => Value::Word(*w, *n),
+            _ => self.clone(),
+        }
+    }
+
     pub fn to_bool(&self) -> Option<bool> {
         match self {
             Value::Word(1, 0) => Some(false),
@@ -89,6 +134,143 @@ impl Value {
             _ => None,
         }
     }
+
+    /// Pack this value of type `typ` into bits.
+    /// Structs put their first field highest and `Vec`s put their first element lowest.
+    /// For `Valid[T]` and `alt` types, see [`Type::ctors`].
+    /// Returns `None` if any part of the value is `X` or if it doesn't fit in 64 bits.
+    pub fn to_bits(&self, typ: &Type) -> Option<u64> {
+        if typ.bitwidth() > 64 {
+            return None;
+        }
+        match (self, typ) {
+            (Value::X, _) => None,
+            (Value::Word(w, n), _) => Some(n & mask(*w)),
+            (Value::SInt(w, n), _) => Some(n & mask(*w)),
+            (Value::Enum(_typ, name), Type::Enum(typedef)) => typedef.value_of(name),
+            (Value::Vec(vs), Type::Vec(elem_typ, _n)) => {
+                let mut bits = 0;
+                for v in vs.iter().rev() {
+                    bits = push_bits(bits, elem_typ.bitwidth(), v.to_bits(elem_typ)?);
+                }
+                Some(bits)
+            },
+            (Value::Struct(_typ, fields), Type::Struct(typedef)) => {
+                let mut bits = 0;
+                for (name, field_typ) in &typedef.fields {
+                    let (_name, v) = fields.iter().find(|(field, _v)| field == name)?;
+                    bits = push_bits(bits, field_typ.bitwidth(), v.to_bits(field_typ)?);
+                }
+                Some(bits)
+            },
+            (Value::Ctor(ctor, vs), _) => {
+                let (tag, field_typs) = typ.ctor_tag(ctor)?;
+                let mut bits = tag;
+                let mut used_width = typ.tag_width()?;
+                for (v, field_typ) in vs.iter().zip(field_typs.iter()) {
+                    bits = push_bits(bits, field_typ.bitwidth(), v.to_bits(field_typ)?);
+                    used_width += field_typ.bitwidth();
+                }
+                Some(push_bits(bits, typ.bitwidth() - used_width, 0))
+            },
+            _ => None,
+        }
+    }
+
+    /// Unpack the bits of a value of type `typ`. The inverse of [`Value::to_bits`].
+    /// Bits which don't name a value, such as an unused tag, give `X`.
+    pub fn from_bits(typ: &Type, bits: u64) -> Value {
+        let width = typ.bitwidth();
+        let bits = bits & mask(width);
+        match typ {
+            Type::Word(n) => Value::Word(*n, bits),
+            Type::SInt(n) => Value::SInt(*n, bits),
+            Type::Enum(typedef) => {
+                match typedef.values.iter().find(|(_name, value)| value.value() == bits) {
+                    Some((name, _value)) => Value::Enum(typ.clone(), name.clone()),
+                    None => Value::X,
+                }
+            },
+            Type::Vec(elem_typ, n) => {
+                let elem_width = elem_typ.bitwidth();
+                Value::Vec((0..*n).map(|i| Value::from_bits(elem_typ, shr(bits, i * elem_width))).collect())
+            },
+            Type::Struct(typedef) => {
+                let mut offset = width;
+                let mut fields = vec![];
+                for (name, field_typ) in &typedef.fields {
+                    offset -= field_typ.bitwidth();
+                    fields.push((name.clone(), Value::from_bits(field_typ, shr(bits, offset))));
+                }
+                Value::Struct(typ.clone(), fields)
+            },
+            Type::Valid(_) | Type::Alt(_, _) => {
+                let mut offset = width - typ.tag_width().unwrap();
+                let tag = shr(bits, offset) as usize;
+                let ctors = typ.ctors().unwrap();
+                let Some((ctor, field_typs)) = ctors.get(tag) else {
+                    return Value::X;
+                };
+                let mut vs = vec![];
+                for field_typ in field_typs {
+                    offset -= field_typ.bitwidth();
+                    vs.push(Value::from_bits(field_typ, shr(bits, offset)));
+                }
+                Value::Ctor(ctor.clone(), vs)
+            },
+            Type::Var(_) | Type::GenericWord(_) | Type::GenericSInt(_) | Type::GenericVec(_, _) => unreachable!(),
+        }
+    }
+
+    /// A literal [`Expr`] which evaluates to this value. Used to fold `const` definitions.
+    /// The value may not contain an X. See [`Value::has_x`].
+    pub(crate) fn to_expr(&self, span: &Span) -> Arc<Expr> {
+        Arc::new(match self {
+            Value::X => unreachable!("A const can't be X"),
+            Value::Word(w, n) => Expr::Word(span.clone(), OnceCell::new(), Some(*w), *n),
+            Value::SInt(w, n) => Expr::AsSigned(span.clone(), OnceCell::new(), Value::Word(*w, *n).to_expr(span)),
+            Value::Vec(vs) => Expr::Vec(span.clone(), OnceCell::new(), vs.iter().map(|v| v.to_expr(span)).collect()),
+            Value::Ctor(name, vs) => {
+                Expr::Ctor(span.clone(), OnceCell::new(), name.clone(), vs.iter().map(|v| v.to_expr(span)).collect())
+            },
+            Value::Enum(typ, name) => Expr::Enum(span.clone(), OnceCell::new(), typ.clone(), name.clone()),
+            Value::Struct(_typ, fields) => {
+                let fields = fields.iter().map(|(name, v)| (name.clone(), v.to_expr(span))).collect();
+                Expr::Struct(span.clone(), OnceCell::new(), fields)
+            },
+        })
+    }
+}
+
+/// The lowest `width` bits set.
+fn mask(width: Width) -> u64 {
+    if width >= 64 {
+        u64::MAX
+    } else {
+        (1 << width) - 1
+    }
+}
+
+fn shr(bits: u64, amount: Width) -> u64 {
+    bits.checked_shr(amount as u32).unwrap_or(0)
+}
+
+/// Append `width` bits below `bits`.
+fn push_bits(bits: u64, width: Width, value: u64) -> u64 {
+    bits.checked_shl(width as u32).unwrap_or(0) | (value & mask(width))
+}
+
+/// Interpret a `Word[w]` as a two's complement signed integer.
+pub(crate) fn to_signed(w: u64, value: u64) -> i64 {
+    if w == 0 {
+        0
+    } else if w >= 64 {
+        value as i64
+    } else if value & (1 << (w - 1)) != 0 {
+        value as i64 - (1 << w)
+    } else {
+        value as i64
+    }
 }
 
 #[test]
@@ -143,6 +325,7 @@ impl std::fmt::Debug for Value {
         match self {
             Value::X => write!(f, "XXX"),
             Value::Word(w, n) => write!(f, "{n}w{w}"),
+            Value::SInt(w, n) => write!(f, "{}s{w}", to_signed(*w, *n)),
             Value::Vec(vs) => {
                 write!(f, "[")?;
                 for (i, v) in vs.iter().enumerate() {
@@ -183,6 +366,7 @@ impl std::fmt::Display for Value {
         match self {
             Value::X => write!(f, "XXX"),
             Value::Word(w, n) => write!(f, "{n}w{w}"),
+            Value::SInt(w, n) => write!(f, "{}s{w}", to_signed(*w, *n)),
             Value::Vec(vs) => {
                 write!(f, "[")?;
                 for (i, v) in vs.iter().enumerate() {
@@ -220,6 +404,7 @@ impl std::fmt::LowerHex for Value {
         match self {
             Value::X => write!(f, "XXX"),
             Value::Word(w, _n) => write!(f, "0x{:x}w{w}", self.to_u64().unwrap()),
+            Value::SInt(w, _n) => write!(f, "0x{:x}s{w}", self.to_u64().unwrap()),
             Value::Vec(vs) => {
                 write!(f, "[")?;
                 for (i, v) in vs.iter().enumerate() {
@@ -257,6 +442,7 @@ impl std::fmt::UpperHex for Value {
         match self {
             Value::X => write!(f, "XXX"),
             Value::Word(w, _n) => write!(f, "0x{:X}w{w}", self.to_u64().unwrap()),
+            Value::SInt(w, _n) => write!(f, "0x{:X}s{w}", self.to_u64().unwrap()),
             Value::Vec(vs) => {
                 write!(f, "[")?;
                 for (i, v) in vs.iter().enumerate() {
@@ -294,6 +480,7 @@ impl std::fmt::Binary for Value {
         match self {
             Value::X => write!(f, "XXX"),
             Value::Word(w, _n) => write!(f, "0b{:b}w{w}", self.to_u64().unwrap()),
+            Value::SInt(w, _n) => write!(f, "0b{:b}s{w}", self.to_u64().unwrap()),
             Value::Vec(vs) => {
                 write!(f, "[")?;
                 for (i, v) in vs.iter().enumerate() {
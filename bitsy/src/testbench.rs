@@ -17,7 +17,7 @@ pub(crate) enum TestbenchCommand {
     Peek(Path),
     Poke(Path, Value),
     Set(Path, Value),
-    Clock,
+    Clock(Option<Path>),
     Reset,
     Show,
     Run,
@@ -1,5 +1,5 @@
 use super::*;
-use crate::sim::{Sim, Value};
+use crate::sim::{run_tb, Sim, SimError, Value};
 
 #[test]
 fn buffer() {
@@ -196,7 +196,7 @@ fn test_locs() {
     package.check().unwrap();
     let top = package.top("Top").unwrap();
     let wires = top.wires();
-    let (_path, Wire(_loc, _target, expr, _wiretype)) = wires.first().unwrap();
+    let (_path, Wire(_loc, _target, _select, expr, _wiretype)) = wires.first().unwrap();
     assert_eq!(source_info.start(expr).to_string(), "6:20");
     assert_eq!(source_info.end(expr).to_string(), "6:22");
 }
@@ -234,3 +234,1637 @@ fn test_parse_package_from_string() {
         panic!("Errors in examples:\n  - {}", errors.join("\n  - "))
     }
 }
+
+#[test]
+fn test_imports() {
+    let dir = std::env::temp_dir().join(format!("bitsy-test-imports-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    std::fs::write(dir.join("lib.bitsy"), "
+        pub enum type State {
+            Idle = 0w1;
+            Busy = 1w1;
+        }
+
+        pub fn inc(x of Word[8]) -> Word[8] {
+            x + 1w8
+        }
+
+        pub mod Adder {
+            incoming in of Word[8];
+            outgoing out of Word[8];
+            out := inc(in);
+        }
+    ").unwrap();
+
+    std::fs::write(dir.join("top.bitsy"), "
+        import lib
+
+        mod Top {
+            incoming in of Word[8];
+            outgoing out of Word[8];
+            outgoing state of lib::State;
+            mod adder of lib::Adder;
+            adder.in := lib::inc(in);
+            out := adder.out;
+            state := lib::State::Busy;
+        }
+    ").unwrap();
+
+    let package = load_package_from_file(dir.join("top.bitsy")).unwrap();
+    package.check().unwrap();
+    assert!(package.imports().contains_key("lib"));
+
+    let top = package.top("Top").unwrap();
+    let mut bitsy = Sim::new(&top, vec![]);
+    bitsy.poke("top.in", Value::Word(8, 40));
+    assert_eq!(bitsy.peek("top.out"), Value::Word(8, 42));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_visibility() {
+    let dir = std::env::temp_dir().join(format!("bitsy-test-visibility-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    std::fs::write(dir.join("lib.bitsy"), "
+        pub struct type Pair {
+            x of Word[8];
+            y of Word[8];
+        }
+
+        enum type State {
+            Idle;
+            Busy;
+        }
+
+        fn helper(x of Word[8]) -> Word[8] {
+            x
+        }
+
+        mod Inner {
+            outgoing out of Word[8];
+            out := helper(0);
+        }
+    ").unwrap();
+
+    std::fs::write(dir.join("top.bitsy"), "
+        import lib
+
+        mod Top {
+            outgoing pair of lib::Pair;
+            outgoing state of lib::State;
+            mod inner of lib::Inner;
+            pair := { x = lib::helper(1), y = inner.out };
+            state := lib::State::Busy;
+        }
+    ").unwrap();
+
+    let errors = load_package_from_file(dir.join("top.bitsy")).unwrap_err();
+    let mut names: Vec<&str> = errors.iter().map(|error| match error {
+        BitsyError::NotPublic(_span, name, item) => {
+            assert!(item.span().start().line() > 1);
+            name.as_str()
+        },
+        error => panic!("Unexpected error: {error}"),
+    }).collect();
+    names.sort();
+    names.dedup();
+    assert_eq!(names, vec!["lib::Inner", "lib::State", "lib::helper"]);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_import_cycle() {
+    let dir = std::env::temp_dir().join(format!("bitsy-test-import-cycle-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    std::fs::write(dir.join("a.bitsy"), "import b\nmod A {}").unwrap();
+    std::fs::write(dir.join("b.bitsy"), "import a\nmod B {}").unwrap();
+
+    let errors = load_package_from_file(dir.join("a.bitsy")).unwrap_err();
+    assert!(matches!(&errors[..], [BitsyError::ImportCycle(_span, cycle)] if cycle == &["a", "b", "a"]));
+
+    let errors = load_package_from_string("import missing\nmod Top {}").unwrap_err();
+    assert!(matches!(&errors[..], [BitsyError::ImportNotFound(_span, name)] if name == "missing"));
+
+    let errors = load_package_from_file(dir.join("missing.bitsy")).unwrap_err();
+    assert!(matches!(&errors[..], [BitsyError::CantReadFile(_span, _path, _error)]));
+
+    // A file which isn't UTF-8 can't be read, and is reported where it's imported.
+    std::fs::write(dir.join("binary.bitsy"), [0xff, 0xfe, 0x00]).unwrap();
+    std::fs::write(dir.join("c.bitsy"), "import binary\nmod C {}").unwrap();
+    let errors = load_package_from_file(dir.join("c.bitsy")).unwrap_err();
+    assert!(matches!(&errors[..], [BitsyError::CantReadFile(span, _path, _error)] if span.start().line() == 1));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_parametric_mods() {
+    let package = load_package_from_string("
+        mod Buffer[T of Type] {
+            incoming in of T;
+            outgoing out of T;
+            reg r of T;
+            r <= in;
+            out := r;
+        }
+
+        mod Counter[n of Nat, limit of Nat] {
+            outgoing out of Word[n];
+            outgoing done of Word[1];
+            reg count of Word[n] reset 0;
+            count <= count + 1;
+            out := count;
+            done := count == limit;
+        }
+
+        mod Top {
+            incoming in of Word[8];
+            outgoing out of Word[8];
+            outgoing count of Word[4];
+            outgoing done of Word[1];
+            mod buf of Buffer[Word[8]];
+            mod buf2 of Buffer[Word[8]];
+            mod counter of Counter[4, 9];
+            buf.in := in;
+            buf2.in := in;
+            out := buf.out;
+            count := counter.out;
+            done := counter.done;
+        }
+    ").unwrap();
+    let top = package.top("Top").unwrap();
+
+    let mut bitsy = Sim::new(&top, vec![]);
+    bitsy.reset();
+    bitsy.poke("top.in", Value::Word(8, 42));
+    assert_eq!(bitsy.peek("top.out"), Value::X);
+    assert!(bitsy.type_of("top.buf.r").equals(&Type::word(8)));
+
+    for i in 0..9 {
+        assert_eq!(bitsy.peek("top.count"), Value::Word(4, i));
+        assert_eq!(bitsy.peek("top.done"), false.into());
+        bitsy.clock();
+    }
+    assert_eq!(bitsy.peek("top.out"), Value::Word(8, 42));
+    assert_eq!(bitsy.peek("top.done"), true.into());
+
+    assert!(package.top("Buffer").is_err());
+
+    // Each set of parameters is elaborated once.
+    let buf = top.component("top.buf".into()).unwrap();
+    let buf2 = top.component("top.buf2".into()).unwrap();
+    assert!(std::sync::Arc::ptr_eq(&buf, &buf2));
+}
+
+#[test]
+fn test_parametric_mods_checked() {
+    let broken = "
+        mod Buffer[T of Type] {
+            incoming in of T;
+            outgoing out of T;
+            node n of T;
+            n := in;
+        }
+    ";
+    let is_out = |error: &BitsyError| matches!(error, BitsyError::NoDrivers(component) if component.name() == "out");
+
+    // A mod is checked even when it's never used.
+    let errors = load_package_from_string(&format!("{broken} mod Top {{ }}")).unwrap_err();
+    assert_eq!(errors.iter().filter(|error| is_out(error)).count(), 1);
+
+    // When it is used, the same mistake isn't reported twice.
+    let errors = load_package_from_string(&format!("{broken} mod Top {{ mod buf of Buffer[Word[8]]; buf.in := 0; }}")).unwrap_err();
+    assert_eq!(errors.iter().filter(|error| is_out(error)).count(), 1);
+}
+
+#[test]
+fn test_parametric_mods_wrong_params() {
+    let errors = load_package_from_string("
+        mod Buffer[T of Type] {
+            incoming in of T;
+            outgoing out of T;
+            out := in;
+        }
+
+        mod Top {
+            mod a of Buffer[3];
+            mod b of Buffer[Word[8], 3];
+            a.in := 0;
+            b.in := 0;
+        }
+    ").unwrap_err();
+    assert_eq!(errors.iter().filter(|error| matches!(error, BitsyError::WrongParams(_span, _name, _message))).count(), 2);
+}
+
+#[test]
+fn test_generic_fns() {
+    let package = load_package_from_string("
+        fn add_one[n of Nat](x of Word[n]) -> Word[n] {
+            x + 1
+        }
+
+        fn widen[n of Nat](x of Word[n]) -> Word[n+1] {
+            cat(0w1, x)
+        }
+
+        fn pick[T of Type](c of Word[1], a of T, b of T) -> T {
+            if c { a } else { b }
+        }
+
+        fn zeros[n of Nat]() -> Word[n] {
+            0
+        }
+
+        mod Top {
+            incoming in of Word[8];
+            outgoing inc of Word[8];
+            outgoing wide of Word[9];
+            outgoing picked of Word[4];
+            outgoing zero of Word[16];
+            outgoing explicit of Word[4];
+            inc := add_one(in);
+            wide := widen(in);
+            picked := pick(1, in[4..0], 0w4);
+            zero := zeros();
+            explicit := add_one::[4](15);
+        }
+    ").unwrap();
+    let top = package.top("Top").unwrap();
+
+    let mut bitsy = Sim::new(&top, vec![]);
+    bitsy.poke("top.in", Value::Word(8, 255));
+    assert_eq!(bitsy.peek("top.inc"), Value::Word(8, 0));
+    assert_eq!(bitsy.peek("top.wide"), Value::Word(9, 255));
+    assert_eq!(bitsy.peek("top.picked"), Value::Word(4, 15));
+    assert_eq!(bitsy.peek("top.zero"), Value::Word(16, 0));
+    assert_eq!(bitsy.peek("top.explicit"), Value::Word(4, 0));
+
+    let errors = load_package_from_string("
+        fn zeros[n of Nat]() -> Word[n] {
+            0
+        }
+
+        mod Top {
+            outgoing out of Word[8];
+            node n of Word[1];
+            n := zeros() == zeros();
+            out := zeros::[Word[8]]();
+        }
+    ").unwrap_err();
+    assert_eq!(errors.len(), 2);
+}
+
+#[test]
+fn test_dynamic_index() {
+    let package = load_package_from_string("
+        mod Top {
+            incoming regs of Vec[Word[8], 3];
+            incoming word of Word[8];
+            incoming i of Word[2];
+            incoming j of Word[3];
+            outgoing reg_out of Word[8];
+            outgoing bit_out of Word[1];
+            reg_out := regs[i];
+            bit_out := word[j];
+        }
+    ").unwrap();
+    let top = package.top("Top").unwrap();
+
+    let mut bitsy = Sim::new(&top, vec![]);
+    bitsy.poke("top.regs", Value::Vec(vec![Value::Word(8, 10), Value::Word(8, 20), Value::Word(8, 30)]));
+    bitsy.poke("top.word", Value::Word(8, 0b0100_0000));
+    bitsy.poke("top.i", Value::Word(2, 2));
+    bitsy.poke("top.j", Value::Word(3, 6));
+    assert_eq!(bitsy.peek("top.reg_out"), Value::Word(8, 30));
+    assert_eq!(bitsy.peek("top.bit_out"), Value::Word(1, 1));
+
+    // Out of range
+    bitsy.poke("top.i", Value::Word(2, 3));
+    assert_eq!(bitsy.peek("top.reg_out"), Value::X);
+
+    let errors = load_package_from_string("
+        mod Top {
+            incoming regs of Vec[Word[8], 32];
+            incoming i of Word[4];
+            outgoing out of Word[8];
+            out := regs[i];
+        }
+    ").unwrap_err();
+    assert_eq!(errors.len(), 1);
+}
+
+#[test]
+fn test_operators() {
+    let package = load_package_from_string("
+        mod Top {
+            incoming a of Word[8];
+            incoming b of Word[8];
+            incoming shamt of Word[3];
+            outgoing mul of Word[8];
+            outgoing sub_borrow of Word[9];
+            outgoing shl of Word[8];
+            outgoing shr of Word[8];
+            outgoing sra of Word[8];
+            outgoing shl_lit of Word[8];
+            outgoing lte of Word[1];
+            outgoing gt of Word[1];
+            outgoing gte of Word[1];
+            outgoing slt of Word[1];
+            outgoing slte of Word[1];
+            outgoing sgt of Word[1];
+            outgoing sgte of Word[1];
+            outgoing assoc of Word[8];
+            outgoing sub_sub of Word[8];
+            outgoing lt_eq of Word[1];
+            mul := a * b;
+            sub_borrow := a -% b;
+            shl := a << shamt;
+            shr := a >> shamt;
+            sra := a >>> shamt;
+            shl_lit := a << 1 + 1;
+            lte := a <= b;
+            gt := a > b;
+            gte := a >= b;
+            slt := a <+ b;
+            slte := a <=+ b;
+            sgt := a >+ b;
+            sgte := a >=+ b;
+            assoc := a - b - 1 + 2 * 3;
+            sub_sub := a - b - b;
+            lt_eq := a < b == 0;
+        }
+    ").unwrap();
+    let top = package.top("Top").unwrap();
+
+    let mut bitsy = Sim::new(&top, vec![]);
+    bitsy.poke("top.a", Value::Word(8, 0b1000_0100));
+    bitsy.poke("top.b", Value::Word(8, 3));
+    bitsy.poke("top.shamt", Value::Word(3, 2));
+    assert_eq!(bitsy.peek("top.mul"), Value::Word(8, (0b1000_0100 * 3) % 256));
+    assert_eq!(bitsy.peek("top.sub_borrow"), Value::Word(9, 0b1000_0001));
+    assert_eq!(bitsy.peek("top.shl"), Value::Word(8, 0b0001_0000));
+    assert_eq!(bitsy.peek("top.shr"), Value::Word(8, 0b0010_0001));
+    assert_eq!(bitsy.peek("top.sra"), Value::Word(8, 0b1110_0001));
+    assert_eq!(bitsy.peek("top.shl_lit"), Value::Word(8, 0b0001_0000));
+    assert_eq!(bitsy.peek("top.lte"), false.into());
+    assert_eq!(bitsy.peek("top.gt"), true.into());
+    assert_eq!(bitsy.peek("top.gte"), true.into());
+    assert_eq!(bitsy.peek("top.slt"), true.into());
+    assert_eq!(bitsy.peek("top.slte"), true.into());
+    assert_eq!(bitsy.peek("top.sgt"), false.into());
+    assert_eq!(bitsy.peek("top.sgte"), false.into());
+    assert_eq!(bitsy.peek("top.assoc"), Value::Word(8, 0b1000_0100 - 3 - 1 + 6));
+    // `-` and the comparisons associate to the left: `(a - b) - b`, not `a - (b - b)`.
+    assert_eq!(bitsy.peek("top.sub_sub"), Value::Word(8, 0b1000_0100 - 3 - 3));
+    assert_eq!(bitsy.peek("top.lt_eq"), true.into());
+
+    bitsy.poke("top.b", Value::Word(8, 0b1111_0000));
+    assert_eq!(bitsy.peek("top.sub_borrow"), Value::Word(9, (0b1000_0100 + 512 - 0b1111_0000) % 512));
+}
+
+#[test]
+fn test_when_errors() {
+    let errors = load_package_from_string("
+        mod Top {
+            incoming en of Word[1];
+            outgoing out of Word[4];
+            node n of Word[4];
+            reg r of Word[4] reset 0w4;
+            reg s of Word[4] reset 0w4;
+            out := r;
+            n := 0w4;
+            s <= s;
+
+            when en {
+                r := 1w4;
+                n <=! 2w4;
+                s <=! 3w4;
+            }
+        }
+    ").unwrap_err();
+    assert_eq!(errors.iter().filter(|error| matches!(error, BitsyError::WrongWireType(_span, _name, _wire_type))).count(), 2);
+    assert_eq!(errors.iter().filter(|error| matches!(error, BitsyError::MultipleDrivers(_span, _name, _first))).count(), 1);
+}
+
+#[test]
+fn test_dom_errors() {
+    let errors = load_package_from_string("
+        mod Top {
+            dom clk;
+            node n of Word[1];
+            reg r of Word[1] on n reset 0;
+            reg s of Word[1] on nope reset 0;
+            n := 0;
+            r <= r;
+            s <= s;
+            mod sub of Sub;
+        }
+
+        mod Sub {
+            dom clk;
+        }
+    ").unwrap_err();
+    assert_eq!(errors.iter().filter(|error| matches!(error, BitsyError::NotADomain(_span, _name))).count(), 1);
+    assert_eq!(errors.iter().filter(|error| matches!(error, BitsyError::NoSuchComponent(_span, _name))).count(), 1);
+    assert_eq!(errors.iter().filter(|error| matches!(error, BitsyError::NoDriversPort(_component, _path, _dom))).count(), 1);
+}
+
+#[test]
+fn test_ext_errors() {
+    let errors = load_package_from_string("
+        ext mod Foo {
+            incoming in of Word[8];
+            assert in == 0;
+            cover in == 1;
+        }
+    ").unwrap_err();
+    let errors: Vec<String> = errors.iter().map(|error| error.to_string()).collect();
+    assert_eq!(errors, vec!["Ext may not contain an assert".to_string(), "Ext may not contain a cover".to_string()]);
+}
+
+#[test]
+fn test_replication() {
+    let package = load_package_from_string("
+        mod Cell {
+            incoming in of Word[8];
+            incoming k of Word[8];
+            outgoing out of Word[8];
+            reg acc of Word[8] reset 0;
+            acc <= acc + k;
+            out := in + acc;
+        }
+
+        mod Top {
+            incoming in of Word[8];
+            outgoing out of Word[8];
+            outgoing first of Word[8];
+            mod cells[4] of Cell;
+
+            cells[0].in := in;
+            for i in 0..3 {
+                cells[i+1].in := cells[i].out;
+            }
+            for i in 0..4 {
+                cells[i].k := i;
+            }
+            out := cells[3].out;
+            first := cells[0].out;
+        }
+    ").unwrap();
+    let top = package.top("Top").unwrap();
+    assert!(top.paths().contains(&"top.cells.2.out".into()));
+
+    let mut bitsy = Sim::new(&top, vec![]);
+    bitsy.reset();
+    bitsy.poke("top.in", Value::Word(8, 10));
+    assert_eq!(bitsy.peek("top.out"), Value::Word(8, 10));
+    bitsy.clock();
+    assert_eq!(bitsy.peek("top.cells.1.acc"), Value::Word(8, 1));
+    assert_eq!(bitsy.peek("top.out"), Value::Word(8, 16));
+
+    // The structure of a parametric mod may depend on its parameters.
+    let package = load_package_from_string("
+        mod Cell {
+            incoming in of Word[8];
+            outgoing out of Word[8];
+            out := in + 1;
+        }
+
+        mod Chain[n of Nat] {
+            incoming in of Word[8];
+            outgoing out of Word[8];
+            mod cells[n] of Cell;
+            cells[0].in := in;
+            for i in 0..n-1 {
+                cells[i+1].in := cells[i].out;
+            }
+            out := cells[n-1].out;
+        }
+
+        mod Top {
+            incoming in of Word[8];
+            outgoing out of Word[8];
+            mod chain of Chain[3];
+            chain.in := in;
+            out := chain.out;
+        }
+    ").unwrap();
+    let mlir = package.mlir();
+    assert!(mlir.contains("hw.module @Chain_3("));
+    assert!(mlir.contains("hw.output %cells.2.out : i8"));
+    let top = package.top("Top").unwrap();
+    assert!(top.paths().contains(&"top.chain.cells.2.out".into()));
+
+    let mut bitsy = Sim::new(&top, vec![]);
+    bitsy.poke("top.in", Value::Word(8, 10));
+    assert_eq!(bitsy.peek("top.out"), Value::Word(8, 13));
+
+    let errors = load_package_from_string("
+        mod Cell {
+            incoming in of Word[8];
+        }
+
+        mod Cells[n of Nat] {
+            mod cells[n] of Cell;
+            for i in 0..n+1 {
+                cells[i].in := 0;
+            }
+        }
+
+        mod Top {
+            mod cells of Cells[2];
+        }
+    ").unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].to_string(), "No such component: cells.2.in");
+
+    let errors = load_package_from_string("
+        mod Cell {
+            incoming in of Word[8];
+        }
+
+        mod Top {
+            mod cells[2] of Cell;
+            cells[1].in := 0;
+        }
+    ").unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].to_string(), "Port is not driven: cells.0.in");
+
+    // Until it's instantiated, only the ports of a mod like Bad are known.
+    let package = load_package_from_string("
+        mod Cell {
+            incoming in of Word[8];
+        }
+
+        mod Bad[n of Nat] {
+            mod cells[n] of Cell;
+            node dangling of Word[1];
+        }
+    ").unwrap();
+    let unchecked: Vec<String> = package.unchecked_moddefs().iter().map(|moddef| moddef.name().to_string()).collect();
+    assert_eq!(unchecked, vec!["Bad".to_string()]);
+
+    let package = load_package_from_string("
+        mod Cell {
+            incoming in of Word[8];
+        }
+
+        mod Good[n of Nat] {
+            mod cells[n] of Cell;
+            for i in 0..n {
+                cells[i].in := 0;
+            }
+        }
+
+        mod Top {
+            mod good of Good[2];
+        }
+    ").unwrap();
+    assert!(package.unchecked_moddefs().is_empty());
+}
+
+#[test]
+fn test_consts() {
+    let package = load_package_from_string("
+        mod Top {
+            outgoing pc of Word[XLEN];
+            outgoing width of Word[8];
+            outgoing lanes of Vec[Word[8], LANES];
+            outgoing shadowed of Word[8];
+            reg r of Word[XLEN] reset RESET_PC;
+
+            r <= r + 4;
+            pc := r;
+            width := XLEN;
+            lanes := [1, 2, 3, 4];
+            shadowed := let XLEN = 7w8; XLEN;
+        }
+
+        const RESET_PC of Word[XLEN] = 0x8000_0000w32;
+        const XLEN of Nat = 16 * 2;
+        const LANES of Nat = XLEN - 28;
+    ").unwrap();
+    let top = package.top("Top").unwrap();
+
+    let mut bitsy = Sim::new(&top, vec![]);
+    bitsy.reset();
+    assert_eq!(bitsy.peek("top.pc"), Value::Word(32, 0x8000_0000));
+    assert_eq!(bitsy.peek("top.width"), Value::Word(8, 32));
+    assert_eq!(bitsy.peek("top.shadowed"), Value::Word(8, 7));
+    bitsy.clock();
+    assert_eq!(bitsy.peek("top.pc"), Value::Word(32, 0x8000_0004));
+
+    let errors = load_package_from_string("
+        const RESET_PC of Word[8] = 0x8000_0000w32;
+    ").unwrap_err();
+    assert_eq!(errors.len(), 1);
+
+    let errors = load_package_from_string("
+        const A of Nat = B;
+        const B of Nat = A;
+    ").unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert!(matches!(&errors[0], BitsyError::ItemCycle(_span, cycle) if cycle == &["A", "B", "A"] || cycle == &["B", "A", "B"]));
+
+    let errors = load_package_from_string("
+        const RESET of Word[8] = ?pc;
+    ").unwrap_err();
+    assert_eq!(errors[0].to_string(), "Invalid const RESET: its value is X, such as from a hole");
+
+    let errors = load_package_from_string("
+        const LANES of Vec[Word[8], 2] = [1, ?];
+    ").unwrap_err();
+    assert!(matches!(&errors[0], BitsyError::InvalidConst(_span, name, _message) if name == "LANES"));
+}
+
+#[test]
+fn test_partial_connections() {
+    let package = load_package_from_string("
+        struct type Packet {
+            valid of Word[1];
+            data of Word[8];
+        }
+
+        mod Top {
+            incoming lo of Word[4];
+            incoming hi of Word[4];
+            outgoing out of Packet;
+            outgoing bus of Word[8];
+            outgoing lanes of Vec[Word[8], 2];
+            reg r of Packet reset { valid = 0, data = 0 };
+
+            out->valid := 1;
+            out->data := bus;
+            bus[4..0] := lo;
+            bus[8..4] := hi;
+            lanes[1] := 7;
+            lanes[0] := r->data;
+            r->valid <= 1;
+            r->data <= r->data + 1;
+        }
+    ").unwrap();
+    let top = package.top("Top").unwrap();
+
+    let mut bitsy = Sim::new(&top, vec![]);
+    bitsy.reset();
+    bitsy.poke("top.lo", Value::Word(4, 0x2));
+    bitsy.poke("top.hi", Value::Word(4, 0xa));
+    assert_eq!(bitsy.peek("top.bus"), Value::Word(8, 0xa2));
+    assert_eq!(format!("{:?}", bitsy.peek("top.out")), "{ valid = 1w1, data = 162w8 }");
+    bitsy.clock();
+    assert_eq!(bitsy.peek("top.lanes"), Value::Vec(vec![Value::Word(8, 1), Value::Word(8, 7)]));
+
+    let errors = load_package_from_string("
+        struct type Packet {
+            valid of Word[1];
+            data of Word[8];
+        }
+
+        mod Top {
+            outgoing out of Packet;
+            outgoing bus of Word[8];
+            outgoing whole of Word[8];
+
+            out->valid := 1;
+            bus[4..0] := 0;
+            bus[8..3] := 0;
+            whole := 0;
+            whole[0] := 1;
+        }
+    ").unwrap_err();
+    assert_eq!(errors.len(), 3);
+}
+
+
+#[test]
+fn test_interfaces() {
+    let package = load_package_from_string("
+        shape Channel {
+            outgoing valid of Word[1];
+            outgoing data of Word[8];
+            incoming ready of Word[1];
+        }
+
+        mod Producer {
+            incoming data of Word[8];
+            port enq of Channel;
+
+            enq.valid := 1;
+            enq.data := data;
+        }
+
+        mod Consumer {
+            outgoing data of Word[8];
+            outgoing fired of Word[1];
+            port deq of flip Channel;
+
+            deq.ready := 1;
+            data := deq.data;
+            fired := deq.valid && deq.ready;
+        }
+
+        mod Top {
+            incoming data of Word[8];
+            outgoing out of Word[8];
+            outgoing fired of Word[1];
+            outgoing ready of Word[1];
+            mod p of Producer;
+            mod c of Consumer;
+
+            p.data := data;
+            p.enq <> c.deq;
+            out := c.data;
+            fired := c.fired;
+            ready := p.enq.ready;
+        }
+    ").unwrap();
+    let top = package.top("Top").unwrap();
+
+    let mut bitsy = Sim::new(&top, vec![]);
+    bitsy.poke("top.data", Value::Word(8, 42));
+    assert_eq!(bitsy.peek("top.out"), Value::Word(8, 42));
+    assert_eq!(bitsy.peek("top.fired"), Value::Word(1, 1));
+    assert_eq!(bitsy.peek("top.ready"), Value::Word(1, 1));
+
+    let errors = load_package_from_string("
+        shape Channel {
+            outgoing valid of Word[1];
+            incoming ready of Word[1];
+        }
+
+        mod Side {
+            port enq of Channel;
+            enq.valid := 1;
+        }
+
+        mod Top {
+            mod a of Side;
+            mod b of Side;
+            a.enq <> b.enq;
+        }
+    ").unwrap_err();
+    assert_eq!(errors.len(), 2);
+    assert!(errors.iter().all(|error| matches!(error, BitsyError::InterfaceMismatch(_span, _message))));
+
+    // Ports are found by the shape of the interface, not by their names.
+    let package = load_package_from_string("
+        shape Bit {
+            outgoing x of Word[1];
+        }
+
+        mod Side {
+            outgoing a_y of Word[1];
+            port a of Bit;
+            port a_b of Bit;
+            a_y := 0;
+            a.x := 0;
+            a_b.x := 1;
+        }
+
+        mod Top {
+            port a of Bit;
+            port b of Bit;
+            mod s of Side;
+            s.a <> a;
+            s.a_b <> b;
+        }
+    ").unwrap();
+    let top = package.top("Top").unwrap();
+    let bitsy = Sim::new(&top, vec![]);
+    assert_eq!(bitsy.peek("top.a_x"), Value::Word(1, 0));
+    assert_eq!(bitsy.peek("top.b_x"), Value::Word(1, 1));
+
+    let errors = load_package_from_string("
+        mod Side {
+            outgoing enq_x of Word[1];
+            enq_x := 0;
+        }
+
+        mod Top {
+            mod a of Side;
+            mod b of Side;
+            a.enq <> b.enq;
+        }
+    ").unwrap_err();
+    assert!(matches!(&errors[0], BitsyError::NotAnInterface(_span, name) if name == "a.enq"));
+
+    let errors = load_package_from_string("
+        shape Channel {
+            outgoing valid of Word[1];
+        }
+
+        mod Top {
+            port a of Channel;
+            port b of Channel;
+            a.valid[0] <> b;
+        }
+    ").unwrap_err();
+    assert_eq!(errors[0].to_string(), "Not an interface port: a.valid[0]");
+}
+
+#[test]
+fn test_parse_error_recovery() {
+    let errors = ast::parse_package_from_string("
+        mod Top {
+            incoming in of Word[8];
+            outgoing out of Word[8];
+            node n of ;
+            out := in +;
+            n := match in {
+                x => 1;
+                => 2;
+                _ => 3;
+            };
+        }
+
+        struct type Bad {
+            x of;
+        }
+
+        fn f(x of Word[8]) -> Word[8] {
+            x
+        }
+
+        const big of Nat = 99999999999999999999999;
+    ").unwrap_err();
+    assert_eq!(errors.len(), 5);
+    for error in &errors {
+        assert!(error.span().start().line() > 1, "{error}");
+    }
+    assert!(errors[0].to_string().starts_with("Parse error: unexpected `;`, expected `SInt`, `Valid`, `Vec`, `Word`"));
+
+    // An error in an item which ends with a `;` doesn't swallow the next item.
+    let errors = ast::parse_package_from_string("
+        const A of Nat = ;
+
+        mod Top {
+            outgoing out of Word[8];
+            out := ;
+        }
+    ").unwrap_err();
+    assert_eq!(errors.len(), 2);
+    assert_eq!(errors[0].span().start().line(), 2);
+    assert_eq!(errors[1].span().start().line(), 6);
+}
+
+#[test]
+fn test_patterns() {
+    let package = load_package_from_string("
+        enum type State {
+            Idle = 0w2;
+            Busy = 1w2;
+            Done = 2w2;
+        }
+
+        struct type Packet {
+            valid of Word[1];
+            data of Word[2];
+        }
+
+        mod Top {
+            incoming state of State;
+            incoming packet of Valid[Packet];
+            outgoing a of Word[4];
+            outgoing b of Word[4];
+
+            a := match state {
+                @State::Idle => 1;
+                @Busy => 2;
+                otherwise => 3;
+            };
+
+            b := match packet {
+                @Valid({ valid = @0w1 }) => 0;
+                @Valid({ valid = @1w1, data = @0b11w2 }) => 15;
+                @Valid({ data = d }) => zext(d);
+                @Invalid => 9;
+            };
+        }
+    ").unwrap();
+    let top = package.top("Top").unwrap();
+    let state = Type::Enum(package.typedef("State").unwrap());
+
+    let mut bitsy = Sim::new(&top, vec![]);
+    let Type::Valid(packet) = bitsy.type_of("top.packet") else { unreachable!() };
+    let packet = *packet;
+    bitsy.poke("top.state", Value::Enum(state.clone(), "Idle".to_string()));
+    assert_eq!(bitsy.peek("top.a"), Value::Word(4, 1));
+    bitsy.poke("top.state", Value::Enum(state.clone(), "Done".to_string()));
+    assert_eq!(bitsy.peek("top.a"), Value::Word(4, 3));
+
+    let packet_value = |valid, data| {
+        let fields = vec![("valid".to_string(), Value::Word(1, valid)), ("data".to_string(), Value::Word(2, data))];
+        Value::Ctor("Valid".to_string(), vec![Value::Struct(packet.clone(), fields)])
+    };
+    bitsy.poke("top.packet", packet_value(0, 3));
+    assert_eq!(bitsy.peek("top.b"), Value::Word(4, 0));
+    bitsy.poke("top.packet", packet_value(1, 3));
+    assert_eq!(bitsy.peek("top.b"), Value::Word(4, 15));
+    bitsy.poke("top.packet", packet_value(1, 2));
+    assert_eq!(bitsy.peek("top.b"), Value::Word(4, 2));
+
+    let errors = load_package_from_string("
+        mod Top {
+            incoming in of Valid[Word[2]];
+            outgoing out of Word[2];
+            out := match in {
+                @Valid(@0w2) => 0;
+                @Valid(@1w2) => 1;
+                @Valid(@3w2) => 3;
+                @Invalid => 0;
+            };
+        }
+    ").unwrap_err();
+    assert_eq!(errors[0].to_string(), "Type Error: Match is not exhaustive: @Valid(@2w2) is not covered");
+
+    let errors = load_package_from_string("
+        mod Top {
+            incoming in of Word[8];
+            outgoing out of Word[8];
+            out := match in {
+                x => x;
+                @0 => 1;
+            };
+        }
+    ").unwrap_err();
+    assert_eq!(errors[0].to_string(), "Type Error: Unreachable match arm: @0");
+}
+
+#[test]
+fn test_holes() {
+    let package = load_package_from_string("
+        fn inc(x of Word[8]) -> Word[8] {
+            x + 1
+        }
+
+        fn is_zero(x of Word[8]) -> Word[1] {
+            x == 0
+        }
+
+        mod Top {
+            incoming a of Word[8];
+            incoming b of Word[8];
+            outgoing out of Word[8];
+            out := let a = 0w4; ?foo;
+        }
+    ").unwrap();
+
+    let holes = package.holes();
+    assert_eq!(holes.len(), 1);
+    let hole = &holes[0];
+    assert_eq!(hole.name, Some("foo".to_string()));
+    assert!(hole.typ.as_ref().unwrap().equals(&Type::word(8)));
+    let fits: Vec<String> = hole.fits.iter().map(|(path, _typ)| path.to_string()).collect();
+    assert_eq!(fits, vec!["b".to_string(), "out".to_string()]);
+    let fndefs: Vec<&str> = hole.fndefs.iter().map(|fndef| fndef.name.as_str()).collect();
+    assert_eq!(fndefs, vec!["inc"]);
+    assert_eq!(hole.to_string(), "Hole ?foo has type Word[8]\n  in scope: b : Word[8], out : Word[8]\n  fns: inc");
+
+    let circuit = package.top("Top").unwrap();
+    let mut bitsy = Sim::new(&circuit, vec![]);
+    bitsy.poke("top.b", Value::Word(8, 1));
+    assert_eq!(bitsy.peek("top.out"), Value::X);
+    let holes_evaluated: Vec<String> = bitsy.holes_evaluated().into_iter().map(|(_span, hole)| hole).collect();
+    assert_eq!(holes_evaluated, vec!["?foo".to_string()]);
+
+    let package = load_package_from_string("
+        mod Buffer[n of Nat] {
+            incoming in of Word[n];
+            outgoing out of Word[n];
+            out := ?;
+        }
+
+        mod Cells[n of Nat] {
+            incoming in of Word[8];
+            outgoing out of Word[8];
+            mod cells[n] of Buffer[8];
+            for i in 0..n {
+                cells[i].in := in;
+            }
+            out := ?;
+        }
+
+        mod Top {
+            incoming in of Word[8];
+            outgoing out of Word[8];
+            outgoing out2 of Word[8];
+            mod buf of Buffer[8];
+            mod cells2 of Cells[2];
+            mod cells3 of Cells[3];
+            buf.in := in;
+            cells2.in := in;
+            cells3.in := in;
+            out := ?;
+            out2 := ?;
+        }
+
+        tb Pokes {
+            mod top of Top;
+            poke top.in = ?;
+        }
+    ").unwrap();
+
+    // Holes are reported in parametric mods, deferred mods, and testbenches, too.
+    let holes = package.holes();
+    assert_eq!(holes.len(), 5);
+
+    // Each unnamed hole is its own hole, even though they share a name.
+    let circuit = package.top("Top").unwrap();
+    let bitsy = Sim::new(&circuit, vec![]);
+    bitsy.peek("top.out");
+    assert_eq!(bitsy.holes_evaluated().len(), 4);
+}
+
+#[test]
+fn test_width_inference() {
+    let package = load_package_from_string("
+        fn inc(x of Word[8]) -> Word[8] {
+            x + 1
+        }
+
+        mod Top {
+            incoming a of Word[4];
+            incoming c of Word[1];
+            outgoing out of Word[8];
+            outgoing incremented of Word[8];
+            node sum of _;
+            node bigger of _;
+
+            out := cat(a, 0);
+            sum := if c { a } else { 1 };
+            bigger := sum + 1;
+            incremented := let x = 1; inc(x);
+        }
+    ").unwrap();
+    let circuit = package.top("Top").unwrap();
+
+    let mut bitsy = Sim::new(&circuit, vec![]);
+    assert!(bitsy.type_of("top.bigger").equals(&Type::word(4)));
+    bitsy.poke("top.a", Value::Word(4, 3));
+    bitsy.poke("top.c", Value::Word(1, 0));
+    assert_eq!(bitsy.peek("top.out"), Value::Word(8, 0x30));
+    assert_eq!(bitsy.peek("top.sum"), Value::Word(4, 1));
+    assert_eq!(bitsy.peek("top.bigger"), Value::Word(4, 2));
+    assert_eq!(bitsy.peek("top.incremented"), Value::Word(8, 2));
+
+    let errors = load_package_from_string("
+        mod Top {
+            node x of _;
+            x := 1 + 2;
+        }
+    ").unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].to_string(), "Type Error: Can't infer the width of the literal 1");
+    assert_eq!(errors[0].span().start().col(), 18);
+}
+
+#[test]
+fn test_width_arithmetic() {
+    let package = load_package_from_string("
+        mod Top {
+            incoming in of Word[2*4];
+            outgoing out of Word[2*4];
+            out := in + 1;
+        }
+
+        tb Adds {
+            mod top of Top;
+            poke top.in = 3;
+            expect top.out == 4;
+        }
+    ").unwrap();
+    let mlir = package.mlir();
+    assert!(mlir.contains("in %in : i8"));
+    assert!(mlir.contains("out out : i8"));
+    let circuit = package.top("Top").unwrap();
+
+    let mut bitsy = Sim::new(&circuit, vec![]);
+    assert!(bitsy.type_of("top.in").equals(&Type::word(8)));
+    bitsy.poke("top.in", Value::Word(8, 3));
+    assert_eq!(bitsy.peek("top.out"), Value::Word(8, 4));
+    assert!(run_tb(&package, &package.tbdefs()[0]).is_ok());
+
+    let errors = load_package_from_string("
+        mod Top {
+            incoming in of Word[2-4];
+        }
+    ").unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].to_string(), "Invalid width: 2-4 is negative");
+    assert_eq!(errors[0].span().start().col(), 28);
+
+    let package = load_package_from_string("
+        mod Signed[n of Nat] {
+            incoming in of SInt[n];
+            outgoing out of SInt[n+1];
+            out := sext(in);
+        }
+
+        mod Top {
+            incoming in of SInt[8];
+            outgoing out of SInt[9];
+            mod signed of Signed[8];
+            signed.in := in;
+            out := signed.out;
+        }
+    ").unwrap();
+    let circuit = package.top("Top").unwrap();
+    let mut bitsy = Sim::new(&circuit, vec![]);
+    assert!(bitsy.type_of("top.signed.in").equals(&Type::SInt(8)));
+    bitsy.poke("top.in", Value::SInt(8, 0xfe));
+    assert_eq!(bitsy.peek("top.out"), Value::SInt(9, 0x1fe));
+
+    let errors = load_package_from_string("
+        mod Narrow[n of Nat] {
+            outgoing out of Word[n-4];
+        }
+
+        mod Top {
+            mod narrow of Narrow[2];
+        }
+    ").unwrap_err();
+    assert!(errors.iter().any(|error| error.to_string() == "Invalid width: the width of Word[2-4] is negative"));
+
+    let errors = load_package_from_string("
+        mod Top {
+            incoming in of Word[4294967296*4294967296];
+        }
+    ").unwrap_err();
+    assert_eq!(errors[0].to_string(), "Invalid width: 4294967296*4294967296 is too large");
+
+    let errors = load_package_from_string("
+        const A of Nat = 4294967296 * 4294967296;
+    ").unwrap_err();
+    assert!(matches!(&errors[0], BitsyError::InvalidWidth(span, _message) if span.start().line() == 2));
+
+    let errors = load_package_from_string("
+        mod Wide[n of Nat] {
+            outgoing out of Word[n*n];
+        }
+
+        mod Top {
+            mod wide of Wide[4294967296];
+        }
+    ").unwrap_err();
+    assert!(errors.iter().any(|error| error.to_string() == "Invalid width: the width of Word[4294967296*4294967296] is too large"));
+
+    let n = Nat::Sub(
+        Box::new(Nat::Param("a".to_string())),
+        Box::new(Nat::Sub(Box::new(Nat::Param("b".to_string())), Box::new(Nat::Param("c".to_string())))),
+    );
+    assert_eq!(n.to_string(), "a-(b-c)");
+}
+
+#[test]
+fn test_const_widths() {
+    let package = load_package_from_string("
+        const XLEN of Nat = 8;
+
+        struct type Pair {
+            lo of Word[XLEN];
+            hi of Word[XLEN*2];
+        }
+
+        fn widen(x of Word[XLEN]) -> Word[XLEN+1] {
+            zext(x)
+        }
+
+        mod Top {
+            incoming in of Word[XLEN];
+            outgoing out of Word[XLEN+1];
+            outgoing pair of Pair;
+            incoming ins of Vec[Word[XLEN], XLEN-6];
+            reg r of Word[XLEN] reset 0;
+            r <= ins[1w1];
+            out := cat(0w1, r);
+            pair := { lo = in, hi = cat(in, in) };
+        }
+    ").unwrap();
+    let widen = package.fndef("widen").unwrap();
+    assert!(widen.ret.equals(&Type::word(9)));
+    let mlir = package.mlir();
+    assert!(mlir.contains("out out : i9"));
+    assert!(mlir.contains("out pair : i24"));
+    assert!(mlir.contains("in %ins : !hw.array<2xi8>"));
+    let circuit = package.top("Top").unwrap();
+
+    let mut bitsy = Sim::new(&circuit, vec![]);
+    assert!(bitsy.type_of("top.out").equals(&Type::word(9)));
+    assert_eq!(bitsy.type_of("top.pair").bitwidth(), 24);
+    bitsy.poke("top.in", Value::Word(8, 3));
+    bitsy.poke("top.ins", Value::Vec(vec![Value::Word(8, 4), Value::Word(8, 5)]));
+    bitsy.clock();
+    assert_eq!(bitsy.peek("top.out"), Value::Word(9, 5));
+}
+
+#[test]
+fn test_enum_numbering() {
+    let package = load_package_from_string("
+        enum type State {
+            Idle;
+            Busy;
+            Done;
+        }
+
+        enum type Opcode of Word[4] {
+            Nop;
+            Load = 8;
+            Store;
+        }
+
+        onehot enum type Color {
+            Red;
+            Green;
+            Blue;
+        }
+
+        mod Top {
+            incoming state of State;
+            outgoing out of Word[2];
+            outgoing opcode of Word[4];
+            outgoing color of Word[3];
+            out := word(state);
+            opcode := word(Opcode::Store);
+            color := word(Color::Blue);
+        }
+    ").unwrap();
+
+    let state = package.typedef("State").unwrap();
+    assert_eq!(state.bitwidth(), 2);
+    assert_eq!(state.value_of("Done"), Some(2));
+    let opcode = package.typedef("Opcode").unwrap();
+    assert_eq!(opcode.bitwidth(), 4);
+    assert_eq!(opcode.value_of("Store"), Some(9));
+    let color = package.typedef("Color").unwrap();
+    assert_eq!(color.bitwidth(), 3);
+    assert_eq!(color.value_of("Blue"), Some(4));
+
+    let circuit = package.top("Top").unwrap();
+    let bitsy = Sim::new(&circuit, vec![]);
+    assert_eq!(bitsy.peek("top.opcode"), Value::Word(4, 9));
+    assert_eq!(bitsy.peek("top.color"), Value::Word(3, 4));
+
+    let errors = load_package_from_string("
+        enum type State {
+            Idle = 0w1;
+            Busy = 1w2;
+        }
+
+        enum type Opcode of Word[2] {
+            Nop;
+            Load = 4;
+            Store = 0;
+        }
+    ").unwrap_err();
+    let errors: Vec<String> = errors.iter().map(|error| error.to_string()).collect();
+    assert_eq!(errors.len(), 3, "{errors:?}");
+    assert!(errors.contains(&"Value Busy of enum State has width 2, but the enum has width 1".to_string()), "{errors:?}");
+    assert!(errors.contains(&"Value Load of enum Opcode doesn't fit in Word[2]".to_string()), "{errors:?}");
+    assert!(errors.contains(&"Duplicate value in enum Opcode: Store = 0".to_string()), "{errors:?}");
+}
+
+#[test]
+fn test_alt_layout() {
+    let package = load_package_from_string("
+        alt type Option[T of Type] {
+            None();
+            Some(T);
+        }
+
+        alt type Op {
+            Nop();
+            Load(Word[4]);
+            Add(Word[2], Word[3]);
+        }
+
+        mod Top {
+            incoming in of Option[Word[4]];
+            outgoing out of Word[4];
+            outgoing wrapped of Option[Word[4]];
+            outgoing load of Op;
+
+            out := match in {
+                @Some(x) => x;
+                @None() => 0;
+            };
+            wrapped := @Some(out + 1);
+            load := @Load(out);
+        }
+    ").unwrap();
+
+    let option = Type::Alt(package.alttypedef("Option").unwrap(), vec![TypeParam::Type(Type::word(4))]);
+    assert_eq!(option.bitwidth(), 5);
+    let some = Value::Ctor("Some".to_string(), vec![Value::Word(4, 3)]);
+    assert_eq!(some.to_bits(&option), Some(0b1_0011));
+    assert_eq!(Value::from_bits(&option, 0b1_0011), some);
+    assert_eq!(Value::Ctor("None".to_string(), vec![]).to_bits(&option), Some(0b0_0000));
+
+    let op = Type::Alt(package.alttypedef("Op").unwrap(), vec![]);
+    assert_eq!(op.bitwidth(), 7);
+    let add = Value::Ctor("Add".to_string(), vec![Value::Word(2, 1), Value::Word(3, 5)]);
+    assert_eq!(add.to_bits(&op), Some(0b10_01_101));
+    assert_eq!(Value::from_bits(&op, 0b10_01_101), add);
+    assert_eq!(Value::from_bits(&op, 0b11_00_000), Value::X);
+
+    let circuit = package.top("Top").unwrap();
+    let mut bitsy = Sim::new(&circuit, vec![]);
+    bitsy.poke("top.in", some);
+    assert_eq!(bitsy.peek("top.out"), Value::Word(4, 3));
+    assert_eq!(bitsy.peek("top.wrapped"), Value::Ctor("Some".to_string(), vec![Value::Word(4, 4)]));
+    assert_eq!(bitsy.peek("top.load"), Value::Ctor("Load".to_string(), vec![Value::Word(4, 3)]));
+
+    // The tag is in the highest bits, then the fields, then the padding.
+    let mlir = package.mlir();
+    assert!(mlir.contains("%$comb0_match_arm0_tag = comb.extract %in from 4 : (i5) -> i1"));
+    assert!(mlir.contains("%$comb0_match_arm0_tag_const = hw.constant 1 : i1"));
+    assert!(mlir.contains("%$comb0_match_arm0_tag_eq = comb.icmp bin eq %$comb0_match_arm0_tag, %$comb0_match_arm0_tag_const : i1"));
+    assert!(mlir.contains("%$comb0_match_arm0_f0 = comb.extract %in from 0 : (i5) -> i4"));
+    assert!(mlir.contains("%$comb0_match_mux0 = comb.mux bin %$comb0_match_arm0_tag_eq, %$comb0_match_arm0_f0, %$comb0_match_arm1_e_word : i4"));
+    assert!(mlir.contains("%$comb1_ctor = comb.concat %$comb1_ctor_tag, %$comb1_ctor_e0_add : i1, i4"));
+    assert!(mlir.contains("%$comb2_ctor_tag = hw.constant 1 : i2"));
+    assert!(mlir.contains("%$comb2_ctor_pad = hw.constant 0 : i1"));
+    assert!(mlir.contains("%$comb2_ctor = comb.concat %$comb2_ctor_tag, %out, %$comb2_ctor_pad : i2, i4, i1"));
+}
+
+#[test]
+fn test_mem() {
+    let package = load_package_from_string("
+        mod Top {
+            incoming raddr of Word[2];
+            incoming waddr of Word[2];
+            incoming wdata of Word[8];
+            incoming wen of Word[1];
+            incoming wmask of Word[2];
+            outgoing rdata of Word[8];
+            outgoing rdata_now of Word[8];
+
+            mem m of Vec[Word[8], 4] {
+                read r;
+                write w mask 2;
+            }
+
+            mem regfile of Vec[Word[8], 4] latency 0 {
+                read r;
+                write w;
+            }
+
+            m.r_addr := raddr;
+            m.w_en := wen;
+            m.w_addr := waddr;
+            m.w_data := wdata;
+            m.w_mask := wmask;
+            rdata := m.r_data;
+
+            regfile.r_addr := raddr;
+            regfile.w_en := wen;
+            regfile.w_addr := waddr;
+            regfile.w_data := wdata;
+            rdata_now := regfile.r_data;
+        }
+    ").unwrap();
+
+    let circuit = package.top("Top").unwrap();
+    let mut bitsy = Sim::new(&circuit, vec![]);
+    bitsy.poke_mem("top.m", 1, Value::Word(8, 0xab)).unwrap();
+    bitsy.poke("top.raddr", Value::Word(2, 1));
+    bitsy.poke("top.waddr", Value::Word(2, 1));
+    bitsy.poke("top.wdata", Value::Word(8, 0x12));
+    bitsy.poke("top.wen", Value::Word(1, 1));
+    bitsy.poke("top.wmask", Value::Word(2, 0b01));
+    assert_eq!(bitsy.peek("top.rdata"), Value::X);
+    assert_eq!(bitsy.peek("top.rdata_now"), Value::Word(8, 0));
+
+    bitsy.clock();
+    assert_eq!(bitsy.peek("top.rdata"), Value::Word(8, 0xab));
+    assert_eq!(bitsy.peek("top.rdata_now"), Value::Word(8, 0x12));
+    assert_eq!(bitsy.peek_mem("top.m", 1), Ok(Value::Word(8, 0xa2)));
+
+    bitsy.poke("top.wen", Value::Word(1, 0));
+    bitsy.clock();
+    assert_eq!(bitsy.peek("top.rdata"), Value::Word(8, 0xa2));
+
+    assert_eq!(bitsy.peek_mem("top.nope", 0), Err(SimError::NoSuchMem("top.nope".into())));
+    assert_eq!(bitsy.poke_mem("top.m", 4, Value::Word(8, 0)), Err(SimError::AddrOutOfRange("top.m".into(), 4)));
+
+    // A mem declared on a domain is only clocked along with it.
+    let package = load_package_from_string("
+        mod Top {
+            dom clk;
+            incoming addr of Word[2];
+            incoming data of Word[8];
+            mem m of Vec[Word[8], 4] on clk latency 0 {
+                write w;
+            }
+            m.w_en := 1;
+            m.w_addr := addr;
+            m.w_data := data;
+        }
+    ").unwrap();
+    let mlir = package.mlir();
+    assert!(mlir.contains("in %clk : !seq.clock"));
+    assert!(mlir.contains("seq.firmem.write_port %m[%m.w_addr] = %m.w_data, clock %clk enable %m.w_en : <4 x 8>"));
+    let circuit = package.top("Top").unwrap();
+    let mut bitsy = Sim::new(&circuit, vec![]);
+    bitsy.poke("top.addr", Value::Word(2, 3));
+    bitsy.poke("top.data", Value::Word(8, 7));
+    bitsy.clock();
+    assert_eq!(bitsy.peek_mem("top.m", 3), Ok(Value::Word(8, 0)));
+    bitsy.clock_domain("top.clk").unwrap();
+    assert_eq!(bitsy.peek_mem("top.m", 3), Ok(Value::Word(8, 7)));
+
+    let errors = load_package_from_string("
+        mod Top {
+            mem m of Vec[Word[8], 4] {
+                read r;
+                write r mask 3;
+            }
+        }
+    ").unwrap_err();
+    let errors: Vec<String> = errors.iter().map(|error| error.to_string()).collect();
+    assert!(errors.contains(&"Invalid mem m: duplicate port r".to_string()), "{errors:?}");
+    assert!(errors.contains(&"Invalid mem m: the write mask of r must have a width which divides the width of Word[8]".to_string()), "{errors:?}");
+
+    // The words in a mem declaration may still be used as names elsewhere.
+    let package = load_package_from_string("
+        mod Ram {
+            incoming read of Word[8];
+            outgoing write of Word[8];
+            write := read;
+        }
+
+        mod Top {
+            incoming latency of Word[8];
+            outgoing mask of Word[8];
+            mod mem of Ram;
+            mem.read := latency;
+            mask := mem.write;
+        }
+    ").unwrap();
+    let circuit = package.top("Top").unwrap();
+    let mut bitsy = Sim::new(&circuit, vec![]);
+    bitsy.poke("top.latency", Value::Word(8, 7));
+    assert_eq!(bitsy.peek("top.mask"), Value::Word(8, 7));
+}
+
+#[test]
+fn test_tb() {
+    let package = load_package_from_string("
+        mod Counter {
+            incoming inc of Word[1];
+            outgoing out of Word[4];
+            reg c of Word[4] reset 0;
+            c <= if inc == 1 { c + 1 } else { c };
+            out := c;
+        }
+
+        tb Counts {
+            mod dut of Counter;
+            reset;
+            poke dut.inc = 1;
+            loop 3 {
+                clock;
+            }
+            expect dut.out == 3;
+            poke dut.inc = 0;
+            clock;
+            expect dut.out == 3;
+        }
+
+        tb Fails {
+            mod dut of Counter;
+            reset;
+            poke dut.inc = 1;
+            clock;
+            expect dut.out == 2;
+        }
+    ").unwrap();
+
+    let tbdef = |name: &str| package.tbdefs().into_iter().find(|tbdef| tbdef.name == name).unwrap();
+    assert!(run_tb(&package, &tbdef("Counts")).is_ok());
+    let errors = run_tb(&package, &tbdef("Fails")).unwrap_err();
+    let errors: Vec<String> = errors.iter().map(|error| error.to_string()).collect();
+    assert_eq!(errors, vec!["Expected dut.out to be 2w4, but it was 1w4".to_string()]);
+
+    let errors = load_package_from_string("
+        mod Counter {
+            incoming inc of Word[1];
+            outgoing out of Word[4];
+            out := 0;
+        }
+
+        tb Bad {
+            mod dut of Counter;
+            poke dut.out = 1;
+            expect dut.missing == 1;
+        }
+    ").unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].code(), "E0025");
+}
+
+#[test]
+fn test_format() {
+    let text = "
+// The top module.
+pub mod Top {
+  outgoing out   of Word[8]; // trailing
+  reg r of Word[8] reset 0;
+  /* block */
+  r <= r+1;
+
+
+  out := (r + 1) * 2 - (if r == 0 { 1 } else { 2 });
+}
+
+enum type Color of Word[2] { Red = 0b01; Green; }
+";
+    let formatted = format_package(text).unwrap();
+    assert_eq!(formatted, "// The top module.
+pub mod Top {
+    outgoing out of Word[8]; // trailing
+    reg r of Word[8] reset 0;
+    /* block */
+    r <= r + 1;
+
+    out := (r + 1) * 2 - (if r == 0 { 1 } else { 2 });
+}
+
+enum type Color of Word[2] {
+    Red = 0b01;
+    Green;
+}
+");
+    assert_eq!(format_package(&formatted).unwrap(), formatted);
+    assert!(format_package("mod Top {").is_err());
+
+    let formatted = format_package("mod Top { out := (a - b) - c; out := a - (b - c); out := (a == b) == c; }").unwrap();
+    assert!(formatted.contains("out := a - b - c;"));
+    assert!(formatted.contains("out := a - (b - c);"));
+    assert!(formatted.contains("out := a == b == c;"));
+}
+
+#[test]
+fn test_doc_comments() {
+    let text = "
+/// A counter.
+///
+/// It counts up.
+pub mod Top {
+    /// The count.
+    outgoing out of Word[8];
+    //// Not a doc comment.
+    reg r of Word[8] reset 0;
+    r <= r + 1;
+    out := r;
+}
+
+/// A pixel.
+struct type Pixel {
+    /// Fields can have doc comments, but they aren't kept.
+    b of Word[4];
+    g of Word[4];
+}
+";
+    let package = load_package_from_string(text).unwrap();
+    let top = package.item("Top").unwrap();
+    assert_eq!(top.doc(), Some("A counter.\n\nIt counts up."));
+    assert_eq!(item_signature(&top), "pub mod Top");
+
+    let moddef = package.moddef("Top").unwrap();
+    assert_eq!(moddef.child("out").unwrap().doc(), Some("The count."));
+    assert_eq!(moddef.child("r").unwrap().doc(), None);
+
+    let markdown = doc_package(&package, "counter", DocFormat::Markdown);
+    assert!(markdown.contains("| `out` | outgoing | Word[8] | The count. |"));
+    assert!(markdown.contains("| `b` | Word[4] | 4 | 4 |"));
+    assert!(markdown.contains("| `g` | Word[4] | 0 | 4 |"));
+}
+
+#[test]
+fn test_render_error() {
+    let text = "mod Top {
+    incoming in of Word[8];
+    outgoing out of Word[8];
+    out := in;
+    out := 1w8;
+}";
+    let errors = load_package_from_string(text).unwrap_err();
+    let error = errors.iter().find(|error| matches!(error, BitsyError::MultipleDrivers(_span, _name, _first))).unwrap();
+    assert_eq!(error.code(), "E0004");
+    assert!(explain(error.code()).is_some());
+    assert_eq!(render_error(error), "error[E0004]: Component has multiple drivers: out.
+ --> <string>:5:5
+  |
+4 |     out := in;
+  |     ---------- out is first driven here
+5 |     out := 1w8;
+  |     ^^^^^^^^^^^
+");
+    assert!(explain("E9999").is_none());
+}
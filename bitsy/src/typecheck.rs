@@ -1,5 +1,6 @@
 use super::*;
 use crate::types::*;
+use std::collections::BTreeMap;
 use std::sync::Arc;
 
 impl Expr {
@@ -18,19 +19,27 @@ impl Expr {
                 if let Some(width_actual) = width_actual {
                     if *width_actual == width_expected {
                         Err(TypeError::Other(self.clone(), format!("Not the expected width")))
-                    } else if n >> *width_actual != 0 {
+                    } else if !fits(*n, *width_actual) {
                         Err(TypeError::Other(self.clone(), format!("Doesn't fit")))
                     } else {
                         Ok(())
                     }
                 } else {
-                    if n >> width_expected != 0 {
+                    if !fits(*n, width_expected) {
                         Err(TypeError::Other(self.clone(), format!("Doesn't fit")))
                     } else {
                         Ok(())
                     }
                 }
             },
+            (Type::SInt(width_expected), Expr::Word(_span, _typ, None, n)) => {
+                // A literal is taken to be the two's complement bits of the SInt.
+                if !fits(*n, width_expected) {
+                    Err(TypeError::Other(self.clone(), "Doesn't fit".to_string()))
+                } else {
+                    Ok(())
+                }
+            },
             (_type_expected, Expr::Enum(_span, _typ, typedef, _name)) => {
                 if type_expected.equals(typedef) {
                     Ok(())
@@ -53,7 +62,7 @@ impl Expr {
                 }
             },
             (Type::Alt(typedef, params), Expr::Ctor(_span, _typ, name, es)) => {
-                if let Some(typs) = typedef.alt(name) {
+                if let Some(typs) = typedef.alt(name, &params) {
                     if es.len() == typs.len() {
                         for (e, typ) in es.iter().zip(typs.iter()) {
                             e.typecheck(typ.clone(), ctx.clone())?;
@@ -79,47 +88,40 @@ impl Expr {
                 Ok(())
             },
             (_type_expected, Expr::Let(_span, _typ, name, ascription, e, b)) => {
-                if let Some(typ) = ascription {
+                let x: Path = name.clone().into();
+                // Without an ascription, the type comes from the bound expression,
+                // or else flows back from where the variable is used. Eg, `let x = 1; x + a`.
+                let typ = ascription.clone()
+                    .or_else(|| e.typeinfer(ctx.clone()))
+                    .or_else(|| b.expected_type_of(&x, Some(&type_expected), ctx.clone()));
+                if let Some(typ) = typ {
                     e.typecheck(typ.clone(), ctx.clone())?;
-                    b.typecheck(type_expected.clone(), ctx.extend(name.clone().into(), typ.clone()))
-                } else if let Some(typ) = e.typeinfer(ctx.clone()) {
-                    b.typecheck(type_expected.clone(), ctx.extend(name.clone().into(), typ))
-                } else {
-                    Err(TypeError::Other(self.clone(), format!("Can infer type of {e:?} in let expression.")))
-                }
-            },
-            (_type_expected, Expr::Match(_span, _typ, subject, arms)) => {
-                if let Some(subject_typ) = subject.typeinfer(ctx.clone()) {
-                    let invalid_arms: Vec<&MatchArm> = arms.into_iter().filter(|MatchArm(pat, _e)| !subject_typ.valid_pat(pat)).collect();
-                    if invalid_arms.len() > 0 {
-                        return Err(TypeError::Other(self.clone(), format!("Invalid patterns for {subject_typ:?}")));
-                    }
-                    // TODO check pattern linearity
-
-                    for MatchArm(pat, e) in arms {
-                        let new_ctx = subject_typ.extend_context_for_pat(ctx.clone(), pat);
-                        e.typecheck(type_expected.clone(), new_ctx)?;
-                    }
+                    b.typecheck(type_expected.clone(), ctx.extend(x, typ))
                 } else {
-                    return Err(TypeError::Other(self.clone(), format!("Match: Can't infer subject type")));
+                    Err(TypeError::CantInferType(e.clone()))
                 }
-                Ok(())
             },
+            (_type_expected, Expr::Match(_span, _typ, _subject, _arms)) => self.typecheck_match(type_expected.clone(), ctx.clone()),
             (_type_expected, Expr::UnOp(_span, _typ, UnOp::Not, e)) => e.typecheck(type_expected.clone(), ctx.clone()),
-            (Type::Word(1), Expr::BinOp(_span, _typ, BinOp::Eq | BinOp::Neq | BinOp::Lt, e1, e2)) => {
+            (Type::Word(1), Expr::BinOp(_span, _typ, op, e1, e2)) if op.is_comparison() => {
                 if let Some(typ1) = e1.typeinfer(ctx.clone()) {
-                    e2.typecheck(typ1, ctx.clone())?;
-                    Ok(())
+                    e2.typecheck(typ1, ctx.clone())
+                } else if let Some(typ2) = e2.typeinfer(ctx.clone()) {
+                    e1.typecheck(typ2, ctx.clone())
                 } else {
-                    Err(TypeError::Other(self.clone(), format!("Can't infer type.")))
+                    Err(TypeError::CantInferType(self.clone()))
                 }
             },
-            (Type::Word(_n), Expr::BinOp(_span, _typ, BinOp::Add | BinOp::Sub | BinOp::And | BinOp::Or | BinOp::Xor, e1, e2)) => {
+            (Type::Word(_n) | Type::SInt(_n), Expr::BinOp(_span, _typ, BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::And | BinOp::Or | BinOp::Xor, e1, e2)) => {
                 e1.typecheck(type_expected.clone(), ctx.clone())?;
                 e2.typecheck(type_expected.clone(), ctx.clone())?;
                 Ok(())
             },
-            (Type::Word(n), Expr::BinOp(_span, _typ, BinOp::AddCarry, e1, e2)) => {
+            (Type::Word(n) | Type::SInt(n), Expr::BinOp(_span, _typ, op, e1, e2)) if op.is_shift() => {
+                e1.typecheck(type_expected.clone(), ctx.clone())?;
+                e2.typecheck_shift_amount(n, ctx.clone())
+            },
+            (Type::Word(n), Expr::BinOp(_span, _typ, BinOp::AddCarry | BinOp::SubBorrow, e1, e2)) => {
                 if let (Some(typ1), Some(typ2)) = (e1.typeinfer(ctx.clone()), e2.typeinfer(ctx.clone())) {
                     if n > 0 && typ1.equals(&typ2) && typ1.equals(&Type::Word(n - 1)) {
                         Ok(())
@@ -130,6 +132,23 @@ impl Expr {
                     Err(TypeError::Other(self.clone(), format!("Can't infer type.")))
                 }
             },
+            (Type::Word(n), Expr::Cat(_span, _typ, es)) => {
+                // One part may leave its width unknown, and it takes up whatever the others don't.
+                let mut known_width = 0;
+                let mut unknown = None;
+                for e in es {
+                    match e.typeinfer(ctx.clone()) {
+                        Some(Type::Word(m)) => known_width += m,
+                        Some(typ) => return Err(TypeError::Other(e.clone(), format!("Can't cat a {typ:?}"))),
+                        None if unknown.is_none() => unknown = Some(e),
+                        None => return Err(TypeError::CantInferType(e.clone())),
+                    }
+                }
+                match unknown {
+                    Some(e) if known_width <= n => e.typecheck(Type::word(n - known_width), ctx.clone()),
+                    _ => Err(TypeError::Other(self.clone(), format!("Can't cat to a Word[{n}]: the parts are {known_width} bits wide"))),
+                }
+            },
             (_type_expected, Expr::If(_span, _typ, cond, e1, e2)) => {
                 cond.typecheck(Type::word(1), ctx.clone())?;
                 e1.typecheck(type_expected.clone(), ctx.clone())?;
@@ -159,6 +178,14 @@ impl Expr {
                     Err(TypeError::CantInferType(self.clone()))
                 }
             },
+            (Type::SInt(width_expected), Expr::Sext(_span, _typ, e)) => {
+                match e.typeinfer(ctx.clone()) {
+                    Some(Type::SInt(0)) => Err(TypeError::Other(self.clone(), "Can't sext an SInt[0]".to_string())),
+                    Some(Type::SInt(m)) if width_expected >= m => Ok(()),
+                    Some(typ) => Err(TypeError::Other(self.clone(), format!("Can't sext a {typ:?} to an SInt[{width_expected}]"))),
+                    None => Err(TypeError::CantInferType(self.clone())),
+                }
+            },
             (Type::Word(width_expected), Expr::Zext(_span, _typ, e)) => {
                 if let Some(type_actual) = e.typeinfer(ctx.clone()) {
                     if let Type::Word(m) = type_actual {
@@ -174,6 +201,8 @@ impl Expr {
                     Err(TypeError::CantInferType(self.clone()))
                 }
             },
+            (Type::SInt(n), Expr::AsSigned(_span, _typ, e)) => e.typecheck(Type::Word(n), ctx.clone()),
+            (Type::Word(n), Expr::AsUnsigned(_span, _typ, e)) => e.typecheck(Type::SInt(n), ctx.clone()),
             (Type::Valid(inner_type), Expr::TryCast(_span, _typ, e)) => {
                 if let Type::Enum(typedef) = &*inner_type {
                     let w = typedef.bitwidth();
@@ -208,48 +237,54 @@ impl Expr {
                     Ok(())
                 }
             },
-            (_type_expected, Expr::Call(_span, _typ, fndef, es)) => {
-                // TODO
-                if fndef.args.len() != es.len() {
-                    let fn_name = &fndef.name;
-                    let m = fndef.args.len();
-                    let n = es.len();
-                    Err(TypeError::Other(self.clone(), format!("{fn_name} takes {n} args, but found {m} instead")))
+            (_type_expected, Expr::Call(_span, _typ, _fndef, _type_params, _instance, _es)) => {
+                let instance = self.typecheck_call(Some(&type_expected), ctx.clone())?;
+                if instance.ret.equals(&type_expected) {
+                    Ok(())
                 } else {
-                    let mut errors = vec![];
-                    for ((arg_name, arg_typ), e) in fndef.args.iter().zip(es.iter()) {
-                        if let Err(error) = e.typecheck(Type::clone(&arg_typ), ctx.clone()) {
-                            errors.push(error);
-                        }
-                    }
-
-                    if errors.len() > 0 {
-                        return Err(errors[0].clone());
+                    Err(TypeError::NotExpectedType(type_expected.clone(), instance.ret.clone(), self.clone()))
+                }
+            },
+            (_type_expected, Expr::IdxDyn(_span, _typ, e, i)) => {
+                // Only reached when typeinfer() fails, so this is to find the error.
+                if let Some(typ) = e.typeinfer(ctx.clone()) {
+                    if let Some(index_width) = typ.index_width() {
+                        i.typecheck(Type::word(index_width), ctx.clone())?;
+                        Err(TypeError::Other(self.clone(), format!("{self:?} is not the expected type {type_expected:?}")))
+                    } else {
+                        Err(TypeError::Other(self.clone(), format!("Can't index into {typ:?}")))
                     }
-
-                    Ok(())
+                } else {
+                    Err(TypeError::CantInferType(e.clone()))
                 }
             },
-            (_type_expected, Expr::Hole(_span, _typ, _opt_name)) => Ok(()),
+            (_type_expected, Expr::Hole(_span, _typ, _opt_name, ctx_cell)) => {
+                let _ = ctx_cell.set(ctx.clone());
+                Ok(())
+            },
             _ => Err(TypeError::Other(self.clone(), format!("{self:?} is not the expected type {type_expected:?}"))),
         };
 
         if let Ok(()) = &result {
             self.annotate_type(type_expected.clone());
         }
-        result
+        // Blame the literal whose width couldn't be worked out, rather than the expression around it.
+        result.map_err(|error| match error {
+            TypeError::CantInferType(e) => TypeError::CantInferType(e.unsized_literal().unwrap_or(e)),
+            error => error,
+        })
     }
 
-    pub fn typeinfer(&self, ctx: Context<Path, Type>) -> Option<Type> {
-        let result = match self {
+    pub fn typeinfer(self: &Arc<Self>, ctx: Context<Path, Type>) -> Option<Type> {
+        let result = match &**self {
             Expr::Reference(_span, _typ, path) => {
                 let type_actual = ctx.lookup(path)?;
                 Some(type_actual)
             },
             Expr::Net(_span, _typ, _netid) => panic!("Can't typecheck a net"),
             Expr::Word(_span, _typ, None, _n) => None,
-            Expr::Word(_span, _typ, Some(w), n) => if n >> w == 0 {
-                // TODO the n >> w condition should be a check, not a typecheck.
+            Expr::Word(_span, _typ, Some(w), n) => if fits(*n, *w) {
+                // TODO the fits condition should be a check, not a typecheck.
                 Some(Type::word(*w))
             } else {
                 None
@@ -257,17 +292,86 @@ impl Expr {
             Expr::Enum(_span, _typ, typedef, _name) => {
                 Some(typedef.clone())
             },
-            Expr::BinOp(_span, _typ, BinOp::Eq | BinOp::Neq | BinOp::Lt, e1, e2) => {
+            Expr::BinOp(_span, _typ, op, e1, e2) if op.is_comparison() => {
                 if let Some(typ1) = e1.typeinfer(ctx.clone()) {
-                    if let Err(_) = e2.typecheck(typ1, ctx.clone()) {
-                        None
+                    e2.typecheck(typ1, ctx.clone()).ok()?;
+                } else {
+                    let typ2 = e2.typeinfer(ctx.clone())?;
+                    e1.typecheck(typ2, ctx.clone()).ok()?;
+                }
+                Some(Type::Word(1))
+            },
+            Expr::BinOp(_span, _typ, BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::And | BinOp::Or | BinOp::Xor, e1, e2) => {
+                // The width of either side determines the width of the other. Eg, `x + 1`.
+                let typ = e1.typeinfer(ctx.clone()).or_else(|| e2.typeinfer(ctx.clone()))?;
+                if let Type::Word(_n) | Type::SInt(_n) = typ {
+                    e1.typecheck(typ.clone(), ctx.clone()).ok()?;
+                    e2.typecheck(typ.clone(), ctx.clone()).ok()?;
+                    Some(typ)
+                } else {
+                    None
+                }
+            },
+            Expr::BinOp(_span, _typ, BinOp::AddCarry | BinOp::SubBorrow, e1, e2) => {
+                let typ = e1.typeinfer(ctx.clone()).or_else(|| e2.typeinfer(ctx.clone()))?;
+                if let Type::Word(n) = typ {
+                    e1.typecheck(typ.clone(), ctx.clone()).ok()?;
+                    e2.typecheck(typ.clone(), ctx.clone()).ok()?;
+                    Some(Type::Word(n + 1))
+                } else {
+                    None
+                }
+            },
+            Expr::UnOp(_span, _typ, UnOp::Not, e) => e.typeinfer(ctx.clone()),
+            Expr::Let(_span, _typ, name, ascription, e, b) => {
+                let typ = if let Some(typ) = ascription {
+                    e.typecheck(typ.clone(), ctx.clone()).ok()?;
+                    typ.clone()
+                } else {
+                    e.typeinfer(ctx.clone())?
+                };
+                b.typeinfer(ctx.extend(name.clone().into(), typ))
+            },
+            Expr::If(_span, _typ, cond, e1, e2) | Expr::Mux(_span, _typ, cond, e1, e2) => {
+                // Either branch may determine the type of the other.
+                cond.typecheck(Type::word(1), ctx.clone()).ok()?;
+                let typ = e1.typeinfer(ctx.clone()).or_else(|| e2.typeinfer(ctx.clone()))?;
+                e1.typecheck(typ.clone(), ctx.clone()).ok()?;
+                e2.typecheck(typ.clone(), ctx.clone()).ok()?;
+                Some(typ)
+            },
+            Expr::Match(_span, _typ, subject, arms) => {
+                let subject_typ = subject.typeinfer(ctx.clone())?;
+                let typ = arms.iter().find_map(|MatchArm(pat, e)| {
+                    if subject_typ.valid_pat(pat) {
+                        e.typeinfer(subject_typ.extend_context_for_pat(ctx.clone(), pat))
                     } else {
-                        Some(Type::Word(1))
+                        None
                     }
+                })?;
+                self.typecheck_match(typ.clone(), ctx.clone()).ok()?;
+                Some(typ)
+            },
+            Expr::BinOp(_span, _typ, op, e1, e2) if op.is_shift() => {
+                if let Some(typ @ (Type::Word(n) | Type::SInt(n))) = e1.typeinfer(ctx.clone()) {
+                    e2.typecheck_shift_amount(n, ctx.clone()).ok()?;
+                    Some(typ)
                 } else {
                     None
                 }
             },
+            Expr::AsSigned(_span, _typ, e) => {
+                match e.typeinfer(ctx.clone())? {
+                    Type::Word(n) => Some(Type::SInt(n)),
+                    _ => None,
+                }
+            },
+            Expr::AsUnsigned(_span, _typ, e) => {
+                match e.typeinfer(ctx.clone())? {
+                    Type::SInt(n) => Some(Type::Word(n)),
+                    _ => None,
+                }
+            },
             Expr::Cat(_span, _typ, es) => {
                 let mut w = 0u64;
                 for e in es {
@@ -279,24 +383,9 @@ impl Expr {
                 }
                 Some(Type::word(w))
             },
-            Expr::Call(_span, _typ, fndef, es) => {
-                // TODO
-                if fndef.args.len() != es.len() {
-                    None
-                } else {
-                    let mut errors = vec![];
-                    for ((arg_name, arg_typ), e) in fndef.args.iter().zip(es.iter()) {
-                        if let Err(error) = e.typecheck(Type::clone(&arg_typ), ctx.clone()) {
-                            errors.push(error);
-                        }
-                    }
-
-                    if errors.len() > 0 {
-                        return None;
-                    }
-
-                    Some(fndef.ret.clone())
-                }
+            Expr::Call(_span, _typ, _fndef, _type_params, _instance, _es) => {
+                let instance = self.typecheck_call(None, ctx.clone()).ok()?;
+                Some(instance.ret.clone())
             },
             Expr::IdxField(_span, _typ, e, field) => {
                 match e.typeinfer(ctx.clone()) {
@@ -324,6 +413,16 @@ impl Expr {
                     None => None,
                 }
             },
+            Expr::IdxDyn(_span, _typ, e, i) => {
+                let typ = e.typeinfer(ctx.clone())?;
+                let index_width = typ.index_width()?;
+                i.typecheck(Type::word(index_width), ctx.clone()).ok()?;
+                match typ {
+                    Type::Word(_n) => Some(Type::word(1)),
+                    Type::Vec(typ, _n) => Some(*typ),
+                    _ => None,
+                }
+            },
             _ => None,
         };
 
@@ -333,6 +432,199 @@ impl Expr {
         result
     }
 
+    /// Typecheck a `match` expression, including that its arms are exhaustive and all reachable.
+    fn typecheck_match(self: &Arc<Self>, type_expected: Type, ctx: Context<Path, Type>) -> Result<(), TypeError> {
+        let (subject, arms) = match &**self {
+            Expr::Match(_span, _typ, subject, arms) => (subject, arms),
+            _ => unreachable!(),
+        };
+
+        if let Some(subject_typ) = subject.typeinfer(ctx.clone()) {
+            let invalid_arms: Vec<&MatchArm> = arms.into_iter().filter(|MatchArm(pat, _e)| !subject_typ.valid_pat(pat)).collect();
+            if invalid_arms.len() > 0 {
+                return Err(TypeError::Other(self.clone(), format!("Invalid patterns for {subject_typ:?}")));
+            }
+            // TODO check pattern linearity
+
+            for MatchArm(pat, e) in arms {
+                let new_ctx = subject_typ.extend_context_for_pat(ctx.clone(), pat);
+                e.typecheck(type_expected.clone(), new_ctx)?;
+            }
+
+            let typs = [subject_typ.clone()];
+            let mut rows: Vec<Vec<Pat>> = vec![];
+            for MatchArm(pat, e) in arms {
+                if !is_useful(&rows, std::slice::from_ref(pat), &typs) {
+                    return Err(TypeError::Other(e.clone(), format!("Unreachable match arm: {pat}")));
+                }
+                rows.push(vec![pat.clone()]);
+            }
+            if let Some(missing) = missing_pats(&rows, &typs) {
+                return Err(TypeError::Other(self.clone(), format!("Match is not exhaustive: {} is not covered", missing[0])));
+            }
+        } else {
+            return Err(TypeError::Other(self.clone(), format!("Match: Can't infer subject type")));
+        }
+        Ok(())
+    }
+
+    /// Find the type which the variable `x` is expected to have where it is used in this expression,
+    /// given the type this expression is expected to have, if that is known.
+    /// Used to infer the type of a `let` binding from its body. Eg, in `let x = 1; x + a`.
+    fn expected_type_of(self: &Arc<Self>, x: &Path, type_expected: Option<&Type>, ctx: Context<Path, Type>) -> Option<Type> {
+        match &**self {
+            Expr::Reference(_span, _typ, path) if path == x => type_expected.cloned(),
+            Expr::Let(_span, _typ, name, ascription, e, b) => {
+                let typ = ascription.clone().or_else(|| e.typeinfer(ctx.clone()));
+                let from_e = e.expected_type_of(x, typ.as_ref(), ctx.clone());
+                let y: Path = name.clone().into();
+                if from_e.is_some() || y == *x {
+                    from_e
+                } else {
+                    let ctx = if let Some(typ) = typ { ctx.extend(y, typ) } else { ctx };
+                    b.expected_type_of(x, type_expected, ctx)
+                }
+            },
+            Expr::UnOp(_span, _typ, UnOp::Not, e) => e.expected_type_of(x, type_expected, ctx),
+            Expr::BinOp(_span, _typ, op, e1, _e2) if op.is_shift() => e1.expected_type_of(x, type_expected, ctx),
+            Expr::BinOp(_span, _typ, op, e1, e2) => {
+                // The two sides of an operator have the same type, except for the carrying ones.
+                let type_expected = if op.is_comparison() || matches!(op, BinOp::AddCarry | BinOp::SubBorrow) {
+                    None
+                } else {
+                    type_expected.cloned()
+                };
+                let typ1 = type_expected.clone().or_else(|| e2.typeinfer(ctx.clone()));
+                let typ2 = type_expected.or_else(|| e1.typeinfer(ctx.clone()));
+                e1.expected_type_of(x, typ1.as_ref(), ctx.clone())
+                    .or_else(|| e2.expected_type_of(x, typ2.as_ref(), ctx))
+            },
+            Expr::If(_span, _typ, cond, e1, e2) | Expr::Mux(_span, _typ, cond, e1, e2) => {
+                let typ1 = type_expected.cloned().or_else(|| e2.typeinfer(ctx.clone()));
+                let typ2 = type_expected.cloned().or_else(|| e1.typeinfer(ctx.clone()));
+                cond.expected_type_of(x, Some(&Type::word(1)), ctx.clone())
+                    .or_else(|| e1.expected_type_of(x, typ1.as_ref(), ctx.clone()))
+                    .or_else(|| e2.expected_type_of(x, typ2.as_ref(), ctx))
+            },
+            Expr::Match(_span, _typ, subject, arms) => {
+                let subject_typ = subject.typeinfer(ctx.clone());
+                arms.iter().find_map(|MatchArm(pat, e)| {
+                    // A variable bound by the pattern shadows x.
+                    if pat.bound_vars().iter().any(|y| x.to_string() == *y) {
+                        return None;
+                    }
+                    let ctx = match &subject_typ {
+                        Some(subject_typ) if subject_typ.valid_pat(pat) => subject_typ.extend_context_for_pat(ctx.clone(), pat),
+                        _ => ctx.clone(),
+                    };
+                    e.expected_type_of(x, type_expected, ctx)
+                })
+            },
+            Expr::Call(_span, _typ, fndef, _type_params, _instance, es) if !fndef.is_parametric() => {
+                fndef.args.iter().zip(es.iter()).find_map(|((_arg_name, arg_typ), e)| {
+                    e.expected_type_of(x, Some(arg_typ), ctx.clone())
+                })
+            },
+            _ => None,
+        }
+    }
+
+    /// The first literal in this expression whose width hasn't been worked out.
+    /// Used to point errors at the literal rather than the expression around it.
+    pub(crate) fn unsized_literal(&self) -> Option<Arc<Expr>> {
+        let mut result = None;
+        self.with_subexprs(&mut |e: &Expr| {
+            if let Expr::Word(_span, typ, None, _n) = e {
+                if result.is_none() && typ.get().is_none() {
+                    result = Some(Arc::new(e.clone()));
+                }
+            }
+        });
+        result
+    }
+
+    /// The amount to shift a `Word[n]` by may be any `Word` which is no wider.
+    /// A literal is taken to be a `Word[n]`.
+    fn typecheck_shift_amount(self: &Arc<Self>, n: Width, ctx: Context<Path, Type>) -> Result<(), TypeError> {
+        match self.typeinfer(ctx.clone()) {
+            Some(Type::Word(m)) if m <= n => Ok(()),
+            Some(typ) => Err(TypeError::Other(self.clone(), format!("Can't shift a Word[{n}] by a {typ:?}"))),
+            None => self.typecheck(Type::word(n), ctx),
+        }
+    }
+
+    /// Typecheck the arguments of a call, returning the instance of the function which is called.
+    ///
+    /// The type parameters of a generic function are either given at the call site, eg `foo::[8](x)`,
+    /// or else inferred from the types of the arguments and from the expected return type.
+    /// The body of the function is then typechecked with the parameters substituted in.
+    fn typecheck_call(self: &Arc<Self>, type_expected: Option<&Type>, ctx: Context<Path, Type>) -> Result<Arc<FnDef>, TypeError> {
+        let (fndef, type_params, instance_cell, es) = match &**self {
+            Expr::Call(_span, _typ, fndef, type_params, instance, es) => (fndef, type_params, instance, es),
+            _ => unreachable!(),
+        };
+
+        if let Some(instance) = instance_cell.get() {
+            return Ok(instance.clone());
+        }
+
+        let fn_name = &fndef.name;
+        if fndef.args.len() != es.len() {
+            let m = fndef.args.len();
+            let n = es.len();
+            return Err(TypeError::Other(self.clone(), format!("{fn_name} takes {m} args, but found {n} instead")));
+        }
+
+        let instance = if !fndef.is_parametric() {
+            if !type_params.is_empty() {
+                return Err(TypeError::Other(self.clone(), format!("{fn_name} does not take type parameters")));
+            }
+            fndef.clone()
+        } else {
+            let mut params: BTreeMap<Name, TypeParam> = BTreeMap::new();
+            if !type_params.is_empty() {
+                if fndef.type_args.len() != type_params.len() {
+                    let m = fndef.type_args.len();
+                    let n = type_params.len();
+                    return Err(TypeError::Other(self.clone(), format!("{fn_name} takes {m} type parameters, but found {n} instead")));
+                }
+                for ((arg_name, kind), param) in fndef.type_args.iter().zip(type_params.iter()) {
+                    if param.kind() != *kind {
+                        return Err(TypeError::Other(self.clone(), format!("{arg_name} should be a {kind:?}, but found {param}")));
+                    }
+                    params.insert(arg_name.clone(), param.clone());
+                }
+            } else {
+                for ((_arg_name, arg_typ), e) in fndef.args.iter().zip(es.iter()) {
+                    if let Some(typ) = e.typeinfer(ctx.clone()) {
+                        if !arg_typ.unify(&typ, &mut params) {
+                            return Err(TypeError::NotExpectedType(arg_typ.subst(&params), typ, e.clone()));
+                        }
+                    }
+                }
+                if let Some(type_expected) = type_expected {
+                    fndef.ret.unify(type_expected, &mut params);
+                }
+                for (arg_name, _kind) in &fndef.type_args {
+                    if !params.contains_key(arg_name) {
+                        return Err(TypeError::Other(self.clone(), format!("Can't infer type parameter {arg_name} of {fn_name}")));
+                    }
+                }
+            }
+
+            let instance = fndef.instantiate(&params);
+            instance.body.typecheck(instance.ret.clone(), instance.context())?;
+            instance
+        };
+
+        for ((_arg_name, arg_typ), e) in instance.args.iter().zip(es.iter()) {
+            e.typecheck(arg_typ.clone(), ctx.clone())?;
+        }
+
+        let _ = instance_cell.set(instance.clone());
+        Ok(instance)
+    }
+
     fn annotate_type(&self, typ: Type) {
         if let Some(type_cell) = self.type_of_cell() {
             let _ = type_cell.set(typ.clone());
@@ -358,13 +650,33 @@ impl Type {
                         let alts: Vec<String> = typedef.values.iter().map(|(name, _val)| name.clone()).collect();
                         alts.contains(ctor)
                     },
-                    Type::Alt(typedef, _params) => {
-                        let alts: Vec<String> = typedef.alts.iter().map(|(name, _val)| name.clone()).collect();
-                        alts.contains(ctor)
+                    Type::Alt(typedef, params) => {
+                        match typedef.alt(ctor, params) {
+                            Some(typs) => typs.len() == subpats.len() && typs.iter().zip(subpats.iter()).all(|(typ, subpat)| typ.valid_pat(subpat)),
+                            None => false,
+                        }
                     },
                     _ => false,
                 }
             },
+            Pat::Enum(typename, value) => match self {
+                Type::Enum(typedef) => typedef.name == *typename && typedef.values.iter().any(|(name, _val)| name == value),
+                _ => false,
+            },
+            Pat::Word(width, value) => match self {
+                Type::Word(n) => width.unwrap_or(*n) == *n && (*n >= 64 || *value < 1 << n),
+                _ => false,
+            },
+            Pat::Struct(fields) => match self {
+                Type::Struct(typedef) => {
+                    fields.iter().enumerate().all(|(i, (field, pat))| {
+                        let duplicate = fields[..i].iter().any(|(other, _pat)| other == field);
+                        let field_typ = typedef.fields.iter().find(|(name, _typ)| name == field).map(|(_name, typ)| typ);
+                        !duplicate && field_typ.is_some_and(|typ| typ.valid_pat(pat))
+                    })
+                },
+                _ => false,
+            },
             Pat::Bind(_x) => true,
             Pat::Otherwise => true,
         }
@@ -386,8 +698,18 @@ impl Type {
             (Type::Enum(_typedef), Pat::At(_ctor, _subpats)) => {
                 ctx.clone()
             },
+            (_, Pat::Enum(_typename, _value)) => ctx.clone(),
+            (_, Pat::Word(_width, _value)) => ctx.clone(),
+            (Type::Struct(typedef), Pat::Struct(fields)) => {
+                let mut new_ctx = ctx.clone();
+                for (field, pat) in fields {
+                    let (_name, typ) = typedef.fields.iter().find(|(name, _typ)| name == field).unwrap();
+                    new_ctx = typ.extend_context_for_pat(new_ctx.clone(), pat);
+                }
+                new_ctx
+            },
             (Type::Alt(typedef, params), Pat::At(ctor, subpats)) => {
-                if let Some(typs) = typedef.alt(ctor) {
+                if let Some(typs) = typedef.alt(ctor, params) {
                     let mut new_ctx = ctx.clone();
                     assert_eq!(subpats.len(), typs.len());
                     for (subpat, typ) in subpats.iter().zip(typs.iter()) {
@@ -402,3 +724,183 @@ impl Type {
         }
     }
 }
+
+/// The widest `Word` whose values are enumerated when checking that a `match` is exhaustive.
+/// A `match` on a wider `Word` needs an arm which matches anything.
+const MAX_ENUMERATED_WIDTH: Width = 8;
+
+/// The constructor at the head of a pattern, for the purposes of checking `match` exhaustiveness.
+#[derive(Debug, Clone, PartialEq)]
+enum PatCtor {
+    /// A constructor of a `Valid` or `alt` type, or a value of an `enum`.
+    Named(String),
+    Word(u64),
+    Struct,
+}
+
+impl PatCtor {
+    fn to_pat(&self, typ: &Type, pats: Vec<Pat>) -> Pat {
+        match (self, typ) {
+            (PatCtor::Named(name), Type::Enum(_typedef)) => Pat::At(name.clone(), vec![]),
+            (PatCtor::Named(name), _) => Pat::At(name.clone(), pats),
+            (PatCtor::Word(value), Type::Word(n)) => Pat::Word(Some(*n), *value),
+            (PatCtor::Word(value), _) => Pat::Word(None, *value),
+            (PatCtor::Struct, Type::Struct(typedef)) => {
+                Pat::Struct(typedef.fields.iter().map(|(name, _typ)| name.clone()).zip(pats).collect())
+            },
+            (PatCtor::Struct, _) => unreachable!(),
+        }
+    }
+}
+
+impl Type {
+    /// Every constructor of this type, along with the types of its fields.
+    /// Returns `None` when the values of the type can't be enumerated.
+    fn pat_ctors(&self) -> Option<Vec<(PatCtor, Vec<Type>)>> {
+        match self {
+            Type::Valid(typ) => Some(vec![
+                (PatCtor::Named("Valid".to_string()), vec![*typ.clone()]),
+                (PatCtor::Named("Invalid".to_string()), vec![]),
+            ]),
+            Type::Enum(typedef) => Some(typedef.values.iter().map(|(name, _val)| (PatCtor::Named(name.clone()), vec![])).collect()),
+            Type::Alt(typedef, params) => Some(typedef.alts.iter().map(|(name, _typs)| (PatCtor::Named(name.clone()), typedef.alt(name, params).unwrap())).collect()),
+            Type::Struct(typedef) => Some(vec![(PatCtor::Struct, typedef.fields.iter().map(|(_name, typ)| typ.clone()).collect())]),
+            Type::Word(n) if *n <= MAX_ENUMERATED_WIDTH => Some((0..1 << n).map(|value| (PatCtor::Word(value), vec![])).collect()),
+            _ => None,
+        }
+    }
+
+    /// The types of the fields of a constructor of this type.
+    fn pat_ctor_fields(&self, ctor: &PatCtor) -> Vec<Type> {
+        match (self, ctor) {
+            (Type::Valid(typ), PatCtor::Named(name)) if name == "Valid" => vec![*typ.clone()],
+            (Type::Alt(typedef, params), PatCtor::Named(name)) => typedef.alt(name, params).unwrap_or_default(),
+            (Type::Struct(typedef), PatCtor::Struct) => typedef.fields.iter().map(|(_name, typ)| typ.clone()).collect(),
+            _ => vec![],
+        }
+    }
+}
+
+impl Pat {
+    /// The constructor at the head of this pattern and its subpatterns.
+    /// Returns `None` for patterns which match anything.
+    fn head(&self, typ: &Type) -> Option<(PatCtor, Vec<Pat>)> {
+        match self {
+            Pat::At(ctor, pats) => Some((PatCtor::Named(ctor.clone()), pats.clone())),
+            Pat::Enum(_typename, value) => Some((PatCtor::Named(value.clone()), vec![])),
+            Pat::Word(_width, value) => Some((PatCtor::Word(*value), vec![])),
+            Pat::Struct(fields) => {
+                let Type::Struct(typedef) = typ else { unreachable!() };
+                let pats = typedef.fields.iter().map(|(name, _typ)| {
+                    match fields.iter().find(|(field, _pat)| field == name) {
+                        Some((_field, pat)) => pat.clone(),
+                        None => Pat::Otherwise,
+                    }
+                }).collect();
+                Some((PatCtor::Struct, pats))
+            },
+            Pat::Bind(_x) => None,
+            Pat::Otherwise => None,
+        }
+    }
+}
+
+/// Whether the literal `n` fits in `width` bits. Any literal fits in 64 bits or more.
+fn fits(n: u64, width: Width) -> bool {
+    u32::try_from(width).ok().and_then(|width| n.checked_shr(width)).unwrap_or(0) == 0
+}
+
+/// Keep the rows of a pattern matrix which match the constructor `ctor`,
+/// replacing their first column with its subpatterns.
+fn specialize(rows: &[Vec<Pat>], ctor: &PatCtor, arity: usize, typ: &Type) -> Vec<Vec<Pat>> {
+    let mut results = vec![];
+    for row in rows {
+        let mut result = match row[0].head(typ) {
+            None => vec![Pat::Otherwise; arity],
+            Some((head, pats)) if head == *ctor => pats,
+            Some(_head) => continue,
+        };
+        result.extend(row[1..].iter().cloned());
+        results.push(result);
+    }
+    results
+}
+
+/// Keep the rows of a pattern matrix whose first column matches anything, removing that column.
+fn default_rows(rows: &[Vec<Pat>]) -> Vec<Vec<Pat>> {
+    rows.iter()
+        .filter(|row| matches!(row[0], Pat::Bind(_) | Pat::Otherwise))
+        .map(|row| row[1..].to_vec())
+        .collect()
+}
+
+/// Whether the constructors at the heads of the rows include every constructor of `typ`.
+fn is_complete(rows: &[Vec<Pat>], typ: &Type) -> Option<Vec<(PatCtor, Vec<Type>)>> {
+    let heads: Vec<PatCtor> = rows.iter().filter_map(|row| row[0].head(typ)).map(|(ctor, _pats)| ctor).collect();
+    let ctors = typ.pat_ctors()?;
+    if ctors.iter().all(|(ctor, _typs)| heads.contains(ctor)) {
+        Some(ctors)
+    } else {
+        None
+    }
+}
+
+/// Whether `pats` matches some value which none of the rows of the pattern matrix do.
+/// The columns of the matrix have types `typs`.
+///
+/// See Maranget, "Warnings for pattern matching".
+fn is_useful(rows: &[Vec<Pat>], pats: &[Pat], typs: &[Type]) -> bool {
+    let Some(typ) = typs.first() else {
+        return rows.is_empty();
+    };
+
+    if let Some((ctor, mut subpats)) = pats[0].head(typ) {
+        let mut field_typs = typ.pat_ctor_fields(&ctor);
+        let arity = field_typs.len();
+        subpats.extend(pats[1..].iter().cloned());
+        field_typs.extend(typs[1..].iter().cloned());
+        is_useful(&specialize(rows, &ctor, arity, typ), &subpats, &field_typs)
+    } else if let Some(ctors) = is_complete(rows, typ) {
+        ctors.into_iter().any(|(ctor, mut field_typs)| {
+            let arity = field_typs.len();
+            let mut subpats = vec![Pat::Otherwise; arity];
+            subpats.extend(pats[1..].iter().cloned());
+            field_typs.extend(typs[1..].iter().cloned());
+            is_useful(&specialize(rows, &ctor, arity, typ), &subpats, &field_typs)
+        })
+    } else {
+        is_useful(&default_rows(rows), &pats[1..], &typs[1..])
+    }
+}
+
+/// Find values which none of the rows of the pattern matrix match, written as patterns.
+/// Returns `None` when the rows match every value.
+fn missing_pats(rows: &[Vec<Pat>], typs: &[Type]) -> Option<Vec<Pat>> {
+    let Some(typ) = typs.first() else {
+        return if rows.is_empty() { Some(vec![]) } else { None };
+    };
+
+    if let Some(ctors) = is_complete(rows, typ) {
+        for (ctor, mut field_typs) in ctors {
+            let arity = field_typs.len();
+            field_typs.extend(typs[1..].iter().cloned());
+            if let Some(mut missing) = missing_pats(&specialize(rows, &ctor, arity, typ), &field_typs) {
+                let rest = missing.split_off(arity);
+                let mut results = vec![ctor.to_pat(typ, missing)];
+                results.extend(rest);
+                return Some(results);
+            }
+        }
+        None
+    } else {
+        let mut missing = missing_pats(&default_rows(rows), &typs[1..])?;
+        let heads: Vec<PatCtor> = rows.iter().filter_map(|row| row[0].head(typ)).map(|(ctor, _pats)| ctor).collect();
+        let unmatched = typ.pat_ctors().and_then(|ctors| ctors.into_iter().find(|(ctor, _typs)| !heads.contains(ctor)));
+        let head = match unmatched {
+            Some((ctor, typs)) if !heads.is_empty() => ctor.to_pat(typ, vec![Pat::Otherwise; typs.len()]),
+            _ => Pat::Otherwise,
+        };
+        missing.insert(0, head);
+        Some(missing)
+    }
+}
@@ -1,12 +1,17 @@
 use super::Context;
 use super::Expr;
 use super::Path;
+use super::Name;
 use super::loc::Span;
 
+use std::collections::BTreeMap;
 use std::sync::Arc;
+use once_cell::sync::OnceCell;
 
 pub use crate::ast::WordLit; // re-export
 pub use crate::ast::Kind;    // re-export
+pub use crate::ast::Direction; // re-export
+pub use crate::ast::Visibility; // re-export
 
 /// The bitwidth of a [`Type::Word`].
 pub type Width = u64;
@@ -19,6 +24,8 @@ pub type Length = u64;
 pub enum Type {
     /// An n-bit two's complement integer. Nominally unsigned. Written `Word[n]`.
     Word(Width),
+    /// An n-bit two's complement signed integer. Written `SInt[n]`.
+    SInt(Width),
     /// A n-element vector. Written `Vec[T, n]`.
     Vec(Box<Type>, Length),
     /// An optional value. Written `Valid[T]`.
@@ -29,17 +36,30 @@ pub enum Type {
     Struct(Arc<StructTypeDef>),
     /// A user-defined `alt`.
     Alt(Arc<AltTypeDef>, Vec<TypeParam>),
+    /// A reference to a `Type` parameter of a parametric definition. Eg, the `T` in `Vec[T, depth]`.
+    Var(Name),
+    /// A `Word[n]` whose width refers to a `Nat` parameter.
+    GenericWord(Nat),
+    /// An `SInt[n]` whose width refers to a `Nat` parameter.
+    GenericSInt(Nat),
+    /// A `Vec[T, n]` whose length refers to a `Nat` parameter.
+    GenericVec(Box<Type>, Nat),
 }
 
 impl Type {
     pub fn name(&self) -> &str {
         match self {
             Type::Word(_width) => "Word",
+            Type::SInt(_width) => "SInt",
             Type::Vec(_typ, _length) => "Vec",
             Type::Valid(_typ) => "Valid",
             Type::Enum(typedef) => &typedef.name,
             Type::Struct(typedef) => &typedef.name,
             Type::Alt(typedef, _params) => &typedef.name,
+            Type::Var(name) => name,
+            Type::GenericWord(_width) => "Word",
+            Type::GenericSInt(_width) => "SInt",
+            Type::GenericVec(_typ, _length) => "Vec",
         }
     }
 
@@ -47,6 +67,7 @@ impl Type {
     pub fn equals(&self, other: &Type) -> bool {
         match (self, other) {
             (Type::Word(width1),      Type::Word(width2)) => width1 == width2,
+            (Type::SInt(width1),      Type::SInt(width2)) => width1 == width2,
             (Type::Vec(typ1, len1),   Type::Vec(typ2, len2)) => len1 == len2 && typ1.equals(typ2),
             (Type::Valid(typ1),       Type::Valid(typ2)) => typ1.equals(typ2),
             (Type::Enum(typedef1),    Type::Enum(typedef2)) => Arc::ptr_eq(typedef1, typedef2),
@@ -64,6 +85,10 @@ impl Type {
                 }
                 Arc::ptr_eq(typedef1, typedef2)
             },
+            (Type::Var(name1),        Type::Var(name2)) => name1 == name2,
+            (Type::GenericWord(n1),   Type::GenericWord(n2)) => n1 == n2,
+            (Type::GenericSInt(n1),   Type::GenericSInt(n2)) => n1 == n2,
+            (Type::GenericVec(typ1, n1), Type::GenericVec(typ2, n2)) => n1 == n2 && typ1.equals(typ2),
             _ => false,
         }
     }
@@ -83,26 +108,325 @@ impl Type {
     pub fn bitwidth(&self) -> Width {
         match self {
             Type::Word(n) => *n,
+            Type::SInt(n) => *n,
             Type::Valid(typ) => typ.bitwidth() + 1,
             Type::Vec(typ, n) => typ.bitwidth() * n,
             Type::Enum(typedef) => typedef.bitwidth(),
             Type::Struct(typedef) => typedef.bitwidth(),
-            Type::Alt(typedef, params) => todo!(), // TODO typedef.bitwidth(),
+            Type::Alt(typedef, params) => typedef.bitwidth(params),
+            Type::Var(_) | Type::GenericWord(_) | Type::GenericSInt(_) | Type::GenericVec(_, _) => {
+                panic!("Can't take the bitwidth of {self:?} before its parameters are substituted")
+            },
+        }
+    }
+
+    /// The constructors of a `Valid[T]` or an `alt` type, with the types of their fields.
+    /// The tag of each constructor is its position in this list.
+    ///
+    /// A value of one of these types is packed with the tag in the highest bits.
+    /// The fields follow, the first field highest, and any bits left over at the bottom are zero.
+    /// Eg, `@Valid(3w4)` of type `Valid[Word[4]]` is `0b1_0011w5`.
+    pub fn ctors(&self) -> Option<Vec<(String, Vec<Type>)>> {
+        match self {
+            Type::Valid(typ) => Some(vec![
+                ("Invalid".to_string(), vec![]),
+                ("Valid".to_string(), vec![*typ.clone()]),
+            ]),
+            Type::Alt(typedef, params) => {
+                Some(typedef.alts.iter().map(|(name, _typs)| (name.clone(), typedef.alt(name, params).unwrap())).collect())
+            },
+            _ => None,
+        }
+    }
+
+    /// The tag and the field types of the constructor `ctor`. See [`Type::ctors`].
+    pub fn ctor_tag(&self, ctor: &str) -> Option<(u64, Vec<Type>)> {
+        let ctors = self.ctors()?;
+        let tag = ctors.iter().position(|(name, _typs)| name == ctor)?;
+        Some((tag as u64, ctors[tag].1.clone()))
+    }
+
+    /// The number of bits used for the tag of a `Valid[T]` or an `alt` type. See [`Type::ctors`].
+    pub fn tag_width(&self) -> Option<Width> {
+        match self {
+            Type::Valid(_typ) => Some(1),
+            Type::Alt(typedef, _params) => Some(typedef.tag_width()),
+            _ => None,
+        }
+    }
+
+    /// The width of the index into a `Word` or a `Vec`. Eg, a `Vec[T, 32]` is indexed by a `Word[5]`.
+    pub fn index_width(&self) -> Option<Width> {
+        let n = match self {
+            Type::Word(n) => *n,
+            Type::Vec(_typ, n) => *n,
+            _ => return None,
+        };
+        let mut width = 1;
+        while (1 << width) < n {
+            width += 1;
+        }
+        Some(width)
+    }
+
+    /// Replace the parameters of a parametric definition with their values.
+    pub fn subst(&self, params: &BTreeMap<Name, TypeParam>) -> Type {
+        match self {
+            Type::Word(_n) => self.clone(),
+            Type::SInt(_n) => self.clone(),
+            Type::Vec(typ, n) => Type::vec(typ.subst(params), *n),
+            Type::Valid(typ) => Type::valid(typ.subst(params)),
+            Type::Enum(_typedef) => self.clone(),
+            Type::Struct(_typedef) => self.clone(),
+            Type::Alt(typedef, alt_params) => {
+                Type::Alt(typedef.clone(), alt_params.iter().map(|param| param.subst(params)).collect())
+            },
+            Type::Var(name) => {
+                if let Some(TypeParam::Type(typ)) = params.get(name) {
+                    typ.clone()
+                } else {
+                    self.clone()
+                }
+            },
+            Type::GenericWord(n) => {
+                match n.subst(params) {
+                    Nat::Lit(n) => Type::Word(n),
+                    n => Type::GenericWord(n),
+                }
+            },
+            Type::GenericSInt(n) => {
+                match n.subst(params) {
+                    Nat::Lit(n) => Type::SInt(n),
+                    n => Type::GenericSInt(n),
+                }
+            },
+            Type::GenericVec(typ, n) => {
+                match n.subst(params) {
+                    Nat::Lit(n) => Type::vec(typ.subst(params), n),
+                    n => Type::GenericVec(Box::new(typ.subst(params)), n),
+                }
+            },
+        }
+    }
+
+    /// Whether the type still mentions the parameters of a parametric definition.
+    pub fn is_generic(&self) -> bool {
+        match self {
+            Type::Word(_n) => false,
+            Type::SInt(_n) => false,
+            Type::Vec(typ, _n) => typ.is_generic(),
+            Type::Valid(typ) => typ.is_generic(),
+            Type::Enum(_typedef) => false,
+            Type::Struct(_typedef) => false,
+            Type::Alt(_typedef, params) => params.iter().any(|param| param.is_generic()),
+            Type::Var(_name) => true,
+            Type::GenericWord(_n) => true,
+            Type::GenericSInt(_n) => true,
+            Type::GenericVec(_typ, _n) => true,
+        }
+    }
+
+    /// Whether a width or length in this type doesn't fit in a `u64`. See [`Nat::is_overflow`].
+    pub fn is_overflow(&self) -> bool {
+        match self {
+            Type::Word(_n) => false,
+            Type::SInt(_n) => false,
+            Type::Vec(typ, _n) => typ.is_overflow(),
+            Type::Valid(typ) => typ.is_overflow(),
+            Type::Enum(_typedef) => false,
+            Type::Struct(_typedef) => false,
+            Type::Alt(_typedef, params) => params.iter().any(|param| param.is_overflow()),
+            Type::Var(_name) => false,
+            Type::GenericWord(n) => n.is_overflow(),
+            Type::GenericSInt(n) => n.is_overflow(),
+            Type::GenericVec(typ, n) => typ.is_overflow() || n.is_overflow(),
         }
     }
 }
 
-#[derive(Clone)]
+impl Type {
+    /// Match this type, which may mention the parameters of a generic definition,
+    /// against the concrete type `actual`, adding any parameters it determines to `params`.
+    /// Returns `false` if the two can't be made equal.
+    pub fn unify(&self, actual: &Type, params: &mut BTreeMap<Name, TypeParam>) -> bool {
+        match (self, actual) {
+            (Type::Var(name), _actual) => {
+                if let Some(TypeParam::Type(typ)) = params.get(name) {
+                    typ.equals(actual)
+                } else {
+                    params.insert(name.clone(), TypeParam::Type(actual.clone()));
+                    true
+                }
+            },
+            (Type::GenericWord(n), Type::Word(width)) => n.unify(*width, params),
+            (Type::GenericSInt(n), Type::SInt(width)) => n.unify(*width, params),
+            (Type::GenericVec(typ, n), Type::Vec(actual_typ, length)) => n.unify(*length, params) && typ.unify(actual_typ, params),
+            (Type::Vec(typ, n), Type::Vec(actual_typ, length)) => n == length && typ.unify(actual_typ, params),
+            (Type::Valid(typ), Type::Valid(actual_typ)) => typ.unify(actual_typ, params),
+            _ => self.equals(actual),
+        }
+    }
+}
+
+/// A natural number used as a width or a length.
+/// It may refer to a `Nat` parameter of a parametric definition.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Nat {
+    Lit(u64),
+    Param(Name),
+    Add(Box<Nat>, Box<Nat>),
+    Sub(Box<Nat>, Box<Nat>),
+    Mul(Box<Nat>, Box<Nat>),
+}
+
+impl Nat {
+    /// Replace the parameters with their values.
+    /// Arithmetic is evaluated once both sides are literals, unless it would overflow.
+    pub fn subst(&self, params: &BTreeMap<Name, TypeParam>) -> Nat {
+        match self {
+            Nat::Lit(_n) => self.clone(),
+            Nat::Param(name) => {
+                if let Some(TypeParam::Nat(n)) = params.get(name) {
+                    n.clone()
+                } else {
+                    self.clone()
+                }
+            },
+            Nat::Add(n, m) => {
+                match (n.subst(params), m.subst(params)) {
+                    (Nat::Lit(n), Nat::Lit(m)) if n.checked_add(m).is_some() => Nat::Lit(n + m),
+                    (n, m) => Nat::Add(Box::new(n), Box::new(m)),
+                }
+            },
+            Nat::Sub(n, m) => {
+                match (n.subst(params), m.subst(params)) {
+                    (Nat::Lit(n), Nat::Lit(m)) if n >= m => Nat::Lit(n - m),
+                    (n, m) => Nat::Sub(Box::new(n), Box::new(m)),
+                }
+            },
+            Nat::Mul(n, m) => {
+                match (n.subst(params), m.subst(params)) {
+                    (Nat::Lit(n), Nat::Lit(m)) if n.checked_mul(m).is_some() => Nat::Lit(n * m),
+                    (n, m) => Nat::Mul(Box::new(n), Box::new(m)),
+                }
+            },
+        }
+    }
+
+    /// Whether this contains a subtraction of literals which would be negative. Eg, `n+(2-4)`.
+    /// Call [`Nat::subst`] first, so that the arithmetic which can be is evaluated.
+    pub fn is_negative(&self) -> bool {
+        match self {
+            Nat::Lit(_n) => false,
+            Nat::Param(_name) => false,
+            Nat::Add(n, m) => n.is_negative() || m.is_negative(),
+            Nat::Sub(n, m) => matches!((&**n, &**m), (Nat::Lit(n), Nat::Lit(m)) if n < m) || n.is_negative() || m.is_negative(),
+            Nat::Mul(n, m) => n.is_negative() || m.is_negative(),
+        }
+    }
+
+    /// Whether this contains arithmetic on literals which doesn't fit in a `u64`. Eg, `4294967296*4294967296`.
+    /// Call [`Nat::subst`] first, so that the arithmetic which can be is evaluated.
+    pub fn is_overflow(&self) -> bool {
+        match self {
+            Nat::Lit(_n) => false,
+            Nat::Param(_name) => false,
+            Nat::Add(n, m) | Nat::Mul(n, m) => matches!((&**n, &**m), (Nat::Lit(_n), Nat::Lit(_m))) || n.is_overflow() || m.is_overflow(),
+            Nat::Sub(n, m) => n.is_overflow() || m.is_overflow(),
+        }
+    }
+
+    /// Solve for the parameters so that this evaluates to `value`, adding them to `params`.
+    /// Only arithmetic with a single unknown parameter can be solved.
+    /// Returns `false` if there is no solution or if it can't be found.
+    pub fn unify(&self, value: u64, params: &mut BTreeMap<Name, TypeParam>) -> bool {
+        match self.subst(params) {
+            Nat::Lit(n) => n == value,
+            Nat::Param(name) => {
+                params.insert(name, TypeParam::Nat(Nat::Lit(value)));
+                true
+            },
+            Nat::Add(n, m) => {
+                match (*n, *m) {
+                    (Nat::Lit(k), m) | (m, Nat::Lit(k)) => value.checked_sub(k).is_some_and(|value| m.unify(value, params)),
+                    _ => false,
+                }
+            },
+            Nat::Sub(n, m) => {
+                match (*n, *m) {
+                    (n, Nat::Lit(k)) => value.checked_add(k).is_some_and(|value| n.unify(value, params)),
+                    (Nat::Lit(k), m) => k.checked_sub(value).is_some_and(|value| m.unify(value, params)),
+                    _ => false,
+                }
+            },
+            Nat::Mul(n, m) => {
+                match (*n, *m) {
+                    (Nat::Lit(0), _m) | (_m, Nat::Lit(0)) => value == 0,
+                    (Nat::Lit(k), m) | (m, Nat::Lit(k)) => value % k == 0 && m.unify(value / k, params),
+                    _ => false,
+                }
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
 pub enum TypeParam {
-    Nat(u64),
+    Nat(Nat),
     Type(Type),
 }
 
+impl TypeParam {
+    pub fn subst(&self, params: &BTreeMap<Name, TypeParam>) -> TypeParam {
+        match self {
+            TypeParam::Nat(n) => TypeParam::Nat(n.subst(params)),
+            TypeParam::Type(typ) => TypeParam::Type(typ.subst(params)),
+        }
+    }
+
+    pub fn kind(&self) -> Kind {
+        match self {
+            TypeParam::Nat(_n) => Kind::Nat,
+            TypeParam::Type(_typ) => Kind::Type,
+        }
+    }
+
+    pub fn equals(&self, other: &TypeParam) -> bool {
+        match (self, other) {
+            (TypeParam::Nat(n1), TypeParam::Nat(n2)) => n1 == n2,
+            (TypeParam::Type(typ1), TypeParam::Type(typ2)) => typ1.equals(typ2),
+            _ => false,
+        }
+    }
+
+    /// Whether the parameter still mentions the parameters of an enclosing parametric definition.
+    pub fn is_generic(&self) -> bool {
+        match self {
+            TypeParam::Nat(n) => !matches!(n, Nat::Lit(_n)),
+            TypeParam::Type(typ) => typ.is_generic(),
+        }
+    }
+
+    pub fn is_overflow(&self) -> bool {
+        match self {
+            TypeParam::Nat(n) => n.is_overflow(),
+            TypeParam::Type(typ) => typ.is_overflow(),
+        }
+    }
+}
+
 /// A user-defined `enum` type.
 #[derive(Debug, Clone)]
 pub struct EnumTypeDef {
     pub name: String,
+    pub visibility: Visibility,
+    pub doc: Option<String>,
+    /// The values, after automatic numbering.
+    /// The width is only present when it was written out.
     pub values: Vec<(String, WordLit)>,
+    /// The width given by `of Word[n]`, if any.
+    pub width: Option<Width>,
+    pub onehot: bool,
     pub span: Span,
 }
 
@@ -110,6 +434,8 @@ pub struct EnumTypeDef {
 #[derive(Debug, Clone)]
 pub struct StructTypeDef {
     pub name: String,
+    pub visibility: Visibility,
+    pub doc: Option<String>,
     pub fields: Vec<(String, Type)>,
     pub span: Span,
 }
@@ -118,19 +444,84 @@ pub struct StructTypeDef {
 #[derive(Debug, Clone)]
 pub struct AltTypeDef {
     pub name: String,
+    pub visibility: Visibility,
+    pub doc: Option<String>,
+    pub type_args: Vec<(String, Kind)>,
+    /// The alternatives, in the order they were declared.
     pub alts: Vec<(String, Vec<Type>)>,
     pub span: Span,
 }
 
 impl AltTypeDef {
-    pub fn alt(&self, name: &str) -> Option<Vec<Type>> {
+    /// The types of the fields of the alternative `name`, given the parameters of the type.
+    pub fn alt(&self, name: &str, params: &[TypeParam]) -> Option<Vec<Type>> {
+        let params: BTreeMap<Name, TypeParam> = self.type_args.iter()
+            .map(|(type_arg, _kind)| type_arg.clone())
+            .zip(params.iter().cloned())
+            .collect();
         for (nam, typs) in &self.alts {
             if name == nam {
-                return Some(typs.clone())
+                return Some(typs.iter().map(|typ| typ.subst(&params)).collect())
             }
         }
         None
     }
+
+    pub fn tag_width(&self) -> Width {
+        let mut width = 1;
+        while (1 << width) < self.alts.len() as Width {
+            width += 1;
+        }
+        width
+    }
+
+    /// The width of the tag plus the width of the widest alternative.
+    pub fn bitwidth(&self, params: &[TypeParam]) -> Width {
+        let payload_width = self.alts.iter()
+            .map(|(name, _typs)| self.alt(name, params).unwrap().iter().map(|typ| typ.bitwidth()).sum())
+            .max()
+            .unwrap_or(0);
+        self.tag_width() + payload_width
+    }
+}
+
+/// A user-defined `shape` of ports. See [`crate::ast::ShapeDef`].
+#[derive(Debug, Clone)]
+pub struct ShapeDef {
+    pub span: Span,
+    pub name: String,
+    pub visibility: Visibility,
+    pub doc: Option<String>,
+    pub ports: Vec<(Name, Direction, Type)>,
+}
+
+/// A package-level `const` definition.
+#[derive(Debug, Clone)]
+pub struct ConstDef {
+    pub span: Span,
+    pub name: String,
+    pub visibility: Visibility,
+    pub doc: Option<String>,
+    pub value: ConstValue,
+}
+
+/// The value of a [`ConstDef`], folded when the package is resolved.
+#[derive(Debug, Clone)]
+pub enum ConstValue {
+    /// Eg, `const XLEN of Nat = 32;`.
+    Nat(u64),
+    /// Eg, `const RESET_PC of Word[32] = 0x8000_0000w32;`.
+    Value(Type, crate::sim::Value),
+}
+
+impl ConstDef {
+    /// The folded value, as it appears where the `const` is used.
+    pub fn to_expr(&self, span: &Span) -> Arc<Expr> {
+        match &self.value {
+            ConstValue::Nat(n) => Arc::new(Expr::Word(span.clone(), OnceCell::new(), None, *n)),
+            ConstValue::Value(_typ, value) => value.to_expr(span),
+        }
+    }
 }
 
 /// A user-defined `fn` function.
@@ -138,6 +529,8 @@ impl AltTypeDef {
 pub struct FnDef {
     pub span: Span,
     pub name: String,
+    pub visibility: Visibility,
+    pub doc: Option<String>,
     pub type_args: Vec<(String, Kind)>,
     pub args: Vec<(String, Type)>,
     pub ret: Type,
@@ -145,6 +538,10 @@ pub struct FnDef {
 }
 
 impl FnDef {
+    pub fn is_parametric(&self) -> bool {
+        !self.type_args.is_empty()
+    }
+
     pub fn context(&self) -> Context<Path, Type> {
         Context::from(self.args.iter().map(|(arg_name, arg_type)| (arg_name.to_string().into(), arg_type.clone())).collect::<Vec<_>>())
     }
@@ -175,20 +572,23 @@ impl EnumTypeDef {
         None
     }
 
+    /// The width given by `of Word[n]`, else the width of the first value with an explicit width,
+    /// else the smallest width which fits every value.
+    /// Disagreements between these are reported by `Package::check`.
     pub fn bitwidth(&self) -> Width {
-        // TODO
-        let mut max_width = None;
+        if let Some(width) = self.width {
+            return width;
+        }
         for (_name, value) in &self.values {
-            if let WordLit(Some(w), _n) = value {
-                if let Some(max_w) = max_width {
-                    assert_eq!(*w, max_w);
-                } else {
-                    max_width = Some(*w);
-                }
-             }
+            if let Some(width) = value.width() {
+                return width;
+            }
         }
-        // TODO
-        max_width.unwrap()
+        if self.onehot {
+            return self.values.len().max(1) as Width;
+        }
+        let max_value = self.values.iter().map(|(_name, value)| value.value()).max().unwrap_or(0);
+        (u64::BITS - max_value.leading_zeros()).max(1) as Width
     }
 }
 
@@ -196,6 +596,7 @@ impl std::fmt::Debug for Type {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
         match self {
                 Type::Word(n) => write!(f, "Word[{n}]"),
+                Type::SInt(n) => write!(f, "SInt[{n}]"),
                 Type::Valid(typ) => write!(f, "Valid[{typ:?}]"),
                 Type::Vec(typ, n) => write!(f, "Vec[{typ:?}, {n}]"),
                 Type::Struct(typedef) => write!(f, "{}", typedef.name),
@@ -207,6 +608,38 @@ impl std::fmt::Debug for Type {
                     write!(f, "{}[{:?}]", typedef.name, params.iter().map(|param| param.to_string()).collect::<Vec<_>>().join(", "))
                 }
             },
+            Type::Var(name) => write!(f, "{name}"),
+            Type::GenericWord(n) => write!(f, "Word[{n}]"),
+            Type::GenericSInt(n) => write!(f, "SInt[{n}]"),
+            Type::GenericVec(typ, n) => write!(f, "Vec[{typ:?}, {n}]"),
+        }
+    }
+}
+
+impl std::fmt::Display for Nat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            Nat::Lit(n) => write!(f, "{n}"),
+            Nat::Param(name) => write!(f, "{name}"),
+            Nat::Add(n, m) => write!(f, "{n}+{m}"),
+            Nat::Sub(n, m) => {
+                match &**m {
+                    Nat::Add(_, _) | Nat::Sub(_, _) => write!(f, "{n}-({m})"),
+                    _ => write!(f, "{n}-{m}"),
+                }
+            },
+            Nat::Mul(n, m) => {
+                for (i, factor) in [n, m].into_iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "*")?;
+                    }
+                    match &**factor {
+                        Nat::Add(_, _) | Nat::Sub(_, _) => write!(f, "({factor})")?,
+                        _ => write!(f, "{factor}")?,
+                    }
+                }
+                Ok(())
+            },
         }
     }
 }
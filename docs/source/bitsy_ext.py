@@ -14,10 +14,11 @@ class BitsyLexer(RegexLexer):
     KEYWORDS = [
         'top', 'reg', 'node', 'mod', 'ext', 'if', 'reset',
         'incoming', 'outgoing', 'enum', 'of', 'type',
-        'fn', 'pub', 'otherwise', 'match', 'alt', 'struct',
+        'fn', 'pub', 'otherwise', 'match', 'alt', 'struct', 'when', 'dom', 'on', 'for', 'in', 'const', 'shape', 'port', 'flip', 'assert', 'cover', 'onehot',
+        'mem', 'latency', 'read', 'write', 'mask', 'tb', 'poke', 'expect', 'loop', 'clock', 'debug', 'import',
     ]
 
-    BUILTINS = ['Bit', 'Word', 'Vec', 'tuple', 'Nat', 'Shape', 'io']
+    BUILTINS = ['Bit', 'Word', 'SInt', 'Vec', 'tuple', 'Nat', 'Shape', 'io']
     CONSTANTS = ['false', 'true']
 
     tokens = {